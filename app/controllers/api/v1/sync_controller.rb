@@ -291,24 +291,21 @@ class Api::V1::SyncController < ApplicationController
   private
 
   def generate_content_hash(post)
-    content_to_hash = [
-      post.content_encrypted,
-      post.timestamp&.to_i&.to_s,
-      post.signature,
-      post.attachments.map(&:checksum).sort.join
-    ].join
-
-    Digest::SHA256.hexdigest(content_to_hash)
+    ContentHash.digest(
+      content_encrypted: post.content_encrypted,
+      timestamp: post.timestamp,
+      signature: post.signature,
+      attachment_checksums: post.attachments.map(&:checksum)
+    )
   end
 
   def generate_content_hash_from_data(post_data)
-    content_to_hash = [
-      post_data[:content_encrypted],
-      post_data[:timestamp],
-      post_data[:signature]
-    ].compact.join
-
-    Digest::SHA256.hexdigest(content_to_hash)
+    ContentHash.digest(
+      content_encrypted: post_data[:content_encrypted],
+      timestamp: post_data[:timestamp],
+      signature: post_data[:signature],
+      attachment_checksums: post_data[:attachment_checksums] || []
+    )
   end
 
   def calculate_storage_used
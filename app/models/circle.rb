@@ -0,0 +1,9 @@
+class Circle < ApplicationRecord
+  belongs_to :user
+  has_many :circle_members, dependent: :destroy
+  has_many :members, through: :circle_members, source: :user
+
+  validates :name, presence: true, length: { maximum: 50 }
+  validates :public_id, presence: true, uniqueness: true
+  validates :epoch, numericality: { only_integer: true, greater_than: 0 }
+end
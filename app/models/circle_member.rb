@@ -0,0 +1,6 @@
+class CircleMember < ApplicationRecord
+  belongs_to :circle
+  belongs_to :user
+
+  validates :user_id, uniqueness: { scope: :circle_id }
+end
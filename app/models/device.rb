@@ -0,0 +1,11 @@
+class Device < ApplicationRecord
+  belongs_to :user
+
+  validates :device_key, presence: true, uniqueness: true
+  validates :name, presence: true, length: { maximum: 64 }
+  validates :certificate, presence: true
+
+  def revoked?
+    revocation.present?
+  end
+end
@@ -0,0 +1,6 @@
+class KeyEvent < ApplicationRecord
+  belongs_to :user
+
+  validates :event_type, inclusion: { in: %w[rotation revocation] }
+  validates :payload, presence: true
+end
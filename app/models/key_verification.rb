@@ -0,0 +1,12 @@
+class KeyVerification < ApplicationRecord
+  belongs_to :user
+  belongs_to :friend, class_name: "User"
+
+  validates :public_key, presence: true
+  validates :friend_id, uniqueness: { scope: :user_id }
+
+  # True once the friend has moved to a key other than the one compared.
+  def key_changed?
+    friend.public_key != public_key
+  end
+end
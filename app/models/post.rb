@@ -221,8 +221,7 @@ class Post < ApplicationRecord
     return false unless original_user && synced_from_user
 
     # Verify the content hash matches
-    expected_hash = generate_content_hash
-    content_hash == expected_hash
+    ContentHash.matches?(content_hash, **content_hash_fields)
   end
 
   def age_since_sync
@@ -252,17 +251,17 @@ class Post < ApplicationRecord
   end
 
   def generate_content_hash
-    # Prevent infinite recursion by using loaded attachments only
-    attachment_checksums = attachments.loaded? ? attachments.map(&:checksum).sort.join : ""
-
-    content_to_hash = [
-      content_encrypted,
-      timestamp&.to_i&.to_s,
-      signature,
-      attachment_checksums
-    ].compact.join
+    ContentHash.digest(**content_hash_fields)
+  end
 
-    Digest::SHA256.hexdigest(content_to_hash)
+  def content_hash_fields
+    {
+      content_encrypted: content_encrypted,
+      timestamp: timestamp,
+      signature: signature,
+      # Prevent infinite recursion by using loaded attachments only
+      attachment_checksums: attachments.loaded? ? attachments.map(&:checksum) : []
+    }
   end
 
   def sync_fields_consistency
@@ -287,8 +286,7 @@ class Post < ApplicationRecord
   def validate_content_hash
     return if content_hash.blank? || content_encrypted.blank?
 
-    expected_hash = generate_content_hash
-    if content_hash != expected_hash
+    unless ContentHash.matches?(content_hash, **content_hash_fields)
       errors.add(:content_hash, "Content hash mismatch detected")
     end
   end
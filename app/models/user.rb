@@ -10,6 +10,12 @@ class User < ApplicationRecord
   has_many :posts, dependent: :destroy
   has_many :comments, dependent: :destroy
   has_many :peers, dependent: :destroy
+  has_many :key_events, dependent: :destroy
+  has_many :key_verifications, dependent: :destroy
+  has_many :received_key_verifications, class_name: "KeyVerification", foreign_key: "friend_id", dependent: :destroy
+  has_many :circles, dependent: :destroy
+  has_many :circle_memberships, class_name: "CircleMember", dependent: :destroy
+  has_many :devices, dependent: :destroy
 
   # Message associations
   has_many :sent_messages, class_name: "Message", foreign_key: "sender_id", dependent: :destroy
@@ -0,0 +1,115 @@
+require "digest"
+require "fiddle"
+require "json"
+
+# Canonical post content hash, shared with the Rust backend
+# (src-tauri/src/crypto/content_hash.rs). Calls into the Rust library when it
+# has been built, or the one CIPHER_NATIVE_LIB names, and otherwise uses the
+# Ruby port below. test-vectors/content_hash.json keeps the two in step.
+module ContentHash
+  VERSION = 1
+  DOMAIN = "cipher-content-hash\0".b.freeze
+  LIBRARY_NAMES = %w[libapp.so libapp.dylib app.dll].freeze
+
+  class << self
+    def digest(content_encrypted:, timestamp:, signature:, attachment_checksums: [])
+      fields = normalize(content_encrypted, timestamp, signature, attachment_checksums)
+      native_digest(fields) || ruby_digest(fields)
+    end
+
+    # True if hash names the post by the canonical hash, or by the legacy
+    # concatenated one that posts stored before it carry.
+    def matches?(hash, **fields)
+      return false if hash.blank?
+
+      hash == digest(**fields) || hash == legacy_digest(**fields)
+    end
+
+    def ruby_digest(fields)
+      Digest::SHA256.hexdigest(canonical_bytes(**fields))
+    end
+
+    def canonical_bytes(content_encrypted:, timestamp:, signature:, attachment_checksums: [])
+      fields = normalize(content_encrypted, timestamp, signature, attachment_checksums)
+      checksums = fields[:attachment_checksums].uniq.sort
+
+      out = DOMAIN.dup
+      out << [ VERSION ].pack("N")
+      out << field(fields[:content_encrypted])
+      out << [ fields[:timestamp] ].pack("q>")
+      out << field(fields[:signature])
+      out << [ checksums.size ].pack("N")
+      checksums.each { |checksum| out << field(checksum) }
+      out
+    end
+
+    def legacy_digest(content_encrypted:, timestamp:, signature:, attachment_checksums: [])
+      Digest::SHA256.hexdigest([
+        content_encrypted,
+        timestamp.nil? ? nil : unix_time(timestamp).to_s,
+        signature,
+        Array(attachment_checksums).compact.map(&:to_s).sort.join
+      ].compact.join)
+    end
+
+    private
+
+    def normalize(content_encrypted, timestamp, signature, attachment_checksums)
+      {
+        content_encrypted: content_encrypted.to_s,
+        timestamp: unix_time(timestamp),
+        signature: signature.to_s,
+        attachment_checksums: Array(attachment_checksums).compact.map(&:to_s)
+      }
+    end
+
+    def unix_time(value)
+      case value
+      when nil then 0
+      when Integer then value
+      when String then value.match?(/\A-?\d+\z/) ? value.to_i : Time.zone.parse(value).to_i
+      else value.to_i
+      end
+    end
+
+    def field(value)
+      bytes = value.b
+      [ bytes.bytesize ].pack("N") + bytes
+    end
+
+    def native_digest(fields)
+      return unless native
+
+      pointer = native[:hash].call(Fiddle::Pointer[JSON.generate(fields) + "\0"])
+      pointer.null? ? nil : pointer.to_s
+    ensure
+      native[:free].call(pointer) if pointer && !pointer.null?
+    end
+
+    def native
+      return @native if defined?(@native)
+
+      @native = load_native
+    end
+
+    def load_native
+      path = ENV["CIPHER_NATIVE_LIB"].presence || built_library
+      return unless path && File.exist?(path)
+
+      library = Fiddle.dlopen(path)
+      {
+        hash: Fiddle::Function.new(library["cipher_content_hash"], [ Fiddle::TYPE_VOIDP ], Fiddle::TYPE_VOIDP),
+        free: Fiddle::Function.new(library["cipher_free_string"], [ Fiddle::TYPE_VOIDP ], Fiddle::TYPE_VOID)
+      }
+    rescue Fiddle::DLError => e
+      Rails.logger.warn("ContentHash: couldn't load #{path}: #{e.message}")
+      nil
+    end
+
+    def built_library
+      %w[release debug].product(LIBRARY_NAMES)
+        .map { |profile, name| Rails.root.join("src-tauri/target", profile, name).to_s }
+        .find { |path| File.exist?(path) }
+    end
+  end
+end
@@ -0,0 +1,38 @@
+# Encrypted database support for packaged applications
+# When the database is encrypted, the Tauri launcher unlocks the key from the
+# keystore and passes it in CIPHER_DATABASE_KEY as 64 hex characters. The key
+# has to be the first thing every new SQLite connection sees.
+#
+# The sqlite3 gem must be built against SQLCipher for this to work:
+#   gem install sqlite3 -- --with-sqlcipher
+
+database_key = ENV.delete("CIPHER_DATABASE_KEY")
+
+if database_key
+  unless database_key.match?(/\A\h{64}\z/)
+    raise ArgumentError, "CIPHER_DATABASE_KEY must be 64 hex characters"
+  end
+
+  module SqlcipherConnection
+    mattr_accessor :database_key
+
+    private
+
+    def configure_connection
+      @raw_connection.execute("PRAGMA key = \"x'#{SqlcipherConnection.database_key}'\"")
+      if @raw_connection.execute("PRAGMA cipher_version").empty?
+        raise ActiveRecord::ConnectionNotEstablished,
+          "The database is encrypted but the sqlite3 gem wasn't built with SQLCipher"
+      end
+      super
+    end
+  end
+
+  SqlcipherConnection.database_key = database_key
+
+  ActiveSupport.on_load(:active_record_sqlite3adapter) do
+    prepend SqlcipherConnection
+  end
+
+  puts "Database encryption enabled (SQLCipher)"
+end
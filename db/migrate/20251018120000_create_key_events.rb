@@ -0,0 +1,11 @@
+class CreateKeyEvents < ActiveRecord::Migration[8.0]
+  def change
+    create_table :key_events do |t|
+      t.references :user, null: false, foreign_key: true
+      t.string :event_type, null: false
+      t.text :payload, null: false
+
+      t.timestamps
+    end
+  end
+end
@@ -0,0 +1,13 @@
+class CreateKeyVerifications < ActiveRecord::Migration[8.0]
+  def change
+    create_table :key_verifications do |t|
+      t.references :user, null: false, foreign_key: true
+      t.references :friend, null: false, foreign_key: { to_table: :users }
+      t.text :public_key, null: false
+
+      t.timestamps
+    end
+
+    add_index :key_verifications, [ :user_id, :friend_id ], unique: true
+  end
+end
@@ -0,0 +1,23 @@
+class CreateCircles < ActiveRecord::Migration[8.0]
+  def change
+    create_table :circles do |t|
+      t.references :user, null: false, foreign_key: true
+      t.string :name, null: false
+      t.string :public_id, null: false
+      t.integer :epoch, null: false, default: 1
+
+      t.timestamps
+    end
+
+    add_index :circles, :public_id, unique: true
+
+    create_table :circle_members do |t|
+      t.references :circle, null: false, foreign_key: true
+      t.references :user, null: false, foreign_key: true
+
+      t.timestamps
+    end
+
+    add_index :circle_members, [ :circle_id, :user_id ], unique: true
+  end
+end
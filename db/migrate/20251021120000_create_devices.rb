@@ -0,0 +1,15 @@
+class CreateDevices < ActiveRecord::Migration[8.0]
+  def change
+    create_table :devices do |t|
+      t.references :user, null: false, foreign_key: true
+      t.string :device_key, null: false
+      t.string :name, null: false
+      t.text :certificate, null: false
+      t.text :revocation
+
+      t.timestamps
+    end
+
+    add_index :devices, :device_key, unique: true
+  end
+end
@@ -10,7 +10,7 @@
 #
 # It's strongly recommended that you check this file into your version control system.
 
-ActiveRecord::Schema[8.0].define(version: 2025_09_22_120000) do
+ActiveRecord::Schema[8.0].define(version: 2025_10_21_120000) do
   create_table "attachment_shares", force: :cascade do |t|
     t.integer "attachment_id", null: false
     t.integer "user_id", null: false
@@ -35,6 +35,27 @@ ActiveRecord::Schema[8.0].define(version: 2025_09_22_120000) do
     t.index ["post_id"], name: "index_attachments_on_post_id"
   end
 
+  create_table "circle_members", force: :cascade do |t|
+    t.integer "circle_id", null: false
+    t.integer "user_id", null: false
+    t.datetime "created_at", null: false
+    t.datetime "updated_at", null: false
+    t.index ["circle_id", "user_id"], name: "index_circle_members_on_circle_id_and_user_id", unique: true
+    t.index ["circle_id"], name: "index_circle_members_on_circle_id"
+    t.index ["user_id"], name: "index_circle_members_on_user_id"
+  end
+
+  create_table "circles", force: :cascade do |t|
+    t.integer "user_id", null: false
+    t.string "name", null: false
+    t.string "public_id", null: false
+    t.integer "epoch", default: 1, null: false
+    t.datetime "created_at", null: false
+    t.datetime "updated_at", null: false
+    t.index ["public_id"], name: "index_circles_on_public_id", unique: true
+    t.index ["user_id"], name: "index_circles_on_user_id"
+  end
+
   create_table "comments", force: :cascade do |t|
     t.integer "user_id", null: false
     t.integer "post_id", null: false
@@ -46,6 +67,18 @@ ActiveRecord::Schema[8.0].define(version: 2025_09_22_120000) do
     t.index ["user_id"], name: "index_comments_on_user_id"
   end
 
+  create_table "devices", force: :cascade do |t|
+    t.integer "user_id", null: false
+    t.string "device_key", null: false
+    t.string "name", null: false
+    t.text "certificate", null: false
+    t.text "revocation"
+    t.datetime "created_at", null: false
+    t.datetime "updated_at", null: false
+    t.index ["device_key"], name: "index_devices_on_device_key", unique: true
+    t.index ["user_id"], name: "index_devices_on_user_id"
+  end
+
   create_table "friendships", force: :cascade do |t|
     t.integer "requester_id", null: false
     t.integer "addressee_id", null: false
@@ -59,6 +92,26 @@ ActiveRecord::Schema[8.0].define(version: 2025_09_22_120000) do
     t.index ["status"], name: "index_friendships_on_status"
   end
 
+  create_table "key_events", force: :cascade do |t|
+    t.integer "user_id", null: false
+    t.string "event_type", null: false
+    t.text "payload", null: false
+    t.datetime "created_at", null: false
+    t.datetime "updated_at", null: false
+    t.index ["user_id"], name: "index_key_events_on_user_id"
+  end
+
+  create_table "key_verifications", force: :cascade do |t|
+    t.integer "user_id", null: false
+    t.integer "friend_id", null: false
+    t.text "public_key", null: false
+    t.datetime "created_at", null: false
+    t.datetime "updated_at", null: false
+    t.index ["friend_id"], name: "index_key_verifications_on_friend_id"
+    t.index ["user_id", "friend_id"], name: "index_key_verifications_on_user_id_and_friend_id", unique: true
+    t.index ["user_id"], name: "index_key_verifications_on_user_id"
+  end
+
   create_table "messages", force: :cascade do |t|
     t.integer "sender_id", null: false
     t.integer "recipient_id", null: false
@@ -150,10 +203,17 @@ ActiveRecord::Schema[8.0].define(version: 2025_09_22_120000) do
   add_foreign_key "attachment_shares", "attachments"
   add_foreign_key "attachment_shares", "users"
   add_foreign_key "attachments", "posts"
+  add_foreign_key "circle_members", "circles"
+  add_foreign_key "circle_members", "users"
+  add_foreign_key "circles", "users"
   add_foreign_key "comments", "posts"
   add_foreign_key "comments", "users"
+  add_foreign_key "devices", "users"
   add_foreign_key "friendships", "users", column: "addressee_id"
   add_foreign_key "friendships", "users", column: "requester_id"
+  add_foreign_key "key_events", "users"
+  add_foreign_key "key_verifications", "users"
+  add_foreign_key "key_verifications", "users", column: "friend_id"
   add_foreign_key "messages", "users", column: "recipient_id"
   add_foreign_key "messages", "users", column: "sender_id"
   add_foreign_key "p2p_connections", "users"
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Error returned to the frontend; `invoke` rejects with
+ * `{ kind, message }`.
+ */
+export type ApiError = { "kind": "invalid", "message": string } | { "kind": "not_found", "message": string } | { "kind": "unavailable", "message": string } | { "kind": "locked", "message": string } | { "kind": "internal", "message": string };
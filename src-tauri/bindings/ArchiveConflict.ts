@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConflictKind } from "./ConflictKind";
+
+export type ArchiveConflict = { kind: ConflictKind, 
+/**
+ * The username, keystore entry name or `settings` concerned.
+ */
+subject: string, message: string, 
+/**
+ * Whether [`ConflictPolicy::UseArchive`] can take the archive's side.
+ */
+resolvable: boolean, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ArchiveSummary = { username: string, public_key: string, platform: string, exported_at: number, contacts: number, posts: number, messages: number, attachments: number, circles: number, };
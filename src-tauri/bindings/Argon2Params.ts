@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Argon2id cost parameters. The defaults follow the OWASP recommendation
+ * for interactive sign-in and take well under a second on phones.
+ */
+export type Argon2Params = { memory_kib: number, iterations: number, parallelism: number, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Attachment = { id: number, post_id: number, filename: string | null, content_type: string | null, file_size: number | null, data_encrypted: string | null, checksum: string | null, created_at: string, updated_at: string, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AttachmentChunk = { offset: number, 
+/**
+ * Base64 plaintext; shorter than requested at the end of the file.
+ */
+data: string, 
+/**
+ * Plaintext size of the whole attachment.
+ */
+size: number, };
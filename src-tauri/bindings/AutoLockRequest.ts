@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AutoLockRequest = { 
+/**
+ * Idle seconds before locking; `0` disables auto-lock and `null`
+ * restores the default.
+ */
+seconds?: number | null, };
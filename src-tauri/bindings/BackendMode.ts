@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Which server the webview talks to on `127.0.0.1:3000`.
+ */
+export type BackendMode = "rails" | "embedded";
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WrappedKey } from "./WrappedKey";
+
+/**
+ * Stored in `attachments.data_encrypted` in place of inline ciphertext:
+ * where the blob is and who can open it.
+ */
+export type BlobEnvelope = { version: number, 
+/**
+ * Hex SHA-256 of the blob, which is also its file name in `blobs/`.
+ */
+blob: string, size: number, chunk_size: number, 
+/**
+ * The blob key sealed to each recipient.
+ */
+recipients: Array<WrappedKey>, };
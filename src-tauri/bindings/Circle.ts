@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A group of the owner's friends that posts can be encrypted to once.
+ */
+export type Circle = { id: number, user_id: number, name: string, 
+/**
+ * Names the circle in keys and envelopes.
+ */
+public_id: string, 
+/**
+ * Bumped on every membership change.
+ */
+epoch: number, created_at: string, updated_at: string, };
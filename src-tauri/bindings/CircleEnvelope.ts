@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A post body encrypted to a circle, stored as JSON in
+ * `posts.content_encrypted` like a [`super::envelope::PostEnvelope`].
+ */
+export type CircleEnvelope = { version: number, cipher: string, circle_id: string, owner_key: string, epoch: number, 
+/**
+ * Base64, 24 bytes.
+ */
+nonce: string, 
+/**
+ * Base64 ciphertext with the Poly1305 tag appended.
+ */
+ciphertext: string, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WrappedKey } from "./WrappedKey";
+
+/**
+ * A circle's epoch key sealed to one member and signed by the owner.
+ */
+export type CircleKey = { version: number, 
+/**
+ * Base64, 16 random bytes chosen by the owner.
+ */
+circle_id: string, owner_key: string, epoch: number, key: WrappedKey, 
+/**
+ * Owner's signature over [`CircleKey::signing_bytes`].
+ */
+signature: string, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Circle } from "./Circle";
+import type { User } from "./User";
+
+export type CircleWithMembers = { circle: Circle, members: Array<User>, };
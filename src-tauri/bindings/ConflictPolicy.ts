@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What an import does when the archive and this device disagree.
+ */
+export type ConflictPolicy = "abort" | "keep_local" | "use_archive";
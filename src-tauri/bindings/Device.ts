@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeviceCertificate } from "./DeviceCertificate";
+import type { DeviceRevocation } from "./DeviceRevocation";
+
+/**
+ * One of a user's devices, with the certificate that vouches for its key.
+ */
+export type Device = { id: number, user_id: number, device_key: string, name: string, certificate: DeviceCertificate, revocation: DeviceRevocation | null, created_at: string, updated_at: string, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What a device key may sign for the account.
+ */
+export type DeviceCapability = "post" | "revoke";
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeviceCapability } from "./DeviceCapability";
+
+/**
+ * A statement by `root_key` that `device_key` is one of its devices.
+ */
+export type DeviceCertificate = { version: number, root_key: string, device_key: string, 
+/**
+ * Shown in device lists, e.g. `Pixel 8`.
+ */
+device_name: string, 
+/**
+ * Sorted, without duplicates.
+ */
+capabilities: Array<DeviceCapability>, 
+/**
+ * Unix seconds. The device key signs nothing valid from before this.
+ */
+created_at: number, 
+/**
+ * Root key's signature over [`DeviceCertificate::signing_bytes`].
+ */
+signature: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeviceCertificate } from "./DeviceCertificate";
+import type { DeviceRevocation } from "./DeviceRevocation";
+
+/**
+ * A change to an account's devices, as sent to peers over sync.
+ */
+export type DeviceEvent = { "type": "certificate" } & DeviceCertificate | { "type": "revocation" } & DeviceRevocation;
@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackendMode } from "./BackendMode";
+import type { Features } from "./Features";
+
+export type DeviceInfo = { 
+/**
+ * `desktop`, `android` or `ios` (see [`get_platform`]).
+ */
+platform: string, 
+/**
+ * `std::env::consts::OS`, e.g. `macos`, `windows`, `linux`, `android`.
+ */
+os: string, arch: string, app_version: string, 
+/**
+ * Newest command API version, e.g. `v1`.
+ */
+api_version: string, backend: BackendMode, backend_port: number, data_dir: string | null, features: Features, };
@@ -0,0 +1,25 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Withdraws a device key from `revoked_at` on. Whether `signer_key` may
+ * revoke it depends on the account's certificates, so only the signature
+ * is checked here.
+ */
+export type DeviceRevocation = { version: number, root_key: string, device_key: string, 
+/**
+ * Unix seconds from which the device key is untrusted. Usually before
+ * `created_at`, to cover the time since the device was lost.
+ */
+revoked_at: number, 
+/**
+ * Unix seconds.
+ */
+created_at: number, 
+/**
+ * The root key, or another of the account's device keys.
+ */
+signer_key: string, 
+/**
+ * Signer's signature over [`DeviceRevocation::signing_bytes`].
+ */
+signature: string, };
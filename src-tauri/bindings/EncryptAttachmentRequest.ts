@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Encrypts a file on disk into an attachment blob.
+ */
+export type EncryptAttachmentRequest = { post_id: number, 
+/**
+ * Absolute path of the file to encrypt, e.g. from a file dialog.
+ */
+path: string, 
+/**
+ * Defaults to the file name of `path`.
+ */
+filename?: string, content_type: string, 
+/**
+ * Base64 public keys to encrypt for. Defaults to the signed-in user and
+ * their accepted friends.
+ */
+recipients?: Array<string>, };
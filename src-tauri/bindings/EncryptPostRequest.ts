@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type EncryptPostRequest = { content: string, 
+/**
+ * Base64 public keys to encrypt for. Defaults to the signed-in user and
+ * their accepted friends.
+ */
+recipients?: Array<string>, };
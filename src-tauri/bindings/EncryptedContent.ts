@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type EncryptedContent = { 
+/**
+ * Envelope JSON for `NewPost.content_encrypted`.
+ */
+content_encrypted: string, 
+/**
+ * Public keys that can open it.
+ */
+recipients: Array<string>, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Not `Debug` or `Serialize`, so the passphrase can't end up in a log.
+ */
+export type ExportArchiveRequest = { 
+/**
+ * Where to write the archive, e.g. from a save dialog.
+ */
+path: string, 
+/**
+ * Encrypts the archive. Needed again to import it.
+ */
+passphrase: string, };
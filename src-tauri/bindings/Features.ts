@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Capabilities the frontend can branch on instead of sniffing user agents.
+ */
+export type Features = { 
+/**
+ * A Ruby runtime can be spawned on this platform.
+ */
+rails_backend: boolean, embedded_backend: boolean, 
+/**
+ * The embedded server has a database and serves `/api/v1`.
+ */
+embedded_api: boolean, 
+/**
+ * The native `v1_*` commands have a database to work with.
+ */
+native_api: boolean, 
+/**
+ * The database is encrypted at rest and opens when the keystore is
+ * unlocked.
+ */
+encrypted_database: boolean, devtools: boolean, mobile: boolean, };
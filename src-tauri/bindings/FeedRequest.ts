@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type FeedRequest = { user_id: number, 
+/**
+ * Defaults to, and is capped at, [`MAX_FEED_LIMIT`].
+ */
+limit?: number, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Looks a user up by exactly one of `id`, `public_key` or `username`.
+ */
+export type FindIdentityRequest = { id?: number, public_key?: string, username?: string, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { VerificationStatus } from "./VerificationStatus";
+
+/**
+ * Where one user stands on a friend's key.
+ */
+export type FriendVerification = { friend_id: number, status: VerificationStatus, public_key: string | null, 
+/**
+ * The key that was verified, if it differs from `public_key`.
+ */
+verified_key: string | null, verified_at: string | null, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Friendship = { id: number, requester_id: number, addressee_id: number, status: string, created_at: string, updated_at: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShareGrant } from "./ShareGrant";
+
+/**
+ * A share held for a friend. `id` is the sync message it arrived in.
+ */
+export type HeldShare = { id: number, grant: ShareGrant, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConflictPolicy } from "./ConflictPolicy";
+
+export type ImportArchiveRequest = { path: string, passphrase: string, 
+/**
+ * Defaults to `abort`.
+ */
+on_conflict?: ConflictPolicy, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveConflict } from "./ArchiveConflict";
+import type { ArchiveSummary } from "./ArchiveSummary";
+
+export type ImportReport = { archive: ArchiveSummary, conflicts: Array<ArchiveConflict>, 
+/**
+ * `false` for an inspection, or when conflicts stopped the import.
+ */
+imported: boolean, added_contacts: number, added_posts: number, added_messages: number, 
+/**
+ * Unsigned posts, or posts whose signature doesn't verify. They are
+ * never imported.
+ */
+skipped_posts: number, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KeyEvent } from "./KeyEvent";
+
+export type KeyChangeResponse = { public_key: string, 
+/**
+ * Already recorded and queued to peers.
+ */
+event: KeyEvent, };
@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KeyRevocation } from "./KeyRevocation";
+import type { KeyRotation } from "./KeyRotation";
+
+/**
+ * One link in an account's key chain, as stored in `key_events` and sent
+ * to peers over sync.
+ */
+export type KeyEvent = { "type": "rotation" } & KeyRotation | { "type": "revocation" } & KeyRevocation;
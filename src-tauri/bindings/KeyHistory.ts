@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KeyPeriod } from "./KeyPeriod";
+
+/**
+ * An account's keys in order, built by following its verified events from
+ * the first key.
+ */
+export type KeyHistory = { periods: Array<KeyPeriod>, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * When one key was the account's. Bounds are Unix seconds; `valid_until`
+ * is exclusive and `None` means still valid.
+ */
+export type KeyPeriod = { public_key: string, valid_from: number | null, valid_until: number | null, };
@@ -0,0 +1,29 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Retires a key that may have been copied, such as one on a lost device,
+ * and hands the account to `new_public_key`. Signed by the revoked key
+ * (recovered from the phrase or friends' shares) and by the new one. Only
+ * the account's current key can be revoked: one that already rotated away
+ * keeps its rotation, see [`KeyHistory::build`].
+ */
+export type KeyRevocation = { version: number, revoked_public_key: string, new_public_key: string, 
+/**
+ * Unix seconds from which the revoked key is untrusted. Usually before
+ * `created_at`, to cover the time since the key was lost; posts it
+ * signed from then on are rejected. Never before the revoked key
+ * became the account's.
+ */
+revoked_at: number, 
+/**
+ * Unix seconds.
+ */
+created_at: number, reason: string, 
+/**
+ * Revoked key's signature over [`KeyRevocation::signing_bytes`].
+ */
+signature: string, 
+/**
+ * New key's signature over the same bytes.
+ */
+new_key_signature: string, };
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A statement that `new_public_key` replaces `old_public_key`, signed by
+ * both keys so friends can follow the change without trusting the server.
+ */
+export type KeyRotation = { version: number, old_public_key: string, new_public_key: string, 
+/**
+ * Unix seconds.
+ */
+created_at: number, 
+/**
+ * Short machine-readable cause, e.g. `kdf_upgrade`.
+ */
+reason: string, 
+/**
+ * Old key's signature over [`KeyRotation::signing_bytes`].
+ */
+signature: string, 
+/**
+ * New key's signature over the same bytes, proving possession.
+ */
+new_key_signature: string, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Not `Debug` or `Serialize`, so the passphrase can't end up in a log.
+ */
+export type KeystorePassphraseRequest = { passphrase: string, };
@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What the webview may know about the keystore. Never includes key bytes.
+ */
+export type KeystoreStatus = { exists: boolean, unlocked: boolean, 
+/**
+ * Public key of the signed-in identity, if any.
+ */
+public_key?: string | null, 
+/**
+ * Usernames with a key in the keystore; empty while locked.
+ */
+usernames: Array<string>, 
+/**
+ * `null` when auto-lock is off.
+ */
+auto_lock_seconds?: bigint | null, };
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConflictPolicy } from "./ConflictPolicy";
+
+export type LinkJoinRequest = { 
+/**
+ * The scanned or pasted pairing code.
+ */
+payload: string, 
+/**
+ * How this device appears to the account's other devices.
+ */
+device_name: string, 
+/**
+ * Defaults to `abort`, as for archive imports.
+ */
+on_conflict?: ConflictPolicy, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A pairing code to show. Not `Debug`: `payload` holds the one-time secret.
+ */
+export type LinkOffer = { 
+/**
+ * What the QR code encodes, for pasting instead of scanning.
+ */
+payload: string, qr_svg: string, address: string, port: number, 
+/**
+ * Unix seconds.
+ */
+expires_at: number, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeviceCapability } from "./DeviceCapability";
+
+export type LinkOfferRequest = { 
+/**
+ * Address the new device should connect to. Defaults to this device's
+ * address on the local network.
+ */
+address?: string, 
+/**
+ * What the new device's key may sign. Defaults to everything.
+ */
+capabilities?: Array<DeviceCapability>, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeviceCertificate } from "./DeviceCertificate";
+import type { ImportReport } from "./ImportReport";
+
+export type LinkedDevice = { certificate: DeviceCertificate, report: ImportReport, };
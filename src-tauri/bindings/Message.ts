@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Message = { id: number, sender_id: number, recipient_id: number, content: string | null, encrypted_content: string | null, read_at: string | null, created_at: string, updated_at: string, };
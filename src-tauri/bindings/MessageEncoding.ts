@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How a `message` string is turned into the bytes that are signed.
+ */
+export type MessageEncoding = "utf8" | "base64";
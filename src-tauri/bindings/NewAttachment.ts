@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type NewAttachment = { 
+/**
+ * Taken from the route, or the post being synced, when omitted.
+ */
+post_id: number, filename: string, content_type: string, file_size: number, data_encrypted: string, checksum: string, };
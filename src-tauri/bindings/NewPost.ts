@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type NewPost = { user_id: number, content_encrypted: string | null, signature: string | null, 
+/**
+ * Defaults to the current time when omitted.
+ */
+timestamp?: string, content_hash: string | null, encryption_key?: string, is_synced: boolean, original_user_id?: number, synced_from_user_id?: number, 
+/**
+ * Checksums of the attachments the signature covers. Not stored; the
+ * attachment rows carry them.
+ */
+attachment_checksums?: Array<string>, };
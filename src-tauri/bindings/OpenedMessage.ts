@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Message } from "./Message";
+
+/**
+ * A forward-secret message with its text. Not `Debug`, for the same reason.
+ */
+export type OpenedMessage = { message: Message, content: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SealedEnvelope } from "./SealedEnvelope";
+
+/**
+ * A pending outbound sync message, sealed for its peer. Only `address`
+ * and `port` are visible to whoever carries it.
+ */
+export type OutboundEnvelope = { sync_message_id: number, peer_id: number, address: string | null, port: number | null, envelope: SealedEnvelope, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Post = { id: number, user_id: number, content_encrypted: string | null, signature: string | null, timestamp: string | null, content_hash: string | null, encryption_key: string | null, is_synced: boolean, original_user_id: number | null, synced_from_user_id: number | null, synced_at: string | null, created_at: string, updated_at: string, };
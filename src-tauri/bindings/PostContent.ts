@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The parts of a post its hash covers.
+ */
+export type PostContent = { 
+/**
+ * `posts.content_encrypted` exactly as stored.
+ */
+content_encrypted: string, 
+/**
+ * Unix seconds, UTC.
+ */
+timestamp: number, signature: string, 
+/**
+ * Lowercase hex SHA-256 checksums of the attachments, in any order.
+ */
+attachment_checksums: Array<string>, };
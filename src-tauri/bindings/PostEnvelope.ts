@@ -0,0 +1,20 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WrappedKey } from "./WrappedKey";
+
+/**
+ * Encrypted post content, stored as JSON in `posts.content_encrypted`.
+ *
+ * The body is encrypted once under a fresh random key, and that key is
+ * sealed (`crypto_box_seal`) to the X25519 form of each recipient's Ed25519
+ * public key. Nothing stored alongside it can open the content; only a
+ * recipient's secret key can.
+ */
+export type PostEnvelope = { version: number, cipher: string, 
+/**
+ * Base64, 24 bytes.
+ */
+nonce: string, 
+/**
+ * Base64 ciphertext with the Poly1305 tag appended.
+ */
+ciphertext: string, recipients: Array<WrappedKey>, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Attachment } from "./Attachment";
+import type { Post } from "./Post";
+
+export type PostWithAttachments = { post: Post, attachments: Array<Attachment>, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A signed prekey and, optionally, one one-time prekey, published so a
+ * friend can start a session while the owner is offline.
+ */
+export type PrekeyBundle = { version: number, 
+/**
+ * The owner's Ed25519 account key.
+ */
+identity_key: string, signed_prekey_id: number, 
+/**
+ * X25519, Base64.
+ */
+signed_prekey: string, 
+/**
+ * Identity key's signature over [`PrekeyBundle::signing_bytes`].
+ */
+signature: string, 
+/**
+ * Used once, then deleted by the owner. Not signed, so a bundle can be
+ * reused without it once it's spent.
+ */
+one_time_prekey_id: number | null, one_time_prekey: string | null, created_at: number, };
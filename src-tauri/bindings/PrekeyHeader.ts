@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent with the first messages of a session, until the recipient replies,
+ * so they can run their side of X3DH.
+ */
+export type PrekeyHeader = { identity_key: string, ephemeral_key: string, signed_prekey_id: number, one_time_prekey_id: number | null, };
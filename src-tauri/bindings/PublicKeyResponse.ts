@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PublicKeyResponse = { 
+/**
+ * Base64 Ed25519 public key, as stored in `users.public_key`.
+ */
+public_key: string, };
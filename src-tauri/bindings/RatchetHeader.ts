@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RatchetHeader = { 
+/**
+ * The sender's current ratchet key, X25519 Base64.
+ */
+dh: string, 
+/**
+ * Messages sent in the sender's previous chain.
+ */
+previous_count: number, number: number, };
@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PrekeyHeader } from "./PrekeyHeader";
+import type { RatchetHeader } from "./RatchetHeader";
+
+/**
+ * One encrypted direct message, stored as JSON in
+ * `messages.encrypted_content`.
+ */
+export type RatchetMessage = { version: number, algorithm: string, header: RatchetHeader, prekey: PrekeyHeader | null, ciphertext: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ReadAttachmentRequest = { id: number, offset?: number, 
+/**
+ * Defaults to, and is capped at, [`MAX_READ_BYTES`].
+ */
+length?: number, };
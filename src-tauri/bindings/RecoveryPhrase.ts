@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The phrase to write down and the words the user will be asked for. Not
+ * `Debug`, so the phrase can't end up in a log.
+ */
+export type RecoveryPhrase = { words: Array<string>, 
+/**
+ * Zero-based positions to ask for in [`v1_recovery_confirm`].
+ */
+positions: Array<number>, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Asks holders to return their shares to a new device. Only the device
+ * that made it has the secret half of `request_public_key`.
+ */
+export type RecoveryRequest = { version: number, owner_username: string, request_public_key: string, 
+/**
+ * Unix seconds.
+ */
+created_at: number, };
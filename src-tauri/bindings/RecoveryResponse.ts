@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KeyRotation } from "./KeyRotation";
+
+export type RecoveryResponse = { public_key: string, 
+/**
+ * Set when the account moved to a new key; friends need it to trust it.
+ */
+rotation?: KeyRotation | null, };
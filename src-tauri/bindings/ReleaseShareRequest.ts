@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RecoveryRequest } from "./RecoveryRequest";
+
+export type ReleaseShareRequest = { 
+/**
+ * A [`HeldShare`] id.
+ */
+id: number, request: RecoveryRequest, 
+/**
+ * The code the owner read out; must match the request.
+ */
+code: string, };
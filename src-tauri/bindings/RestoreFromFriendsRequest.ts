@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ReturnedShare } from "./ReturnedShare";
+
+/**
+ * Not `Debug` or `Serialize`, so the password can't end up in a log.
+ */
+export type RestoreFromFriendsRequest = { username: string, 
+/**
+ * Replaces the forgotten password on this device.
+ */
+password: string, shares: Array<ReturnedShare>, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShareGrant } from "./ShareGrant";
+
+/**
+ * A share resealed by its holder to a [`RecoveryRequest`].
+ */
+export type ReturnedShare = { grant: ShareGrant, request_public_key: string, 
+/**
+ * Base64 sealed box of the share, for the request key.
+ */
+sealed_share: string, 
+/**
+ * Holder's signature over [`ReturnedShare::signing_bytes`].
+ */
+signature: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RevokeDeviceRequest = { user_id: number, device_key: string, 
+/**
+ * Unix seconds from which the device's signatures are rejected, e.g.
+ * when it was lost. Defaults to now.
+ */
+revoked_at?: number, };
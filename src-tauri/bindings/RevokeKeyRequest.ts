@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RevokeKeyRequest = { username: string, password: string, 
+/**
+ * Unix seconds from which the current key is untrusted, e.g. when the
+ * device holding it was lost. Defaults to now.
+ */
+revoked_at?: number, reason?: string, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FriendVerification } from "./FriendVerification";
+
+export type SafetyNumberResponse = { 
+/**
+ * Sixty digits in groups of five, the same on both devices.
+ */
+number: string, qr_payload: string, 
+/**
+ * The payload as an SVG QR code for the other device to scan.
+ */
+qr_svg: string, verification: FriendVerification, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What travels between peers. Only the recipient can open it.
+ */
+export type SealedEnvelope = { version: number, 
+/**
+ * Base64 sealed box, always one of the [`BUCKETS`] sizes (or a multiple
+ * of the largest).
+ */
+ciphertext: string, };
@@ -0,0 +1,33 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Argon2Params } from "./Argon2Params";
+import type { BackendMode } from "./BackendMode";
+
+/**
+ * User-editable launcher settings stored as `settings.json` in the app data
+ * directory. Unknown or missing fields fall back to their defaults.
+ */
+export type Settings = { 
+/**
+ * Backend to start when neither `--backend` nor `CIPHER_BACKEND` is given.
+ */
+backend?: BackendMode | null, 
+/**
+ * Argon2id cost for new and migrated accounts; defaults when unset.
+ */
+kdf?: Argon2Params | null, 
+/**
+ * Give PBKDF2 accounts a new key on migration instead of keeping the
+ * old one under Argon2id.
+ */
+rotate_legacy_keys: boolean, 
+/**
+ * Seconds without key use before the keystore locks; `0` disables
+ * auto-lock. Defaults to [`DEFAULT_AUTO_LOCK_SECONDS`] when unset.
+ */
+auto_lock_seconds?: number | null, 
+/**
+ * Keep the database encrypted at rest with SQLCipher, under a key kept
+ * in the keystore. The database opens when the keystore is unlocked.
+ * Needs a build with the `sqlcipher` feature; applies from next launch.
+ */
+encrypt_database: boolean, };
@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * One friend's share of an account's seed, as delivered over sync.
+ */
+export type ShareGrant = { version: number, 
+/**
+ * Base64, 16 random bytes common to every grant from one split.
+ */
+set_id: string, owner_username: string, owner_public_key: string, holder_public_key: string, threshold: number, total: number, 
+/**
+ * Unix seconds.
+ */
+created_at: number, 
+/**
+ * Base64 sealed box of the share, for the holder's key.
+ */
+sealed_share: string, 
+/**
+ * Owner's signature over [`ShareGrant::signing_bytes`].
+ */
+signature: string, };
@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KeyRotation } from "./KeyRotation";
+
+export type SignInResponse = { public_key: string, 
+/**
+ * KDF the account uses from now on, e.g. `argon2id_v1`.
+ */
+kdf: string, 
+/**
+ * The account was moved off the Rails PBKDF2 derivation by this sign-in.
+ */
+migrated: boolean, 
+/**
+ * Set when migration gave the account a new key; friends need it to
+ * trust the new key.
+ */
+rotation?: KeyRotation | null, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Everything a post signature covers. Replaces the Rails scheme of signing
+ * `content_encrypted` and checksums concatenated, which falls back to an
+ * unkeyed SHA-256 when no key is at hand.
+ */
+export type SignedPost = { version: number, 
+/**
+ * Base64 Ed25519 public key of the author.
+ */
+author: string, 
+/**
+ * `posts.content_encrypted` exactly as stored, e.g. a post envelope.
+ */
+content_encrypted: string, 
+/**
+ * Lowercase hex SHA-256 checksums of the attachments, sorted.
+ */
+attachment_checksums: Array<string>, 
+/**
+ * Unix seconds, UTC.
+ */
+timestamp: number, };
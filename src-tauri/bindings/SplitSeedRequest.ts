@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SplitSeedRequest = { user_id: number, 
+/**
+ * Accepted friends to hold one share each.
+ */
+friend_ids: Array<number>, 
+/**
+ * How many of them it takes to recover.
+ */
+threshold: number, };
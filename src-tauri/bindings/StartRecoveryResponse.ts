@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RecoveryRequest } from "./RecoveryRequest";
+
+export type StartRecoveryResponse = { 
+/**
+ * Give this to each friend holding a share.
+ */
+request: RecoveryRequest, 
+/**
+ * Read this to them over a channel you trust.
+ */
+code: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type UnlockKeystoreRequest = { passphrase: string, 
+/**
+ * Signs in as this user with the key stored for them.
+ */
+username?: string, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type User = { id: number, public_key: string | null, username: string | null, display_name: string | null, email: string | null, created_at: string, updated_at: string, };
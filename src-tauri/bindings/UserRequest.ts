@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Request scoped to one user, e.g. their feed or friend list.
+ */
+export type UserRequest = { user_id: number, };
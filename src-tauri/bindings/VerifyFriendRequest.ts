@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type VerifyFriendRequest = { user_id: number, friend_id: number, 
+/**
+ * The scanned QR payload, or the digits read from the friend's screen.
+ */
+scanned: string, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The content key sealed to one recipient.
+ */
+export type WrappedKey = { 
+/**
+ * Base64 Ed25519 public key, as stored in `users.public_key`.
+ */
+public_key: string, 
+/**
+ * Base64 sealed box of the 32-byte content key.
+ */
+wrapped_key: string, };
@@ -0,0 +1,370 @@
+// Generated from src-tauri/src/api by `cargo test`. Do not edit by hand.
+
+import { invoke } from "@tauri-apps/api/core";
+import type { ApplyKeyEventRequest } from "./ApplyKeyEventRequest";
+import type { ArchiveSummary } from "./ArchiveSummary";
+import type { Attachment } from "./Attachment";
+import type { AttachmentChunk } from "./AttachmentChunk";
+import type { AutoLockRequest } from "./AutoLockRequest";
+import type { Circle } from "./Circle";
+import type { CircleMemberRequest } from "./CircleMemberRequest";
+import type { CircleWithMembers } from "./CircleWithMembers";
+import type { ConfirmRecoveryRequest } from "./ConfirmRecoveryRequest";
+import type { ContentHashRequest } from "./ContentHashRequest";
+import type { ConversationRequest } from "./ConversationRequest";
+import type { DecryptPostRequest } from "./DecryptPostRequest";
+import type { Device } from "./Device";
+import type { DeviceInfo } from "./DeviceInfo";
+import type { EncryptAttachmentRequest } from "./EncryptAttachmentRequest";
+import type { EncryptCircleRequest } from "./EncryptCircleRequest";
+import type { EncryptPostRequest } from "./EncryptPostRequest";
+import type { EncryptedContent } from "./EncryptedContent";
+import type { ExportArchiveRequest } from "./ExportArchiveRequest";
+import type { FeedRequest } from "./FeedRequest";
+import type { FindIdentityRequest } from "./FindIdentityRequest";
+import type { FriendKeyRequest } from "./FriendKeyRequest";
+import type { FriendVerification } from "./FriendVerification";
+import type { Friendship } from "./Friendship";
+import type { HeldShare } from "./HeldShare";
+import type { IdRequest } from "./IdRequest";
+import type { ImportArchiveRequest } from "./ImportArchiveRequest";
+import type { ImportReport } from "./ImportReport";
+import type { KeyChangeResponse } from "./KeyChangeResponse";
+import type { KeyHistory } from "./KeyHistory";
+import type { KeystorePassphraseRequest } from "./KeystorePassphraseRequest";
+import type { KeystoreStatus } from "./KeystoreStatus";
+import type { LinkJoinRequest } from "./LinkJoinRequest";
+import type { LinkOffer } from "./LinkOffer";
+import type { LinkOfferRequest } from "./LinkOfferRequest";
+import type { LinkedDevice } from "./LinkedDevice";
+import type { Message } from "./Message";
+import type { NewAttachment } from "./NewAttachment";
+import type { NewCircle } from "./NewCircle";
+import type { NewMessage } from "./NewMessage";
+import type { NewPost } from "./NewPost";
+import type { NewUser } from "./NewUser";
+import type { OpenedMessage } from "./OpenedMessage";
+import type { OpenedSync } from "./OpenedSync";
+import type { OutboundEnvelope } from "./OutboundEnvelope";
+import type { Post } from "./Post";
+import type { PostAttachmentsRequest } from "./PostAttachmentsRequest";
+import type { PostWithAttachments } from "./PostWithAttachments";
+import type { PublicKeyResponse } from "./PublicKeyResponse";
+import type { ReadAttachmentRequest } from "./ReadAttachmentRequest";
+import type { RecoveryPhrase } from "./RecoveryPhrase";
+import type { RecoveryResponse } from "./RecoveryResponse";
+import type { ReleaseShareRequest } from "./ReleaseShareRequest";
+import type { RespondToFriendRequest } from "./RespondToFriendRequest";
+import type { RestoreFromFriendsRequest } from "./RestoreFromFriendsRequest";
+import type { RestoreRecoveryRequest } from "./RestoreRecoveryRequest";
+import type { ReturnedShare } from "./ReturnedShare";
+import type { RevokeDeviceRequest } from "./RevokeDeviceRequest";
+import type { RevokeKeyRequest } from "./RevokeKeyRequest";
+import type { RotateKeyRequest } from "./RotateKeyRequest";
+import type { SafetyNumberResponse } from "./SafetyNumberResponse";
+import type { SecureMessageRequest } from "./SecureMessageRequest";
+import type { SendFriendRequest } from "./SendFriendRequest";
+import type { Settings } from "./Settings";
+import type { ShareGrant } from "./ShareGrant";
+import type { SignInRequest } from "./SignInRequest";
+import type { SignInResponse } from "./SignInResponse";
+import type { SignRequest } from "./SignRequest";
+import type { SignResponse } from "./SignResponse";
+import type { SplitSeedRequest } from "./SplitSeedRequest";
+import type { StartRecoveryRequest } from "./StartRecoveryRequest";
+import type { StartRecoveryResponse } from "./StartRecoveryResponse";
+import type { UnlockKeystoreRequest } from "./UnlockKeystoreRequest";
+import type { User } from "./User";
+import type { UserRequest } from "./UserRequest";
+import type { VerifyFriendRequest } from "./VerifyFriendRequest";
+import type { VerifyRequest } from "./VerifyRequest";
+
+export const API_VERSION = "v1";
+
+export function getPlatform(): Promise<string> {
+  return invoke("get_platform");
+}
+
+export function getDeviceInfo(): Promise<DeviceInfo> {
+  return invoke("get_device_info");
+}
+
+export function v1IdentityList(): Promise<Array<User>> {
+  return invoke("v1_identity_list");
+}
+
+export function v1IdentityRegister(request: NewUser): Promise<User> {
+  return invoke("v1_identity_register", { request });
+}
+
+export function v1IdentityFind(request: FindIdentityRequest): Promise<User | null> {
+  return invoke("v1_identity_find", { request });
+}
+
+export function v1IdentitySignIn(request: SignInRequest): Promise<SignInResponse> {
+  return invoke("v1_identity_sign_in", { request });
+}
+
+export function v1IdentitySignOut(): Promise<null> {
+  return invoke("v1_identity_sign_out");
+}
+
+export function v1IdentityPublicKey(): Promise<PublicKeyResponse> {
+  return invoke("v1_identity_public_key");
+}
+
+export function v1IdentitySign(request: SignRequest): Promise<SignResponse> {
+  return invoke("v1_identity_sign", { request });
+}
+
+export function v1IdentityVerify(request: VerifyRequest): Promise<boolean> {
+  return invoke("v1_identity_verify", { request });
+}
+
+export function v1KeystoreStatus(): Promise<KeystoreStatus> {
+  return invoke("v1_keystore_status");
+}
+
+export function v1KeystoreCreate(request: KeystorePassphraseRequest): Promise<KeystoreStatus> {
+  return invoke("v1_keystore_create", { request });
+}
+
+export function v1KeystoreUnlock(request: UnlockKeystoreRequest): Promise<KeystoreStatus> {
+  return invoke("v1_keystore_unlock", { request });
+}
+
+export function v1KeystoreLock(): Promise<KeystoreStatus> {
+  return invoke("v1_keystore_lock");
+}
+
+export function v1KeystoreSetAutoLock(request: AutoLockRequest): Promise<KeystoreStatus> {
+  return invoke("v1_keystore_set_auto_lock", { request });
+}
+
+export function v1KeysRotate(request: RotateKeyRequest): Promise<KeyChangeResponse> {
+  return invoke("v1_keys_rotate", { request });
+}
+
+export function v1KeysRevoke(request: RevokeKeyRequest): Promise<KeyChangeResponse> {
+  return invoke("v1_keys_revoke", { request });
+}
+
+export function v1KeysHistory(request: UserRequest): Promise<KeyHistory> {
+  return invoke("v1_keys_history", { request });
+}
+
+export function v1KeysApply(request: ApplyKeyEventRequest): Promise<User> {
+  return invoke("v1_keys_apply", { request });
+}
+
+export function v1RecoveryGenerate(): Promise<RecoveryPhrase> {
+  return invoke("v1_recovery_generate");
+}
+
+export function v1RecoveryConfirm(request: ConfirmRecoveryRequest): Promise<RecoveryResponse> {
+  return invoke("v1_recovery_confirm", { request });
+}
+
+export function v1RecoveryRestore(request: RestoreRecoveryRequest): Promise<RecoveryResponse> {
+  return invoke("v1_recovery_restore", { request });
+}
+
+export function v1SocialRecoverySplit(request: SplitSeedRequest): Promise<Array<ShareGrant>> {
+  return invoke("v1_social_recovery_split", { request });
+}
+
+export function v1SocialRecoveryHeld(request: UserRequest): Promise<Array<HeldShare>> {
+  return invoke("v1_social_recovery_held", { request });
+}
+
+export function v1SocialRecoveryRequest(request: StartRecoveryRequest): Promise<StartRecoveryResponse> {
+  return invoke("v1_social_recovery_request", { request });
+}
+
+export function v1SocialRecoveryRelease(request: ReleaseShareRequest): Promise<ReturnedShare> {
+  return invoke("v1_social_recovery_release", { request });
+}
+
+export function v1SocialRecoveryRestore(request: RestoreFromFriendsRequest): Promise<RecoveryResponse> {
+  return invoke("v1_social_recovery_restore", { request });
+}
+
+export function v1PostsFeed(request: FeedRequest): Promise<Array<Post>> {
+  return invoke("v1_posts_feed", { request });
+}
+
+export function v1PostsByUser(request: UserRequest): Promise<Array<Post>> {
+  return invoke("v1_posts_by_user", { request });
+}
+
+export function v1PostsCreate(request: NewPost): Promise<Post> {
+  return invoke("v1_posts_create", { request });
+}
+
+export function v1PostsSign(request: NewPost): Promise<NewPost> {
+  return invoke("v1_posts_sign", { request });
+}
+
+export function v1PostsVerify(request: IdRequest): Promise<boolean> {
+  return invoke("v1_posts_verify", { request });
+}
+
+export function v1PostsGet(request: IdRequest): Promise<PostWithAttachments> {
+  return invoke("v1_posts_get", { request });
+}
+
+export function v1PostsExists(request: ContentHashRequest): Promise<boolean> {
+  return invoke("v1_posts_exists", { request });
+}
+
+export function v1PostsEncrypt(request: EncryptPostRequest): Promise<EncryptedContent> {
+  return invoke("v1_posts_encrypt", { request });
+}
+
+export function v1PostsDecrypt(request: DecryptPostRequest): Promise<string> {
+  return invoke("v1_posts_decrypt", { request });
+}
+
+export function v1CirclesCreate(request: NewCircle): Promise<Circle> {
+  return invoke("v1_circles_create", { request });
+}
+
+export function v1CirclesList(request: UserRequest): Promise<Array<CircleWithMembers>> {
+  return invoke("v1_circles_list", { request });
+}
+
+export function v1CirclesAddMember(request: CircleMemberRequest): Promise<Circle> {
+  return invoke("v1_circles_add_member", { request });
+}
+
+export function v1CirclesRemoveMember(request: CircleMemberRequest): Promise<Circle> {
+  return invoke("v1_circles_remove_member", { request });
+}
+
+export function v1CirclesEncrypt(request: EncryptCircleRequest): Promise<EncryptedContent> {
+  return invoke("v1_circles_encrypt", { request });
+}
+
+export function v1CirclesDecrypt(request: DecryptPostRequest): Promise<string> {
+  return invoke("v1_circles_decrypt", { request });
+}
+
+export function v1FriendsList(request: UserRequest): Promise<Array<User>> {
+  return invoke("v1_friends_list", { request });
+}
+
+export function v1FriendsRequests(request: UserRequest): Promise<Array<Friendship>> {
+  return invoke("v1_friends_requests", { request });
+}
+
+export function v1FriendsSend(request: SendFriendRequest): Promise<Friendship> {
+  return invoke("v1_friends_send", { request });
+}
+
+export function v1FriendsRespond(request: RespondToFriendRequest): Promise<Friendship> {
+  return invoke("v1_friends_respond", { request });
+}
+
+export function v1FriendsSafetyNumber(request: FriendKeyRequest): Promise<SafetyNumberResponse> {
+  return invoke("v1_friends_safety_number", { request });
+}
+
+export function v1FriendsVerify(request: VerifyFriendRequest): Promise<FriendVerification> {
+  return invoke("v1_friends_verify", { request });
+}
+
+export function v1FriendsKeyWarnings(request: UserRequest): Promise<Array<FriendVerification>> {
+  return invoke("v1_friends_key_warnings", { request });
+}
+
+export function v1MessagesConversation(request: ConversationRequest): Promise<Array<Message>> {
+  return invoke("v1_messages_conversation", { request });
+}
+
+export function v1MessagesSend(request: NewMessage): Promise<Message> {
+  return invoke("v1_messages_send", { request });
+}
+
+export function v1MessagesMarkRead(request: IdRequest): Promise<null> {
+  return invoke("v1_messages_mark_read", { request });
+}
+
+export function v1MessagesPublishPrekeys(request: UserRequest): Promise<number> {
+  return invoke("v1_messages_publish_prekeys", { request });
+}
+
+export function v1MessagesSendSecure(request: SecureMessageRequest): Promise<Message> {
+  return invoke("v1_messages_send_secure", { request });
+}
+
+export function v1MessagesOpen(request: IdRequest): Promise<OpenedMessage> {
+  return invoke("v1_messages_open", { request });
+}
+
+export function v1SyncOutbox(request: UserRequest): Promise<Array<OutboundEnvelope>> {
+  return invoke("v1_sync_outbox", { request });
+}
+
+export function v1SyncMarkSent(request: IdRequest): Promise<null> {
+  return invoke("v1_sync_mark_sent", { request });
+}
+
+export function v1SyncOpenSealed(request: UserRequest): Promise<OpenedSync> {
+  return invoke("v1_sync_open_sealed", { request });
+}
+
+export function v1AttachmentsCreate(request: NewAttachment): Promise<Attachment> {
+  return invoke("v1_attachments_create", { request });
+}
+
+export function v1AttachmentsGet(request: IdRequest): Promise<Attachment> {
+  return invoke("v1_attachments_get", { request });
+}
+
+export function v1AttachmentsForPost(request: PostAttachmentsRequest): Promise<Array<Attachment>> {
+  return invoke("v1_attachments_for_post", { request });
+}
+
+export function v1AttachmentsEncryptFile(request: EncryptAttachmentRequest): Promise<Attachment> {
+  return invoke("v1_attachments_encrypt_file", { request });
+}
+
+export function v1AttachmentsRead(request: ReadAttachmentRequest): Promise<AttachmentChunk> {
+  return invoke("v1_attachments_read", { request });
+}
+
+export function v1ArchiveExport(request: ExportArchiveRequest): Promise<ArchiveSummary> {
+  return invoke("v1_archive_export", { request });
+}
+
+export function v1ArchiveInspect(request: ImportArchiveRequest): Promise<ImportReport> {
+  return invoke("v1_archive_inspect", { request });
+}
+
+export function v1ArchiveImport(request: ImportArchiveRequest): Promise<ImportReport> {
+  return invoke("v1_archive_import", { request });
+}
+
+export function v1DevicesLinkOffer(request: LinkOfferRequest): Promise<LinkOffer> {
+  return invoke("v1_devices_link_offer", { request });
+}
+
+export function v1DevicesLinkJoin(request: LinkJoinRequest): Promise<LinkedDevice> {
+  return invoke("v1_devices_link_join", { request });
+}
+
+export function v1DevicesList(request: UserRequest): Promise<Array<Device>> {
+  return invoke("v1_devices_list", { request });
+}
+
+export function v1DevicesRevoke(request: RevokeDeviceRequest): Promise<Device> {
+  return invoke("v1_devices_revoke", { request });
+}
+
+export function v1SettingsGet(): Promise<Settings> {
+  return invoke("v1_settings_get");
+}
+
+export function v1SettingsUpdate(request: Settings): Promise<Settings> {
+  return invoke("v1_settings_update", { request });
+}
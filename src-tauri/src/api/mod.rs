@@ -0,0 +1,455 @@
+//! Native IPC API for the webview. Commands are versioned by module
+//! (`v1_*`) so the frontend can keep calling an old version while a new one
+//! is introduced, and every request and response type derives `TS` so the
+//! TypeScript bindings in `bindings/` stay in step with the Rust side.
+
+use std::{
+    collections::BTreeSet,
+    fmt, io,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use ts_rs::{TypeVisitor, TS};
+
+use crate::api::v1::devices::PendingLink;
+use crate::commands::DeviceInfo;
+use crate::crypto::{
+    self,
+    keystore::{Keystore, KeystoreError},
+    recovery::PendingBackup,
+    social_recovery::RecoveryRequest,
+    stream::BlobError,
+    Identity,
+};
+use crate::db::{self, Database, SharedDatabase, User};
+use crate::settings::Settings;
+
+pub mod v1;
+
+/// Managed state shared by the command handlers. The database is the same
+/// file the embedded server uses. It is unset if it could not be opened, and
+/// until the keystore is unlocked if it is encrypted.
+#[derive(Default)]
+pub struct ApiState {
+    db: SharedDatabase,
+    session: Mutex<Session>,
+}
+
+impl ApiState {
+    pub fn new(db: Option<Database>) -> ApiState {
+        ApiState {
+            db: Arc::new(db.map(OnceLock::from).unwrap_or_default()),
+            session: Mutex::new(Session::default()),
+        }
+    }
+
+    pub fn db(&self) -> ApiResult<&Database> {
+        self.db
+            .get()
+            .ok_or_else(|| ApiError::Unavailable("Database is not available".to_string()))
+    }
+
+    /// The database cell, for the embedded server to read from.
+    pub fn shared_db(&self) -> SharedDatabase {
+        Arc::clone(&self.db)
+    }
+
+    /// Sets the database opened after launch. Returns `false` if one was
+    /// already open.
+    pub fn open_db(&self, db: Database) -> bool {
+        self.db.set(db).is_ok()
+    }
+
+    pub fn session(&self) -> MutexGuard<'_, Session> {
+        self.session
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `f` with the signed-in identity, or fails with `Locked`.
+    pub fn with_identity<T>(&self, f: impl FnOnce(&Identity) -> T) -> ApiResult<T> {
+        let mut session = self.session();
+        session.touch(Instant::now());
+        match session.identity() {
+            Some(identity) => Ok(f(identity)),
+            None => Err(ApiError::Locked("Not signed in".to_string())),
+        }
+    }
+
+    /// Runs `f` with the key to sign as `user`: the signed-in identity if it
+    /// is theirs, otherwise this device's certified key for them from the
+    /// unlocked keystore.
+    pub fn with_signer<T>(&self, user: &User, f: impl FnOnce(&Identity) -> T) -> ApiResult<T> {
+        let mut session = self.session();
+        session.touch(Instant::now());
+        if let Some(identity) = session
+            .identity()
+            .filter(|identity| user.public_key.as_deref() == Some(&identity.public_key()))
+        {
+            return Ok(f(identity));
+        }
+        let device = match (session.keystore(), &user.username) {
+            (Some(keystore), Some(username)) => keystore.device_identity(username),
+            _ => None,
+        };
+        match device {
+            Some(device) => Ok(f(&device?)),
+            None => Err(ApiError::Locked(
+                "Not signed in as this user on this device".to_string(),
+            )),
+        }
+    }
+
+    /// Runs `f` with the signed-in identity and the unlocked keystore, or
+    /// fails with `Locked` naming `feature`.
+    pub fn with_secrets<T>(
+        &self,
+        feature: &str,
+        f: impl FnOnce(&Identity, &mut Keystore) -> ApiResult<T>,
+    ) -> ApiResult<T> {
+        let mut session = self.session();
+        session.touch(Instant::now());
+        let (identity, keystore) = session.identity_and_keystore().ok_or_else(|| {
+            ApiError::Locked(format!(
+                "Sign in and unlock the keystore to use {}",
+                feature
+            ))
+        })?;
+        f(identity, keystore)
+    }
+
+    /// Locks the session if it has been idle past its auto-lock timeout.
+    /// Returns `true` if anything was locked.
+    pub fn lock_if_idle(&self, now: Instant) -> bool {
+        let mut session = self.session();
+        if session.is_idle(now) && !session.is_locked() {
+            session.lock();
+            return true;
+        }
+        false
+    }
+}
+
+/// Secrets held in native memory for the webview: the signed-in identity and
+/// the unlocked keystore. Both are zeroized when dropped.
+pub struct Session {
+    identity: Option<Identity>,
+    keystore: Option<Keystore>,
+    /// A recovery phrase shown to the user but not yet confirmed.
+    pub pending_backup: Option<PendingBackup>,
+    /// A request for friends' recovery shares and the one-off identity they
+    /// are sealed to. Kept across locks: it exists before anyone signs in.
+    pub pending_recovery: Option<(RecoveryRequest, Identity)>,
+    /// The pairing code being offered to a new device, if any. Dropped on
+    /// lock so the listener doesn't outlive the keys it copied.
+    pub pending_link: Option<PendingLink>,
+    last_activity: Instant,
+    /// Idle time after which [`ApiState::lock_if_idle`] locks; `None` never does.
+    pub auto_lock: Option<Duration>,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session {
+            identity: None,
+            keystore: None,
+            pending_backup: None,
+            pending_recovery: None,
+            pending_link: None,
+            last_activity: Instant::now(),
+            auto_lock: Settings::default().auto_lock(),
+        }
+    }
+}
+
+impl Session {
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    pub fn set_identity(&mut self, identity: Option<Identity>) {
+        self.identity = identity;
+    }
+
+    pub fn keystore(&self) -> Option<&Keystore> {
+        self.keystore.as_ref()
+    }
+
+    pub fn keystore_mut(&mut self) -> Option<&mut Keystore> {
+        self.keystore.as_mut()
+    }
+
+    /// Both secrets at once, for commands that sign and store keys.
+    pub fn identity_and_keystore(&mut self) -> Option<(&Identity, &mut Keystore)> {
+        Some((self.identity.as_ref()?, self.keystore.as_mut()?))
+    }
+
+    pub fn set_keystore(&mut self, keystore: Keystore) {
+        self.keystore = Some(keystore);
+        self.touch(Instant::now());
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.identity.is_none() && self.keystore.is_none()
+    }
+
+    /// Drops the identity and the keystore contents from memory.
+    pub fn lock(&mut self) {
+        self.identity = None;
+        self.keystore = None;
+        self.pending_backup = None;
+        self.pending_link = None;
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    pub fn is_idle(&self, now: Instant) -> bool {
+        self.auto_lock
+            .is_some_and(|timeout| now.saturating_duration_since(self.last_activity) >= timeout)
+    }
+}
+
+/// Safe to log: the identity shows its public key, the keystore its entry
+/// names and the pending seeds nothing.
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("identity", &self.identity)
+            .field("keystore", &self.keystore)
+            .field("pending_backup", &self.pending_backup)
+            .field("pending_recovery", &self.pending_recovery)
+            .field("pending_link", &self.pending_link)
+            .field("auto_lock", &self.auto_lock)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How often the auto-lock thread checks for an idle session.
+const AUTO_LOCK_POLL: Duration = Duration::from_secs(5);
+
+/// Event emitted to the webview when the session locks itself.
+pub const LOCKED_EVENT: &str = "session-locked";
+
+/// Applies the configured auto-lock timeout and starts the thread that
+/// enforces it. Call after [`ApiState`] is managed.
+pub fn start_auto_lock<R: Runtime>(app: AppHandle<R>, timeout: Option<Duration>) {
+    if let Some(state) = app.try_state::<ApiState>() {
+        state.session().auto_lock = timeout;
+    }
+    std::thread::spawn(move || loop {
+        std::thread::sleep(AUTO_LOCK_POLL);
+        let Some(state) = app.try_state::<ApiState>() else {
+            continue;
+        };
+        if state.lock_if_idle(Instant::now()) {
+            println!("Locked keystore after inactivity");
+            let _ = app.emit(LOCKED_EVENT, ());
+        }
+    });
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Error returned to the frontend; `invoke` rejects with
+/// `{ kind, message }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+#[ts(export)]
+pub enum ApiError {
+    /// The request broke a validation rule.
+    Invalid(String),
+    NotFound(String),
+    /// The native database could not be opened on this device.
+    Unavailable(String),
+    /// The command needs a signed-in identity.
+    Locked(String),
+    Internal(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Invalid(message)
+            | ApiError::NotFound(message)
+            | ApiError::Unavailable(message)
+            | ApiError::Locked(message)
+            | ApiError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<db::Error> for ApiError {
+    fn from(error: db::Error) -> ApiError {
+        match error {
+            db::Error::Invalid(message) => ApiError::Invalid(message),
+            db::Error::NotFound(message) => ApiError::NotFound(message.to_string()),
+            db::Error::Sqlite(e) => ApiError::from(e),
+            db::Error::Io(e) => ApiError::from(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(error: rusqlite::Error) -> ApiError {
+        println!("Command API database error: {}", error);
+        ApiError::Internal(error.to_string())
+    }
+}
+
+impl From<crypto::Error> for ApiError {
+    fn from(error: crypto::Error) -> ApiError {
+        ApiError::Invalid(error.to_string())
+    }
+}
+
+impl From<KeystoreError> for ApiError {
+    fn from(error: KeystoreError) -> ApiError {
+        match error {
+            KeystoreError::Crypto(e) => ApiError::from(e),
+            KeystoreError::Io(e) => ApiError::from(e),
+        }
+    }
+}
+
+impl From<BlobError> for ApiError {
+    fn from(error: BlobError) -> ApiError {
+        match error {
+            BlobError::Crypto(e) => ApiError::from(e),
+            BlobError::Io(e) => ApiError::from(e),
+        }
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(error: io::Error) -> ApiError {
+        match error.kind() {
+            io::ErrorKind::NotFound => ApiError::NotFound(error.to_string()),
+            // Blob chunks that fail authentication
+            io::ErrorKind::InvalidData => ApiError::Invalid(error.to_string()),
+            _ => ApiError::Internal(error.to_string()),
+        }
+    }
+}
+
+/// Describes one command for the TypeScript generator. Keep this list in
+/// step with [`crate::commands::handler`].
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// TypeScript type of the `request` argument, if the command takes one.
+    pub request: Option<String>,
+    pub response: String,
+    /// Exported types the signature refers to.
+    pub imports: BTreeSet<String>,
+}
+
+impl CommandSpec {
+    pub fn new<Req: TS + 'static, Res: TS + 'static>(name: &'static str) -> CommandSpec {
+        let mut imports = Imports::default();
+        imports.collect::<Req>();
+        imports.collect::<Res>();
+        CommandSpec {
+            name,
+            request: Some(Req::name()),
+            response: Res::name(),
+            imports: imports.0,
+        }
+    }
+
+    pub fn without_request<Res: TS + 'static>(name: &'static str) -> CommandSpec {
+        let mut imports = Imports::default();
+        imports.collect::<Res>();
+        CommandSpec {
+            name,
+            request: None,
+            response: Res::name(),
+            imports: imports.0,
+        }
+    }
+
+    /// `v1_posts_create` becomes `v1PostsCreate`.
+    pub fn function_name(&self) -> String {
+        let mut parts = self.name.split('_');
+        let mut name = parts.next().unwrap_or_default().to_string();
+        for part in parts {
+            let mut chars = part.chars();
+            if let Some(first) = chars.next() {
+                name.extend(first.to_uppercase());
+                name.push_str(chars.as_str());
+            }
+        }
+        name
+    }
+}
+
+#[derive(Default)]
+struct Imports(BTreeSet<String>);
+
+impl Imports {
+    fn collect<T: TS + 'static>(&mut self) {
+        self.visit::<T>();
+        T::visit_generics(self);
+    }
+}
+
+impl TypeVisitor for Imports {
+    fn visit<T: TS + 'static + ?Sized>(&mut self) {
+        if T::output_path().is_some() {
+            self.0.insert(T::ident());
+        }
+    }
+}
+
+/// Every command registered by [`crate::commands::handler`].
+pub fn commands() -> Vec<CommandSpec> {
+    let mut commands = vec![
+        CommandSpec::without_request::<String>("get_platform"),
+        CommandSpec::without_request::<DeviceInfo>("get_device_info"),
+    ];
+    commands.extend(v1::commands());
+    commands
+}
+
+/// Source of `bindings/commands.ts`: one typed `invoke` wrapper per command.
+pub fn typescript_bindings() -> String {
+    let commands = commands();
+    let imports: BTreeSet<&String> = commands
+        .iter()
+        .flat_map(|command| &command.imports)
+        .collect();
+
+    let mut ts = String::from(
+        "// Generated from src-tauri/src/api by `cargo test`. Do not edit by hand.\n\n\
+         import { invoke } from \"@tauri-apps/api/core\";\n",
+    );
+    for import in &imports {
+        ts.push_str(&format!("import type {{ {0} }} from \"./{0}\";\n", import));
+    }
+    ts.push_str(&format!(
+        "\nexport const API_VERSION = \"{}\";\n",
+        v1::VERSION
+    ));
+
+    for command in &commands {
+        let (params, args) = match &command.request {
+            Some(request) => (format!("request: {}", request), ", { request }"),
+            None => (String::new(), ""),
+        };
+        ts.push_str(&format!(
+            "\nexport function {}({}): Promise<{}> {{\n  return invoke(\"{}\"{});\n}}\n",
+            command.function_name(),
+            params,
+            command.response,
+            command.name,
+            args
+        ));
+    }
+    ts
+}
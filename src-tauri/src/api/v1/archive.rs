@@ -0,0 +1,1064 @@
+//! Moving an account between devices. An export gathers what the signed-in
+//! account has on this device into an [`AccountArchive`] manifest, adds its
+//! keystore entries and seals both into a [`SealedArchive`]. An import checks
+//! the signature, opens the archive with the export passphrase, validates
+//! every key chain and post signature in it, reports conflicts with what
+//! this device already has and merges the rest in.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::identity::unix_now;
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::commands::get_platform;
+use crate::crypto::{
+    self,
+    account::{AccountRecord, ACCOUNTS_DIR},
+    archive::{ArchiveContents, SealedArchive},
+    decode_base64, encode_base64,
+    kdf::Argon2Params,
+    keystore::Keystore,
+    rotation::{KeyEvent, KeyHistory},
+    secret::Secret,
+    signed_post::{self, SignedPost},
+    stream::{BlobEnvelope, BLOBS_DIR},
+    Identity,
+};
+use crate::db::{Database, NewAttachment, NewMessage, NewPeer, NewPost, NewSyncMessage, User};
+use crate::settings::Settings;
+
+/// Version of the [`AccountArchive`] manifest inside a sealed archive.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Keystore entries by name, as they travel with a manifest.
+pub type Secrets = BTreeMap<String, Secret<Vec<u8>>>;
+
+/// Everything an account has on one device, apart from its keystore
+/// entries. Other users are named by their current public key, since local
+/// ids differ between devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountArchive {
+    pub version: u32,
+    /// Unix seconds.
+    pub exported_at: i64,
+    /// Platform the archive was made on, e.g. `desktop` or `android`.
+    pub platform: String,
+    pub account: ArchivedUser,
+    /// How the exporting device unlocks the key with the account password,
+    /// so the same password signs in after import.
+    pub account_record: Option<AccountRecord>,
+    /// Everyone else the account's data refers to.
+    pub contacts: Vec<ArchivedUser>,
+    pub friendships: Vec<ArchivedFriendship>,
+    pub peers: Vec<ArchivedPeer>,
+    /// Oldest first.
+    pub posts: Vec<ArchivedPost>,
+    /// Oldest first.
+    pub messages: Vec<ArchivedMessage>,
+    pub circles: Vec<ArchivedCircle>,
+    /// Sync messages kept for later, such as friends' recovery shares.
+    pub held: Vec<ArchivedSyncMessage>,
+    /// Attachment blob files by name, Base64. They are already encrypted.
+    pub blobs: BTreeMap<String, String>,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedUser {
+    pub username: Option<String>,
+    pub public_key: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    /// The user's rotations and revocations, so their key chain can be
+    /// rebuilt and checked.
+    pub key_events: Vec<KeyEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedFriendship {
+    pub contact_key: String,
+    /// Whether the account sent the request.
+    pub outgoing: bool,
+    pub status: String,
+    /// The contact's key as the account verified it, if it did.
+    pub verified_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPeer {
+    pub address: String,
+    pub port: i64,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPost {
+    /// Set for a contact's post synced to the account.
+    pub original_author_key: Option<String>,
+    pub content_encrypted: Option<String>,
+    pub signature: Option<String>,
+    pub timestamp: Option<String>,
+    pub content_hash: Option<String>,
+    pub encryption_key: Option<String>,
+    pub attachments: Vec<ArchivedAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub file_size: i64,
+    pub data_encrypted: String,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedMessage {
+    /// Id on the exporting device. Names the keystore entry holding the
+    /// text of a forward-secret message.
+    pub id: i64,
+    pub sender_key: String,
+    pub recipient_key: String,
+    pub content: Option<String>,
+    pub encrypted_content: Option<String>,
+    pub read_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedCircle {
+    pub name: String,
+    pub public_id: String,
+    pub epoch: u32,
+    pub member_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedSyncMessage {
+    pub peer_key: String,
+    pub message_type: String,
+    pub payload: String,
+    pub status: String,
+}
+
+/// Not `Debug` or `Serialize`, so the passphrase can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ExportArchiveRequest {
+    /// Where to write the archive, e.g. from a save dialog.
+    pub path: String,
+    /// Encrypts the archive. Needed again to import it.
+    pub passphrase: String,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ImportArchiveRequest {
+    pub path: String,
+    pub passphrase: String,
+    /// Defaults to `abort`.
+    #[serde(default)]
+    #[ts(optional)]
+    pub on_conflict: Option<ConflictPolicy>,
+}
+
+/// What an import does when the archive and this device disagree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ConflictPolicy {
+    /// Import nothing while there are conflicts.
+    #[default]
+    Abort,
+    /// Keep this device's side of every conflict and skip contacts that
+    /// can't be matched.
+    KeepLocal,
+    /// Take the archive's side where it can be taken.
+    UseArchive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ConflictKind {
+    /// The account's key or password setup differs from this device's.
+    Account,
+    /// A contact's username belongs to someone else on this device.
+    Contact,
+    /// A keystore entry differs, e.g. a message session used on both devices.
+    Keystore,
+    Settings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveConflict {
+    pub kind: ConflictKind,
+    /// The username, keystore entry name or `settings` concerned.
+    pub subject: String,
+    pub message: String,
+    /// Whether [`ConflictPolicy::UseArchive`] can take the archive's side.
+    pub resolvable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveSummary {
+    pub username: String,
+    pub public_key: String,
+    pub platform: String,
+    #[ts(type = "number")]
+    pub exported_at: i64,
+    pub contacts: u32,
+    pub posts: u32,
+    pub messages: u32,
+    pub attachments: u32,
+    pub circles: u32,
+}
+
+impl ArchiveSummary {
+    fn new(archive: &AccountArchive) -> ArchiveSummary {
+        ArchiveSummary {
+            username: archive.account.username.clone().unwrap_or_default(),
+            public_key: archive.account.public_key.clone(),
+            platform: archive.platform.clone(),
+            exported_at: archive.exported_at,
+            contacts: archive.contacts.len() as u32,
+            posts: archive.posts.len() as u32,
+            messages: archive.messages.len() as u32,
+            attachments: archive
+                .posts
+                .iter()
+                .map(|post| post.attachments.len() as u32)
+                .sum(),
+            circles: archive.circles.len() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ImportReport {
+    pub archive: ArchiveSummary,
+    pub conflicts: Vec<ArchiveConflict>,
+    /// `false` for an inspection, or when conflicts stopped the import.
+    pub imported: bool,
+    pub added_contacts: u32,
+    pub added_posts: u32,
+    pub added_messages: u32,
+    /// Unsigned posts, or posts whose signature doesn't verify. They are
+    /// never imported.
+    pub skipped_posts: u32,
+}
+
+fn app_data_dir(app: &AppHandle) -> ApiResult<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))
+}
+
+/// Writes the signed-in account to an encrypted archive at `path`.
+#[tauri::command]
+pub async fn v1_archive_export(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: ExportArchiveRequest,
+) -> ApiResult<ArchiveSummary> {
+    let app_data_dir = app_data_dir(&app)?;
+    let passphrase = Secret::new(std::mem::take(&mut request.passphrase));
+    if passphrase.expose().is_empty() {
+        return Err(ApiError::Invalid("Passphrase can't be blank".to_string()));
+    }
+    let db = state.db()?.clone();
+    let (identity, archive, secrets) =
+        state.with_secrets("account export", |identity, keystore| {
+            let (archive, secrets) = export(&db, &app_data_dir, identity, keystore, unix_now())?;
+            // A copy to sign with off the session lock
+            Ok((
+                Identity::from_seed(identity.seed().expose()),
+                archive,
+                secrets,
+            ))
+        })?;
+
+    let path = PathBuf::from(&request.path);
+    tauri::async_runtime::spawn_blocking(move || {
+        let params = Settings::load(&app_data_dir).kdf.unwrap_or_default();
+        let sealed = seal(&identity, passphrase.expose(), params, &archive, secrets)?;
+        sealed.save(&path)?;
+        Ok(ArchiveSummary::new(&archive))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+}
+
+/// Opens an archive and reports what importing it would do, without
+/// changing anything.
+#[tauri::command]
+pub async fn v1_archive_inspect(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: ImportArchiveRequest,
+) -> ApiResult<ImportReport> {
+    run_import(app, state, request, false).await
+}
+
+/// Imports an archive into this device. With conflicts and the default
+/// policy, nothing is written and the report lists them. Needs an unlocked
+/// keystore to take the account's keys; sign in afterwards as usual.
+#[tauri::command]
+pub async fn v1_archive_import(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: ImportArchiveRequest,
+) -> ApiResult<ImportReport> {
+    run_import(app, state, request, true).await
+}
+
+async fn run_import(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: ImportArchiveRequest,
+    apply: bool,
+) -> ApiResult<ImportReport> {
+    let app_data_dir = app_data_dir(&app)?;
+    let passphrase = Secret::new(std::mem::take(&mut request.passphrase));
+    let path = PathBuf::from(&request.path);
+    let (archive, secrets) = tauri::async_runtime::spawn_blocking(move || {
+        open(&SealedArchive::load(&path)?, passphrase.expose())
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let db = state.db()?;
+    let mut session = state.session();
+    let keystore = session
+        .keystore_mut()
+        .ok_or_else(|| ApiError::Locked("Unlock the keystore to import an account".to_string()))?;
+    import(
+        db,
+        &app_data_dir,
+        keystore,
+        &archive,
+        &secrets,
+        request.on_conflict.unwrap_or_default(),
+        apply,
+    )
+}
+
+/// Gathers the account `identity` is signed in as, with its keystore
+/// entries and the texts of its forward-secret messages.
+pub fn export(
+    db: &Database,
+    app_data_dir: &Path,
+    identity: &Identity,
+    keystore: &Keystore,
+    now: i64,
+) -> ApiResult<(AccountArchive, Secrets)> {
+    let user = db
+        .find_user_by_public_key(&identity.public_key())?
+        .ok_or_else(|| {
+            ApiError::NotFound("Register this account before exporting it".to_string())
+        })?;
+    let username = user
+        .username
+        .clone()
+        .ok_or_else(|| ApiError::Invalid("Set a username first".to_string()))?;
+    let mut contacts = BTreeMap::new();
+
+    let mut friendships = Vec::new();
+    for friendship in db.friendships_for_user(user.id)? {
+        let outgoing = friendship.requester_id == user.id;
+        let other = if outgoing {
+            friendship.addressee_id
+        } else {
+            friendship.requester_id
+        };
+        friendships.push(ArchivedFriendship {
+            contact_key: contact_key(db, &mut contacts, other)?,
+            outgoing,
+            status: friendship.status,
+            verified_key: db
+                .find_key_verification(user.id, other)?
+                .map(|(key, _)| key),
+        });
+    }
+
+    let peers = db
+        .peers_for_user(user.id)?
+        .into_iter()
+        .filter_map(|peer| {
+            Some(ArchivedPeer {
+                address: peer.address?,
+                port: peer.port?,
+                public_key: peer.public_key?,
+            })
+        })
+        .collect();
+
+    let blobs_dir = app_data_dir.join(BLOBS_DIR);
+    let mut blobs = BTreeMap::new();
+    let mut posts = Vec::new();
+    for post in db.posts_for_user(user.id)?.into_iter().rev() {
+        let original_author_key = match post.original_user_id {
+            Some(id) if id != user.id => Some(contact_key(db, &mut contacts, id)?),
+            _ => None,
+        };
+        let mut attachments = Vec::new();
+        for attachment in db.attachments_for_post(post.id)? {
+            let data_encrypted = attachment.data_encrypted.unwrap_or_default();
+            if let Ok(envelope) = BlobEnvelope::from_json(&data_encrypted) {
+                match fs::read(envelope.path(&blobs_dir)?) {
+                    Ok(blob) => {
+                        blobs.insert(envelope.blob.clone(), encode_base64(&blob));
+                    }
+                    Err(e) => println!("Leaving blob {} out of the archive: {}", envelope.blob, e),
+                }
+            }
+            attachments.push(ArchivedAttachment {
+                filename: attachment.filename.unwrap_or_default(),
+                content_type: attachment.content_type.unwrap_or_default(),
+                file_size: attachment.file_size.unwrap_or_default(),
+                data_encrypted,
+                checksum: attachment.checksum.unwrap_or_default(),
+            });
+        }
+        posts.push(ArchivedPost {
+            original_author_key,
+            content_encrypted: post.content_encrypted,
+            signature: post.signature,
+            timestamp: post.timestamp,
+            content_hash: post.content_hash,
+            encryption_key: post.encryption_key,
+            attachments,
+        });
+    }
+
+    let account_key = identity.public_key();
+    let mut messages = Vec::new();
+    for message in db.messages_for_user(user.id)? {
+        let key = |id: i64, contacts: &mut BTreeMap<i64, ArchivedUser>| {
+            if id == user.id {
+                Ok(account_key.clone())
+            } else {
+                contact_key(db, contacts, id)
+            }
+        };
+        messages.push(ArchivedMessage {
+            id: message.id,
+            sender_key: key(message.sender_id, &mut contacts)?,
+            recipient_key: key(message.recipient_id, &mut contacts)?,
+            content: message.content,
+            encrypted_content: message.encrypted_content,
+            read_at: message.read_at,
+            created_at: message.created_at,
+        });
+    }
+
+    let mut circles = Vec::new();
+    for circle in db.circles_for_user(user.id)? {
+        let mut member_keys = Vec::new();
+        for member in db.circle_members(circle.id)? {
+            member_keys.push(contact_key(db, &mut contacts, member.id)?);
+        }
+        circles.push(ArchivedCircle {
+            name: circle.name,
+            public_id: circle.public_id,
+            epoch: circle.epoch,
+            member_keys,
+        });
+    }
+
+    let mut held = Vec::new();
+    for message in db.sync_messages_with_status(user.id, "held")? {
+        let peer_key = db
+            .find_peer(message.peer_id)?
+            .and_then(|peer| peer.public_key);
+        if let (Some(peer_key), Some(message_type), Some(payload)) =
+            (peer_key, message.message_type, message.payload)
+        {
+            held.push(ArchivedSyncMessage {
+                peer_key,
+                message_type,
+                payload,
+                status: "held".to_string(),
+            });
+        }
+    }
+
+    let archive = AccountArchive {
+        version: MANIFEST_VERSION,
+        exported_at: now,
+        platform: get_platform(),
+        account: archived_user(db, user)?,
+        account_record: AccountRecord::load(&app_data_dir.join(ACCOUNTS_DIR), &username)?,
+        contacts: contacts.into_values().collect(),
+        friendships,
+        peers,
+        posts,
+        messages,
+        circles,
+        held,
+        blobs,
+        settings: Settings::load(app_data_dir),
+    };
+    Ok((archive, keystore.account_entries(&username)))
+}
+
+/// The contact's current key, adding them to `contacts` the first time.
+fn contact_key(
+    db: &Database,
+    contacts: &mut BTreeMap<i64, ArchivedUser>,
+    id: i64,
+) -> ApiResult<String> {
+    if let Some(contact) = contacts.get(&id) {
+        return Ok(contact.public_key.clone());
+    }
+    let user = db
+        .find_user(id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let contact = archived_user(db, user)?;
+    let public_key = contact.public_key.clone();
+    contacts.insert(id, contact);
+    Ok(public_key)
+}
+
+fn archived_user(db: &Database, user: User) -> ApiResult<ArchivedUser> {
+    let public_key = user.public_key.ok_or_else(|| {
+        ApiError::Invalid(format!(
+            "{} has no public key",
+            user.username.as_deref().unwrap_or("A user")
+        ))
+    })?;
+    Ok(ArchivedUser {
+        username: user.username,
+        public_key,
+        display_name: user.display_name,
+        email: user.email,
+        key_events: db.key_events_for_user(user.id)?,
+    })
+}
+
+/// Seals the manifest and secrets under `passphrase`, signed by `identity`.
+pub fn seal(
+    identity: &Identity,
+    passphrase: &str,
+    params: Argon2Params,
+    archive: &AccountArchive,
+    secrets: Secrets,
+) -> ApiResult<SealedArchive> {
+    let manifest = serde_json::to_vec(archive).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let contents = ArchiveContents {
+        manifest: manifest.into(),
+        secrets,
+    };
+    Ok(SealedArchive::seal(
+        identity,
+        passphrase,
+        params,
+        &contents,
+        archive.exported_at,
+    )?)
+}
+
+/// Checks the archive's signature, decrypts it and parses the manifest,
+/// which must be signed for by the account it describes.
+pub fn open(sealed: &SealedArchive, passphrase: &str) -> ApiResult<(AccountArchive, Secrets)> {
+    let contents = sealed.open(passphrase).map_err(|e| match e {
+        crypto::Error::DecryptionFailed => {
+            ApiError::Invalid("That passphrase doesn't open the archive".to_string())
+        }
+        e => ApiError::Invalid(format!("Not a valid account archive: {}", e)),
+    })?;
+    let archive = parse_manifest(&contents.manifest)?;
+    if archive.account.public_key != sealed.public_key {
+        return Err(ApiError::Invalid(
+            "The archive isn't signed by the account in it".to_string(),
+        ));
+    }
+    Ok((archive, contents.secrets))
+}
+
+/// Reads a manifest of the version this build writes.
+pub fn parse_manifest(manifest: &[u8]) -> ApiResult<AccountArchive> {
+    let archive: AccountArchive = serde_json::from_slice(manifest)
+        .map_err(|e| ApiError::Invalid(format!("Invalid archive manifest: {}", e)))?;
+    if archive.version != MANIFEST_VERSION {
+        return Err(ApiError::Invalid(format!(
+            "Archive version {} isn't supported",
+            archive.version
+        )));
+    }
+    Ok(archive)
+}
+
+/// How a user in the archive lines up with this device.
+enum Match {
+    Existing(User),
+    New,
+    /// Someone else here has the username.
+    Taken,
+}
+
+fn invalid(message: impl std::fmt::Display) -> ApiError {
+    ApiError::Invalid(format!("Invalid account archive: {}", message))
+}
+
+/// The user's key chain from the archive, which must end at their key.
+fn key_history(user: &ArchivedUser) -> ApiResult<KeyHistory> {
+    let root = KeyHistory::root(&user.key_events).unwrap_or(&user.public_key);
+    let history = KeyHistory::build(root, &user.key_events).map_err(invalid)?;
+    if history.current() != user.public_key {
+        return Err(invalid("a key chain doesn't end at the user's key"));
+    }
+    Ok(history)
+}
+
+fn match_user(db: &Database, user: &ArchivedUser, history: &KeyHistory) -> ApiResult<Match> {
+    for period in history.periods.iter().rev() {
+        if let Some(local) = db.find_user_by_public_key(&period.public_key)? {
+            return Ok(Match::Existing(local));
+        }
+    }
+    match &user.username {
+        Some(username) if db.find_user_by_username(username)?.is_some() => Ok(Match::Taken),
+        _ => Ok(Match::New),
+    }
+}
+
+/// Finds or creates the user, then brings their key chain up to date.
+fn restore_user(
+    db: &Database,
+    user: &ArchivedUser,
+    history: &KeyHistory,
+    existing: Option<User>,
+) -> ApiResult<(i64, bool)> {
+    let (local, created) = match existing {
+        Some(local) => (local, false),
+        None => {
+            let local = db.create_user(&crate::db::NewUser {
+                public_key: history.periods[0].public_key.clone(),
+                username: user.username.clone().unwrap_or_default(),
+                display_name: user.display_name.clone(),
+                email: user.email.clone(),
+            })?;
+            (local, true)
+        }
+    };
+    for event in &user.key_events {
+        db.record_key_event(local.id, event)?;
+    }
+    Ok((local.id, created))
+}
+
+/// Checks `archive` against this device and, if `apply` and the policy
+/// allows, merges it in. Nothing is written when the report comes back
+/// with `imported: false`, and the database is left as it was on an error.
+pub fn import(
+    db: &Database,
+    app_data_dir: &Path,
+    keystore: &mut Keystore,
+    archive: &AccountArchive,
+    secrets: &Secrets,
+    policy: ConflictPolicy,
+    apply: bool,
+) -> ApiResult<ImportReport> {
+    let username = archive
+        .account
+        .username
+        .clone()
+        .filter(|username| !username.is_empty())
+        .ok_or_else(|| invalid("the account has no username"))?;
+    let accounts_dir = app_data_dir.join(ACCOUNTS_DIR);
+
+    // Validate everything before looking at this device
+    let account_history = key_history(&archive.account)?;
+    let mut histories = BTreeMap::new();
+    for contact in &archive.contacts {
+        histories.insert(contact.public_key.clone(), key_history(contact)?);
+    }
+    if let Some(record) = &archive.account_record {
+        if record.username != username || record.public_key != archive.account.public_key {
+            return Err(invalid("the account record is for another key"));
+        }
+    }
+    if let Some(name) = secrets
+        .keys()
+        .find(|name| !Keystore::is_account_entry(&username, name))
+    {
+        return Err(invalid(format!(
+            "the keystore entry {} isn't the account's",
+            name
+        )));
+    }
+    if let Some(seed) = secrets.get(&Keystore::identity_entry(&username)) {
+        let seed: &[u8; 32] = seed
+            .expose()
+            .as_slice()
+            .try_into()
+            .map_err(|_| invalid("the stored key is malformed"))?;
+        if Identity::from_seed(seed).public_key() != archive.account.public_key {
+            return Err(invalid("the stored key isn't the account's key"));
+        }
+    }
+    for (name, blob) in &archive.blobs {
+        let bytes = decode_base64(blob, "blob")?;
+        if *name != crypto::encode_hex(&Sha256::digest(&bytes)) {
+            return Err(invalid("an attachment blob doesn't match its name"));
+        }
+    }
+    let verified_posts: Vec<&ArchivedPost> = archive
+        .posts
+        .iter()
+        .filter(|post| {
+            let history = match &post.original_author_key {
+                Some(key) => histories.get(key),
+                None => Some(&account_history),
+            };
+            history.is_some_and(|history| post_verifies(post, history))
+        })
+        .collect();
+
+    let mut conflicts = Vec::new();
+    let account_match = match_user(db, &archive.account, &account_history)?;
+    let local_account = match account_match {
+        Match::Taken => {
+            return Err(ApiError::Invalid(format!(
+                "{} on this device is a different account, so this archive can't be imported here",
+                username
+            )))
+        }
+        Match::Existing(local) => Some(local),
+        Match::New => None,
+    };
+    // This device has moved on to a key the archive doesn't know
+    let local_ahead = local_account.as_ref().is_some_and(|local| {
+        local
+            .public_key
+            .as_deref()
+            .is_some_and(|key| !account_history.contains(key))
+    });
+    if local_ahead {
+        conflicts.push(ArchiveConflict {
+            kind: ConflictKind::Account,
+            subject: username.clone(),
+            message: format!(
+                "This device has a newer key for {} than the archive",
+                username
+            ),
+            resolvable: false,
+        });
+    }
+    let local_record = AccountRecord::load(&accounts_dir, &username)?;
+    let record_differs = match (&local_record, &archive.account_record) {
+        (Some(local), Some(theirs)) => local.public_key == theirs.public_key && local != theirs,
+        _ => false,
+    };
+    if record_differs {
+        conflicts.push(ArchiveConflict {
+            kind: ConflictKind::Account,
+            subject: username.clone(),
+            message: format!(
+                "This device already unlocks {}, possibly with a different password",
+                username
+            ),
+            resolvable: true,
+        });
+    }
+
+    let mut contact_matches = BTreeMap::new();
+    for contact in &archive.contacts {
+        let matched = match_user(db, contact, &histories[&contact.public_key])?;
+        if let Match::Taken = matched {
+            let name = contact.username.clone().unwrap_or_default();
+            conflicts.push(ArchiveConflict {
+                kind: ConflictKind::Contact,
+                subject: name.clone(),
+                message: format!(
+                    "{} on this device has a different key; the archive's {} would be skipped",
+                    name, name
+                ),
+                resolvable: false,
+            });
+        }
+        contact_matches.insert(contact.public_key.clone(), matched);
+    }
+
+    let message_entries: BTreeSet<String> = archive
+        .messages
+        .iter()
+        .map(|message| Keystore::message_entry(&username, message.id))
+        .collect();
+    for (name, secret) in secrets {
+        if message_entries.contains(name) {
+            continue;
+        }
+        if let Some(local) = keystore.get(name) {
+            if Secret::new(local.to_vec()) != *secret {
+                conflicts.push(ArchiveConflict {
+                    kind: ConflictKind::Keystore,
+                    subject: name.clone(),
+                    message: format!("The keystore entry {} differs on this device", name),
+                    resolvable: true,
+                });
+            }
+        }
+    }
+
+    let has_settings = Settings::path(app_data_dir).exists();
+    if has_settings && Settings::load(app_data_dir) != archive.settings {
+        conflicts.push(ArchiveConflict {
+            kind: ConflictKind::Settings,
+            subject: "settings".to_string(),
+            message: "This device's settings differ from the archive's".to_string(),
+            resolvable: true,
+        });
+    }
+
+    let mut report = ImportReport {
+        archive: ArchiveSummary::new(archive),
+        conflicts,
+        imported: false,
+        added_contacts: 0,
+        added_posts: 0,
+        added_messages: 0,
+        skipped_posts: (archive.posts.len() - verified_posts.len()) as u32,
+    };
+    let blocked = match policy {
+        ConflictPolicy::Abort => !report.conflicts.is_empty(),
+        ConflictPolicy::KeepLocal => false,
+        ConflictPolicy::UseArchive => report.conflicts.iter().any(|c| !c.resolvable),
+    };
+    if !apply || blocked {
+        return Ok(report);
+    }
+    let use_archive = policy == ConflictPolicy::UseArchive;
+
+    // The database in one go, so a failure part way leaves it as it was;
+    // files and the keystore only once that has committed
+    let mut message_texts = Vec::new();
+    db.transaction(|db| -> ApiResult<()> {
+        // Users first: everything else refers to them
+        let mut ids = BTreeMap::new();
+        for contact in &archive.contacts {
+            let existing = match contact_matches.remove(&contact.public_key) {
+                Some(Match::Existing(local)) => Some(local),
+                Some(Match::New) => None,
+                _ => continue,
+            };
+            let history = &histories[&contact.public_key];
+            let (id, created) = restore_user(db, contact, history, existing)?;
+            report.added_contacts += created as u32;
+            ids.insert(contact.public_key.clone(), id);
+        }
+        let (account_id, _) = restore_user(db, &archive.account, &account_history, local_account)?;
+        ids.insert(archive.account.public_key.clone(), account_id);
+
+        for friendship in &archive.friendships {
+            let Some(&contact_id) = ids.get(&friendship.contact_key) else {
+                continue;
+            };
+            let exists = db
+                .friendships_for_user(account_id)?
+                .iter()
+                .any(|local| local.requester_id == contact_id || local.addressee_id == contact_id);
+            if !exists {
+                let (requester, addressee) = if friendship.outgoing {
+                    (account_id, contact_id)
+                } else {
+                    (contact_id, account_id)
+                };
+                let created = db.send_friend_request(requester, addressee)?;
+                if friendship.status != "pending" {
+                    db.update_friendship_status(created.id, &friendship.status)?;
+                }
+            }
+            if let Some(verified_key) = &friendship.verified_key {
+                if db.find_key_verification(account_id, contact_id)?.is_none() {
+                    db.upsert_key_verification(account_id, contact_id, verified_key)?;
+                }
+            }
+        }
+        for peer in &archive.peers {
+            db.record_peer(&NewPeer {
+                user_id: account_id,
+                address: peer.address.clone(),
+                port: peer.port,
+                public_key: peer.public_key.clone(),
+            })?;
+        }
+
+        for post in verified_posts {
+            let original_user_id = match &post.original_author_key {
+                Some(key) => match ids.get(key) {
+                    Some(&id) => Some(id),
+                    None => continue,
+                },
+                None => None,
+            };
+            let signature = post.signature.as_deref().unwrap_or_default();
+            if db.post_exists_with_signature(account_id, signature)? {
+                continue;
+            }
+            let created = db.ingest_post(&NewPost {
+                user_id: account_id,
+                content_encrypted: post.content_encrypted.clone(),
+                signature: post.signature.clone(),
+                timestamp: post.timestamp.clone(),
+                content_hash: post.content_hash.clone(),
+                encryption_key: post.encryption_key.clone(),
+                is_synced: original_user_id.is_some(),
+                original_user_id,
+                synced_from_user_id: original_user_id,
+                attachment_checksums: Some(
+                    post.attachments
+                        .iter()
+                        .map(|attachment| attachment.checksum.clone())
+                        .collect(),
+                ),
+            })?;
+            for attachment in &post.attachments {
+                db.create_attachment(&NewAttachment {
+                    post_id: created.id,
+                    filename: attachment.filename.clone(),
+                    content_type: attachment.content_type.clone(),
+                    file_size: attachment.file_size,
+                    data_encrypted: attachment.data_encrypted.clone(),
+                    checksum: attachment.checksum.clone(),
+                })?;
+            }
+            report.added_posts += 1;
+        }
+
+        for message in &archive.messages {
+            let (Some(&sender_id), Some(&recipient_id)) = (
+                ids.get(&message.sender_key),
+                ids.get(&message.recipient_key),
+            ) else {
+                continue;
+            };
+            let imported = db.import_message(
+                &NewMessage {
+                    sender_id,
+                    recipient_id,
+                    content: message.content.clone().unwrap_or_default(),
+                    encrypted_content: message.encrypted_content.clone(),
+                },
+                &message.created_at,
+                message.read_at.as_deref(),
+            )?;
+            if let Some(imported) = imported {
+                if let Some(text) = secrets.get(&Keystore::message_entry(&username, message.id)) {
+                    message_texts.push((imported.id, text));
+                }
+                report.added_messages += 1;
+            }
+        }
+
+        for circle in &archive.circles {
+            if db.find_circle_by_public_id(&circle.public_id)?.is_some() {
+                continue;
+            }
+            let created = db.insert_circle(account_id, &circle.name, &circle.public_id)?;
+            if circle.epoch != created.epoch {
+                db.update_circle_epoch(created.id, circle.epoch)?;
+            }
+            for key in &circle.member_keys {
+                if let Some(&member_id) = ids.get(key) {
+                    db.insert_circle_member(created.id, member_id)?;
+                }
+            }
+        }
+
+        let peers = db.peers_for_user(account_id)?;
+        for message in &archive.held {
+            let Some(peer) = peers
+                .iter()
+                .find(|peer| peer.public_key.as_deref() == Some(message.peer_key.as_str()))
+            else {
+                continue;
+            };
+            if db.sync_message_exists(account_id, &message.message_type, &message.payload)? {
+                continue;
+            }
+            db.create_sync_message(&NewSyncMessage {
+                user_id: account_id,
+                peer_id: peer.id,
+                payload: message.payload.clone(),
+                message_type: message.message_type.clone(),
+                status: message.status.clone(),
+                processed_count: None,
+                error_count: None,
+            })?;
+        }
+        Ok(())
+    })?;
+
+    if let Some(record) = &archive.account_record {
+        let replace = match &local_record {
+            None => true,
+            Some(local) if local.public_key == record.public_key => use_archive,
+            Some(local) => !local_ahead && account_history.contains(&local.public_key),
+        };
+        if replace {
+            record.save(&accounts_dir)?;
+        }
+    }
+    for (name, secret) in secrets {
+        if message_entries.contains(name) || (local_ahead && !use_archive) {
+            continue;
+        }
+        if keystore.get(name).is_none() || use_archive {
+            keystore.insert(name, secret.expose());
+        }
+    }
+
+    for (id, text) in message_texts {
+        keystore.set_message_text(&username, id, text.expose());
+    }
+    let blobs_dir = app_data_dir.join(BLOBS_DIR);
+    for (name, blob) in &archive.blobs {
+        let path = blobs_dir.join(name);
+        if !path.exists() {
+            fs::create_dir_all(&blobs_dir)?;
+            fs::write(path, decode_base64(blob, "blob")?)?;
+        }
+    }
+    if !has_settings || use_archive {
+        archive.settings.save(app_data_dir)?;
+    }
+    keystore.save()?;
+    report.imported = true;
+    Ok(report)
+}
+
+/// Whether the post carries its author's signature by the key valid at its
+/// timestamp.
+fn post_verifies(post: &ArchivedPost, history: &KeyHistory) -> bool {
+    let (Some(signature), Some(timestamp)) = (&post.signature, &post.timestamp) else {
+        return false;
+    };
+    let Ok(timestamp) = signed_post::parse_timestamp(timestamp) else {
+        return false;
+    };
+    let Some(author) = history.key_at(timestamp) else {
+        return false;
+    };
+    SignedPost::new(
+        author,
+        post.content_encrypted.as_deref().unwrap_or_default(),
+        post.attachments
+            .iter()
+            .map(|attachment| attachment.checksum.clone()),
+        timestamp,
+    )
+    .is_ok_and(|signed| signed.verify(signature).is_ok())
+}
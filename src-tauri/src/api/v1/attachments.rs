@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::{posts::default_recipients, IdRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    encode_base64,
+    stream::{BlobEnvelope, BLOBS_DIR},
+};
+use crate::db::{self, Attachment, NewAttachment};
+
+/// Largest slice [`v1_attachments_read`] returns in one call.
+pub const MAX_READ_BYTES: u32 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PostAttachmentsRequest {
+    #[ts(type = "number")]
+    pub post_id: i64,
+}
+
+/// Encrypts a file on disk into an attachment blob.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EncryptAttachmentRequest {
+    #[ts(type = "number")]
+    pub post_id: i64,
+    /// Absolute path of the file to encrypt, e.g. from a file dialog.
+    pub path: String,
+    /// Defaults to the file name of `path`.
+    #[serde(default)]
+    #[ts(optional)]
+    pub filename: Option<String>,
+    pub content_type: String,
+    /// Base64 public keys to encrypt for. Defaults to the signed-in user and
+    /// their accepted friends.
+    #[serde(default)]
+    #[ts(optional)]
+    pub recipients: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReadAttachmentRequest {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub offset: Option<u64>,
+    /// Defaults to, and is capped at, [`MAX_READ_BYTES`].
+    #[serde(default)]
+    #[ts(optional)]
+    pub length: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AttachmentChunk {
+    #[ts(type = "number")]
+    pub offset: u64,
+    /// Base64 plaintext; shorter than requested at the end of the file.
+    pub data: String,
+    /// Plaintext size of the whole attachment.
+    #[ts(type = "number")]
+    pub size: u64,
+}
+
+fn blobs_dir(app: &AppHandle) -> ApiResult<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(BLOBS_DIR))
+        .map_err(|e| ApiError::Unavailable(e.to_string()))
+}
+
+#[tauri::command]
+pub fn v1_attachments_create(
+    state: State<'_, ApiState>,
+    request: NewAttachment,
+) -> ApiResult<Attachment> {
+    Ok(state
+        .db()?
+        .create_attachment(&request)
+        .map_err(db::Error::from)?)
+}
+
+#[tauri::command]
+pub fn v1_attachments_get(state: State<'_, ApiState>, request: IdRequest) -> ApiResult<Attachment> {
+    state
+        .db()?
+        .find_attachment(request.id)?
+        .ok_or_else(|| ApiError::NotFound("Attachment not found".to_string()))
+}
+
+#[tauri::command]
+pub fn v1_attachments_for_post(
+    state: State<'_, ApiState>,
+    request: PostAttachmentsRequest,
+) -> ApiResult<Vec<Attachment>> {
+    Ok(state.db()?.attachments_for_post(request.post_id)?)
+}
+
+/// Encrypts the file in chunks into `blobs/` and records an attachment whose
+/// `data_encrypted` is the [`BlobEnvelope`] and `checksum` the blob's
+/// SHA-256. The file is never held in memory whole.
+#[tauri::command]
+pub async fn v1_attachments_encrypt_file(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: EncryptAttachmentRequest,
+) -> ApiResult<Attachment> {
+    let blobs_dir = blobs_dir(&app)?;
+    let author = state.with_identity(|identity| identity.public_key())?;
+    let db = state.db()?.clone();
+    let recipients = match request.recipients {
+        Some(recipients) => recipients,
+        None => default_recipients(&db, &author)?,
+    };
+    let source = PathBuf::from(&request.path);
+    let filename = match request.filename {
+        Some(filename) => filename,
+        None => source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| ApiError::Invalid("path has no file name".to_string()))?,
+    };
+
+    let envelope = tauri::async_runtime::spawn_blocking(move || {
+        BlobEnvelope::encrypt_file(&source, &blobs_dir, &recipients)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let attachment = NewAttachment {
+        post_id: request.post_id,
+        filename,
+        content_type: request.content_type,
+        file_size: envelope.size as i64,
+        data_encrypted: envelope.to_json(),
+        checksum: envelope.blob.clone(),
+    };
+    Ok(db.create_attachment(&attachment).map_err(db::Error::from)?)
+}
+
+/// Decrypts part of a blob attachment, so media can seek without reading
+/// the rest of the file.
+#[tauri::command]
+pub fn v1_attachments_read(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: ReadAttachmentRequest,
+) -> ApiResult<AttachmentChunk> {
+    let attachment = state
+        .db()?
+        .find_attachment(request.id)?
+        .ok_or_else(|| ApiError::NotFound("Attachment not found".to_string()))?;
+    let envelope = BlobEnvelope::from_json(attachment.data_encrypted.as_deref().unwrap_or(""))?;
+    let blobs_dir = blobs_dir(&app)?;
+
+    let offset = request.offset.unwrap_or(0);
+    let length = request.length.unwrap_or(MAX_READ_BYTES).min(MAX_READ_BYTES);
+    let mut reader = state.with_identity(|identity| envelope.open(&blobs_dir, identity))??;
+    let data = reader.read_range(offset, length as usize)?;
+    Ok(AttachmentChunk {
+        offset,
+        data: encode_base64(&data),
+        size: reader.len(),
+    })
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ts_rs::TS;
+
+use super::{
+    posts::{DecryptPostRequest, EncryptedContent},
+    UserRequest,
+};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    circle::{CircleEnvelope, CircleKeys, EpochChange},
+    keystore::Keystore,
+    Identity,
+};
+use crate::db::{Circle, Database, NewCircle, User};
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CircleWithMembers {
+    pub circle: Circle,
+    pub members: Vec<User>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CircleMemberRequest {
+    #[ts(type = "number")]
+    pub circle_id: i64,
+    #[ts(type = "number")]
+    pub user_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EncryptCircleRequest {
+    #[ts(type = "number")]
+    pub circle_id: i64,
+    pub content: String,
+}
+
+/// Creates a circle and queues its key to each member.
+#[tauri::command]
+pub fn v1_circles_create(state: State<'_, ApiState>, request: NewCircle) -> ApiResult<Circle> {
+    let db = state.db()?;
+    state.with_secrets("circles", |identity, keystore| {
+        create(db, identity, keystore, &request)
+    })
+}
+
+/// The user's circles with their members.
+#[tauri::command]
+pub fn v1_circles_list(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<Vec<CircleWithMembers>> {
+    let db = state.db()?;
+    db.circles_for_user(request.user_id)?
+        .into_iter()
+        .map(|circle| {
+            let members = db.circle_members(circle.id)?;
+            Ok(CircleWithMembers { circle, members })
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn v1_circles_add_member(
+    state: State<'_, ApiState>,
+    request: CircleMemberRequest,
+) -> ApiResult<Circle> {
+    let db = state.db()?;
+    state.with_secrets("circles", |identity, keystore| {
+        add_member(db, identity, keystore, &request)
+    })
+}
+
+#[tauri::command]
+pub fn v1_circles_remove_member(
+    state: State<'_, ApiState>,
+    request: CircleMemberRequest,
+) -> ApiResult<Circle> {
+    let db = state.db()?;
+    state.with_secrets("circles", |identity, keystore| {
+        remove_member(db, identity, keystore, &request)
+    })
+}
+
+/// Encrypts a post body once for the whole circle. Store the result in
+/// `NewPost.content_encrypted` as with [`super::posts::v1_posts_encrypt`].
+#[tauri::command]
+pub fn v1_circles_encrypt(
+    state: State<'_, ApiState>,
+    request: EncryptCircleRequest,
+) -> ApiResult<EncryptedContent> {
+    let db = state.db()?;
+    state.with_secrets("circles", |identity, keystore| {
+        encrypt(db, identity, keystore, &request)
+    })
+}
+
+/// Opens a circle post, as its owner or with a key the owner sent.
+#[tauri::command]
+pub fn v1_circles_decrypt(
+    state: State<'_, ApiState>,
+    request: DecryptPostRequest,
+) -> ApiResult<String> {
+    let db = state.db()?;
+    state.with_secrets("circles", |identity, keystore| {
+        decrypt(db, identity, keystore, &request.content_encrypted)
+    })
+}
+
+pub fn create(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    request: &NewCircle,
+) -> ApiResult<Circle> {
+    let username = owner_username(db, identity, request.user_id)?;
+    let members = request
+        .member_ids
+        .iter()
+        .map(|&id| member_key(db, id))
+        .collect::<ApiResult<Vec<_>>>()?;
+    let keys = CircleKeys::generate();
+    let distributed = if members.is_empty() {
+        Vec::new()
+    } else {
+        keys.distribute(identity, &members)?
+    };
+    let circle = db.create_circle(request, keys.circle_id(), &distributed)?;
+    keystore.set_circle_keys(&username, &keys);
+    keystore.save()?;
+    Ok(circle)
+}
+
+/// Hashes the circle key forward and sends the result to the new member
+/// only, so they can't read earlier posts.
+pub fn add_member(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    request: &CircleMemberRequest,
+) -> ApiResult<Circle> {
+    let (circle, username, mut keys) = owned_circle(db, identity, keystore, request.circle_id)?;
+    let member = member_key(db, request.user_id)?;
+    let epoch = keys.rotate(EpochChange::Advanced);
+    let distributed = keys.distribute(identity, &[member])?;
+    let circle = db.add_circle_member(circle.id, request.user_id, epoch, &distributed)?;
+    keystore.set_circle_keys(&username, &keys);
+    keystore.save()?;
+    Ok(circle)
+}
+
+/// Replaces the circle key and sends it to the remaining members, so the
+/// removed one can't read later posts.
+pub fn remove_member(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    request: &CircleMemberRequest,
+) -> ApiResult<Circle> {
+    let (circle, username, mut keys) = owned_circle(db, identity, keystore, request.circle_id)?;
+    let remaining = db
+        .circle_members(circle.id)?
+        .into_iter()
+        .filter(|member| member.id != request.user_id)
+        .map(|member| member_key(db, member.id))
+        .collect::<ApiResult<Vec<_>>>()?;
+    let epoch = keys.rotate(EpochChange::Reset);
+    let distributed = if remaining.is_empty() {
+        Vec::new()
+    } else {
+        keys.distribute(identity, &remaining)?
+    };
+    let circle = db.remove_circle_member(circle.id, request.user_id, epoch, &distributed)?;
+    keystore.set_circle_keys(&username, &keys);
+    keystore.save()?;
+    Ok(circle)
+}
+
+pub fn encrypt(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    request: &EncryptCircleRequest,
+) -> ApiResult<EncryptedContent> {
+    let (circle, _, keys) = owned_circle(db, identity, keystore, request.circle_id)?;
+    let envelope = keys.seal(identity, request.content.as_bytes())?;
+    let mut recipients = vec![identity.public_key()];
+    recipients.extend(
+        db.circle_members(circle.id)?
+            .into_iter()
+            .filter_map(|member| member.public_key),
+    );
+    Ok(EncryptedContent {
+        content_encrypted: envelope.to_json(),
+        recipients,
+    })
+}
+
+pub fn decrypt(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    content_encrypted: &str,
+) -> ApiResult<String> {
+    let envelope = CircleEnvelope::from_json(content_encrypted)?;
+    let user = db
+        .find_user_by_public_key(&identity.public_key())?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let plaintext = if envelope.owner_key == identity.public_key() {
+        let username = user
+            .username
+            .ok_or_else(|| ApiError::Invalid("Set a username first".to_string()))?;
+        keystore
+            .circle_keys(&username, &envelope.circle_id)
+            .ok_or_else(|| ApiError::NotFound("Circle keys not found".to_string()))??
+            .open(&envelope)?
+    } else {
+        let keys = db.held_circle_keys(user.id, &envelope.owner_key, &envelope.circle_id)?;
+        envelope.open_with(&keys, identity)?
+    };
+    String::from_utf8(plaintext.to_vec())
+        .map_err(|_| ApiError::Invalid("Post content is not UTF-8".to_string()))
+}
+
+/// The circle, its owner's username and keys. The signed-in identity must
+/// own it.
+fn owned_circle(
+    db: &Database,
+    identity: &Identity,
+    keystore: &Keystore,
+    circle_id: i64,
+) -> ApiResult<(Circle, String, CircleKeys)> {
+    let circle = db
+        .find_circle(circle_id)?
+        .ok_or_else(|| ApiError::NotFound("Circle not found".to_string()))?;
+    let username = owner_username(db, identity, circle.user_id)?;
+    let keys = keystore
+        .circle_keys(&username, &circle.public_id)
+        .ok_or_else(|| ApiError::NotFound("Circle keys not found".to_string()))??;
+    if keys.epoch() != circle.epoch {
+        return Err(ApiError::Invalid(
+            "Circle keys are out of date with the circle".to_string(),
+        ));
+    }
+    Ok((circle, username, keys))
+}
+
+fn owner_username(db: &Database, identity: &Identity, user_id: i64) -> ApiResult<String> {
+    let user = db
+        .find_user(user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if user.public_key.as_deref() != Some(identity.public_key().as_str()) {
+        return Err(ApiError::Invalid(
+            "Sign in as the circle's owner".to_string(),
+        ));
+    }
+    user.username
+        .ok_or_else(|| ApiError::Invalid("Set a username first".to_string()))
+}
+
+fn member_key(db: &Database, user_id: i64) -> ApiResult<String> {
+    db.find_user(user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
+        .public_key
+        .ok_or_else(|| ApiError::Invalid("Member has no public key".to_string()))
+}
@@ -0,0 +1,459 @@
+//! Linking another device to the signed-in account. The existing device
+//! listens on the local network and shows a pairing code; the new device
+//! connects with it, gets its own key certified by the account's root key
+//! and imports the account's data. See [`crate::crypto::device_link`].
+
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::archive::{self, ConflictPolicy, ImportReport};
+use super::identity::unix_now;
+use super::UserRequest;
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    archive::ArchiveContents,
+    device::{DeviceCapability, DeviceCertificate, DeviceEvent, DeviceRevocation},
+    device_link::{self, Joiner, LinkBundle, LinkRequest, LinkResponse, PairingOffer},
+    keystore::Keystore,
+    Identity,
+};
+use crate::db::{Database, Device, User};
+
+/// How long a pairing code works, in seconds.
+pub const LINK_TIMEOUT_SECONDS: i64 = 300;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Per read or write once connected; account data can take a while.
+const IO_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// For a connecting device to send its request. Connections are answered one
+/// at a time, so a host that connects and says nothing holds up the real
+/// device only this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LinkOfferRequest {
+    /// Address the new device should connect to. Defaults to this device's
+    /// address on the local network.
+    #[serde(default)]
+    #[ts(optional)]
+    pub address: Option<String>,
+    /// What the new device's key may sign. Defaults to everything.
+    #[serde(default)]
+    #[ts(optional)]
+    pub capabilities: Option<Vec<DeviceCapability>>,
+}
+
+/// A pairing code to show. Not `Debug`: `payload` holds the one-time secret.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LinkOffer {
+    /// What the QR code encodes, for pasting instead of scanning.
+    pub payload: String,
+    pub qr_svg: String,
+    pub address: String,
+    pub port: u16,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub expires_at: i64,
+}
+
+/// Keeps an offered pairing code listening. Dropping it, as locking the
+/// session does, stops the listener, which then drops its copies of the
+/// account's keys.
+#[derive(Debug)]
+pub struct PendingLink(Arc<()>);
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct LinkJoinRequest {
+    /// The scanned or pasted pairing code.
+    pub payload: String,
+    /// How this device appears to the account's other devices.
+    pub device_name: String,
+    /// Defaults to `abort`, as for archive imports.
+    #[serde(default)]
+    #[ts(optional)]
+    pub on_conflict: Option<ConflictPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LinkedDevice {
+    pub certificate: DeviceCertificate,
+    pub report: ImportReport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RevokeDeviceRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub device_key: String,
+    /// Unix seconds from which the device's signatures are rejected, e.g.
+    /// when it was lost. Defaults to now.
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub revoked_at: Option<i64>,
+}
+
+fn app_data_dir(app: &AppHandle) -> ApiResult<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))
+}
+
+/// Starts listening for a new device and returns the code to show it. The
+/// code works once, for [`LINK_TIMEOUT_SECONDS`] or until the session locks
+/// or another code is offered.
+#[tauri::command]
+pub async fn v1_devices_link_offer(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: LinkOfferRequest,
+) -> ApiResult<LinkOffer> {
+    let app_data_dir = app_data_dir(&app)?;
+    let db = state.db()?.clone();
+    let address = request.address.unwrap_or_else(local_address);
+    let capabilities = request
+        .capabilities
+        .unwrap_or_else(|| DeviceCapability::ALL.to_vec());
+    let listener = TcpListener::bind(("0.0.0.0", 0))?;
+    let (offer, pending, _) = state.with_secrets("device linking", |identity, keystore| {
+        offer(
+            &db,
+            &app_data_dir,
+            identity,
+            keystore,
+            listener,
+            &address,
+            &capabilities,
+            unix_now(),
+        )
+    })?;
+    let mut session = state.session();
+    if session.is_locked() {
+        return Err(locked());
+    }
+    session.pending_link = Some(pending);
+    Ok(offer)
+}
+
+/// Links this device to the account whose pairing code was scanned, then
+/// imports the account's data. Needs an unlocked keystore to keep this
+/// device's key in.
+#[tauri::command]
+pub async fn v1_devices_link_join(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: LinkJoinRequest,
+) -> ApiResult<LinkedDevice> {
+    let app_data_dir = app_data_dir(&app)?;
+    let offer = PairingOffer::parse(&request.payload)
+        .map_err(|_| ApiError::Invalid("That isn't a device pairing code".to_string()))?;
+    let device_name = check_device_name(&request.device_name)?;
+    if state.session().keystore().is_none() {
+        return Err(locked());
+    }
+    let (device, bundle) =
+        tauri::async_runtime::spawn_blocking(move || exchange(offer, &device_name))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let db = state.db()?;
+    let mut session = state.session();
+    let keystore = session.keystore_mut().ok_or_else(locked)?;
+    join(
+        db,
+        &app_data_dir,
+        keystore,
+        &device,
+        &bundle,
+        request.on_conflict.unwrap_or_default(),
+    )
+}
+
+/// The user's devices, revoked ones included.
+#[tauri::command]
+pub fn v1_devices_list(state: State<'_, ApiState>, request: UserRequest) -> ApiResult<Vec<Device>> {
+    Ok(state.db()?.devices_for_user(request.user_id)?)
+}
+
+/// Revokes one of the user's devices, signed by the account's key or this
+/// device's, and tells the user's peers.
+#[tauri::command]
+pub fn v1_devices_revoke(
+    state: State<'_, ApiState>,
+    request: RevokeDeviceRequest,
+) -> ApiResult<Device> {
+    let db = state.db()?;
+    let user = db
+        .find_user(request.user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let now = unix_now();
+    state.with_signer(&user, |signer| {
+        revoke(
+            db,
+            &user,
+            signer,
+            &request.device_key,
+            request.revoked_at.unwrap_or(now),
+            now,
+        )
+    })?
+}
+
+fn locked() -> ApiError {
+    ApiError::Locked("Unlock the keystore to link this device".to_string())
+}
+
+fn check_device_name(name: &str) -> ApiResult<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 64 || name.contains('\n') {
+        return Err(ApiError::Invalid(
+            "Device name must be 1 to 64 characters on one line".to_string(),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// This device's address on the local network, or loopback without one.
+fn local_address() -> String {
+    // Connecting a UDP socket sends nothing; it only picks the interface
+    UdpSocket::bind(("0.0.0.0", 0))
+        .and_then(|socket| {
+            socket.connect(("192.0.2.1", 9))?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+/// Gathers the account's data for a new device and answers the first
+/// connection on `listener` that presents the returned code. The thread
+/// records and broadcasts the certificate it issued and yields it, or
+/// `None` once the code expires or the [`PendingLink`] is dropped.
+#[allow(clippy::too_many_arguments)]
+pub fn offer(
+    db: &Database,
+    app_data_dir: &Path,
+    identity: &Identity,
+    keystore: &Keystore,
+    listener: TcpListener,
+    address: &str,
+    capabilities: &[DeviceCapability],
+    now: i64,
+) -> ApiResult<(
+    LinkOffer,
+    PendingLink,
+    JoinHandle<Option<DeviceCertificate>>,
+)> {
+    let (manifest, _) = archive::export(db, app_data_dir, identity, keystore, now)?;
+    let user_id = db
+        .find_user_by_public_key(&identity.public_key())?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
+        .id;
+    let username = manifest.account.username.clone().unwrap_or_default();
+    let contents = ArchiveContents {
+        manifest: serde_json::to_vec(&manifest)
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+            .into(),
+        secrets: keystore.linked_device_entries(&username),
+    };
+    let pairing = PairingOffer::new(
+        &identity.public_key(),
+        address,
+        listener.local_addr()?.port(),
+        now + LINK_TIMEOUT_SECONDS,
+    );
+    let offer = LinkOffer {
+        payload: pairing.qr_payload(),
+        qr_svg: pairing.qr_svg(),
+        address: pairing.address.clone(),
+        port: pairing.port,
+        expires_at: pairing.expires_at,
+    };
+    // A copy to sign with off the session lock
+    let root = Identity::from_seed(identity.seed().expose());
+    let capabilities = capabilities.to_vec();
+    let db = db.clone();
+    let pending = PendingLink(Arc::new(()));
+    let cancelled = Arc::downgrade(&pending.0);
+    let handle = thread::spawn(move || {
+        let certificate = serve(
+            listener,
+            &pairing,
+            &cancelled,
+            &root,
+            &capabilities,
+            &contents,
+        )?;
+        let event = DeviceEvent::Certificate(certificate.clone());
+        if let Err(e) = db
+            .record_device_event(user_id, &event)
+            .and_then(|_| db.broadcast_device_event(user_id, &event))
+        {
+            println!("Couldn't record the linked device's certificate: {}", e);
+        }
+        Some(certificate)
+    });
+    Ok((offer, pending, handle))
+}
+
+fn serve(
+    listener: TcpListener,
+    pairing: &PairingOffer,
+    pending: &Weak<()>,
+    root: &Identity,
+    capabilities: &[DeviceCapability],
+    contents: &ArchiveContents,
+) -> Option<DeviceCertificate> {
+    if let Err(e) = listener.set_nonblocking(true) {
+        println!("Device link listener failed: {}", e);
+        return None;
+    }
+    while !pairing.is_expired(unix_now()) {
+        if pending.strong_count() == 0 {
+            println!("Device linking cancelled");
+            return None;
+        }
+        match listener.accept() {
+            Ok((mut stream, peer)) => {
+                match answer(&mut stream, pairing, root, capabilities, contents) {
+                    Ok(certificate) => {
+                        println!("Linked device {} from {}", certificate.device_name, peer);
+                        return Some(certificate);
+                    }
+                    Err(e) => println!("Rejected device link attempt from {}: {}", peer, e),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(200))
+            }
+            Err(e) => {
+                println!("Device link listener failed: {}", e);
+                return None;
+            }
+        }
+    }
+    println!("Device pairing code expired");
+    None
+}
+
+fn answer(
+    stream: &mut TcpStream,
+    pairing: &PairingOffer,
+    root: &Identity,
+    capabilities: &[DeviceCapability],
+    contents: &ArchiveContents,
+) -> ApiResult<DeviceCertificate> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let request: LinkRequest = device_link::read_message(stream)?;
+    let (response, certificate) =
+        device_link::respond(pairing, root, &request, capabilities, contents, unix_now())?;
+    device_link::write_message(stream, &response)?;
+    Ok(certificate)
+}
+
+/// The new device's side of the exchange. Returns its new key and what the
+/// existing device sent.
+pub fn exchange(pairing: PairingOffer, device_name: &str) -> ApiResult<(Identity, LinkBundle)> {
+    if pairing.is_expired(unix_now()) {
+        return Err(ApiError::Invalid(
+            "That pairing code has expired".to_string(),
+        ));
+    }
+    let unreachable =
+        |e: io::Error| ApiError::Unavailable(format!("Couldn't reach the other device: {}", e));
+    let address = (pairing.address.as_str(), pairing.port)
+        .to_socket_addrs()
+        .map_err(unreachable)?
+        .next()
+        .ok_or_else(|| ApiError::Invalid("The pairing code has no address".to_string()))?;
+    let joiner = Joiner::new(pairing, device_name)?;
+
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(unreachable)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    device_link::write_message(&mut stream, joiner.request())?;
+    let response: LinkResponse = device_link::read_message(&mut stream).map_err(|e| {
+        ApiError::Unavailable(format!("The other device didn't link this one: {}", e))
+    })?;
+    joiner.finish(&response).map_err(|e| {
+        ApiError::Invalid(format!(
+            "The other device's answer doesn't match the pairing code: {}",
+            e
+        ))
+    })
+}
+
+/// Imports what the existing device sent and, once imported, records the
+/// certificate and keeps the device key with it in the keystore.
+pub fn join(
+    db: &Database,
+    app_data_dir: &Path,
+    keystore: &mut Keystore,
+    device: &Identity,
+    bundle: &LinkBundle,
+    policy: ConflictPolicy,
+) -> ApiResult<LinkedDevice> {
+    let manifest = archive::parse_manifest(&bundle.contents.manifest)?;
+    if manifest.account.public_key != bundle.certificate.root_key {
+        return Err(ApiError::Invalid(
+            "The account data isn't for the account that linked this device".to_string(),
+        ));
+    }
+    let report = archive::import(
+        db,
+        app_data_dir,
+        keystore,
+        &manifest,
+        &bundle.contents.secrets,
+        policy,
+        true,
+    )?;
+    if report.imported {
+        let user = db
+            .find_user_by_username(&report.archive.username)?
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+        db.record_device_certificate(user.id, &bundle.certificate)?;
+        keystore.set_device(&report.archive.username, device, &bundle.certificate);
+        keystore.save()?;
+    }
+    Ok(LinkedDevice {
+        certificate: bundle.certificate.clone(),
+        report,
+    })
+}
+
+/// Revokes `device_key` as one of `user`'s devices from `revoked_at` on,
+/// signed by `signer`, and queues the revocation to the user's peers.
+pub fn revoke(
+    db: &Database,
+    user: &User,
+    signer: &Identity,
+    device_key: &str,
+    revoked_at: i64,
+    now: i64,
+) -> ApiResult<Device> {
+    let root_key = db.key_history(user.id)?.current().to_string();
+    let revocation = DeviceRevocation::sign(signer, &root_key, device_key, revoked_at, now);
+    let event = DeviceEvent::Revocation(revocation);
+    let device = db.record_device_event(user.id, &event)?;
+    db.broadcast_device_event(user.id, &event)?;
+    Ok(device)
+}
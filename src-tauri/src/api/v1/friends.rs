@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ts_rs::TS;
+
+use super::UserRequest;
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::safety_number::SafetyNumber;
+use crate::db::{Database, FriendVerification, Friendship, User};
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SendFriendRequest {
+    #[ts(type = "number")]
+    pub requester_id: i64,
+    #[ts(type = "number")]
+    pub addressee_id: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum FriendRequestResponse {
+    Accepted,
+    Declined,
+    Blocked,
+}
+
+impl FriendRequestResponse {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FriendRequestResponse::Accepted => "accepted",
+            FriendRequestResponse::Declined => "declined",
+            FriendRequestResponse::Blocked => "blocked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RespondToFriendRequest {
+    #[ts(type = "number")]
+    pub friendship_id: i64,
+    pub status: FriendRequestResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FriendKeyRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    #[ts(type = "number")]
+    pub friend_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SafetyNumberResponse {
+    /// Sixty digits in groups of five, the same on both devices.
+    pub number: String,
+    pub qr_payload: String,
+    /// The payload as an SVG QR code for the other device to scan.
+    pub qr_svg: String,
+    pub verification: FriendVerification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyFriendRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    #[ts(type = "number")]
+    pub friend_id: i64,
+    /// The scanned QR payload, or the digits read from the friend's screen.
+    pub scanned: String,
+}
+
+/// Accepted friends of the user.
+#[tauri::command]
+pub fn v1_friends_list(state: State<'_, ApiState>, request: UserRequest) -> ApiResult<Vec<User>> {
+    Ok(state.db()?.friends_of(request.user_id)?)
+}
+
+/// Every friendship the user is part of, in any status.
+#[tauri::command]
+pub fn v1_friends_requests(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<Vec<Friendship>> {
+    Ok(state.db()?.friendships_for_user(request.user_id)?)
+}
+
+#[tauri::command]
+pub fn v1_friends_send(
+    state: State<'_, ApiState>,
+    request: SendFriendRequest,
+) -> ApiResult<Friendship> {
+    Ok(state
+        .db()?
+        .request_friendship(request.requester_id, request.addressee_id)?)
+}
+
+#[tauri::command]
+pub fn v1_friends_respond(
+    state: State<'_, ApiState>,
+    request: RespondToFriendRequest,
+) -> ApiResult<Friendship> {
+    Ok(state
+        .db()?
+        .respond_to_friendship(request.friendship_id, request.status.as_str())?)
+}
+
+/// The safety number for the user and a friend, and whether the friend's
+/// key has been verified.
+#[tauri::command]
+pub fn v1_friends_safety_number(
+    state: State<'_, ApiState>,
+    request: FriendKeyRequest,
+) -> ApiResult<SafetyNumberResponse> {
+    safety_number(state.db()?, request.user_id, request.friend_id)
+}
+
+/// Marks the friend's current key verified if `scanned` matches the safety
+/// number.
+#[tauri::command]
+pub fn v1_friends_verify(
+    state: State<'_, ApiState>,
+    request: VerifyFriendRequest,
+) -> ApiResult<FriendVerification> {
+    verify(state.db()?, &request)
+}
+
+/// Verified friends whose key has changed since. Anything here should be
+/// shown prominently until the user verifies again.
+#[tauri::command]
+pub fn v1_friends_key_warnings(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<Vec<FriendVerification>> {
+    Ok(state.db()?.key_change_warnings(request.user_id)?)
+}
+
+pub fn safety_number(
+    db: &Database,
+    user_id: i64,
+    friend_id: i64,
+) -> ApiResult<SafetyNumberResponse> {
+    let (number, _) = pair_safety_number(db, user_id, friend_id)?;
+    Ok(SafetyNumberResponse {
+        number: number.to_string(),
+        qr_payload: number.qr_payload(),
+        qr_svg: number.qr_svg(),
+        verification: db.friend_verification(user_id, friend_id)?,
+    })
+}
+
+pub fn verify(db: &Database, request: &VerifyFriendRequest) -> ApiResult<FriendVerification> {
+    let (number, friend_key) = pair_safety_number(db, request.user_id, request.friend_id)?;
+    if !number.matches(&request.scanned) {
+        return Err(ApiError::Invalid(
+            "Safety numbers don't match. Your friend may not be using the key you have for them."
+                .to_string(),
+        ));
+    }
+    Ok(db.verify_friend_key(request.user_id, request.friend_id, &friend_key)?)
+}
+
+/// The pair's safety number and the friend key it was computed from.
+fn pair_safety_number(
+    db: &Database,
+    user_id: i64,
+    friend_id: i64,
+) -> ApiResult<(SafetyNumber, String)> {
+    let public_key = |id: i64| -> ApiResult<String> {
+        db.find_user(id)?
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
+            .public_key
+            .ok_or_else(|| ApiError::Invalid(format!("User {} has no public key", id)))
+    };
+    let friend_key = public_key(friend_id)?;
+    Ok((
+        SafetyNumber::new(&public_key(user_id)?, &friend_key)?,
+        friend_key,
+    ))
+}
@@ -0,0 +1,318 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    self,
+    account::{AccountRecord, LegacyMigration, ACCOUNTS_DIR},
+    decode_base64, identity,
+    kdf::KdfDescriptor,
+    rotation::{KeyEvent, KeyRotation},
+    secret::Secret,
+    Identity,
+};
+use crate::db::{Database, NewUser, User};
+use crate::settings::Settings;
+
+/// Looks a user up by exactly one of `id`, `public_key` or `username`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FindIdentityRequest {
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub id: Option<i64>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub username: Option<String>,
+}
+
+#[tauri::command]
+pub fn v1_identity_list(state: State<'_, ApiState>) -> ApiResult<Vec<User>> {
+    Ok(state.db()?.list_users()?)
+}
+
+#[tauri::command]
+pub fn v1_identity_register(state: State<'_, ApiState>, request: NewUser) -> ApiResult<User> {
+    Ok(state.db()?.register_user(&request)?)
+}
+
+#[tauri::command]
+pub fn v1_identity_find(
+    state: State<'_, ApiState>,
+    request: FindIdentityRequest,
+) -> ApiResult<Option<User>> {
+    let db = state.db()?;
+    match request {
+        FindIdentityRequest {
+            id: Some(id),
+            public_key: None,
+            username: None,
+        } => Ok(db.find_user(id)?),
+        FindIdentityRequest {
+            id: None,
+            public_key: Some(public_key),
+            username: None,
+        } => Ok(db.find_user_by_public_key(&public_key)?),
+        FindIdentityRequest {
+            id: None,
+            public_key: None,
+            username: Some(username),
+        } => Ok(db.find_user_by_username(&username)?),
+        _ => Err(ApiError::Invalid(
+            "Exactly one of id, public_key or username is required".to_string(),
+        )),
+    }
+}
+
+/// Not `Debug` or `Serialize`, so the password can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct SignInRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PublicKeyResponse {
+    /// Base64 Ed25519 public key, as stored in `users.public_key`.
+    pub public_key: String,
+}
+
+/// How a `message` string is turned into the bytes that are signed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum MessageEncoding {
+    /// The UTF-8 bytes of the string, as Rails signs `content_encrypted`.
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl MessageEncoding {
+    fn bytes(self, message: &str) -> ApiResult<Vec<u8>> {
+        match self {
+            MessageEncoding::Utf8 => Ok(message.as_bytes().to_vec()),
+            MessageEncoding::Base64 => Ok(decode_base64(message, "message")?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignRequest {
+    pub message: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub encoding: Option<MessageEncoding>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignResponse {
+    /// Base64 detached signature.
+    pub signature: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyRequest {
+    pub public_key: String,
+    pub message: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub encoding: Option<MessageEncoding>,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignInResponse {
+    pub public_key: String,
+    /// KDF the account uses from now on, e.g. `argon2id_v1`.
+    pub kdf: String,
+    /// The account was moved off the Rails PBKDF2 derivation by this sign-in.
+    pub migrated: bool,
+    /// Set when migration gave the account a new key; friends need it to
+    /// trust the new key.
+    #[ts(optional = nullable)]
+    pub rotation: Option<KeyRotation>,
+}
+
+/// Derives the signing key from the credentials and keeps it in native
+/// memory, and in the keystore if it is unlocked. Accounts still on the Rails PBKDF2 derivation are migrated to
+/// Argon2id on the way; see [`sign_in`].
+#[tauri::command]
+pub async fn v1_identity_sign_in(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: SignInRequest,
+) -> ApiResult<SignInResponse> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))?;
+    let db = state.db()?.clone();
+    let username = request.username.clone();
+    let password = Secret::new(std::mem::take(&mut request.password));
+
+    let (identity, response) = tauri::async_runtime::spawn_blocking(move || {
+        sign_in(
+            &db,
+            &app_data_dir.join(ACCOUNTS_DIR),
+            &username,
+            password.expose(),
+            &Settings::load(&app_data_dir),
+            unix_now(),
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    if let Some(keystore) = session.keystore_mut() {
+        keystore.set_identity(&request.username, &identity);
+        keystore.save()?;
+    }
+    session.set_identity(Some(identity));
+    Ok(response)
+}
+
+/// Signs in against the account record in `accounts_dir`, creating or
+/// migrating it as needed:
+///
+/// - a record matching the registered key is unlocked with the password;
+/// - a registered user without one must match the Rails PBKDF2 key and is
+///   migrated to Argon2id, keeping the key unless `rotate_legacy_keys` is set;
+/// - an unregistered username gets a fresh Argon2id account to register with.
+pub fn sign_in(
+    db: &Database,
+    accounts_dir: &Path,
+    username: &str,
+    password: &str,
+    settings: &Settings,
+    now: i64,
+) -> ApiResult<(Identity, SignInResponse)> {
+    let user = db.find_user_by_username(username)?;
+    let registered_key = user.as_ref().and_then(|user| user.public_key.clone());
+
+    let record = AccountRecord::load(accounts_dir, username)?
+        .filter(|record| user.is_none() || registered_key.as_deref() == Some(&record.public_key));
+    if let Some(record) = record {
+        let identity = record.unlock(password)?;
+        let response = SignInResponse {
+            public_key: record.public_key,
+            kdf: record.kdf.name().to_string(),
+            migrated: false,
+            rotation: None,
+        };
+        return Ok((identity, response));
+    }
+
+    let params = settings.kdf.unwrap_or_default();
+    let (record, identity, migrated) = match &user {
+        Some(user) => {
+            let legacy = Identity::from_legacy_credentials(username, password);
+            if registered_key.as_deref() != Some(legacy.public_key().as_str()) {
+                return Err(crypto::Error::WrongPassword.into());
+            }
+            let migration = if settings.rotate_legacy_keys {
+                LegacyMigration::Rotate
+            } else {
+                LegacyMigration::KeepKey
+            };
+            let (record, identity) =
+                AccountRecord::migrate_legacy(&legacy, username, password, params, migration, now)?;
+            println!(
+                "Migrated user {} from {} to {}",
+                user.id,
+                KdfDescriptor::legacy().name(),
+                record.kdf.name()
+            );
+            (record, identity, true)
+        }
+        None => {
+            let (record, identity) = AccountRecord::create(username, password, params)?;
+            (record, identity, false)
+        }
+    };
+
+    // Save first: if the key changed, the record is the only way back in
+    record.save(accounts_dir)?;
+    let rotation = record.rotations.last().cloned();
+    if let (Some(user), Some(rotation)) = (&user, &rotation) {
+        let event = KeyEvent::Rotation(rotation.clone());
+        db.record_key_event(user.id, &event)?;
+        db.broadcast_key_event(user.id, &event)?;
+    }
+
+    let response = SignInResponse {
+        public_key: record.public_key.clone(),
+        kdf: record.kdf.name().to_string(),
+        migrated,
+        rotation,
+    };
+    Ok((identity, response))
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Drops the signing key from memory.
+#[tauri::command]
+pub fn v1_identity_sign_out(state: State<'_, ApiState>) {
+    let mut session = state.session();
+    session.set_identity(None);
+    session.pending_backup = None;
+}
+
+#[tauri::command]
+pub fn v1_identity_public_key(state: State<'_, ApiState>) -> ApiResult<PublicKeyResponse> {
+    let public_key = state.with_identity(Identity::public_key)?;
+    Ok(PublicKeyResponse { public_key })
+}
+
+#[tauri::command]
+pub fn v1_identity_sign(
+    state: State<'_, ApiState>,
+    request: SignRequest,
+) -> ApiResult<SignResponse> {
+    let message = request
+        .encoding
+        .unwrap_or_default()
+        .bytes(&request.message)?;
+    state.with_identity(|identity| SignResponse {
+        signature: identity.sign_base64(&message),
+        public_key: identity.public_key(),
+    })
+}
+
+/// `true` if the signature is valid; malformed keys or signatures are an
+/// `invalid` error rather than `false`.
+#[tauri::command]
+pub fn v1_identity_verify(request: VerifyRequest) -> ApiResult<bool> {
+    let message = request
+        .encoding
+        .unwrap_or_default()
+        .bytes(&request.message)?;
+    match identity::verify(&request.public_key, &message, &request.signature) {
+        Ok(()) => Ok(true),
+        Err(crate::crypto::Error::BadSignature) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::{identity::unix_now, UserRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    account::{AccountRecord, ACCOUNTS_DIR},
+    rotation::{KeyEvent, KeyHistory},
+    secret::Secret,
+    Identity,
+};
+use crate::db::{Database, User};
+use crate::settings::Settings;
+
+/// Not `Debug` or `Serialize`, so the password can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct RotateKeyRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct RevokeKeyRequest {
+    pub username: String,
+    pub password: String,
+    /// Unix seconds from which the current key is untrusted, e.g. when the
+    /// device holding it was lost. Defaults to now.
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub revoked_at: Option<i64>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeyChangeResponse {
+    pub public_key: String,
+    /// Already recorded and queued to peers.
+    pub event: KeyEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ApplyKeyEventRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub event: KeyEvent,
+}
+
+/// How [`change_key`] retires the current key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange {
+    Rotate,
+    Revoke { revoked_at: i64, reason: String },
+}
+
+/// Moves the account onto a new key signed over by the current one, and
+/// signs in with it.
+#[tauri::command]
+pub async fn v1_keys_rotate(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: RotateKeyRequest,
+) -> ApiResult<KeyChangeResponse> {
+    let password = Secret::new(std::mem::take(&mut request.password));
+    apply_change(app, state, request.username, password, KeyChange::Rotate).await
+}
+
+/// Revokes the current key from `revoked_at` and moves onto a new one. For
+/// a lost key, restore it from the recovery phrase or friends first.
+#[tauri::command]
+pub async fn v1_keys_revoke(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: RevokeKeyRequest,
+) -> ApiResult<KeyChangeResponse> {
+    let password = Secret::new(std::mem::take(&mut request.password));
+    let change = KeyChange::Revoke {
+        revoked_at: request.revoked_at.unwrap_or_else(unix_now),
+        reason: request.reason.unwrap_or_else(|| "compromised".to_string()),
+    };
+    apply_change(app, state, request.username, password, change).await
+}
+
+/// The user's keys over time, as their verified key events describe.
+#[tauri::command]
+pub fn v1_keys_history(state: State<'_, ApiState>, request: UserRequest) -> ApiResult<KeyHistory> {
+    Ok(state.db()?.key_history(request.user_id)?)
+}
+
+/// Applies a friend's rotation or revocation received outside sync.
+#[tauri::command]
+pub fn v1_keys_apply(state: State<'_, ApiState>, request: ApplyKeyEventRequest) -> ApiResult<User> {
+    Ok(state
+        .db()?
+        .record_key_event(request.user_id, &request.event)?)
+}
+
+async fn apply_change(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    username: String,
+    password: Secret<String>,
+    change: KeyChange,
+) -> ApiResult<KeyChangeResponse> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))?;
+    let db = state.db()?.clone();
+    let account = username.clone();
+    let (identity, response) = tauri::async_runtime::spawn_blocking(move || {
+        change_key(
+            &db,
+            &app_data_dir.join(ACCOUNTS_DIR),
+            &account,
+            password.expose(),
+            change,
+            &Settings::load(&app_data_dir),
+            unix_now(),
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    if let Some(keystore) = session.keystore_mut() {
+        keystore.set_identity(&username, &identity);
+        keystore.save()?;
+    }
+    session.set_identity(Some(identity));
+    Ok(response)
+}
+
+/// Retires `username`'s key after checking the password: records the signed
+/// event, points the registered user at the new key and queues the event to
+/// their peers.
+pub fn change_key(
+    db: &Database,
+    accounts_dir: &Path,
+    username: &str,
+    password: &str,
+    change: KeyChange,
+    settings: &Settings,
+    now: i64,
+) -> ApiResult<(Identity, KeyChangeResponse)> {
+    let record = AccountRecord::load(accounts_dir, username)?.ok_or_else(|| {
+        ApiError::NotFound("Sign in with your password on this device first".to_string())
+    })?;
+    let user = db
+        .find_user_by_username(username)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if user.public_key.as_deref() != Some(record.public_key.as_str()) {
+        return Err(ApiError::Invalid(
+            "This device's key is not the account's current key".to_string(),
+        ));
+    }
+    let params = settings.kdf.unwrap_or_default();
+    let (record, identity, event) = match change {
+        KeyChange::Rotate => {
+            let (record, identity, rotation) = record.rotate(password, params, "manual", now)?;
+            (record, identity, KeyEvent::Rotation(rotation))
+        }
+        KeyChange::Revoke { revoked_at, reason } => {
+            let (record, identity, revocation) =
+                record.revoke(password, params, revoked_at, &reason, now)?;
+            (record, identity, KeyEvent::Revocation(revocation))
+        }
+    };
+
+    // Record the event before saving: if it's rejected, the old key stays
+    db.record_key_event(user.id, &event)?;
+    record.save(accounts_dir)?;
+    db.broadcast_key_event(user.id, &event)?;
+    Ok((
+        identity,
+        KeyChangeResponse {
+            public_key: record.public_key,
+            event,
+        },
+    ))
+}
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use crate::api::{ApiError, ApiResult, ApiState, Session};
+use crate::backend;
+use crate::crypto::{keystore::Keystore, secret::Secret};
+use crate::settings::Settings;
+
+/// Not `Debug` or `Serialize`, so the passphrase can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct KeystorePassphraseRequest {
+    pub passphrase: String,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct UnlockKeystoreRequest {
+    pub passphrase: String,
+    /// Signs in as this user with the key stored for them.
+    #[serde(default)]
+    #[ts(optional)]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AutoLockRequest {
+    /// Idle seconds before locking; `0` disables auto-lock and `null`
+    /// restores the default.
+    #[ts(optional = nullable)]
+    pub seconds: Option<u32>,
+}
+
+/// What the webview may know about the keystore. Never includes key bytes.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeystoreStatus {
+    pub exists: bool,
+    pub unlocked: bool,
+    /// Public key of the signed-in identity, if any.
+    #[ts(optional = nullable)]
+    pub public_key: Option<String>,
+    /// Usernames with a key in the keystore; empty while locked.
+    pub usernames: Vec<String>,
+    /// `null` when auto-lock is off.
+    #[ts(optional = nullable)]
+    pub auto_lock_seconds: Option<u64>,
+}
+
+impl KeystoreStatus {
+    pub fn new(path: &Path, session: &Session) -> KeystoreStatus {
+        KeystoreStatus {
+            exists: path.exists(),
+            unlocked: session.keystore().is_some(),
+            public_key: session.identity().map(|identity| identity.public_key()),
+            usernames: session
+                .keystore()
+                .map(Keystore::usernames)
+                .unwrap_or_default(),
+            auto_lock_seconds: session.auto_lock.map(|timeout| timeout.as_secs()),
+        }
+    }
+}
+
+fn app_data_dir(app: &AppHandle) -> ApiResult<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))
+}
+
+#[tauri::command]
+pub fn v1_keystore_status(app: AppHandle, state: State<'_, ApiState>) -> ApiResult<KeystoreStatus> {
+    let path = Keystore::path(&app_data_dir(&app)?);
+    Ok(KeystoreStatus::new(&path, &state.session()))
+}
+
+/// Creates an empty keystore and leaves it unlocked. The signed-in identity,
+/// if any, is stored in it straight away. An encrypted database gets its key
+/// here and opens.
+#[tauri::command]
+pub async fn v1_keystore_create(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: KeystorePassphraseRequest,
+) -> ApiResult<KeystoreStatus> {
+    let app_data_dir = app_data_dir(&app)?;
+    let path = Keystore::path(&app_data_dir);
+    let passphrase = Secret::new(std::mem::take(&mut request.passphrase));
+    if passphrase.expose().is_empty() {
+        return Err(ApiError::Invalid("Passphrase can't be blank".to_string()));
+    }
+
+    let keystore_path = path.clone();
+    let keystore = tauri::async_runtime::spawn_blocking(move || {
+        let params = Settings::load(&app_data_dir).kdf.unwrap_or_default();
+        Keystore::create(&keystore_path, passphrase.expose(), params)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    session.set_keystore(keystore);
+    if let Some(keystore) = session.keystore_mut() {
+        backend::open_encrypted_database(&app, keystore)?;
+    }
+    Ok(KeystoreStatus::new(&path, &session))
+}
+
+/// Decrypts the keystore into native memory. With `username`, also signs in
+/// with that user's stored key, so no password is needed. Opens the database
+/// if it is encrypted and not open yet.
+#[tauri::command]
+pub async fn v1_keystore_unlock(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: UnlockKeystoreRequest,
+) -> ApiResult<KeystoreStatus> {
+    let path = Keystore::path(&app_data_dir(&app)?);
+    if !path.exists() {
+        return Err(ApiError::NotFound("No keystore on this device".to_string()));
+    }
+    let passphrase = Secret::new(std::mem::take(&mut request.passphrase));
+
+    let keystore_path = path.clone();
+    let keystore = tauri::async_runtime::spawn_blocking(move || {
+        Keystore::unlock(&keystore_path, passphrase.expose())
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let identity = match &request.username {
+        Some(username) => match keystore.identity(username) {
+            Some(identity) => Some(identity?),
+            None => {
+                return Err(ApiError::NotFound(format!(
+                    "No key for {} in the keystore",
+                    username
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let mut session = state.session();
+    session.set_keystore(keystore);
+    if identity.is_some() {
+        session.set_identity(identity);
+    }
+    if let Some(keystore) = session.keystore_mut() {
+        backend::open_encrypted_database(&app, keystore)?;
+    }
+    Ok(KeystoreStatus::new(&path, &session))
+}
+
+/// Drops the keystore contents and the signed-in identity from memory.
+#[tauri::command]
+pub fn v1_keystore_lock(app: AppHandle, state: State<'_, ApiState>) -> ApiResult<KeystoreStatus> {
+    let path = Keystore::path(&app_data_dir(&app)?);
+    let mut session = state.session();
+    session.lock();
+    Ok(KeystoreStatus::new(&path, &session))
+}
+
+/// Sets how long the session may sit idle before it locks itself. Saved to
+/// the settings file and applied immediately.
+#[tauri::command]
+pub fn v1_keystore_set_auto_lock(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: AutoLockRequest,
+) -> ApiResult<KeystoreStatus> {
+    let app_data_dir = app_data_dir(&app)?;
+    let mut settings = Settings::load(&app_data_dir);
+    settings.auto_lock_seconds = request.seconds;
+    settings.save(&app_data_dir)?;
+
+    let mut session = state.session();
+    session.auto_lock = settings.auto_lock();
+    Ok(KeystoreStatus::new(
+        &Keystore::path(&app_data_dir),
+        &session,
+    ))
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ts_rs::TS;
+
+use super::{identity::unix_now, IdRequest, UserRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    keystore::Keystore,
+    ratchet::{PrekeySecrets, RatchetMessage, RatchetSession},
+    Identity,
+};
+use crate::db::{Database, Message, NewMessage};
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ConversationRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    #[ts(type = "number")]
+    pub with_user_id: i64,
+}
+
+/// Not `Debug`, so the text can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct SecureMessageRequest {
+    #[ts(type = "number")]
+    pub sender_id: i64,
+    #[ts(type = "number")]
+    pub recipient_id: i64,
+    pub content: String,
+}
+
+/// A forward-secret message with its text. Not `Debug`, for the same reason.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OpenedMessage {
+    pub message: Message,
+    pub content: String,
+}
+
+/// Messages between the two users in either direction, oldest first.
+#[tauri::command]
+pub fn v1_messages_conversation(
+    state: State<'_, ApiState>,
+    request: ConversationRequest,
+) -> ApiResult<Vec<Message>> {
+    Ok(state
+        .db()?
+        .messages_between(request.user_id, request.with_user_id)?)
+}
+
+#[tauri::command]
+pub fn v1_messages_send(state: State<'_, ApiState>, request: NewMessage) -> ApiResult<Message> {
+    Ok(state.db()?.send_message(&request)?)
+}
+
+#[tauri::command]
+pub fn v1_messages_mark_read(state: State<'_, ApiState>, request: IdRequest) -> ApiResult<()> {
+    Ok(state.db()?.mark_message_read(request.id)?)
+}
+
+/// Signs a fresh set of prekeys and queues one bundle to each peer, so
+/// friends can start secure conversations. Returns how many were queued.
+#[tauri::command]
+pub fn v1_messages_publish_prekeys(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<u32> {
+    let db = state.db()?;
+    state.with_secrets("secure messages", |identity, keystore| {
+        publish_prekeys(db, identity, keystore, request.user_id, unix_now())
+    })
+}
+
+/// Sends a message under the Double Ratchet session with the recipient,
+/// starting one from their prekeys if needed.
+#[tauri::command]
+pub fn v1_messages_send_secure(
+    state: State<'_, ApiState>,
+    request: SecureMessageRequest,
+) -> ApiResult<Message> {
+    let db = state.db()?;
+    state.with_secrets("secure messages", |identity, keystore| {
+        send_secure(db, identity, keystore, &request)
+    })
+}
+
+/// The text of a secure message. Received messages are decrypted once and
+/// kept in the keystore, since their keys are deleted afterwards.
+#[tauri::command]
+pub fn v1_messages_open(
+    state: State<'_, ApiState>,
+    request: IdRequest,
+) -> ApiResult<OpenedMessage> {
+    let db = state.db()?;
+    state.with_secrets("secure messages", |identity, keystore| {
+        open_secure(db, identity, keystore, request.id)
+    })
+}
+
+/// Refreshes `user_id`'s signed prekey and sends each peer a bundle with its
+/// own one-time prekey. The secrets are saved before anything is queued.
+pub fn publish_prekeys(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    user_id: i64,
+    now: i64,
+) -> ApiResult<u32> {
+    let username = local_username(db, identity, user_id)?;
+    let mut prekeys = match keystore.prekeys(&username) {
+        Some(prekeys) => {
+            let mut prekeys = prekeys?;
+            prekeys.refresh();
+            prekeys
+        }
+        None => PrekeySecrets::generate(),
+    };
+    let bundles = prekeys.issue(identity, db.peers_for_user(user_id)?.len(), now);
+    keystore.set_prekeys(&username, &prekeys);
+    keystore.save()?;
+    Ok(db.deliver_prekey_bundles(user_id, bundles)? as u32)
+}
+
+pub fn send_secure(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    request: &SecureMessageRequest,
+) -> ApiResult<Message> {
+    let username = local_username(db, identity, request.sender_id)?;
+    let length = request.content.chars().count();
+    if length == 0 || length > 2000 {
+        return Err(ApiError::Invalid(
+            "Content must be between 1 and 2000 characters".to_string(),
+        ));
+    }
+    let recipient_key = db
+        .find_user(request.recipient_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
+        .public_key
+        .ok_or_else(|| ApiError::Invalid("Recipient has no public key".to_string()))?;
+
+    let mut session = match keystore.ratchet_session(&username, &recipient_key) {
+        Some(session) => session?,
+        None => {
+            let bundle = db
+                .take_prekey_bundle(request.sender_id, &recipient_key)?
+                .ok_or_else(|| {
+                    ApiError::NotFound(
+                        "No prekeys from this user yet. They need to publish prekeys first."
+                            .to_string(),
+                    )
+                })?;
+            RatchetSession::initiate(identity, &bundle)?
+        }
+    };
+    let envelope = session.encrypt(request.content.as_bytes())?;
+    let encrypted_content =
+        serde_json::to_string(&envelope).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let message =
+        db.send_encrypted_message(request.sender_id, request.recipient_id, &encrypted_content)?;
+
+    keystore.set_ratchet_session(&username, &session);
+    keystore.set_message_text(&username, message.id, request.content.as_bytes());
+    keystore.save()?;
+    Ok(message)
+}
+
+/// Returns the kept text of message `id`, or decrypts it if this identity
+/// is the recipient and hasn't read it yet.
+pub fn open_secure(
+    db: &Database,
+    identity: &Identity,
+    keystore: &mut Keystore,
+    id: i64,
+) -> ApiResult<OpenedMessage> {
+    let message = db
+        .find_message(id)?
+        .ok_or_else(|| ApiError::NotFound("Message not found".to_string()))?;
+    let user = db
+        .find_user_by_public_key(&identity.public_key())?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if user.id != message.sender_id && user.id != message.recipient_id {
+        return Err(ApiError::NotFound("Message not found".to_string()));
+    }
+    let username = user
+        .username
+        .ok_or_else(|| ApiError::Invalid("Set a username first".to_string()))?;
+    if let Some(text) = keystore.message_text(&username, id) {
+        let content = String::from_utf8_lossy(text).into_owned();
+        return Ok(OpenedMessage { message, content });
+    }
+    if user.id != message.recipient_id {
+        return Err(ApiError::NotFound(
+            "This message's text isn't kept on this device".to_string(),
+        ));
+    }
+
+    let envelope: RatchetMessage = message
+        .encrypted_content
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .ok_or_else(|| ApiError::Invalid("Not a secure message".to_string()))?;
+    let sender_key = db
+        .find_user(message.sender_id)?
+        .and_then(|sender| sender.public_key)
+        .ok_or_else(|| ApiError::NotFound("Sender not found".to_string()))?;
+    let (session, text) = match keystore
+        .ratchet_session(&username, &sender_key)
+        .transpose()?
+    {
+        Some(mut session) if session.started_by(&envelope) => {
+            let text = session.decrypt(&envelope)?;
+            (session, text)
+        }
+        _ => {
+            let prekey = envelope.prekey.as_ref().ok_or_else(|| {
+                ApiError::Invalid("No secure session with this sender".to_string())
+            })?;
+            if prekey.identity_key != sender_key {
+                return Err(ApiError::Invalid(
+                    "Message wasn't sent with the sender's current key".to_string(),
+                ));
+            }
+            let mut prekeys = keystore
+                .prekeys(&username)
+                .ok_or_else(|| ApiError::NotFound("No prekeys on this device".to_string()))??;
+            let (session, text) = RatchetSession::respond(identity, &mut prekeys, &envelope)?;
+            keystore.set_prekeys(&username, &prekeys);
+            (session, text)
+        }
+    };
+    let content = String::from_utf8(text.to_vec())
+        .map_err(|_| ApiError::Invalid("Message isn't valid text".to_string()))?;
+
+    keystore.set_ratchet_session(&username, &session);
+    keystore.set_message_text(&username, id, content.as_bytes());
+    keystore.save()?;
+    Ok(OpenedMessage { message, content })
+}
+
+/// The username of `user_id`, who must be the signed-in identity.
+fn local_username(db: &Database, identity: &Identity, user_id: i64) -> ApiResult<String> {
+    let user = db
+        .find_user(user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if user.public_key.as_deref() != Some(identity.public_key().as_str()) {
+        return Err(ApiError::Invalid(
+            "Sign in as this user to use secure messages".to_string(),
+        ));
+    }
+    user.username
+        .ok_or_else(|| ApiError::Invalid("Set a username first".to_string()))
+}
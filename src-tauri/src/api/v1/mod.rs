@@ -0,0 +1,161 @@
+//! Version 1 of the command API. Command names carry the `v1_` prefix;
+//! breaking changes go in a new module rather than changing these.
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::CommandSpec;
+use crate::crypto::{
+    rotation::KeyHistory,
+    social_recovery::{ReturnedShare, ShareGrant},
+};
+use crate::db::{
+    Attachment, Circle, Device, FriendVerification, Friendship, Message, NewAttachment, NewCircle,
+    NewMessage, NewPost, NewUser, Post, User,
+};
+use crate::settings::Settings;
+
+pub mod archive;
+pub mod attachments;
+pub mod circles;
+pub mod devices;
+pub mod friends;
+pub mod identity;
+pub mod keys;
+pub mod keystore;
+pub mod messages;
+pub mod posts;
+pub mod recovery;
+pub mod settings;
+pub mod social_recovery;
+pub mod sync;
+
+pub const VERSION: &str = "v1";
+
+/// Request naming a single record.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct IdRequest {
+    #[ts(type = "number")]
+    pub id: i64,
+}
+
+/// Request scoped to one user, e.g. their feed or friend list.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UserRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+}
+
+pub fn commands() -> Vec<CommandSpec> {
+    vec![
+        CommandSpec::without_request::<Vec<User>>("v1_identity_list"),
+        CommandSpec::new::<NewUser, User>("v1_identity_register"),
+        CommandSpec::new::<identity::FindIdentityRequest, Option<User>>("v1_identity_find"),
+        CommandSpec::new::<identity::SignInRequest, identity::SignInResponse>(
+            "v1_identity_sign_in",
+        ),
+        CommandSpec::without_request::<()>("v1_identity_sign_out"),
+        CommandSpec::without_request::<identity::PublicKeyResponse>("v1_identity_public_key"),
+        CommandSpec::new::<identity::SignRequest, identity::SignResponse>("v1_identity_sign"),
+        CommandSpec::new::<identity::VerifyRequest, bool>("v1_identity_verify"),
+        CommandSpec::without_request::<keystore::KeystoreStatus>("v1_keystore_status"),
+        CommandSpec::new::<keystore::KeystorePassphraseRequest, keystore::KeystoreStatus>(
+            "v1_keystore_create",
+        ),
+        CommandSpec::new::<keystore::UnlockKeystoreRequest, keystore::KeystoreStatus>(
+            "v1_keystore_unlock",
+        ),
+        CommandSpec::without_request::<keystore::KeystoreStatus>("v1_keystore_lock"),
+        CommandSpec::new::<keystore::AutoLockRequest, keystore::KeystoreStatus>(
+            "v1_keystore_set_auto_lock",
+        ),
+        CommandSpec::new::<keys::RotateKeyRequest, keys::KeyChangeResponse>("v1_keys_rotate"),
+        CommandSpec::new::<keys::RevokeKeyRequest, keys::KeyChangeResponse>("v1_keys_revoke"),
+        CommandSpec::new::<UserRequest, KeyHistory>("v1_keys_history"),
+        CommandSpec::new::<keys::ApplyKeyEventRequest, User>("v1_keys_apply"),
+        CommandSpec::without_request::<recovery::RecoveryPhrase>("v1_recovery_generate"),
+        CommandSpec::new::<recovery::ConfirmRecoveryRequest, recovery::RecoveryResponse>(
+            "v1_recovery_confirm",
+        ),
+        CommandSpec::new::<recovery::RestoreRecoveryRequest, recovery::RecoveryResponse>(
+            "v1_recovery_restore",
+        ),
+        CommandSpec::new::<social_recovery::SplitSeedRequest, Vec<ShareGrant>>(
+            "v1_social_recovery_split",
+        ),
+        CommandSpec::new::<UserRequest, Vec<social_recovery::HeldShare>>("v1_social_recovery_held"),
+        CommandSpec::new::<
+            social_recovery::StartRecoveryRequest,
+            social_recovery::StartRecoveryResponse,
+        >("v1_social_recovery_request"),
+        CommandSpec::new::<social_recovery::ReleaseShareRequest, ReturnedShare>(
+            "v1_social_recovery_release",
+        ),
+        CommandSpec::new::<social_recovery::RestoreFromFriendsRequest, recovery::RecoveryResponse>(
+            "v1_social_recovery_restore",
+        ),
+        CommandSpec::new::<posts::FeedRequest, Vec<Post>>("v1_posts_feed"),
+        CommandSpec::new::<UserRequest, Vec<Post>>("v1_posts_by_user"),
+        CommandSpec::new::<NewPost, Post>("v1_posts_create"),
+        CommandSpec::new::<NewPost, NewPost>("v1_posts_sign"),
+        CommandSpec::new::<IdRequest, bool>("v1_posts_verify"),
+        CommandSpec::new::<IdRequest, posts::PostWithAttachments>("v1_posts_get"),
+        CommandSpec::new::<posts::ContentHashRequest, bool>("v1_posts_exists"),
+        CommandSpec::new::<posts::EncryptPostRequest, posts::EncryptedContent>("v1_posts_encrypt"),
+        CommandSpec::new::<posts::DecryptPostRequest, String>("v1_posts_decrypt"),
+        CommandSpec::new::<NewCircle, Circle>("v1_circles_create"),
+        CommandSpec::new::<UserRequest, Vec<circles::CircleWithMembers>>("v1_circles_list"),
+        CommandSpec::new::<circles::CircleMemberRequest, Circle>("v1_circles_add_member"),
+        CommandSpec::new::<circles::CircleMemberRequest, Circle>("v1_circles_remove_member"),
+        CommandSpec::new::<circles::EncryptCircleRequest, posts::EncryptedContent>(
+            "v1_circles_encrypt",
+        ),
+        CommandSpec::new::<posts::DecryptPostRequest, String>("v1_circles_decrypt"),
+        CommandSpec::new::<UserRequest, Vec<User>>("v1_friends_list"),
+        CommandSpec::new::<UserRequest, Vec<Friendship>>("v1_friends_requests"),
+        CommandSpec::new::<friends::SendFriendRequest, Friendship>("v1_friends_send"),
+        CommandSpec::new::<friends::RespondToFriendRequest, Friendship>("v1_friends_respond"),
+        CommandSpec::new::<friends::FriendKeyRequest, friends::SafetyNumberResponse>(
+            "v1_friends_safety_number",
+        ),
+        CommandSpec::new::<friends::VerifyFriendRequest, FriendVerification>("v1_friends_verify"),
+        CommandSpec::new::<UserRequest, Vec<FriendVerification>>("v1_friends_key_warnings"),
+        CommandSpec::new::<messages::ConversationRequest, Vec<Message>>("v1_messages_conversation"),
+        CommandSpec::new::<NewMessage, Message>("v1_messages_send"),
+        CommandSpec::new::<IdRequest, ()>("v1_messages_mark_read"),
+        CommandSpec::new::<UserRequest, u32>("v1_messages_publish_prekeys"),
+        CommandSpec::new::<messages::SecureMessageRequest, Message>("v1_messages_send_secure"),
+        CommandSpec::new::<IdRequest, messages::OpenedMessage>("v1_messages_open"),
+        CommandSpec::new::<UserRequest, Vec<sync::OutboundEnvelope>>("v1_sync_outbox"),
+        CommandSpec::new::<IdRequest, ()>("v1_sync_mark_sent"),
+        CommandSpec::new::<UserRequest, sync::OpenedSync>("v1_sync_open_sealed"),
+        CommandSpec::new::<NewAttachment, Attachment>("v1_attachments_create"),
+        CommandSpec::new::<IdRequest, Attachment>("v1_attachments_get"),
+        CommandSpec::new::<attachments::PostAttachmentsRequest, Vec<Attachment>>(
+            "v1_attachments_for_post",
+        ),
+        CommandSpec::new::<attachments::EncryptAttachmentRequest, Attachment>(
+            "v1_attachments_encrypt_file",
+        ),
+        CommandSpec::new::<attachments::ReadAttachmentRequest, attachments::AttachmentChunk>(
+            "v1_attachments_read",
+        ),
+        CommandSpec::new::<archive::ExportArchiveRequest, archive::ArchiveSummary>(
+            "v1_archive_export",
+        ),
+        CommandSpec::new::<archive::ImportArchiveRequest, archive::ImportReport>(
+            "v1_archive_inspect",
+        ),
+        CommandSpec::new::<archive::ImportArchiveRequest, archive::ImportReport>(
+            "v1_archive_import",
+        ),
+        CommandSpec::new::<devices::LinkOfferRequest, devices::LinkOffer>("v1_devices_link_offer"),
+        CommandSpec::new::<devices::LinkJoinRequest, devices::LinkedDevice>("v1_devices_link_join"),
+        CommandSpec::new::<UserRequest, Vec<Device>>("v1_devices_list"),
+        CommandSpec::new::<devices::RevokeDeviceRequest, Device>("v1_devices_revoke"),
+        CommandSpec::without_request::<Settings>("v1_settings_get"),
+        CommandSpec::new::<Settings, Settings>("v1_settings_update"),
+    ]
+}
@@ -0,0 +1,189 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ts_rs::TS;
+
+use super::{IdRequest, UserRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    self,
+    envelope::PostEnvelope,
+    signed_post::{self, SignedPost},
+};
+use crate::db::{self, Attachment, Database, NewPost, Post};
+
+/// Largest feed page a single call returns.
+pub const MAX_FEED_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FeedRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    /// Defaults to, and is capped at, [`MAX_FEED_LIMIT`].
+    #[serde(default)]
+    #[ts(optional)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ContentHashRequest {
+    pub content_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PostWithAttachments {
+    pub post: Post,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EncryptPostRequest {
+    pub content: String,
+    /// Base64 public keys to encrypt for. Defaults to the signed-in user and
+    /// their accepted friends.
+    #[serde(default)]
+    #[ts(optional)]
+    pub recipients: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EncryptedContent {
+    /// Envelope JSON for `NewPost.content_encrypted`.
+    pub content_encrypted: String,
+    /// Public keys that can open it.
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DecryptPostRequest {
+    pub content_encrypted: String,
+}
+
+#[tauri::command]
+pub fn v1_posts_feed(state: State<'_, ApiState>, request: FeedRequest) -> ApiResult<Vec<Post>> {
+    let limit = request.limit.unwrap_or(MAX_FEED_LIMIT).min(MAX_FEED_LIMIT);
+    Ok(state.db()?.feed_for_user(request.user_id, limit.into())?)
+}
+
+#[tauri::command]
+pub fn v1_posts_by_user(state: State<'_, ApiState>, request: UserRequest) -> ApiResult<Vec<Post>> {
+    Ok(state.db()?.posts_for_user(request.user_id)?)
+}
+
+/// Rejects posts without a valid signature by their author; see
+/// [`v1_posts_sign`].
+#[tauri::command]
+pub fn v1_posts_create(state: State<'_, ApiState>, request: NewPost) -> ApiResult<Post> {
+    Ok(state.db()?.ingest_post(&request)?)
+}
+
+/// Signs a post as its author: with the signed-in identity if it is the
+/// author's, otherwise with this device's certified key for them. Fills in
+/// `timestamp` (now, if missing) and `signature`; pass the result to
+/// [`v1_posts_create`].
+#[tauri::command]
+pub fn v1_posts_sign(state: State<'_, ApiState>, mut request: NewPost) -> ApiResult<NewPost> {
+    let db = state.db()?;
+    let author_id = request.original_user_id.unwrap_or(request.user_id);
+    let author = db
+        .find_user(author_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let timestamp = match &request.timestamp {
+        Some(timestamp) => signed_post::parse_timestamp(timestamp)?,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+            .as_secs() as i64,
+    };
+    let content = request.content_encrypted.clone().unwrap_or_default();
+    let checksums = request.attachment_checksums.take().unwrap_or_default();
+    let (signed, signature) = state.with_signer(&author, |signer| {
+        let signed = SignedPost::new(&signer.public_key(), &content, checksums, timestamp)?;
+        let signature = signed.sign(signer)?;
+        Ok::<_, crypto::Error>((signed, signature))
+    })??;
+    request.signature = Some(signature);
+    request.timestamp = Some(signed_post::format_timestamp(signed.timestamp));
+    request.attachment_checksums = Some(signed.attachment_checksums);
+    Ok(request)
+}
+
+/// Whether a stored post's signature still matches its content and
+/// attachments.
+#[tauri::command]
+pub fn v1_posts_verify(state: State<'_, ApiState>, request: IdRequest) -> ApiResult<bool> {
+    match state.db()?.verify_post(request.id) {
+        Ok(_) => Ok(true),
+        Err(db::Error::Invalid(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+pub fn v1_posts_get(
+    state: State<'_, ApiState>,
+    request: IdRequest,
+) -> ApiResult<PostWithAttachments> {
+    let db = state.db()?;
+    let post = db
+        .find_post(request.id)?
+        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+    let attachments = db.attachments_for_post(post.id)?;
+    Ok(PostWithAttachments { post, attachments })
+}
+
+#[tauri::command]
+pub fn v1_posts_exists(state: State<'_, ApiState>, request: ContentHashRequest) -> ApiResult<bool> {
+    Ok(state.db()?.post_exists_with_hash(&request.content_hash)?)
+}
+
+/// Encrypts a post body into a [`PostEnvelope`]. The content key never
+/// leaves native code; the webview gets back only the envelope to store.
+#[tauri::command]
+pub fn v1_posts_encrypt(
+    state: State<'_, ApiState>,
+    request: EncryptPostRequest,
+) -> ApiResult<EncryptedContent> {
+    let author = state.with_identity(|identity| identity.public_key())?;
+    let recipients = match request.recipients {
+        Some(recipients) => recipients,
+        None => default_recipients(state.db()?, &author)?,
+    };
+    let envelope = PostEnvelope::seal(request.content.as_bytes(), &recipients)?;
+    Ok(EncryptedContent {
+        content_encrypted: envelope.to_json(),
+        recipients: envelope.recipient_keys().map(str::to_string).collect(),
+    })
+}
+
+/// Opens an envelope with the signed-in identity's key.
+#[tauri::command]
+pub fn v1_posts_decrypt(
+    state: State<'_, ApiState>,
+    request: DecryptPostRequest,
+) -> ApiResult<String> {
+    let envelope = PostEnvelope::from_json(&request.content_encrypted)?;
+    let plaintext = state.with_identity(|identity| envelope.open(identity))??;
+    String::from_utf8(plaintext.to_vec())
+        .map_err(|_| ApiError::Invalid("Post content is not UTF-8".to_string()))
+}
+
+/// The author followed by every accepted friend with a public key.
+pub fn default_recipients(db: &Database, author: &str) -> ApiResult<Vec<String>> {
+    let mut recipients = vec![author.to_string()];
+    if let Some(user) = db.find_user_by_public_key(author)? {
+        recipients.extend(
+            db.friends_of(user.id)?
+                .into_iter()
+                .filter_map(|friend| friend.public_key),
+        );
+    }
+    Ok(recipients)
+}
@@ -0,0 +1,252 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::identity::unix_now;
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    account::{AccountRecord, ACCOUNTS_DIR},
+    recovery::{MasterSeed, PendingBackup},
+    rotation::{KeyEvent, KeyRotation},
+    secret::Secret,
+    Identity,
+};
+use crate::db::Database;
+use crate::settings::Settings;
+
+/// The phrase to write down and the words the user will be asked for. Not
+/// `Debug`, so the phrase can't end up in a log.
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub struct RecoveryPhrase {
+    pub words: Vec<String>,
+    /// Zero-based positions to ask for in [`v1_recovery_confirm`].
+    pub positions: Vec<u32>,
+}
+
+/// Not `Debug` or `Serialize`, so the password can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct ConfirmRecoveryRequest {
+    pub username: String,
+    pub password: String,
+    /// The words at `RecoveryPhrase.positions`, in order.
+    pub words: Vec<String>,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct RestoreRecoveryRequest {
+    pub username: String,
+    pub phrase: String,
+    /// Replaces the forgotten password on this device.
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RecoveryResponse {
+    pub public_key: String,
+    /// Set when the account moved to a new key; friends need it to trust it.
+    #[ts(optional = nullable)]
+    pub rotation: Option<KeyRotation>,
+}
+
+/// Generates a recovery phrase for the signed-in account. Nothing changes
+/// until [`v1_recovery_confirm`] shows the user wrote it down.
+#[tauri::command]
+pub fn v1_recovery_generate(state: State<'_, ApiState>) -> ApiResult<RecoveryPhrase> {
+    state.with_identity(|_| ())?;
+    let pending = PendingBackup::generate();
+    let phrase = RecoveryPhrase {
+        words: pending
+            .seed
+            .phrase()
+            .split(' ')
+            .map(str::to_string)
+            .collect(),
+        positions: pending.positions.iter().map(|&p| p as u32).collect(),
+    };
+    state.session().pending_backup = Some(pending);
+    Ok(phrase)
+}
+
+/// Checks the re-entered words and moves the account onto the phrase's key.
+/// Wrong words keep the pending phrase so the user can try again.
+#[tauri::command]
+pub async fn v1_recovery_confirm(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: ConfirmRecoveryRequest,
+) -> ApiResult<RecoveryResponse> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))?;
+    let seed = {
+        let session = state.session();
+        let pending = session.pending_backup.as_ref().ok_or_else(|| {
+            ApiError::NotFound("No recovery phrase is waiting to be confirmed".to_string())
+        })?;
+        if !pending.check(&request.words) {
+            return Err(ApiError::Invalid(
+                "Those words don't match the recovery phrase".to_string(),
+            ));
+        }
+        MasterSeed::from_entropy(pending.seed.entropy())
+    };
+
+    let db = state.db()?.clone();
+    let username = request.username.clone();
+    let password = Secret::new(std::mem::take(&mut request.password));
+    let (identity, seed, response) = tauri::async_runtime::spawn_blocking(move || {
+        confirm(
+            &db,
+            &app_data_dir.join(ACCOUNTS_DIR),
+            &username,
+            password.expose(),
+            seed,
+            &Settings::load(&app_data_dir),
+            unix_now(),
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    session.pending_backup = None;
+    if let Some(keystore) = session.keystore_mut() {
+        keystore.set_identity(&request.username, &identity);
+        keystore.set_master_seed(&request.username, &seed);
+        keystore.save()?;
+    }
+    session.set_identity(Some(identity));
+    Ok(response)
+}
+
+/// Restores an account from its recovery phrase and signs in, setting a
+/// new password on this device.
+#[tauri::command]
+pub async fn v1_recovery_restore(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: RestoreRecoveryRequest,
+) -> ApiResult<RecoveryResponse> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))?;
+    let seed = MasterSeed::from_phrase(Secret::new(std::mem::take(&mut request.phrase)).expose())?;
+    let password = Secret::new(std::mem::take(&mut request.password));
+    if password.expose().is_empty() {
+        return Err(ApiError::Invalid("Password can't be blank".to_string()));
+    }
+
+    let db = state.db()?.clone();
+    let username = request.username.clone();
+    let (identity, seed, response) = tauri::async_runtime::spawn_blocking(move || {
+        restore(
+            &db,
+            &app_data_dir.join(ACCOUNTS_DIR),
+            &username,
+            password.expose(),
+            seed,
+            &Settings::load(&app_data_dir),
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    if let Some(keystore) = session.keystore_mut() {
+        keystore.set_identity(&request.username, &identity);
+        keystore.set_master_seed(&request.username, &seed);
+        keystore.save()?;
+    }
+    session.set_identity(Some(identity));
+    Ok(response)
+}
+
+/// Moves `username`'s account record onto `seed`'s key after checking the
+/// password, and points the registered user at the new key.
+pub fn confirm(
+    db: &Database,
+    accounts_dir: &Path,
+    username: &str,
+    password: &str,
+    seed: MasterSeed,
+    settings: &Settings,
+    now: i64,
+) -> ApiResult<(Identity, MasterSeed, RecoveryResponse)> {
+    let record = AccountRecord::load(accounts_dir, username)?.ok_or_else(|| {
+        ApiError::NotFound("Sign in with your password on this device first".to_string())
+    })?;
+    let previous_rotations = record.rotations.len();
+    let (record, identity) =
+        record.adopt_master_seed(password, &seed, settings.kdf.unwrap_or_default(), now)?;
+
+    // Save first: the record is the only way back into the new key
+    record.save(accounts_dir)?;
+    let rotation = record.rotations.get(previous_rotations).cloned();
+    if let (Some(user), Some(rotation)) = (db.find_user_by_username(username)?, &rotation) {
+        let event = KeyEvent::Rotation(rotation.clone());
+        db.record_key_event(user.id, &event)?;
+        db.broadcast_key_event(user.id, &event)?;
+    }
+    let response = RecoveryResponse {
+        public_key: record.public_key,
+        rotation,
+    };
+    Ok((identity, seed, response))
+}
+
+/// Rebuilds `username`'s account record from `seed` under `password`. A
+/// registered user must already have the phrase's key.
+pub fn restore(
+    db: &Database,
+    accounts_dir: &Path,
+    username: &str,
+    password: &str,
+    seed: MasterSeed,
+    settings: &Settings,
+) -> ApiResult<(Identity, MasterSeed, RecoveryResponse)> {
+    let identity = seed.identity();
+    let response = restore_identity(db, accounts_dir, username, password, &identity, settings)?;
+    Ok((identity, seed, response))
+}
+
+/// Rebuilds `username`'s account record around a recovered `identity`,
+/// keeping any rotations already recorded on this device.
+pub fn restore_identity(
+    db: &Database,
+    accounts_dir: &Path,
+    username: &str,
+    password: &str,
+    identity: &Identity,
+    settings: &Settings,
+) -> ApiResult<RecoveryResponse> {
+    let mut record = AccountRecord::restore_identity(
+        username,
+        password,
+        identity,
+        settings.kdf.unwrap_or_default(),
+    )?;
+    if let Some(user) = db.find_user_by_username(username)? {
+        if user.public_key.as_deref() != Some(record.public_key.as_str()) {
+            return Err(ApiError::Invalid(
+                "That recovery key doesn't belong to this account".to_string(),
+            ));
+        }
+    }
+    if let Some(previous) = AccountRecord::load(accounts_dir, username)? {
+        record.rotations = previous.rotations;
+        record.revocations = previous.revocations;
+    }
+    record.save(accounts_dir)?;
+    Ok(RecoveryResponse {
+        public_key: record.public_key,
+        rotation: None,
+    })
+}
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::settings::Settings;
+
+fn app_data_dir(app: &AppHandle) -> ApiResult<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))
+}
+
+#[tauri::command]
+pub fn v1_settings_get(app: AppHandle) -> ApiResult<Settings> {
+    Ok(Settings::load(&app_data_dir(&app)?))
+}
+
+/// Saves the settings; changes to the backend take effect on next launch,
+/// the auto-lock timeout straight away.
+#[tauri::command]
+pub fn v1_settings_update(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    request: Settings,
+) -> ApiResult<Settings> {
+    request.save(&app_data_dir(&app)?)?;
+    state.session().auto_lock = request.auto_lock();
+    Ok(request)
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ts_rs::TS;
+
+use super::{identity::unix_now, recovery, recovery::RecoveryResponse, UserRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{
+    account::ACCOUNTS_DIR,
+    secret::Secret,
+    social_recovery::{self, RecoveryRequest, ReturnedShare, ShareGrant},
+    Identity,
+};
+use crate::db::Database;
+use crate::settings::Settings;
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SplitSeedRequest {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    /// Accepted friends to hold one share each.
+    #[ts(type = "Array<number>")]
+    pub friend_ids: Vec<i64>,
+    /// How many of them it takes to recover.
+    pub threshold: u32,
+}
+
+/// A share held for a friend. `id` is the sync message it arrived in.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HeldShare {
+    #[ts(type = "number")]
+    pub id: i64,
+    pub grant: ShareGrant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StartRecoveryRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StartRecoveryResponse {
+    /// Give this to each friend holding a share.
+    pub request: RecoveryRequest,
+    /// Read this to them over a channel you trust.
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReleaseShareRequest {
+    /// A [`HeldShare`] id.
+    #[ts(type = "number")]
+    pub id: i64,
+    pub request: RecoveryRequest,
+    /// The code the owner read out; must match the request.
+    pub code: String,
+}
+
+/// Not `Debug` or `Serialize`, so the password can't end up in a log.
+#[derive(Deserialize, TS)]
+#[ts(export)]
+pub struct RestoreFromFriendsRequest {
+    pub username: String,
+    /// Replaces the forgotten password on this device.
+    pub password: String,
+    pub shares: Vec<ReturnedShare>,
+}
+
+/// Splits the signed-in account's seed among friends and queues each share
+/// to them over sync.
+#[tauri::command]
+pub fn v1_social_recovery_split(
+    state: State<'_, ApiState>,
+    request: SplitSeedRequest,
+) -> ApiResult<Vec<ShareGrant>> {
+    let db = state.db()?;
+    state.with_identity(|identity| split(db, identity, &request, unix_now()))?
+}
+
+/// Shares this user holds for friends.
+#[tauri::command]
+pub fn v1_social_recovery_held(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<Vec<HeldShare>> {
+    Ok(state
+        .db()?
+        .held_recovery_shares(request.user_id)?
+        .into_iter()
+        .map(|(id, grant)| HeldShare { id, grant })
+        .collect())
+}
+
+/// Starts recovery on a new device. Replaces any earlier request, so shares
+/// returned to that one can no longer be opened.
+#[tauri::command]
+pub fn v1_social_recovery_request(
+    state: State<'_, ApiState>,
+    request: StartRecoveryRequest,
+) -> ApiResult<StartRecoveryResponse> {
+    if request.username.trim().is_empty() {
+        return Err(ApiError::Invalid("Username can't be blank".to_string()));
+    }
+    let (recovery_request, identity) = RecoveryRequest::generate(&request.username, unix_now());
+    let code = recovery_request.code();
+    state.session().pending_recovery = Some((recovery_request.clone(), identity));
+    Ok(StartRecoveryResponse {
+        request: recovery_request,
+        code,
+    })
+}
+
+/// Reseals a held share to a friend's recovery request.
+#[tauri::command]
+pub fn v1_social_recovery_release(
+    state: State<'_, ApiState>,
+    request: ReleaseShareRequest,
+) -> ApiResult<ReturnedShare> {
+    let db = state.db()?;
+    state.with_identity(|identity| release(db, identity, &request))?
+}
+
+/// Rebuilds the account from returned shares and signs in, setting a new
+/// password on this device.
+#[tauri::command]
+pub async fn v1_social_recovery_restore(
+    app: AppHandle,
+    state: State<'_, ApiState>,
+    mut request: RestoreFromFriendsRequest,
+) -> ApiResult<RecoveryResponse> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| ApiError::Unavailable(e.to_string()))?;
+    let password = Secret::new(std::mem::take(&mut request.password));
+    if password.expose().is_empty() {
+        return Err(ApiError::Invalid("Password can't be blank".to_string()));
+    }
+    let identity = {
+        let session = state.session();
+        let (recovery_request, request_identity) = session
+            .pending_recovery
+            .as_ref()
+            .ok_or_else(|| ApiError::NotFound("Start recovery before adding shares".to_string()))?;
+        if recovery_request.owner_username != request.username {
+            return Err(ApiError::Invalid(
+                "Recovery was started for another account".to_string(),
+            ));
+        }
+        social_recovery::recover(recovery_request, request_identity, &request.shares)?
+    };
+
+    let db = state.db()?.clone();
+    let username = request.username.clone();
+    let (identity, response) = tauri::async_runtime::spawn_blocking(move || {
+        let response = recovery::restore_identity(
+            &db,
+            &app_data_dir.join(ACCOUNTS_DIR),
+            &username,
+            password.expose(),
+            &identity,
+            &Settings::load(&app_data_dir),
+        )?;
+        Ok::<_, ApiError>((identity, response))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    let mut session = state.session();
+    session.pending_recovery = None;
+    if let Some(keystore) = session.keystore_mut() {
+        keystore.set_identity(&request.username, &identity);
+        keystore.save()?;
+    }
+    session.set_identity(Some(identity));
+    Ok(response)
+}
+
+/// Deals `identity`'s seed to `request.friend_ids` and queues the grants.
+/// The identity must be `request.user_id`'s registered key.
+pub fn split(
+    db: &Database,
+    identity: &Identity,
+    request: &SplitSeedRequest,
+    now: i64,
+) -> ApiResult<Vec<ShareGrant>> {
+    let owner = db
+        .find_user(request.user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if owner.public_key.as_deref() != Some(identity.public_key().as_str()) {
+        return Err(ApiError::Invalid(
+            "Sign in as this user to share their key".to_string(),
+        ));
+    }
+    let username = owner
+        .username
+        .as_deref()
+        .ok_or_else(|| ApiError::Invalid("Set a username before sharing".to_string()))?;
+    let mut holders = Vec::with_capacity(request.friend_ids.len());
+    for &friend_id in &request.friend_ids {
+        let friend = match db.find_user(friend_id)? {
+            Some(friend) if db.are_friends(owner.id, friend_id)? => friend,
+            _ => {
+                return Err(ApiError::Invalid(format!(
+                    "User {} is not an accepted friend",
+                    friend_id
+                )))
+            }
+        };
+        holders.push(
+            friend.public_key.ok_or_else(|| {
+                ApiError::Invalid(format!("User {} has no public key", friend_id))
+            })?,
+        );
+    }
+    let threshold = request.threshold as usize;
+    if holders.len() < social_recovery::MIN_THRESHOLD
+        || !(social_recovery::MIN_THRESHOLD..=holders.len()).contains(&threshold)
+    {
+        return Err(ApiError::Invalid(format!(
+            "Choose at least {0} friends and a threshold between {0} and the number of friends",
+            social_recovery::MIN_THRESHOLD
+        )));
+    }
+    let grants = ShareGrant::deal(identity, username, &holders, threshold, now)?;
+    db.deliver_recovery_shares(owner.id, &grants)?;
+    Ok(grants)
+}
+
+/// Reseals the share held in sync message `request.id` to `request.request`
+/// once the code matches.
+pub fn release(
+    db: &Database,
+    identity: &Identity,
+    request: &ReleaseShareRequest,
+) -> ApiResult<ReturnedShare> {
+    let holder = db
+        .find_user_by_public_key(&identity.public_key())?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let (_, grant) = db
+        .held_recovery_shares(holder.id)?
+        .into_iter()
+        .find(|(id, _)| *id == request.id)
+        .ok_or_else(|| ApiError::NotFound("Recovery share not found".to_string()))?;
+    let code: String = request.code.chars().filter(char::is_ascii_digit).collect();
+    if code != request.request.code().replace('-', "") {
+        return Err(ApiError::Invalid(
+            "That code doesn't match the recovery request".to_string(),
+        ));
+    }
+    Ok(grant.release(identity, &request.request)?)
+}
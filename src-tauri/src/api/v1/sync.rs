@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ts_rs::TS;
+
+use super::{identity::unix_now, IdRequest, UserRequest};
+use crate::api::{ApiError, ApiResult, ApiState};
+use crate::crypto::{sealed_sender::SealedEnvelope, Identity};
+use crate::db::{Database, INBOUND_SEALED};
+
+/// A pending outbound sync message, sealed for its peer. Only `address`
+/// and `port` are visible to whoever carries it.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutboundEnvelope {
+    #[ts(type = "number")]
+    pub sync_message_id: i64,
+    #[ts(type = "number")]
+    pub peer_id: i64,
+    pub address: Option<String>,
+    #[ts(type = "number | null")]
+    pub port: Option<i64>,
+    pub envelope: SealedEnvelope,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OpenedSync {
+    pub opened: u32,
+    pub rejected: u32,
+}
+
+/// Seals the user's pending outbound sync messages, one envelope per
+/// message, for the transport to deliver as `inbound_sealed`.
+#[tauri::command]
+pub fn v1_sync_outbox(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<Vec<OutboundEnvelope>> {
+    let db = state.db()?;
+    state.with_identity(|identity| outbox(db, identity, request.user_id, unix_now()))?
+}
+
+/// Marks an outbound sync message as handed to the transport.
+#[tauri::command]
+pub fn v1_sync_mark_sent(state: State<'_, ApiState>, request: IdRequest) -> ApiResult<()> {
+    Ok(state.db()?.update_sync_message_status(request.id, "sent")?)
+}
+
+/// Opens the sealed sync messages held for the user and applies them.
+#[tauri::command]
+pub fn v1_sync_open_sealed(
+    state: State<'_, ApiState>,
+    request: UserRequest,
+) -> ApiResult<OpenedSync> {
+    let db = state.db()?;
+    state.with_identity(|identity| open_sealed(db, identity, request.user_id))?
+}
+
+pub fn outbox(
+    db: &Database,
+    identity: &Identity,
+    user_id: i64,
+    now: i64,
+) -> ApiResult<Vec<OutboundEnvelope>> {
+    check_user(db, identity, user_id)?;
+    let peers = db.peers_for_user(user_id)?;
+    let mut envelopes = Vec::new();
+    for message in db.pending_sync_messages(user_id)? {
+        let message_type = match message.message_type.as_deref() {
+            Some(kind) if kind.starts_with("outbound_") => kind,
+            _ => continue,
+        };
+        let Some(peer) = peers.iter().find(|peer| peer.id == message.peer_id) else {
+            continue;
+        };
+        let Some(recipient_key) = &peer.public_key else {
+            println!(
+                "Not sealing sync message {}: peer {} has no public key",
+                message.id, peer.id
+            );
+            continue;
+        };
+        let envelope = SealedEnvelope::seal(
+            identity,
+            recipient_key,
+            message_type,
+            message.payload.as_deref().unwrap_or_default(),
+            now,
+        )?;
+        envelopes.push(OutboundEnvelope {
+            sync_message_id: message.id,
+            peer_id: peer.id,
+            address: peer.address.clone(),
+            port: peer.port,
+            envelope,
+        });
+    }
+    Ok(envelopes)
+}
+
+pub fn open_sealed(db: &Database, identity: &Identity, user_id: i64) -> ApiResult<OpenedSync> {
+    check_user(db, identity, user_id)?;
+    let mut result = OpenedSync {
+        opened: 0,
+        rejected: 0,
+    };
+    for message in db.sync_messages_with_status(user_id, "sealed")? {
+        if message.message_type.as_deref() != Some(INBOUND_SEALED) {
+            continue;
+        }
+        let opened =
+            serde_json::from_str::<SealedEnvelope>(message.payload.as_deref().unwrap_or_default())
+                .map_err(|e| ApiError::Invalid(e.to_string()))
+                .and_then(|envelope| Ok(envelope.open(identity)?))
+                .and_then(|content| Ok(db.receive_unsealed(message.id, &content)?));
+        match opened {
+            Ok(_) => result.opened += 1,
+            Err(e) => {
+                println!("Rejected sealed sync message {}: {}", message.id, e);
+                db.update_sync_message_status(message.id, "rejected")?;
+                result.rejected += 1;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Sealing and opening use the signed-in identity, which must be the user's.
+fn check_user(db: &Database, identity: &Identity, user_id: i64) -> ApiResult<()> {
+    let user = db
+        .find_user(user_id)?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if user.public_key.as_deref() != Some(identity.public_key().as_str()) {
+        return Err(ApiError::Invalid(
+            "Sign in as this user to sync".to_string(),
+        ));
+    }
+    Ok(())
+}
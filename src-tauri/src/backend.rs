@@ -0,0 +1,335 @@
+use std::{
+    fmt,
+    net::TcpStream,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, Url};
+use ts_rs::TS;
+
+use crate::api::{ApiResult, ApiState};
+use crate::crypto::{keystore::Keystore, secret::Secret};
+use crate::db::{
+    database_key_hex, encrypt_database_file, is_plaintext_database, Database, DATABASE_KEY_ENV,
+};
+use crate::server::ServerHandle;
+use crate::settings::Settings;
+
+/// How long to wait for a Rails server started after unlock to take the port.
+const RAILS_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Which server the webview talks to on `127.0.0.1:3000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum BackendMode {
+    /// Spawn the bundled Rails app (requires a Ruby install).
+    Rails,
+    /// Serve from the Rust embedded server; no Ruby needed.
+    Embedded,
+}
+
+impl BackendMode {
+    /// Picks the backend from, in order: a `--backend=<mode>` / `--backend <mode>`
+    /// / `--embedded` argument, the `CIPHER_BACKEND` environment variable, the
+    /// saved settings, then the platform default.
+    pub fn resolve<I>(args: I, env_value: Option<&str>, settings: &Settings) -> BackendMode
+    where
+        I: IntoIterator<Item = String>,
+    {
+        BackendMode::from_args(args)
+            .or_else(|| env_value.and_then(BackendMode::parse))
+            .or(settings.backend)
+            .unwrap_or_else(BackendMode::platform_default)
+    }
+
+    /// Mobile has no Ruby runtime to spawn, so it always defaults to embedded.
+    pub fn platform_default() -> BackendMode {
+        if cfg!(any(target_os = "android", target_os = "ios")) {
+            BackendMode::Embedded
+        } else {
+            BackendMode::Rails
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<BackendMode> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "rails" | "ruby" => Some(BackendMode::Rails),
+            "embedded" | "rust" | "native" => Some(BackendMode::Embedded),
+            _ => None,
+        }
+    }
+
+    fn from_args<I>(args: I) -> Option<BackendMode>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--embedded" {
+                return Some(BackendMode::Embedded);
+            }
+            if let Some(value) = arg.strip_prefix("--backend=") {
+                return BackendMode::parse(value);
+            }
+            if arg == "--backend" {
+                return args.next().as_deref().and_then(BackendMode::parse);
+            }
+        }
+        None
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendMode::Rails => "rails",
+            BackendMode::Embedded => "embedded",
+        }
+    }
+}
+
+impl fmt::Display for BackendMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The backend the launcher actually started, kept as Tauri managed state so
+/// commands can report it to the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendStatus {
+    pub mode: BackendMode,
+    pub port: u16,
+    /// SQLite file opened by the embedded server, if any.
+    pub database_path: Option<PathBuf>,
+}
+
+/// A launch waiting for the keystore because the database is encrypted at
+/// rest. Managed as Tauri state; [`open_encrypted_database`] completes it.
+pub struct EncryptedLaunch {
+    pub mode: BackendMode,
+    pub platform: String,
+    pub database_path: PathBuf,
+    /// Rails app to start once the key is known, in Rails mode.
+    pub rails_root: Option<PathBuf>,
+    /// Serves the pages, and so the unlock flow, until then. Stopped to hand
+    /// the port to Rails.
+    pub server: Option<ServerHandle>,
+}
+
+/// Gets the key for `platform`'s database from `keystore`, creating and saving
+/// one on first use, encrypts the file at `path` if it is still plaintext and
+/// opens it.
+pub fn unlock_database(
+    path: &Path,
+    platform: &str,
+    keystore: &mut Keystore,
+) -> ApiResult<(Database, Secret<[u8; 32]>)> {
+    let key = match keystore.database_key(platform) {
+        Some(key) => key?,
+        None => {
+            let mut key = Secret::zeroed();
+            OsRng.fill_bytes(key.expose_mut());
+            // Saved before anything is encrypted under it
+            keystore.set_database_key(platform, &key);
+            keystore.save()?;
+            key
+        }
+    };
+    if is_plaintext_database(path)? {
+        encrypt_database_file(path, &key)?;
+        println!("Encrypted database at {:?}", path);
+    }
+    let db = Database::open_encrypted(path, &key)?;
+    Ok((db, key))
+}
+
+/// Opens the database of an [`EncryptedLaunch`] with the key in the newly
+/// unlocked `keystore` and starts the backend that was waiting for it. Does
+/// nothing if the database isn't encrypted or is already open.
+pub fn open_encrypted_database<R: Runtime>(
+    app: &AppHandle<R>,
+    keystore: &mut Keystore,
+) -> ApiResult<()> {
+    let (Some(launch), Some(state)) = (
+        app.try_state::<EncryptedLaunch>(),
+        app.try_state::<ApiState>(),
+    ) else {
+        return Ok(());
+    };
+    if state.db().is_ok() {
+        return Ok(());
+    }
+    let (db, key) = unlock_database(&launch.database_path, &launch.platform, keystore)?;
+    if !state.open_db(db) {
+        return Ok(());
+    }
+    println!("Opened encrypted database for {}", launch.platform);
+
+    if let (BackendMode::Rails, Some(root)) = (launch.mode, &launch.rails_root) {
+        if let Some(server) = &launch.server {
+            server.stop();
+        }
+        let (root, platform, path) = (
+            root.clone(),
+            launch.platform.clone(),
+            launch.database_path.clone(),
+        );
+        let key = database_key_hex(&key);
+        std::thread::spawn(move || launch_rails(&root, &platform, &path, Some(&key)));
+
+        let app = app.clone();
+        std::thread::spawn(move || {
+            let port = crate::server::DEFAULT_PORT;
+            if !wait_for_port(port, RAILS_START_TIMEOUT) {
+                println!("Rails didn't start listening on port {}", port);
+                return;
+            }
+            let url = format!("http://127.0.0.1:{}/", port);
+            if let (Some(window), Ok(url)) = (app.get_webview_window("main"), Url::parse(&url)) {
+                if let Err(e) = window.navigate(url) {
+                    println!("Failed to navigate webview to Rails: {}", e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+fn wait_for_port(port: u16, timeout: Duration) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+    false
+}
+
+/// Prepares the database with the bundled Rails app in `root` and runs its
+/// server on port 3000 until it exits. `database_key` is passed in
+/// [`DATABASE_KEY_ENV`] when the database is encrypted.
+pub fn launch_rails(
+    root: &Path,
+    platform: &str,
+    db_path: &Path,
+    database_key: Option<&Secret<String>>,
+) {
+    println!("Database path set to: {:?}", db_path);
+    let rails = |args: &[&str]| rails_command(root, platform, db_path, database_key, args);
+
+    // Initialize the database with explicit steps
+    println!("Initializing database for {} environment...", platform);
+
+    // Step 1: Create database
+    match rails(&["bin/rails", "db:create"]).output() {
+        Ok(output) => {
+            println!("Database create result: {}", output.status);
+            if !output.stdout.is_empty() {
+                println!("Create stdout: {}", String::from_utf8_lossy(&output.stdout));
+            }
+            if !output.stderr.is_empty() {
+                println!("Create stderr: {}", String::from_utf8_lossy(&output.stderr));
+            }
+        }
+        Err(e) => println!("Failed to run db:create for {}: {}", platform, e),
+    }
+
+    // Step 2: Load schema, falling back to migrations
+    match rails(&["bin/rails", "db:schema:load"]).output() {
+        Ok(output) if output.status.success() => {
+            println!("Database schema loaded successfully for {}", platform);
+        }
+        Ok(output) => {
+            println!(
+                "Schema load failed for {}: {}",
+                platform,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            println!("Attempting fallback to db:migrate...");
+            match rails(&["bin/rails", "db:migrate"]).output() {
+                Ok(output) if output.status.success() => {
+                    println!(
+                        "Database migrations completed successfully for {}",
+                        platform
+                    );
+                }
+                Ok(output) => println!(
+                    "Migration failed for {}: {}",
+                    platform,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+                Err(e) => println!("Failed to run db:migrate for {}: {}", platform, e),
+            }
+        }
+        Err(e) => println!("Failed to run db:schema:load for {}: {}", platform, e),
+    }
+
+    // Verify database was properly initialized by checking for users table
+    println!("Verifying database initialization for {}...", platform);
+    match rails(&[
+        "-e",
+        "require_relative 'config/environment'; puts User.table_exists? ? 'Database verified' : 'Database missing tables'",
+    ])
+    .output()
+    {
+        Ok(output) => println!(
+            "Database verification result: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ),
+        Err(e) => println!("Failed to verify database for {}: {}", platform, e),
+    }
+
+    // Now start the Rails server
+    match rails(&[
+        "bin/rails",
+        "server",
+        "-p",
+        "3000",
+        "-b",
+        "127.0.0.1",
+        "-e",
+        platform,
+    ])
+    .spawn()
+    {
+        Ok(child) => {
+            println!("Rails server started successfully for {}", platform);
+            let _ = child.wait_with_output();
+        }
+        Err(e) => println!("Failed to start Rails server for {}: {}", platform, e),
+    }
+}
+
+/// `ruby <args>` in `root`, through `cmd /C` on Windows.
+fn rails_command(
+    root: &Path,
+    platform: &str,
+    db_path: &Path,
+    database_key: Option<&Secret<String>>,
+    args: &[&str],
+) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "ruby"]);
+        command
+    } else {
+        Command::new("ruby")
+    };
+    command
+        .args(args)
+        .env("RAILS_ENV", platform)
+        .env(
+            "DATABASE_URL",
+            format!("sqlite3://{}", db_path.to_string_lossy()),
+        )
+        .current_dir(root);
+    if let Some(key) = database_key {
+        command.env(DATABASE_KEY_ENV, key.expose());
+    }
+    command
+}
@@ -0,0 +1,167 @@
+use serde::Serialize;
+use tauri::{ipc::Invoke, AppHandle, Manager, State, Wry};
+use ts_rs::TS;
+
+use crate::api::{self, v1};
+use crate::backend::{BackendMode, BackendStatus, EncryptedLaunch};
+
+/// Every command the webview can invoke. Shared by the desktop and mobile
+/// entry points so both platforms expose the same IPC surface. New commands
+/// also need an entry in [`api::commands`] to get a TypeScript binding.
+pub fn handler() -> impl Fn(Invoke<Wry>) -> bool + Send + Sync + 'static {
+    tauri::generate_handler![
+        get_platform,
+        get_device_info,
+        v1::identity::v1_identity_list,
+        v1::identity::v1_identity_register,
+        v1::identity::v1_identity_find,
+        v1::identity::v1_identity_sign_in,
+        v1::identity::v1_identity_sign_out,
+        v1::identity::v1_identity_public_key,
+        v1::identity::v1_identity_sign,
+        v1::identity::v1_identity_verify,
+        v1::keystore::v1_keystore_status,
+        v1::keystore::v1_keystore_create,
+        v1::keystore::v1_keystore_unlock,
+        v1::keystore::v1_keystore_lock,
+        v1::keystore::v1_keystore_set_auto_lock,
+        v1::keys::v1_keys_rotate,
+        v1::keys::v1_keys_revoke,
+        v1::keys::v1_keys_history,
+        v1::keys::v1_keys_apply,
+        v1::recovery::v1_recovery_generate,
+        v1::recovery::v1_recovery_confirm,
+        v1::recovery::v1_recovery_restore,
+        v1::social_recovery::v1_social_recovery_split,
+        v1::social_recovery::v1_social_recovery_held,
+        v1::social_recovery::v1_social_recovery_request,
+        v1::social_recovery::v1_social_recovery_release,
+        v1::social_recovery::v1_social_recovery_restore,
+        v1::posts::v1_posts_feed,
+        v1::posts::v1_posts_by_user,
+        v1::posts::v1_posts_create,
+        v1::posts::v1_posts_sign,
+        v1::posts::v1_posts_verify,
+        v1::posts::v1_posts_get,
+        v1::posts::v1_posts_exists,
+        v1::posts::v1_posts_encrypt,
+        v1::posts::v1_posts_decrypt,
+        v1::circles::v1_circles_create,
+        v1::circles::v1_circles_list,
+        v1::circles::v1_circles_add_member,
+        v1::circles::v1_circles_remove_member,
+        v1::circles::v1_circles_encrypt,
+        v1::circles::v1_circles_decrypt,
+        v1::friends::v1_friends_list,
+        v1::friends::v1_friends_requests,
+        v1::friends::v1_friends_send,
+        v1::friends::v1_friends_respond,
+        v1::friends::v1_friends_safety_number,
+        v1::friends::v1_friends_verify,
+        v1::friends::v1_friends_key_warnings,
+        v1::messages::v1_messages_conversation,
+        v1::messages::v1_messages_send,
+        v1::messages::v1_messages_mark_read,
+        v1::messages::v1_messages_publish_prekeys,
+        v1::messages::v1_messages_send_secure,
+        v1::messages::v1_messages_open,
+        v1::sync::v1_sync_outbox,
+        v1::sync::v1_sync_mark_sent,
+        v1::sync::v1_sync_open_sealed,
+        v1::attachments::v1_attachments_create,
+        v1::attachments::v1_attachments_get,
+        v1::attachments::v1_attachments_for_post,
+        v1::attachments::v1_attachments_encrypt_file,
+        v1::attachments::v1_attachments_read,
+        v1::archive::v1_archive_export,
+        v1::archive::v1_archive_inspect,
+        v1::archive::v1_archive_import,
+        v1::devices::v1_devices_link_offer,
+        v1::devices::v1_devices_link_join,
+        v1::devices::v1_devices_list,
+        v1::devices::v1_devices_revoke,
+        v1::settings::v1_settings_get,
+        v1::settings::v1_settings_update,
+    ]
+}
+
+#[tauri::command]
+pub fn get_platform() -> String {
+    if cfg!(target_os = "android") {
+        "android".to_string()
+    } else if cfg!(target_os = "ios") {
+        "ios".to_string()
+    } else if cfg!(desktop) {
+        "desktop".to_string()
+    } else {
+        "mobile".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct DeviceInfo {
+    /// `desktop`, `android` or `ios` (see [`get_platform`]).
+    pub platform: String,
+    /// `std::env::consts::OS`, e.g. `macos`, `windows`, `linux`, `android`.
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub app_version: String,
+    /// Newest command API version, e.g. `v1`.
+    pub api_version: &'static str,
+    pub backend: BackendMode,
+    pub backend_port: u16,
+    pub data_dir: Option<String>,
+    pub features: Features,
+}
+
+/// Capabilities the frontend can branch on instead of sniffing user agents.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct Features {
+    /// A Ruby runtime can be spawned on this platform.
+    pub rails_backend: bool,
+    pub embedded_backend: bool,
+    /// The embedded server has a database and serves `/api/v1`.
+    pub embedded_api: bool,
+    /// The native `v1_*` commands have a database to work with.
+    pub native_api: bool,
+    /// The database is encrypted at rest and opens when the keystore is
+    /// unlocked.
+    pub encrypted_database: bool,
+    pub devtools: bool,
+    pub mobile: bool,
+}
+
+#[tauri::command]
+pub fn get_device_info(
+    app: AppHandle,
+    backend: State<'_, BackendStatus>,
+    api_state: State<'_, api::ApiState>,
+) -> DeviceInfo {
+    let data_dir = app
+        .path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.to_string_lossy().into_owned());
+
+    DeviceInfo {
+        platform: get_platform(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        app_version: app.package_info().version.to_string(),
+        api_version: v1::VERSION,
+        backend: backend.mode,
+        backend_port: backend.port,
+        data_dir,
+        features: Features {
+            rails_backend: cfg!(desktop),
+            embedded_backend: true,
+            embedded_api: backend.mode == BackendMode::Embedded && backend.database_path.is_some(),
+            native_api: api_state.db().is_ok(),
+            encrypted_database: app.try_state::<EncryptedLaunch>().is_some(),
+            devtools: cfg!(debug_assertions),
+            mobile: cfg!(mobile),
+        },
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::{
+    decode_base64, encode_base64, encode_hex,
+    kdf::{Argon2Params, KdfDescriptor},
+    recovery::MasterSeed,
+    rotation::{KeyRevocation, KeyRotation},
+    Error, Identity,
+};
+
+/// Directory under the app data dir holding one record per account.
+pub const ACCOUNTS_DIR: &str = "identities";
+
+/// What the device knows about an account's key derivation. The password
+/// and the secret key are never written here in the clear.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub version: u32,
+    pub username: String,
+    pub public_key: String,
+    pub kdf: KdfDescriptor,
+    /// The signing seed encrypted under the KDF output. Absent when the KDF
+    /// output is the seed itself.
+    #[serde(default)]
+    pub wrapped_seed: Option<WrappedSeed>,
+    /// Rotations this account has gone through, oldest first.
+    #[serde(default)]
+    pub rotations: Vec<KeyRotation>,
+    /// Keys this account has revoked, oldest first.
+    #[serde(default)]
+    pub revocations: Vec<KeyRevocation>,
+}
+
+/// XChaCha20-Poly1305 ciphertext of a 32-byte seed, Base64 encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedSeed {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// What [`AccountRecord::migrate_legacy`] should do with the PBKDF2 key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyMigration {
+    /// Wrap the existing seed under an Argon2id key; the public key stays.
+    KeepKey,
+    /// Derive a new key with Argon2id and sign a rotation with the old one.
+    /// The old key can still be brute-forced from its public half, so this
+    /// is the only option that fully retires it.
+    Rotate,
+}
+
+impl AccountRecord {
+    pub const VERSION: u32 = 1;
+
+    pub fn path(accounts_dir: &Path, username: &str) -> PathBuf {
+        accounts_dir.join(format!("{}.json", encode_hex(username.as_bytes())))
+    }
+
+    pub fn load(accounts_dir: &Path, username: &str) -> io::Result<Option<AccountRecord>> {
+        match fs::read(AccountRecord::path(accounts_dir, username)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the record via a temporary file so a crash can't leave a
+    /// half-written descriptor behind.
+    pub fn save(&self, accounts_dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(accounts_dir)?;
+        let path = AccountRecord::path(accounts_dir, &self.username);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(temp, path)
+    }
+
+    /// A new account whose key is derived straight from Argon2id.
+    pub fn create(
+        username: &str,
+        password: &str,
+        params: Argon2Params,
+    ) -> Result<(AccountRecord, Identity), Error> {
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        let seed = kdf.derive(username, password)?;
+        let identity = Identity::from_seed(&seed);
+        let record = AccountRecord {
+            version: AccountRecord::VERSION,
+            username: username.to_string(),
+            public_key: identity.public_key(),
+            kdf,
+            wrapped_seed: None,
+            rotations: Vec::new(),
+            revocations: Vec::new(),
+        };
+        Ok((record, identity))
+    }
+
+    /// Moves an account that still uses the Rails PBKDF2 derivation onto
+    /// Argon2id. `legacy` must be the identity derived from the same password.
+    pub fn migrate_legacy(
+        legacy: &Identity,
+        username: &str,
+        password: &str,
+        params: Argon2Params,
+        migration: LegacyMigration,
+        now: i64,
+    ) -> Result<(AccountRecord, Identity), Error> {
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        let key = kdf.derive(username, password)?;
+
+        match migration {
+            LegacyMigration::KeepKey => {
+                let seed = super::identity::legacy_seed(username, password);
+                let public_key = legacy.public_key();
+                let wrapped_seed = wrap_seed(&key, &seed, username, &public_key);
+                let record = AccountRecord {
+                    version: AccountRecord::VERSION,
+                    username: username.to_string(),
+                    public_key,
+                    kdf,
+                    wrapped_seed: Some(wrapped_seed),
+                    rotations: Vec::new(),
+                    revocations: Vec::new(),
+                };
+                Ok((record, Identity::from_seed(&seed)))
+            }
+            LegacyMigration::Rotate => {
+                let identity = Identity::from_seed(&key);
+                let rotation = KeyRotation::sign(legacy, &identity, "kdf_upgrade", now);
+                let record = AccountRecord {
+                    version: AccountRecord::VERSION,
+                    username: username.to_string(),
+                    public_key: identity.public_key(),
+                    kdf,
+                    wrapped_seed: None,
+                    rotations: vec![rotation],
+                    revocations: Vec::new(),
+                };
+                Ok((record, identity))
+            }
+        }
+    }
+
+    /// Moves the account onto the identity derived from `master`, keeping the
+    /// password as the way to unlock it on this device. The old key signs a
+    /// rotation to the new one, so the password must be correct.
+    pub fn adopt_master_seed(
+        &self,
+        password: &str,
+        master: &MasterSeed,
+        params: Argon2Params,
+        now: i64,
+    ) -> Result<(AccountRecord, Identity), Error> {
+        let current = self.unlock(password)?;
+        let identity = master.identity();
+        let mut record = self.succeeded_by(password, &identity, params)?;
+        if identity.public_key() != self.public_key {
+            record
+                .rotations
+                .push(KeyRotation::sign(&current, &identity, "recovery_seed", now));
+        }
+        Ok((record, identity))
+    }
+
+    /// Moves the account onto a fresh random key, signed over by the current
+    /// one. The recovery phrase and friends' shares no longer match it.
+    pub fn rotate(
+        &self,
+        password: &str,
+        params: Argon2Params,
+        reason: &str,
+        now: i64,
+    ) -> Result<(AccountRecord, Identity, KeyRotation), Error> {
+        let current = self.unlock(password)?;
+        let identity = random_identity();
+        let rotation = KeyRotation::sign(&current, &identity, reason, now);
+        let mut record = self.succeeded_by(password, &identity, params)?;
+        record.rotations.push(rotation.clone());
+        Ok((record, identity, rotation))
+    }
+
+    /// Like [`AccountRecord::rotate`], but also marks the current key
+    /// untrusted from `revoked_at`, for a key that may have been copied.
+    pub fn revoke(
+        &self,
+        password: &str,
+        params: Argon2Params,
+        revoked_at: i64,
+        reason: &str,
+        now: i64,
+    ) -> Result<(AccountRecord, Identity, KeyRevocation), Error> {
+        let current = self.unlock(password)?;
+        let identity = random_identity();
+        let revocation = KeyRevocation::sign(&current, &identity, revoked_at, reason, now);
+        revocation.verify()?;
+        let mut record = self.succeeded_by(password, &identity, params)?;
+        record.revocations.push(revocation.clone());
+        Ok((record, identity, revocation))
+    }
+
+    /// This account moved onto `identity`, keeping its history.
+    fn succeeded_by(
+        &self,
+        password: &str,
+        identity: &Identity,
+        params: Argon2Params,
+    ) -> Result<AccountRecord, Error> {
+        Ok(AccountRecord {
+            rotations: self.rotations.clone(),
+            revocations: self.revocations.clone(),
+            ..AccountRecord::wrapping(&self.username, password, identity, params)?
+        })
+    }
+
+    /// Rebuilds the account from its recovery phrase under a new password.
+    /// Callers check the restored key against the registered one.
+    pub fn restore(
+        username: &str,
+        password: &str,
+        master: &MasterSeed,
+        params: Argon2Params,
+    ) -> Result<(AccountRecord, Identity), Error> {
+        let identity = master.identity();
+        let record = AccountRecord::restore_identity(username, password, &identity, params)?;
+        Ok((record, identity))
+    }
+
+    /// Rebuilds the account around an identity recovered some other way,
+    /// such as from friends' shares, under a new password.
+    pub fn restore_identity(
+        username: &str,
+        password: &str,
+        identity: &Identity,
+        params: Argon2Params,
+    ) -> Result<AccountRecord, Error> {
+        AccountRecord::wrapping(username, password, identity, params)
+    }
+
+    /// A record whose seed is wrapped under an Argon2id key from `password`.
+    fn wrapping(
+        username: &str,
+        password: &str,
+        identity: &Identity,
+        params: Argon2Params,
+    ) -> Result<AccountRecord, Error> {
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        let key = kdf.derive(username, password)?;
+        let public_key = identity.public_key();
+        let wrapped_seed = wrap_seed(&key, identity.seed().expose(), username, &public_key);
+        Ok(AccountRecord {
+            version: AccountRecord::VERSION,
+            username: username.to_string(),
+            public_key,
+            kdf,
+            wrapped_seed: Some(wrapped_seed),
+            rotations: Vec::new(),
+            revocations: Vec::new(),
+        })
+    }
+
+    /// Re-derives the identity from the password, failing with
+    /// [`Error::WrongPassword`] if it doesn't reproduce the stored key.
+    pub fn unlock(&self, password: &str) -> Result<Identity, Error> {
+        let key = self.kdf.derive(&self.username, password)?;
+        let identity = match &self.wrapped_seed {
+            Some(wrapped) => {
+                let seed = unwrap_seed(&key, wrapped, &self.username, &self.public_key)?;
+                Identity::from_seed(&seed)
+            }
+            None => Identity::from_seed(&key),
+        };
+        if identity.public_key() != self.public_key {
+            return Err(Error::WrongPassword);
+        }
+        Ok(identity)
+    }
+}
+
+fn random_identity() -> Identity {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(seed.as_mut());
+    Identity::from_seed(&seed)
+}
+
+/// Binds the ciphertext to the account so a wrapped seed can't be moved to
+/// another record.
+fn associated_data(username: &str, public_key: &str) -> Vec<u8> {
+    format!("cipher-wrapped-seed\n{}\n{}", username, public_key).into_bytes()
+}
+
+fn wrap_seed(key: &[u8; 32], seed: &[u8; 32], username: &str, public_key: &str) -> WrappedSeed {
+    let mut nonce = [0u8; 24];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: seed,
+                aad: &associated_data(username, public_key),
+            },
+        )
+        .expect("XChaCha20-Poly1305 encryption of 32 bytes cannot fail");
+    WrappedSeed {
+        nonce: encode_base64(&nonce),
+        ciphertext: encode_base64(&ciphertext),
+    }
+}
+
+fn unwrap_seed(
+    key: &[u8; 32],
+    wrapped: &WrappedSeed,
+    username: &str,
+    public_key: &str,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let nonce = decode_base64(&wrapped.nonce, "nonce")?;
+    if nonce.len() != 24 {
+        return Err(Error::Malformed("nonce"));
+    }
+    let ciphertext = decode_base64(&wrapped.ciphertext, "wrapped seed")?;
+    let seed = Zeroizing::new(
+        XChaCha20Poly1305::new(key.into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &associated_data(username, public_key),
+                },
+            )
+            .map_err(|_| Error::WrongPassword)?,
+    );
+    let mut out = Zeroizing::new([0u8; 32]);
+    if seed.len() != 32 {
+        return Err(Error::Malformed("wrapped seed"));
+    }
+    out.copy_from_slice(&seed);
+    Ok(out)
+}
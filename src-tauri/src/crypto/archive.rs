@@ -0,0 +1,183 @@
+//! The sealed container an account archive travels in. The contents are
+//! encrypted under a passphrase chosen for the export (Argon2id, then
+//! XChaCha20-Poly1305), and the container is signed by the account's key so
+//! an archive can be checked before anything in it is trusted.
+//!
+//! Inside, the plaintext is a big-endian `u32` manifest length, the JSON
+//! manifest, then the account's keystore entries in the keystore's own flat
+//! format. Secrets never pass through a JSON string.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::{
+    decode_base64, encode_base64, encode_hex, identity,
+    kdf::{Argon2Params, KdfDescriptor},
+    keystore::{decode_entries, encode_entries},
+    secret::Secret,
+    Error, Identity,
+};
+
+/// Value of [`SealedArchive::format`], so other JSON files are told apart.
+pub const ARCHIVE_FORMAT: &str = "cipher-account-archive";
+
+/// An archive file as written to disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedArchive {
+    pub format: String,
+    pub version: u32,
+    /// The account's key when the archive was made; it signed the archive.
+    pub public_key: String,
+    /// Unix seconds.
+    pub created_at: i64,
+    /// How the key is derived from the export passphrase.
+    pub kdf: KdfDescriptor,
+    pub nonce: String,
+    pub ciphertext: String,
+    /// Signature over [`SealedArchive::signing_bytes`].
+    pub signature: String,
+}
+
+/// What a sealed archive holds once opened.
+pub struct ArchiveContents {
+    /// The JSON manifest.
+    pub manifest: Zeroizing<Vec<u8>>,
+    /// Keystore entries by name.
+    pub secrets: BTreeMap<String, Secret<Vec<u8>>>,
+}
+
+impl SealedArchive {
+    pub const VERSION: u32 = 1;
+
+    /// Encrypts `contents` under `passphrase` and signs the result as
+    /// `identity`.
+    pub fn seal(
+        identity: &Identity,
+        passphrase: &str,
+        params: Argon2Params,
+        contents: &ArchiveContents,
+        created_at: i64,
+    ) -> Result<SealedArchive, Error> {
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        let key = kdf.derive("", passphrase)?;
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+
+        let entries = encode_entries(&contents.secrets)?;
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(
+            4 + contents.manifest.len() + entries.len(),
+        ));
+        plaintext.extend_from_slice(&(contents.manifest.len() as u32).to_be_bytes());
+        plaintext.extend_from_slice(&contents.manifest);
+        plaintext.extend_from_slice(&entries);
+
+        let mut archive = SealedArchive {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: SealedArchive::VERSION,
+            public_key: identity.public_key(),
+            created_at,
+            kdf,
+            nonce: encode_base64(&nonce),
+            ciphertext: String::new(),
+            signature: String::new(),
+        };
+        let ciphertext = XChaCha20Poly1305::new((&*key).into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &archive.associated_data(),
+                },
+            )
+            .map_err(|_| Error::Malformed("archive"))?;
+        archive.ciphertext = encode_base64(&ciphertext);
+        archive.signature = identity.sign_base64(&archive.signing_bytes());
+        Ok(archive)
+    }
+
+    /// The header the ciphertext is bound to, so it can't be moved under
+    /// another account's signature.
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}",
+            ARCHIVE_FORMAT, self.version, self.public_key, self.created_at
+        )
+        .into_bytes()
+    }
+
+    /// The header, the KDF, the nonce and the SHA-256 of the ciphertext.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let kdf = serde_json::to_string(&self.kdf).expect("kdf serializes to JSON");
+        let digest = encode_hex(&Sha256::digest(self.ciphertext.as_bytes()));
+        let mut bytes = self.associated_data();
+        bytes.extend_from_slice(format!("\n{}\n{}\n{}", kdf, self.nonce, digest).as_bytes());
+        bytes
+    }
+
+    /// Checks the format, version and signature. Needs no passphrase.
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.format != ARCHIVE_FORMAT || self.version != SealedArchive::VERSION {
+            return Err(Error::Malformed("archive version"));
+        }
+        if self.kdf.is_legacy() {
+            return Err(Error::Malformed("kdf"));
+        }
+        identity::verify(&self.public_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// Verifies the archive, then decrypts it. A wrong passphrase fails with
+    /// [`Error::DecryptionFailed`].
+    pub fn open(&self, passphrase: &str) -> Result<ArchiveContents, Error> {
+        self.verify()?;
+        let key = self.kdf.derive("", passphrase)?;
+        let nonce = decode_base64(&self.nonce, "nonce")?;
+        if nonce.len() != 24 {
+            return Err(Error::Malformed("nonce"));
+        }
+        let ciphertext = decode_base64(&self.ciphertext, "archive")?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new((&*key).into())
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &self.associated_data(),
+                    },
+                )
+                .map_err(|_| Error::DecryptionFailed)?,
+        );
+
+        let length = plaintext
+            .get(..4)
+            .map(|prefix| u32::from_be_bytes(prefix.try_into().unwrap()) as usize)
+            .filter(|&length| length <= plaintext.len() - 4)
+            .ok_or(Error::Malformed("archive"))?;
+        Ok(ArchiveContents {
+            manifest: Zeroizing::new(plaintext[4..4 + length].to_vec()),
+            secrets: decode_entries(&plaintext[4 + length..])?,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<SealedArchive> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes the archive via a temporary file next to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(self)?)?;
+        fs::rename(temp, path)
+    }
+}
@@ -0,0 +1,315 @@
+//! Friend circles. A circle's owner encrypts each post once under the
+//! circle's current epoch key instead of sealing a content key to every
+//! member, so envelopes stay the same size and don't list who can read them.
+//!
+//! Membership changes move the circle to a new epoch. Adding a member hashes
+//! the key forward: existing members derive the new key themselves, and the
+//! newcomer, who only gets the new key, can't read older posts. Removing a
+//! member starts from a fresh random key, sealed to each remaining member, so
+//! the removed member can't read anything after it. Keys within an epoch are
+//! kept, since posts are read more than once.
+
+use std::collections::BTreeMap;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+use super::{
+    decode_base64, encode_base64,
+    envelope::{self, WrappedKey, CIPHER_XCHACHA20POLY1305},
+    identity,
+    secret::StoredKey,
+    Error, Identity,
+};
+
+/// Most epochs a reader will hash a key forward, which bounds the work a
+/// forged epoch number can cause.
+pub const MAX_EPOCH_GAP: u32 = 1000;
+
+const EPOCH_INFO: &[u8] = b"cipher-circle-epoch-v1";
+const CONTENT_INFO: &[u8] = b"cipher-circle-content-v1";
+
+/// How a circle reached its current epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpochChange {
+    /// The key was hashed forward; only new members need it.
+    Advanced,
+    /// The key is new; every member needs it.
+    Reset,
+}
+
+/// A circle's epoch key sealed to one member and signed by the owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CircleKey {
+    pub version: u32,
+    /// Base64, 16 random bytes chosen by the owner.
+    pub circle_id: String,
+    pub owner_key: String,
+    pub epoch: u32,
+    pub key: WrappedKey,
+    /// Owner's signature over [`CircleKey::signing_bytes`].
+    pub signature: String,
+}
+
+impl CircleKey {
+    pub const VERSION: u32 = 1;
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-circle-key\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.circle_id,
+            self.owner_key,
+            self.epoch,
+            self.key.public_key,
+            self.key.wrapped_key
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != CircleKey::VERSION || self.epoch == 0 {
+            return Err(Error::Malformed("circle key"));
+        }
+        identity::verify(&self.owner_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// The base64 public key this was sealed to.
+    pub fn recipient_key(&self) -> &str {
+        &self.key.public_key
+    }
+}
+
+/// A post body encrypted to a circle, stored as JSON in
+/// `posts.content_encrypted` like a [`super::envelope::PostEnvelope`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CircleEnvelope {
+    pub version: u32,
+    pub cipher: String,
+    pub circle_id: String,
+    pub owner_key: String,
+    pub epoch: u32,
+    /// Base64, 24 bytes.
+    pub nonce: String,
+    /// Base64 ciphertext with the Poly1305 tag appended.
+    pub ciphertext: String,
+}
+
+impl CircleEnvelope {
+    pub const VERSION: u32 = 1;
+
+    /// Decrypts with keys received from the owner, using the newest one
+    /// sealed to `identity` at or before the envelope's epoch. Fails with
+    /// [`Error::NotRecipient`] if none can open it, as after a removal.
+    pub fn open_with(
+        &self,
+        keys: &[CircleKey],
+        identity: &Identity,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let public_key = identity.public_key();
+        let key = keys
+            .iter()
+            .filter(|key| {
+                key.circle_id == self.circle_id
+                    && key.owner_key == self.owner_key
+                    && key.key.public_key == public_key
+                    && key.epoch <= self.epoch
+                    && key.verify().is_ok()
+            })
+            .max_by_key(|key| key.epoch)
+            .ok_or(Error::NotRecipient)?;
+        let epoch_key = envelope::unwrap_key(std::slice::from_ref(&key.key), identity)?;
+        let epoch_key = advance_to(&epoch_key, key.epoch, self.epoch)?;
+        self.open_with_key(&epoch_key)
+            .map_err(|_| Error::NotRecipient)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("circle envelope serializes to JSON")
+    }
+
+    pub fn from_json(content_encrypted: &str) -> Result<CircleEnvelope, Error> {
+        serde_json::from_str(content_encrypted).map_err(|_| Error::Malformed("circle envelope"))
+    }
+
+    fn open_with_key(&self, epoch_key: &[u8; 32]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if self.version != Self::VERSION || self.cipher != CIPHER_XCHACHA20POLY1305 {
+            return Err(Error::Malformed("circle envelope version"));
+        }
+        let nonce = decode_base64(&self.nonce, "nonce")?;
+        if nonce.len() != 24 {
+            return Err(Error::Malformed("nonce"));
+        }
+        let ciphertext = decode_base64(&self.ciphertext, "ciphertext")?;
+        XChaCha20Poly1305::new(content_key(epoch_key).as_ref().into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &self.associated_data(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| Error::DecryptionFailed)
+    }
+
+    /// Binds the circle, owner and epoch into the tag.
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "cipher-circle-envelope\n{}\n{}\n{}\n{}",
+            self.version, self.circle_id, self.owner_key, self.epoch
+        )
+        .into_bytes()
+    }
+}
+
+/// The owner's secret state for one circle: every epoch key it has started
+/// from, so the owner can still read their older posts.
+#[derive(Serialize, Deserialize)]
+pub struct CircleKeys {
+    circle_id: String,
+    epoch: u32,
+    /// Epochs that began with a fresh key, and that key.
+    resets: BTreeMap<u32, StoredKey>,
+}
+
+impl CircleKeys {
+    pub fn generate() -> CircleKeys {
+        let mut circle_id = [0u8; 16];
+        OsRng.fill_bytes(&mut circle_id);
+        CircleKeys {
+            circle_id: encode_base64(&circle_id),
+            epoch: 1,
+            resets: BTreeMap::from([(1, StoredKey::generate())]),
+        }
+    }
+
+    pub fn circle_id(&self) -> &str {
+        &self.circle_id
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Moves to the next epoch. Advance when adding members and reset when
+    /// removing them.
+    pub fn rotate(&mut self, change: EpochChange) -> u32 {
+        self.epoch += 1;
+        if change == EpochChange::Reset {
+            self.resets.insert(self.epoch, StoredKey::generate());
+        }
+        self.epoch
+    }
+
+    /// The current epoch key sealed to each of `members` and signed.
+    pub fn distribute(
+        &self,
+        owner: &Identity,
+        members: &[String],
+    ) -> Result<Vec<CircleKey>, Error> {
+        let key = self.key_at(self.epoch)?;
+        let owner_key = owner.public_key();
+        envelope::wrap_key(&key, members)?
+            .into_iter()
+            .map(|wrapped| {
+                let mut circle_key = CircleKey {
+                    version: CircleKey::VERSION,
+                    circle_id: self.circle_id.clone(),
+                    owner_key: owner_key.clone(),
+                    epoch: self.epoch,
+                    key: wrapped,
+                    signature: String::new(),
+                };
+                circle_key.signature = owner.sign_base64(&circle_key.signing_bytes());
+                Ok(circle_key)
+            })
+            .collect()
+    }
+
+    /// Encrypts `plaintext` under the current epoch.
+    pub fn seal(&self, owner: &Identity, plaintext: &[u8]) -> Result<CircleEnvelope, Error> {
+        let key = self.key_at(self.epoch)?;
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let mut envelope = CircleEnvelope {
+            version: CircleEnvelope::VERSION,
+            cipher: CIPHER_XCHACHA20POLY1305.to_string(),
+            circle_id: self.circle_id.clone(),
+            owner_key: owner.public_key(),
+            epoch: self.epoch,
+            nonce: encode_base64(&nonce),
+            ciphertext: String::new(),
+        };
+        let ciphertext = XChaCha20Poly1305::new(content_key(&key).as_ref().into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &envelope.associated_data(),
+                },
+            )
+            .map_err(|_| Error::Malformed("content"))?;
+        envelope.ciphertext = encode_base64(&ciphertext);
+        Ok(envelope)
+    }
+
+    /// Opens a post the owner sealed to this circle at any epoch.
+    pub fn open(&self, envelope: &CircleEnvelope) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if envelope.circle_id != self.circle_id || envelope.epoch > self.epoch {
+            return Err(Error::NotRecipient);
+        }
+        envelope.open_with_key(&*self.key_at(envelope.epoch)?)
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(serde_json::to_vec(self).expect("circle keys serialize"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<CircleKeys, Error> {
+        serde_json::from_slice(bytes).map_err(|_| Error::Malformed("circle keys"))
+    }
+
+    fn key_at(&self, epoch: u32) -> Result<Zeroizing<[u8; 32]>, Error> {
+        let (&start, key) = self
+            .resets
+            .range(..=epoch)
+            .next_back()
+            .ok_or(Error::Malformed("circle epoch"))?;
+        advance_to(key.expose(), start, epoch)
+    }
+}
+
+/// Hashes the key for epoch `from` forward to epoch `to`.
+fn advance_to(key: &[u8; 32], from: u32, to: u32) -> Result<Zeroizing<[u8; 32]>, Error> {
+    if to < from || to - from > MAX_EPOCH_GAP {
+        return Err(Error::Malformed("circle epoch"));
+    }
+    let mut key = Zeroizing::new(*key);
+    for _ in from..to {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(key.as_ref()).expect("HMAC takes any key size");
+        mac.update(EPOCH_INFO);
+        key.copy_from_slice(&mac.finalize().into_bytes());
+    }
+    Ok(key)
+}
+
+fn content_key(epoch_key: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, epoch_key)
+        .expand(CONTENT_INFO, key.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
@@ -0,0 +1,95 @@
+//! Content addressing for posts. `posts.content_hash` names a post by what
+//! was signed, so the same post synced through different devices or
+//! backends is recognised as a duplicate. Rails calls the same routine
+//! through [`crate::ffi::cipher_content_hash`], or its Ruby port in
+//! `app/services/content_hash.rb`; `test-vectors/content_hash.json` keeps
+//! them in step.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+
+use super::{encode_hex, signed_post::field};
+
+const DOMAIN: &[u8] = b"cipher-content-hash\0";
+
+/// The parts of a post its hash covers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PostContent {
+    /// `posts.content_encrypted` exactly as stored.
+    #[serde(default)]
+    pub content_encrypted: String,
+    /// Unix seconds, UTC.
+    #[ts(type = "number")]
+    pub timestamp: i64,
+    #[serde(default)]
+    pub signature: String,
+    /// Lowercase hex SHA-256 checksums of the attachments, in any order.
+    #[serde(default)]
+    pub attachment_checksums: Vec<String>,
+}
+
+impl PostContent {
+    pub const VERSION: u32 = 1;
+
+    /// The bytes that are hashed:
+    ///
+    /// ```text
+    /// "cipher-content-hash" 0x00
+    /// u32 version
+    /// field(content_encrypted)
+    /// i64 timestamp
+    /// field(signature)
+    /// u32 count, then field(checksum) for each, sorted and deduplicated
+    /// ```
+    ///
+    /// Encoded as in [`super::signed_post::SignedPost::canonical_bytes`].
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut checksums: Vec<&str> = self
+            .attachment_checksums
+            .iter()
+            .map(String::as_str)
+            .collect();
+        checksums.sort_unstable();
+        checksums.dedup();
+
+        let mut out = Vec::with_capacity(128 + self.content_encrypted.len());
+        out.extend_from_slice(DOMAIN);
+        out.extend_from_slice(&Self::VERSION.to_be_bytes());
+        field(&mut out, &self.content_encrypted);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        field(&mut out, &self.signature);
+        out.extend_from_slice(&(checksums.len() as u32).to_be_bytes());
+        for checksum in checksums {
+            field(&mut out, checksum);
+        }
+        out
+    }
+
+    /// Lowercase hex SHA-256 of [`PostContent::canonical_bytes`].
+    pub fn hash(&self) -> String {
+        encode_hex(&Sha256::digest(self.canonical_bytes()))
+    }
+
+    /// The hash `Post#generate_content_hash` wrote before this one: SHA-256
+    /// over the fields concatenated, which two different posts can share.
+    pub fn legacy_hash(&self) -> String {
+        let mut checksums = self.attachment_checksums.clone();
+        checksums.sort();
+        let joined = format!(
+            "{}{}{}{}",
+            self.content_encrypted,
+            self.timestamp,
+            self.signature,
+            checksums.concat()
+        );
+        encode_hex(&Sha256::digest(joined.as_bytes()))
+    }
+
+    /// Whether `hash` names this content, by either scheme, so rows stored
+    /// before the canonical hash still check out.
+    pub fn matches(&self, hash: &str) -> bool {
+        hash == self.hash() || hash == self.legacy_hash()
+    }
+}
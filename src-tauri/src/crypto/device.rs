@@ -0,0 +1,179 @@
+//! Device keys. A linked device signs with its own Ed25519 key, which the
+//! account's root key certifies, so the account key never has to be copied
+//! onto a new device to link it. A lost device is cut off with a
+//! [`DeviceRevocation`], signed by the root key or by another of the
+//! account's devices, without giving up the account.
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{identity, Error, Identity};
+
+/// What a device key may sign for the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum DeviceCapability {
+    Post,
+    /// Revoke the account's other devices.
+    Revoke,
+}
+
+impl DeviceCapability {
+    pub const ALL: [DeviceCapability; 2] = [DeviceCapability::Post, DeviceCapability::Revoke];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceCapability::Post => "post",
+            DeviceCapability::Revoke => "revoke",
+        }
+    }
+}
+
+/// A statement by `root_key` that `device_key` is one of its devices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceCertificate {
+    pub version: u32,
+    pub root_key: String,
+    pub device_key: String,
+    /// Shown in device lists, e.g. `Pixel 8`.
+    pub device_name: String,
+    /// Sorted, without duplicates.
+    pub capabilities: Vec<DeviceCapability>,
+    /// Unix seconds. The device key signs nothing valid from before this.
+    #[ts(type = "number")]
+    pub created_at: i64,
+    /// Root key's signature over [`DeviceCertificate::signing_bytes`].
+    pub signature: String,
+}
+
+impl DeviceCertificate {
+    pub const VERSION: u32 = 1;
+
+    /// Certifies `device_key`. Check the device holds the key first, e.g.
+    /// by a signature from it, as device linking does.
+    pub fn sign(
+        root: &Identity,
+        device_key: &str,
+        device_name: &str,
+        capabilities: &[DeviceCapability],
+        created_at: i64,
+    ) -> DeviceCertificate {
+        let mut capabilities = capabilities.to_vec();
+        capabilities.sort();
+        capabilities.dedup();
+        let mut certificate = DeviceCertificate {
+            version: DeviceCertificate::VERSION,
+            root_key: root.public_key(),
+            device_key: device_key.to_string(),
+            device_name: device_name.to_string(),
+            capabilities,
+            created_at,
+            signature: String::new(),
+        };
+        certificate.signature = root.sign_base64(&certificate.signing_bytes());
+        certificate
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let capabilities: Vec<&str> = self.capabilities.iter().map(|c| c.as_str()).collect();
+        format!(
+            "cipher-device-certificate\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.root_key,
+            self.device_key,
+            self.created_at,
+            capabilities.join(","),
+            self.device_name
+        )
+        .into_bytes()
+    }
+
+    pub fn allows(&self, capability: DeviceCapability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != DeviceCertificate::VERSION || self.device_name.contains('\n') {
+            return Err(Error::Malformed("device certificate"));
+        }
+        identity::decode_public_key(&self.device_key)?;
+        identity::verify(&self.root_key, &self.signing_bytes(), &self.signature)
+    }
+}
+
+/// Withdraws a device key from `revoked_at` on. Whether `signer_key` may
+/// revoke it depends on the account's certificates, so only the signature
+/// is checked here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeviceRevocation {
+    pub version: u32,
+    pub root_key: String,
+    pub device_key: String,
+    /// Unix seconds from which the device key is untrusted. Usually before
+    /// `created_at`, to cover the time since the device was lost.
+    #[ts(type = "number")]
+    pub revoked_at: i64,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub created_at: i64,
+    /// The root key, or another of the account's device keys.
+    pub signer_key: String,
+    /// Signer's signature over [`DeviceRevocation::signing_bytes`].
+    pub signature: String,
+}
+
+impl DeviceRevocation {
+    pub const VERSION: u32 = 1;
+
+    pub fn sign(
+        signer: &Identity,
+        root_key: &str,
+        device_key: &str,
+        revoked_at: i64,
+        created_at: i64,
+    ) -> DeviceRevocation {
+        let mut revocation = DeviceRevocation {
+            version: DeviceRevocation::VERSION,
+            root_key: root_key.to_string(),
+            device_key: device_key.to_string(),
+            revoked_at,
+            created_at,
+            signer_key: signer.public_key(),
+            signature: String::new(),
+        };
+        revocation.signature = signer.sign_base64(&revocation.signing_bytes());
+        revocation
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-device-revocation\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.root_key,
+            self.device_key,
+            self.revoked_at,
+            self.created_at,
+            self.signer_key
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != DeviceRevocation::VERSION {
+            return Err(Error::Malformed("device revocation"));
+        }
+        identity::verify(&self.signer_key, &self.signing_bytes(), &self.signature)
+    }
+}
+
+/// A change to an account's devices, as sent to peers over sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum DeviceEvent {
+    Certificate(DeviceCertificate),
+    Revocation(DeviceRevocation),
+}
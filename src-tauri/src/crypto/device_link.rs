@@ -0,0 +1,445 @@
+//! Linking a new device to an account. The existing device shows a
+//! [`PairingOffer`] as a QR code: where to reach it on the local network,
+//! the account's root key and a one-time secret. The new device connects
+//! and the two run an X25519 exchange with both halves authenticated by
+//! HMACs under that secret, so only a device that read the code can take
+//! part and a relay in between learns nothing. The existing device then
+//! sends a [`DeviceCertificate`] for the new device's key and the account's
+//! data, encrypted under the exchanged key.
+//!
+//! Messages go over one TCP connection as big-endian `u32` length-prefixed
+//! JSON: a [`LinkRequest`] from the new device, then a [`LinkResponse`].
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use rand::{rngs::OsRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::{
+    archive::ArchiveContents,
+    decode_base64,
+    device::{DeviceCapability, DeviceCertificate},
+    encode_base64, identity,
+    keystore::{decode_entries, encode_entries},
+    secret::Secret,
+    Error, Identity,
+};
+
+pub const VERSION: u32 = 1;
+
+const QR_PREFIX: &str = "cipher-device-link";
+
+/// Largest message either side reads, to bound memory for a hostile peer.
+/// Account data with attachments can be large.
+pub const MAX_MESSAGE_BYTES: usize = 512 * 1024 * 1024;
+
+/// What the existing device's QR code carries. The secret works for one
+/// link, until `expires_at`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PairingOffer {
+    pub address: String,
+    pub port: u16,
+    pub root_key: String,
+    /// Unix seconds.
+    pub expires_at: i64,
+    secret: Secret<[u8; 32]>,
+}
+
+impl PairingOffer {
+    /// An offer with a fresh random secret.
+    pub fn new(root_key: &str, address: &str, port: u16, expires_at: i64) -> PairingOffer {
+        let mut secret = Secret::zeroed();
+        OsRng.fill_bytes(secret.expose_mut());
+        PairingOffer {
+            address: address.to_string(),
+            port,
+            root_key: root_key.to_string(),
+            expires_at,
+            secret,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+
+    /// `cipher-device-link:1:<root key>:<secret>:<expires at>@<address>:<port>`.
+    /// An IPv6 address goes in brackets.
+    pub fn qr_payload(&self) -> String {
+        let host = if self.address.contains(':') {
+            format!("[{}]", self.address)
+        } else {
+            self.address.clone()
+        };
+        format!(
+            "{}:{}:{}:{}:{}@{}:{}",
+            QR_PREFIX,
+            VERSION,
+            self.root_key,
+            encode_base64(self.secret.expose()),
+            self.expires_at,
+            host,
+            self.port
+        )
+    }
+
+    pub fn qr_svg(&self) -> String {
+        QrCode::new(self.qr_payload())
+            .expect("a pairing offer fits in a QR code")
+            .render::<svg::Color>()
+            .min_dimensions(240, 240)
+            .build()
+    }
+
+    /// Reads a scanned or pasted [`PairingOffer::qr_payload`].
+    pub fn parse(scanned: &str) -> Result<PairingOffer, Error> {
+        let malformed = || Error::Malformed("pairing code");
+        let rest = scanned
+            .trim()
+            .strip_prefix(&format!("{}:{}:", QR_PREFIX, VERSION))
+            .ok_or_else(malformed)?;
+        let (keys, location) = rest.split_once('@').ok_or_else(malformed)?;
+        let mut fields = keys.split(':');
+        let (Some(root_key), Some(secret), Some(expires_at), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(malformed());
+        };
+        identity::decode_public_key(root_key)?;
+        let secret: [u8; 32] = decode_base64(secret, "pairing secret")?
+            .try_into()
+            .map_err(|_| Error::Malformed("pairing secret"))?;
+        let (host, port) = location.rsplit_once(':').ok_or_else(malformed)?;
+        let address = host.trim_start_matches('[').trim_end_matches(']');
+        if address.is_empty() {
+            return Err(malformed());
+        }
+        Ok(PairingOffer {
+            address: address.to_string(),
+            port: port.parse().map_err(|_| malformed())?,
+            root_key: root_key.to_string(),
+            expires_at: expires_at.parse().map_err(|_| malformed())?,
+            secret: Secret::new(secret),
+        })
+    }
+
+    fn mac(&self, label: &str, transcript: &[u8]) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.secret.expose())
+            .expect("HMAC takes any key size");
+        mac.update(label.as_bytes());
+        mac.update(b"\n");
+        mac.update(transcript);
+        mac
+    }
+
+    fn check_mac(&self, label: &str, transcript: &[u8], tag: &str) -> Result<(), Error> {
+        let tag = decode_base64(tag, "mac")?;
+        self.mac(label, transcript)
+            .verify_slice(&tag)
+            .map_err(|_| Error::BadSignature)
+    }
+
+    /// The key both sides encrypt the account data under.
+    fn session_key(&self, shared: &[u8; 32], transcript: &[u8]) -> Zeroizing<[u8; 32]> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(Some(self.secret.expose()), shared)
+            .expand(transcript, key.as_mut())
+            .expect("32 bytes is a valid HKDF-SHA256 length");
+        key
+    }
+}
+
+impl fmt::Debug for PairingOffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PairingOffer")
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("root_key", &self.root_key)
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The new device's half of the exchange.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkRequest {
+    pub version: u32,
+    pub ephemeral_key: String,
+    /// The key the new device wants certified.
+    pub device_key: String,
+    pub device_name: String,
+    /// HMAC-SHA256 under the pairing secret over [`LinkRequest::transcript`].
+    pub mac: String,
+    /// The device key's signature over the same transcript, proving the new
+    /// device holds it.
+    pub device_signature: String,
+}
+
+impl LinkRequest {
+    fn transcript(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            QR_PREFIX, self.version, self.ephemeral_key, self.device_key, self.device_name
+        )
+        .into_bytes()
+    }
+}
+
+/// The existing device's half, with the certificate and account data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkResponse {
+    pub version: u32,
+    pub ephemeral_key: String,
+    /// HMAC-SHA256 under the pairing secret over the request's transcript
+    /// and `ephemeral_key`.
+    pub mac: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// What the new device receives.
+pub struct LinkBundle {
+    pub certificate: DeviceCertificate,
+    pub contents: ArchiveContents,
+}
+
+/// The request transcript followed by the existing device's ephemeral key.
+/// Both MACs and the session key cover it.
+fn full_transcript(request: &LinkRequest, ephemeral_key: &str) -> Vec<u8> {
+    let mut transcript = request.transcript();
+    transcript.extend_from_slice(format!("\n{}", ephemeral_key).as_bytes());
+    transcript
+}
+
+fn public_key_bytes(value: &str) -> Result<[u8; 32], Error> {
+    decode_base64(value, "ephemeral key")?
+        .try_into()
+        .map_err(|_| Error::Malformed("ephemeral key"))
+}
+
+/// Rejects low-order keys, whose output the other side doesn't influence.
+fn dh(secret: &StaticSecret, public: &str) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let shared = secret.diffie_hellman(&PublicKey::from(public_key_bytes(public)?));
+    if !shared.was_contributory() {
+        return Err(Error::InvalidKey);
+    }
+    Ok(Zeroizing::new(shared.to_bytes()))
+}
+
+fn random_identity() -> Identity {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(seed.as_mut());
+    Identity::from_seed(&seed)
+}
+
+/// The new device: holds its fresh device key until the link completes.
+pub struct Joiner {
+    offer: PairingOffer,
+    ephemeral: StaticSecret,
+    device: Identity,
+    request: LinkRequest,
+}
+
+impl Joiner {
+    pub fn new(offer: PairingOffer, device_name: &str) -> Result<Joiner, Error> {
+        if device_name.contains('\n') {
+            return Err(Error::Malformed("device name"));
+        }
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let device = random_identity();
+        let mut request = LinkRequest {
+            version: VERSION,
+            ephemeral_key: encode_base64(PublicKey::from(&ephemeral).as_bytes()),
+            device_key: device.public_key(),
+            device_name: device_name.to_string(),
+            mac: String::new(),
+            device_signature: String::new(),
+        };
+        let transcript = request.transcript();
+        request.mac = encode_base64(&offer.mac("request", &transcript).finalize().into_bytes());
+        request.device_signature = device.sign_base64(&transcript);
+        Ok(Joiner {
+            offer,
+            ephemeral,
+            device,
+            request,
+        })
+    }
+
+    pub fn request(&self) -> &LinkRequest {
+        &self.request
+    }
+
+    /// Checks the response came from the device that showed the code and
+    /// opens it. Returns the new device's key and what it was sent.
+    pub fn finish(self, response: &LinkResponse) -> Result<(Identity, LinkBundle), Error> {
+        if response.version != VERSION {
+            return Err(Error::Malformed("link version"));
+        }
+        let transcript = full_transcript(&self.request, &response.ephemeral_key);
+        self.offer
+            .check_mac("response", &transcript, &response.mac)?;
+        let shared = dh(&self.ephemeral, &response.ephemeral_key)?;
+        let key = self.offer.session_key(&shared, &transcript);
+
+        let nonce = decode_base64(&response.nonce, "nonce")?;
+        if nonce.len() != 24 {
+            return Err(Error::Malformed("nonce"));
+        }
+        let ciphertext = decode_base64(&response.ciphertext, "link")?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new((&*key).into())
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &transcript,
+                    },
+                )
+                .map_err(|_| Error::DecryptionFailed)?,
+        );
+
+        let (certificate, rest) = split_frame(&plaintext)?;
+        let certificate: DeviceCertificate =
+            serde_json::from_slice(certificate).map_err(|_| Error::Malformed("certificate"))?;
+        certificate.verify()?;
+        if certificate.root_key != self.offer.root_key
+            || certificate.device_key != self.device.public_key()
+        {
+            return Err(Error::Malformed("certificate"));
+        }
+        let (manifest, entries) = split_frame(rest)?;
+        let bundle = LinkBundle {
+            certificate,
+            contents: ArchiveContents {
+                manifest: Zeroizing::new(manifest.to_vec()),
+                secrets: decode_entries(entries)?,
+            },
+        };
+        Ok((self.device, bundle))
+    }
+}
+
+/// The existing device: checks `request` against its offer, certifies the
+/// new device's key as `root`, with `capabilities`, and encrypts
+/// `contents` for it.
+pub fn respond(
+    offer: &PairingOffer,
+    root: &Identity,
+    request: &LinkRequest,
+    capabilities: &[DeviceCapability],
+    contents: &ArchiveContents,
+    now: i64,
+) -> Result<(LinkResponse, DeviceCertificate), Error> {
+    if offer.is_expired(now) {
+        return Err(Error::Malformed("expired pairing code"));
+    }
+    if request.version != VERSION || request.device_name.contains('\n') {
+        return Err(Error::Malformed("link request"));
+    }
+    let request_transcript = request.transcript();
+    offer.check_mac("request", &request_transcript, &request.mac)?;
+    identity::verify(
+        &request.device_key,
+        &request_transcript,
+        &request.device_signature,
+    )?;
+    if root.public_key() != offer.root_key {
+        return Err(Error::InvalidKey);
+    }
+
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_key = encode_base64(PublicKey::from(&ephemeral).as_bytes());
+    let transcript = full_transcript(request, &ephemeral_key);
+    let shared = dh(&ephemeral, &request.ephemeral_key)?;
+    let key = offer.session_key(&shared, &transcript);
+
+    let certificate = DeviceCertificate::sign(
+        root,
+        &request.device_key,
+        &request.device_name,
+        capabilities,
+        now,
+    );
+    let certificate_json =
+        serde_json::to_vec(&certificate).map_err(|_| Error::Malformed("certificate"))?;
+
+    let entries = encode_entries(&contents.secrets)?;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(
+        8 + certificate_json.len() + contents.manifest.len() + entries.len(),
+    ));
+    push_frame(&mut plaintext, &certificate_json);
+    push_frame(&mut plaintext, &contents.manifest);
+    plaintext.extend_from_slice(&entries);
+
+    let mut nonce = [0u8; 24];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = XChaCha20Poly1305::new((&*key).into())
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &transcript,
+            },
+        )
+        .map_err(|_| Error::Malformed("link"))?;
+    let response = LinkResponse {
+        version: VERSION,
+        mac: encode_base64(&offer.mac("response", &transcript).finalize().into_bytes()),
+        ephemeral_key,
+        nonce: encode_base64(&nonce),
+        ciphertext: encode_base64(&ciphertext),
+    };
+    Ok((response, certificate))
+}
+
+fn push_frame(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn split_frame(bytes: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let length = bytes
+        .get(..4)
+        .map(|prefix| u32::from_be_bytes(prefix.try_into().unwrap()) as usize)
+        .filter(|&length| length <= bytes.len() - 4)
+        .ok_or(Error::Malformed("link"))?;
+    Ok((&bytes[4..4 + length], &bytes[4 + length..]))
+}
+
+/// Writes one length-prefixed JSON message.
+pub fn write_message(stream: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let json = serde_json::to_vec(message)?;
+    stream.write_all(&(json.len() as u32).to_be_bytes())?;
+    stream.write_all(&json)?;
+    stream.flush()
+}
+
+/// Reads one length-prefixed JSON message of at most [`MAX_MESSAGE_BYTES`].
+pub fn read_message<T: DeserializeOwned>(stream: &mut impl Read) -> io::Result<T> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too large",
+        ));
+    }
+    let mut json = vec![0u8; length];
+    stream.read_exact(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
@@ -0,0 +1,173 @@
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+use super::{decode_base64, encode_base64, identity::box_public_key, Error, Identity};
+
+/// Content cipher of version 1 envelopes. Matches RbNaCl's
+/// `AEAD::XChaCha20Poly1305IETF`.
+pub const CIPHER_XCHACHA20POLY1305: &str = "xchacha20poly1305";
+
+/// Encrypted post content, stored as JSON in `posts.content_encrypted`.
+///
+/// The body is encrypted once under a fresh random key, and that key is
+/// sealed (`crypto_box_seal`) to the X25519 form of each recipient's Ed25519
+/// public key. Nothing stored alongside it can open the content; only a
+/// recipient's secret key can.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PostEnvelope {
+    pub version: u32,
+    pub cipher: String,
+    /// Base64, 24 bytes.
+    pub nonce: String,
+    /// Base64 ciphertext with the Poly1305 tag appended.
+    pub ciphertext: String,
+    pub recipients: Vec<WrappedKey>,
+}
+
+/// The content key sealed to one recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WrappedKey {
+    /// Base64 Ed25519 public key, as stored in `users.public_key`.
+    pub public_key: String,
+    /// Base64 sealed box of the 32-byte content key.
+    pub wrapped_key: String,
+}
+
+impl PostEnvelope {
+    pub const VERSION: u32 = 1;
+
+    /// Encrypts `plaintext` for each of `recipients` (Base64 Ed25519 public
+    /// keys). Duplicates are dropped; include the author to keep the post
+    /// readable to them.
+    pub fn seal(plaintext: &[u8], recipients: &[String]) -> Result<PostEnvelope, Error> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(key.as_mut());
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+
+        let wrapped = wrap_key(&key, recipients)?;
+
+        let ciphertext = XChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &associated_data(Self::VERSION),
+                },
+            )
+            .map_err(|_| Error::Malformed("content"))?;
+
+        Ok(PostEnvelope {
+            version: Self::VERSION,
+            cipher: CIPHER_XCHACHA20POLY1305.to_string(),
+            nonce: encode_base64(&nonce),
+            ciphertext: encode_base64(&ciphertext),
+            recipients: wrapped,
+        })
+    }
+
+    /// Decrypts the content with `identity`'s key. Fails with
+    /// [`Error::NotRecipient`] if the envelope wasn't sealed to it.
+    pub fn open(&self, identity: &Identity) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if self.version != Self::VERSION || self.cipher != CIPHER_XCHACHA20POLY1305 {
+            return Err(Error::Malformed("envelope version"));
+        }
+        let key = unwrap_key(&self.recipients, identity)?;
+
+        let nonce = decode_base64(&self.nonce, "nonce")?;
+        if nonce.len() != 24 {
+            return Err(Error::Malformed("nonce"));
+        }
+        let ciphertext = decode_base64(&self.ciphertext, "ciphertext")?;
+        let plaintext = XChaCha20Poly1305::new(key.as_ref().into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &associated_data(self.version),
+                },
+            )
+            .map_err(|_| Error::DecryptionFailed)?;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    /// Base64 public keys the envelope is sealed to.
+    pub fn recipient_keys(&self) -> impl Iterator<Item = &str> {
+        self.recipients
+            .iter()
+            .map(|entry| entry.public_key.as_str())
+    }
+
+    /// The JSON form stored in `posts.content_encrypted`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("envelope serializes to JSON")
+    }
+
+    /// Parses a stored envelope. Legacy `content_encrypted` values (Base64
+    /// SecretBox output or plaintext) are not envelopes and fail here.
+    pub fn from_json(content_encrypted: &str) -> Result<PostEnvelope, Error> {
+        serde_json::from_str(content_encrypted).map_err(|_| Error::Malformed("envelope"))
+    }
+}
+
+/// Seals `key` to each of `recipients` (Base64 Ed25519 public keys),
+/// skipping duplicates.
+pub fn wrap_key(key: &[u8; 32], recipients: &[String]) -> Result<Vec<WrappedKey>, Error> {
+    if recipients.is_empty() {
+        return Err(Error::Malformed("recipients"));
+    }
+    let mut wrapped: Vec<WrappedKey> = Vec::with_capacity(recipients.len());
+    for public_key in recipients {
+        if wrapped.iter().any(|entry| &entry.public_key == public_key) {
+            continue;
+        }
+        let sealed = box_public_key(public_key)?
+            .seal(&mut OsRng, key)
+            .map_err(|_| Error::InvalidKey)?;
+        wrapped.push(WrappedKey {
+            public_key: public_key.clone(),
+            wrapped_key: encode_base64(&sealed),
+        });
+    }
+    Ok(wrapped)
+}
+
+/// Opens the key sealed to `identity`, failing with [`Error::NotRecipient`]
+/// if there is none.
+pub fn unwrap_key(
+    recipients: &[WrappedKey],
+    identity: &Identity,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let public_key = identity.public_key();
+    let entry = recipients
+        .iter()
+        .find(|entry| entry.public_key == public_key)
+        .ok_or(Error::NotRecipient)?;
+
+    let sealed = decode_base64(&entry.wrapped_key, "wrapped key")?;
+    let opened = Zeroizing::new(
+        identity
+            .box_secret_key()
+            .unseal(&sealed)
+            .map_err(|_| Error::DecryptionFailed)?,
+    );
+    let mut key = Zeroizing::new([0u8; 32]);
+    if opened.len() != key.len() {
+        return Err(Error::Malformed("wrapped key"));
+    }
+    key.copy_from_slice(&opened);
+    Ok(key)
+}
+
+/// Binds the version into the tag so an envelope can't be relabelled.
+fn associated_data(version: u32) -> Vec<u8> {
+    format!("cipher-post-envelope\n{}", version).into_bytes()
+}
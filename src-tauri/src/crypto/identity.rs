@@ -0,0 +1,112 @@
+use std::fmt;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use zeroize::Zeroizing;
+
+use super::{decode_base64, encode_base64, kdf::pbkdf2_seed, secret::Secret, Error};
+
+/// Iteration count used by `User.derive_private_key_from_credentials`.
+pub const LEGACY_PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// An Ed25519 signing identity. The seed never leaves this struct except to
+/// the keystore; the expanded signing key only exists while signing.
+pub struct Identity {
+    seed: Secret<[u8; 32]>,
+    verifying_key: VerifyingKey,
+}
+
+impl Identity {
+    /// Builds the key pair RbNaCl's `SigningKey.new(seed)` would.
+    pub fn from_seed(seed: &[u8; 32]) -> Identity {
+        let seed = Secret::copy_of(seed);
+        let verifying_key = SigningKey::from_bytes(seed.expose()).verifying_key();
+        Identity {
+            seed,
+            verifying_key,
+        }
+    }
+
+    /// Derives the identity the Rails app derives from a username and
+    /// password, so existing accounts keep their public key.
+    pub fn from_legacy_credentials(username: &str, password: &str) -> Identity {
+        Identity::from_seed(&legacy_seed(username, password))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.verifying_key
+    }
+
+    /// Base64 public key, the format stored in `users.public_key`.
+    pub fn public_key(&self) -> String {
+        encode_base64(self.verifying_key().as_bytes())
+    }
+
+    /// Detached 64-byte signature, like RbNaCl's `SigningKey#sign`.
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.signing_key().sign(message).to_bytes()
+    }
+
+    /// The secret seed, for the keystore only. Never hand it to the webview.
+    pub(crate) fn seed(&self) -> &Secret<[u8; 32]> {
+        &self.seed
+    }
+
+    /// Zeroized by ed25519-dalek when dropped.
+    fn signing_key(&self) -> SigningKey {
+        SigningKey::from_bytes(self.seed.expose())
+    }
+
+    /// The X25519 secret for opening sealed boxes sent to this identity, as
+    /// libsodium's `crypto_sign_ed25519_sk_to_curve25519` derives it.
+    pub(crate) fn box_secret_key(&self) -> crypto_box::SecretKey {
+        let scalar = Zeroizing::new(self.signing_key().to_scalar_bytes());
+        crypto_box::SecretKey::from_bytes(*scalar)
+    }
+
+    /// Base64 signature, the format stored in `posts.signature`.
+    pub fn sign_base64(&self, message: &[u8]) -> String {
+        encode_base64(&self.sign(message))
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The seed `User.derive_private_key_from_credentials` returns.
+pub fn legacy_seed(username: &str, password: &str) -> Zeroizing<[u8; 32]> {
+    pbkdf2_seed(username, password, LEGACY_PBKDF2_ITERATIONS)
+}
+
+pub fn decode_public_key(public_key: &str) -> Result<VerifyingKey, Error> {
+    let bytes: [u8; 32] = decode_base64(public_key, "public key")?
+        .try_into()
+        .map_err(|_| Error::Malformed("public key"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| Error::InvalidKey)
+}
+
+/// The X25519 key sealed boxes for `public_key` are encrypted to, as
+/// libsodium's `crypto_sign_ed25519_pk_to_curve25519` derives it.
+pub fn box_public_key(public_key: &str) -> Result<crypto_box::PublicKey, Error> {
+    let key = decode_public_key(public_key)?;
+    if key.is_weak() {
+        return Err(Error::InvalidKey);
+    }
+    Ok(key.to_montgomery().into())
+}
+
+/// Checks a detached signature the way `User#verify_signature` does, taking
+/// the Base64 key and signature strings stored by Rails.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), Error> {
+    let key = decode_public_key(public_key)?;
+    let signature: [u8; 64] = decode_base64(signature, "signature")?
+        .try_into()
+        .map_err(|_| Error::Malformed("signature"))?;
+    // libsodium rejects non-canonical and small-order inputs, as does verify_strict
+    key.verify_strict(message, &Signature::from_bytes(&signature))
+        .map_err(|_| Error::BadSignature)
+}
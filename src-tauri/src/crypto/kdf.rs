@@ -0,0 +1,126 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use pbkdf2::pbkdf2_hmac;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+use super::{decode_base64, encode_base64, Error};
+
+/// Salt length for new Argon2id descriptors.
+pub const SALT_BYTES: usize = 16;
+
+/// How an account's 32-byte key is derived from its password. Stored with the
+/// account so the parameters can be raised later without breaking sign-in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kdf", rename_all = "snake_case")]
+pub enum KdfDescriptor {
+    /// `User.derive_private_key_from_credentials`: PBKDF2-HMAC-SHA256 over
+    /// `"<username>:<password>"` with the fixed salt `cipher_salt_<username>`.
+    Pbkdf2Sha256V1 { iterations: u32 },
+    /// Argon2id (v1.3) over the password with a random per-account salt.
+    Argon2idV1 {
+        /// Base64, [`SALT_BYTES`] long.
+        salt: String,
+        #[serde(flatten)]
+        params: Argon2Params,
+    },
+}
+
+impl KdfDescriptor {
+    /// The descriptor every account created by Rails implicitly has.
+    pub fn legacy() -> KdfDescriptor {
+        KdfDescriptor::Pbkdf2Sha256V1 {
+            iterations: super::identity::LEGACY_PBKDF2_ITERATIONS,
+        }
+    }
+
+    /// A new Argon2id descriptor with a fresh random salt.
+    pub fn argon2id(params: Argon2Params) -> KdfDescriptor {
+        let mut salt = [0u8; SALT_BYTES];
+        OsRng.fill_bytes(&mut salt);
+        KdfDescriptor::Argon2idV1 {
+            salt: encode_base64(&salt),
+            params,
+        }
+    }
+
+    /// The serialized tag, e.g. `argon2id_v1`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KdfDescriptor::Pbkdf2Sha256V1 { .. } => "pbkdf2_sha256_v1",
+            KdfDescriptor::Argon2idV1 { .. } => "argon2id_v1",
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, KdfDescriptor::Pbkdf2Sha256V1 { .. })
+    }
+
+    pub fn derive(&self, username: &str, password: &str) -> Result<Zeroizing<[u8; 32]>, Error> {
+        match self {
+            KdfDescriptor::Pbkdf2Sha256V1 { iterations } => {
+                Ok(pbkdf2_seed(username, password, *iterations))
+            }
+            KdfDescriptor::Argon2idV1 { salt, params } => {
+                let salt = decode_base64(salt, "salt")?;
+                let mut key = Zeroizing::new([0u8; 32]);
+                params
+                    .argon2()?
+                    .hash_password_into(password.as_bytes(), &salt, key.as_mut())
+                    .map_err(|_| Error::Malformed("salt"))?;
+                Ok(key)
+            }
+        }
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 over `"<username>:<password>"` with the salt
+/// `cipher_salt_<username>`, matching `User.derive_private_key_from_credentials`.
+pub fn pbkdf2_seed(username: &str, password: &str, iterations: u32) -> Zeroizing<[u8; 32]> {
+    let input = Zeroizing::new(format!("{}:{}", username, password));
+    let salt = format!("cipher_salt_{}", username);
+    let mut seed = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(input.as_bytes(), salt.as_bytes(), iterations, seed.as_mut());
+    seed
+}
+
+/// Argon2id cost parameters. The defaults follow the OWASP recommendation
+/// for interactive sign-in and take well under a second on phones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Argon2Params {
+        Argon2Params {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Floor below which settings are rejected rather than silently weakening
+    /// new accounts.
+    pub const MIN_MEMORY_KIB: u32 = 8 * 1024;
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.memory_kib < Argon2Params::MIN_MEMORY_KIB || self.iterations == 0 {
+            return Err(Error::Malformed("argon2 parameters"));
+        }
+        self.argon2().map(|_| ())
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|_| Error::Malformed("argon2 parameters"))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::{
+    circle::CircleKeys,
+    decode_base64,
+    device::DeviceCertificate,
+    encode_base64,
+    kdf::{Argon2Params, KdfDescriptor},
+    ratchet::{PrekeySecrets, RatchetSession},
+    recovery::MasterSeed,
+    secret::Secret,
+    Error, Identity,
+};
+
+/// File under the app data dir holding every private key the app keeps.
+pub const KEYSTORE_FILE: &str = "keystore.json";
+
+/// Entry-name prefix for account signing seeds, followed by the username.
+const IDENTITY_PREFIX: &str = "identity/";
+
+/// Entry-name prefix for recovery master seeds, followed by the username.
+const RECOVERY_PREFIX: &str = "recovery/";
+
+/// Entry-name prefix for published prekeys, followed by the username.
+const PREKEYS_PREFIX: &str = "prekeys/";
+
+/// Entry-name prefix for Double Ratchet sessions, followed by the username,
+/// `/` and the other side's public key.
+const RATCHET_PREFIX: &str = "ratchet/";
+
+/// Entry-name prefix for the text of forward-secret messages, followed by
+/// the username, `/` and the message id. Once its keys are gone this is the
+/// only copy.
+const MESSAGE_PREFIX: &str = "message/";
+
+/// Entry-name prefix for the keys of circles a user owns, followed by the
+/// username, `/` and the circle id.
+const CIRCLE_PREFIX: &str = "circle/";
+
+/// Entry-name prefix for SQLCipher keys, followed by the platform the
+/// database file is named for.
+const DATABASE_PREFIX: &str = "database/";
+
+/// Entry-name prefix for this device's own signing seed on a linked
+/// account, followed by the username.
+const DEVICE_PREFIX: &str = "device/";
+
+/// Entry-name prefix for the root key's certificate for that seed, followed
+/// by the username.
+const DEVICE_CERTIFICATE_PREFIX: &str = "device-certificate/";
+
+const ASSOCIATED_DATA: &[u8] = b"cipher-keystore-v1";
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Crypto(Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Crypto(e) => e.fmt(f),
+            KeystoreError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<Error> for KeystoreError {
+    fn from(error: Error) -> KeystoreError {
+        KeystoreError::Crypto(error)
+    }
+}
+
+impl From<io::Error> for KeystoreError {
+    fn from(error: io::Error) -> KeystoreError {
+        KeystoreError::Io(error)
+    }
+}
+
+/// On-disk form: the whole entry table sealed under one passphrase key, so
+/// not even the entry names are readable while locked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u32,
+    kdf: KdfDescriptor,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// An unlocked keystore. Holds the passphrase-derived key so entries can be
+/// saved without asking again; all of it is zeroized on drop.
+pub struct Keystore {
+    path: PathBuf,
+    kdf: KdfDescriptor,
+    key: Secret<[u8; 32]>,
+    entries: BTreeMap<String, Secret<Vec<u8>>>,
+}
+
+impl Keystore {
+    pub const VERSION: u32 = 1;
+
+    pub fn path(app_data_dir: &Path) -> PathBuf {
+        app_data_dir.join(KEYSTORE_FILE)
+    }
+
+    /// Creates an empty keystore at `path`; fails if one is already there.
+    pub fn create(
+        path: &Path,
+        passphrase: &str,
+        params: Argon2Params,
+    ) -> Result<Keystore, KeystoreError> {
+        if path.exists() {
+            return Err(
+                io::Error::new(io::ErrorKind::AlreadyExists, "keystore already exists").into(),
+            );
+        }
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        let keystore = Keystore {
+            path: path.to_path_buf(),
+            key: derive_key(&kdf, passphrase)?,
+            kdf,
+            entries: BTreeMap::new(),
+        };
+        keystore.save()?;
+        Ok(keystore)
+    }
+
+    /// Decrypts the keystore at `path`, failing with
+    /// [`Error::WrongPassphrase`] if the passphrase doesn't open it.
+    pub fn unlock(path: &Path, passphrase: &str) -> Result<Keystore, KeystoreError> {
+        let file: KeystoreFile =
+            serde_json::from_slice(&fs::read(path)?).map_err(io::Error::from)?;
+        if file.version != Keystore::VERSION {
+            return Err(Error::Malformed("keystore version").into());
+        }
+        let key = derive_key(&file.kdf, passphrase)?;
+        let nonce = decode_base64(&file.nonce, "nonce")?;
+        if nonce.len() != 24 {
+            return Err(Error::Malformed("nonce").into());
+        }
+        let ciphertext = decode_base64(&file.ciphertext, "keystore")?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new(key.expose().into())
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: ASSOCIATED_DATA,
+                    },
+                )
+                .map_err(|_| Error::WrongPassphrase)?,
+        );
+        Ok(Keystore {
+            path: path.to_path_buf(),
+            kdf: file.kdf,
+            key,
+            entries: decode_entries(&plaintext)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .get(name)
+            .map(|secret| secret.expose().as_slice())
+    }
+
+    /// Adds or replaces an entry in memory; call [`Keystore::save`] to keep it.
+    pub fn insert(&mut self, name: &str, secret: &[u8]) {
+        self.entries
+            .insert(name.to_string(), Secret::new(secret.to_vec()));
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Name of the entry holding `username`'s signing seed.
+    pub fn identity_entry(username: &str) -> String {
+        entry_name(IDENTITY_PREFIX, username)
+    }
+
+    /// The signing identity stored for `username`, if any.
+    pub fn identity(&self, username: &str) -> Option<Result<Identity, Error>> {
+        self.get(&Keystore::identity_entry(username)).map(|seed| {
+            let seed: &[u8; 32] = seed.try_into().map_err(|_| Error::InvalidKey)?;
+            Ok(Identity::from_seed(seed))
+        })
+    }
+
+    pub fn set_identity(&mut self, username: &str, identity: &Identity) {
+        self.insert(
+            &Keystore::identity_entry(username),
+            identity.seed().expose(),
+        );
+    }
+
+    /// The master seed behind `username`'s recovery phrase, if they have one.
+    pub fn master_seed(&self, username: &str) -> Option<Result<MasterSeed, Error>> {
+        self.get(&entry_name(RECOVERY_PREFIX, username))
+            .map(|entropy| {
+                let entropy: &[u8; 32] = entropy.try_into().map_err(|_| Error::InvalidKey)?;
+                Ok(MasterSeed::from_entropy(entropy))
+            })
+    }
+
+    pub fn set_master_seed(&mut self, username: &str, seed: &MasterSeed) {
+        self.insert(&entry_name(RECOVERY_PREFIX, username), seed.entropy());
+    }
+
+    pub fn prekeys(&self, username: &str) -> Option<Result<PrekeySecrets, Error>> {
+        self.get(&entry_name(PREKEYS_PREFIX, username))
+            .map(PrekeySecrets::from_bytes)
+    }
+
+    pub fn set_prekeys(&mut self, username: &str, prekeys: &PrekeySecrets) {
+        self.insert(&entry_name(PREKEYS_PREFIX, username), &prekeys.to_bytes());
+    }
+
+    /// `username`'s session with the holder of `remote_key`, if any.
+    pub fn ratchet_session(
+        &self,
+        username: &str,
+        remote_key: &str,
+    ) -> Option<Result<RatchetSession, Error>> {
+        self.get(&scoped_entry_name(RATCHET_PREFIX, username, remote_key))
+            .map(RatchetSession::from_bytes)
+    }
+
+    pub fn set_ratchet_session(&mut self, username: &str, session: &RatchetSession) {
+        let name = scoped_entry_name(RATCHET_PREFIX, username, session.remote_identity_key());
+        self.insert(&name, &session.to_bytes());
+    }
+
+    /// Name of the entry holding the text of `username`'s message
+    /// `message_id`. Message ids are local, so archives rename these.
+    pub fn message_entry(username: &str, message_id: i64) -> String {
+        scoped_entry_name(MESSAGE_PREFIX, username, &message_id.to_string())
+    }
+
+    pub fn message_text(&self, username: &str, message_id: i64) -> Option<&[u8]> {
+        self.get(&Keystore::message_entry(username, message_id))
+    }
+
+    pub fn set_message_text(&mut self, username: &str, message_id: i64, text: &[u8]) {
+        self.insert(&Keystore::message_entry(username, message_id), text);
+    }
+
+    pub fn circle_keys(
+        &self,
+        username: &str,
+        circle_id: &str,
+    ) -> Option<Result<CircleKeys, Error>> {
+        self.get(&scoped_entry_name(CIRCLE_PREFIX, username, circle_id))
+            .map(CircleKeys::from_bytes)
+    }
+
+    pub fn set_circle_keys(&mut self, username: &str, keys: &CircleKeys) {
+        self.insert(
+            &scoped_entry_name(CIRCLE_PREFIX, username, keys.circle_id()),
+            &keys.to_bytes(),
+        );
+    }
+
+    /// The key the `<platform>.sqlite3` database is encrypted under, if it is.
+    pub fn database_key(&self, platform: &str) -> Option<Result<Secret<[u8; 32]>, Error>> {
+        self.get(&format!("{}{}", DATABASE_PREFIX, platform))
+            .map(|key| {
+                let key: &[u8; 32] = key.try_into().map_err(|_| Error::InvalidKey)?;
+                Ok(Secret::copy_of(key))
+            })
+    }
+
+    pub fn set_database_key(&mut self, platform: &str, key: &Secret<[u8; 32]>) {
+        self.insert(&format!("{}{}", DATABASE_PREFIX, platform), key.expose());
+    }
+
+    /// This device's key for `username`'s account, if it was linked.
+    pub fn device_identity(&self, username: &str) -> Option<Result<Identity, Error>> {
+        self.get(&entry_name(DEVICE_PREFIX, username)).map(|seed| {
+            let seed: &[u8; 32] = seed.try_into().map_err(|_| Error::InvalidKey)?;
+            Ok(Identity::from_seed(seed))
+        })
+    }
+
+    pub fn device_certificate(&self, username: &str) -> Option<Result<DeviceCertificate, Error>> {
+        self.get(&entry_name(DEVICE_CERTIFICATE_PREFIX, username))
+            .map(|json| {
+                serde_json::from_slice(json).map_err(|_| Error::Malformed("device certificate"))
+            })
+    }
+
+    pub fn set_device(
+        &mut self,
+        username: &str,
+        device: &Identity,
+        certificate: &DeviceCertificate,
+    ) {
+        self.insert(&entry_name(DEVICE_PREFIX, username), device.seed().expose());
+        self.insert(
+            &entry_name(DEVICE_CERTIFICATE_PREFIX, username),
+            &serde_json::to_vec(certificate).expect("certificate serializes to JSON"),
+        );
+    }
+
+    /// The entries of `username`'s that every linked device holds: message
+    /// texts and circle keys. Signing seeds, prekeys and ratchet sessions
+    /// belong to one device.
+    pub fn linked_device_entries(&self, username: &str) -> BTreeMap<String, Secret<Vec<u8>>> {
+        let shared =
+            [MESSAGE_PREFIX, CIRCLE_PREFIX].map(|prefix| scoped_entry_name(prefix, username, ""));
+        self.entries
+            .iter()
+            .filter(|(name, _)| shared.iter().any(|prefix| name.starts_with(prefix)))
+            .map(|(name, secret)| (name.clone(), secret.clone()))
+            .collect()
+    }
+
+    /// Every entry belonging to `username`, for an account archive. Entries
+    /// tied to this device, such as database keys, stay behind.
+    pub fn account_entries(&self, username: &str) -> BTreeMap<String, Secret<Vec<u8>>> {
+        self.entries
+            .iter()
+            .filter(|(name, _)| Keystore::is_account_entry(username, name))
+            .map(|(name, secret)| (name.clone(), secret.clone()))
+            .collect()
+    }
+
+    /// Whether `name` is one of the entries [`Keystore::account_entries`]
+    /// selects for `username`.
+    pub fn is_account_entry(username: &str, name: &str) -> bool {
+        [IDENTITY_PREFIX, RECOVERY_PREFIX, PREKEYS_PREFIX]
+            .iter()
+            .any(|prefix| name == entry_name(prefix, username))
+            || [RATCHET_PREFIX, MESSAGE_PREFIX, CIRCLE_PREFIX]
+                .iter()
+                .any(|prefix| name.starts_with(&scoped_entry_name(prefix, username, "")))
+    }
+
+    /// Usernames with a stored signing identity.
+    pub fn usernames(&self) -> Vec<String> {
+        self.names()
+            .filter_map(|name| name.strip_prefix(IDENTITY_PREFIX))
+            .map(unescape_username)
+            .collect()
+    }
+
+    /// Re-encrypts every entry under a key derived from `passphrase`.
+    pub fn change_passphrase(
+        &mut self,
+        passphrase: &str,
+        params: Argon2Params,
+    ) -> Result<(), KeystoreError> {
+        params.validate()?;
+        let kdf = KdfDescriptor::argon2id(params);
+        self.key = derive_key(&kdf, passphrase)?;
+        self.kdf = kdf;
+        self.save()
+    }
+
+    /// Encrypts the entries with a fresh nonce and replaces the file via a
+    /// temporary one, readable only by the current user where supported.
+    pub fn save(&self) -> Result<(), KeystoreError> {
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let plaintext = encode_entries(&self.entries)?;
+        let ciphertext = XChaCha20Poly1305::new(self.key.expose().into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: ASSOCIATED_DATA,
+                },
+            )
+            .map_err(|_| Error::Malformed("keystore"))?;
+        let file = KeystoreFile {
+            version: Keystore::VERSION,
+            kdf: self.kdf.clone(),
+            nonce: encode_base64(&nonce),
+            ciphertext: encode_base64(&ciphertext),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = self.path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = options.open(&temp)?;
+        out.write_all(&serde_json::to_vec_pretty(&file).map_err(io::Error::from)?)?;
+        out.sync_all()?;
+        fs::rename(temp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("path", &self.path)
+            .field("entries", &self.entries.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// `prefix` and `username`. `%` and `/` in the username are percent-encoded,
+/// so one user's entries never share a prefix with another's. Other
+/// usernames are written as they are, so existing entries keep their names.
+fn entry_name(prefix: &str, username: &str) -> String {
+    format!("{}{}", prefix, escape_username(username))
+}
+
+/// An entry under `username` named by `rest`, after a `/`.
+fn scoped_entry_name(prefix: &str, username: &str, rest: &str) -> String {
+    format!("{}{}/{}", prefix, escape_username(username), rest)
+}
+
+fn escape_username(username: &str) -> String {
+    username.replace('%', "%25").replace('/', "%2F")
+}
+
+fn unescape_username(escaped: &str) -> String {
+    escaped.replace("%2F", "/").replace("%25", "%")
+}
+
+fn derive_key(kdf: &KdfDescriptor, passphrase: &str) -> Result<Secret<[u8; 32]>, Error> {
+    if kdf.is_legacy() {
+        return Err(Error::Malformed("kdf"));
+    }
+    // Argon2id doesn't use the username
+    Ok(Secret::copy_of(&*kdf.derive("", passphrase)?))
+}
+
+/// Entries as `name_len: u16, name, secret_len: u32, secret`, big-endian.
+/// A flat format keeps secrets out of intermediate JSON strings. Account
+/// archives carry their secrets the same way. Names longer than a `u16`
+/// can count are refused rather than cut short.
+pub(crate) fn encode_entries(
+    entries: &BTreeMap<String, Secret<Vec<u8>>>,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut len = 0;
+    for (name, secret) in entries {
+        if name.len() > u16::MAX as usize {
+            return Err(Error::Malformed("keystore entry name"));
+        }
+        if secret.expose().len() > u32::MAX as usize {
+            return Err(Error::Malformed("keystore entry"));
+        }
+        len += 2 + name.len() + 4 + secret.expose().len();
+    }
+    // Sized up front: growing the buffer would free copies of the secrets
+    // without zeroizing them
+    let mut out = Zeroizing::new(Vec::with_capacity(len));
+    for (name, secret) in entries {
+        out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(secret.expose().len() as u32).to_be_bytes());
+        out.extend_from_slice(secret.expose());
+    }
+    Ok(out)
+}
+
+pub(crate) fn decode_entries(mut bytes: &[u8]) -> Result<BTreeMap<String, Secret<Vec<u8>>>, Error> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+        if bytes.len() < len {
+            return Err(Error::Malformed("keystore"));
+        }
+        let (head, rest) = bytes.split_at(len);
+        *bytes = rest;
+        Ok(head)
+    }
+
+    let mut entries = BTreeMap::new();
+    while !bytes.is_empty() {
+        let name_len = u16::from_be_bytes(take(&mut bytes, 2)?.try_into().unwrap());
+        let name = std::str::from_utf8(take(&mut bytes, name_len as usize)?)
+            .map_err(|_| Error::Malformed("keystore"))?;
+        let secret_len = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+        let secret = take(&mut bytes, secret_len as usize)?;
+        entries.insert(name.to_string(), Secret::new(secret.to_vec()));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,83 @@
+//! Native cryptography for Cipher identities and content. Byte formats match
+//! what the Rails models produce with RbNaCl, so either side can check the
+//! other's output.
+
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+pub mod account;
+pub mod archive;
+pub mod circle;
+pub mod content_hash;
+pub mod device;
+pub mod device_link;
+pub mod envelope;
+pub mod identity;
+pub mod kdf;
+pub mod keystore;
+pub mod ratchet;
+pub mod recovery;
+pub mod rotation;
+pub mod safety_number;
+pub mod sealed_sender;
+pub mod secret;
+pub mod secretbox;
+pub mod signed_post;
+pub mod social_recovery;
+pub mod stream;
+
+pub use identity::Identity;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A key had the wrong length or is not a valid curve point.
+    InvalidKey,
+    /// Input was not valid Base64 or had the wrong length; names the field.
+    Malformed(&'static str),
+    /// The signature does not match the message and key.
+    BadSignature,
+    /// The password did not reproduce the account's key.
+    WrongPassword,
+    /// The passphrase does not open the keystore.
+    WrongPassphrase,
+    /// The content was not encrypted for this identity.
+    NotRecipient,
+    /// A ciphertext failed authentication.
+    DecryptionFailed,
+    /// Fewer recovery shares than the split's threshold.
+    NotEnoughShares,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidKey => f.write_str("invalid key"),
+            Error::Malformed(field) => write!(f, "malformed {}", field),
+            Error::BadSignature => f.write_str("signature verification failed"),
+            Error::WrongPassword => f.write_str("invalid username or password"),
+            Error::WrongPassphrase => f.write_str("incorrect keystore passphrase"),
+            Error::NotRecipient => f.write_str("not a recipient of this content"),
+            Error::DecryptionFailed => f.write_str("decryption failed"),
+            Error::NotEnoughShares => f.write_str("not enough recovery shares"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Base64 with padding, as written by Ruby's `Base64.strict_encode64`.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+pub fn decode_base64(value: &str, field: &'static str) -> Result<Vec<u8>, Error> {
+    STANDARD
+        .decode(value.trim())
+        .map_err(|_| Error::Malformed(field))
+}
+
+/// Lowercase hex, as checksums and blob names are written.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,651 @@
+//! Forward-secret direct messages. A conversation starts with X3DH against
+//! the recipient's signed prekey bundle and then runs the Double Ratchet, so
+//! every message has its own key and spent keys are forgotten. Identity keys
+//! are the Ed25519 account keys, converted to X25519 the way sealed boxes
+//! convert them.
+
+use std::{collections::BTreeMap, fmt};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::{decode_base64, encode_base64, identity, secret::StoredKey, Error, Identity};
+
+/// Most message keys kept for late or reordered messages.
+pub const MAX_SKIP: u32 = 1000;
+
+/// Unused one-time prekeys kept; the oldest go first.
+pub const MAX_ONE_TIME_PREKEYS: usize = 100;
+
+/// Marks stored messages, like the `algorithm` field of the Rails NaCl-Box
+/// JSON.
+pub const ALGORITHM: &str = "double-ratchet";
+
+const X3DH_INFO: &[u8] = b"cipher-x3dh-v1";
+const ROOT_INFO: &[u8] = b"cipher-ratchet-root-v1";
+const MESSAGE_INFO: &[u8] = b"cipher-ratchet-message-v1";
+
+/// A signed prekey and, optionally, one one-time prekey, published so a
+/// friend can start a session while the owner is offline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PrekeyBundle {
+    pub version: u32,
+    /// The owner's Ed25519 account key.
+    pub identity_key: String,
+    pub signed_prekey_id: u32,
+    /// X25519, Base64.
+    pub signed_prekey: String,
+    /// Identity key's signature over [`PrekeyBundle::signing_bytes`].
+    pub signature: String,
+    /// Used once, then deleted by the owner. Not signed, so a bundle can be
+    /// reused without it once it's spent.
+    pub one_time_prekey_id: Option<u32>,
+    pub one_time_prekey: Option<String>,
+    #[ts(type = "number")]
+    pub created_at: i64,
+}
+
+impl PrekeyBundle {
+    pub const VERSION: u32 = 1;
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-signed-prekey\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.identity_key,
+            self.signed_prekey_id,
+            self.signed_prekey,
+            self.created_at
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != PrekeyBundle::VERSION {
+            return Err(Error::Malformed("prekey bundle version"));
+        }
+        identity::verify(&self.identity_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// The same bundle without its one-time prekey.
+    pub fn without_one_time_prekey(&self) -> PrekeyBundle {
+        PrekeyBundle {
+            one_time_prekey_id: None,
+            one_time_prekey: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// The private halves of the prekeys this device has published.
+#[derive(Serialize, Deserialize)]
+pub struct PrekeySecrets {
+    signed_prekey_id: u32,
+    signed_prekey: StoredKey,
+    /// The signed prekey before the last refresh, for bundles still in flight.
+    previous_signed_prekey: Option<(u32, StoredKey)>,
+    one_time_prekeys: BTreeMap<u32, StoredKey>,
+    next_id: u32,
+}
+
+impl PrekeySecrets {
+    pub fn generate() -> PrekeySecrets {
+        PrekeySecrets {
+            signed_prekey_id: 1,
+            signed_prekey: StoredKey::generate(),
+            previous_signed_prekey: None,
+            one_time_prekeys: BTreeMap::new(),
+            next_id: 2,
+        }
+    }
+
+    /// Replaces the signed prekey, keeping the old one for one more round.
+    pub fn refresh(&mut self) {
+        let previous = std::mem::replace(&mut self.signed_prekey, StoredKey::generate());
+        self.previous_signed_prekey = Some((self.signed_prekey_id, previous));
+        self.signed_prekey_id = self.take_id();
+    }
+
+    /// Signs `count` bundles, each with a new one-time prekey.
+    pub fn issue(&mut self, identity: &Identity, count: usize, now: i64) -> Vec<PrekeyBundle> {
+        let signed_prekey = x25519_secret(&self.signed_prekey);
+        let mut unsigned = PrekeyBundle {
+            version: PrekeyBundle::VERSION,
+            identity_key: identity.public_key(),
+            signed_prekey_id: self.signed_prekey_id,
+            signed_prekey: encode_base64(PublicKey::from(&signed_prekey).as_bytes()),
+            signature: String::new(),
+            one_time_prekey_id: None,
+            one_time_prekey: None,
+            created_at: now,
+        };
+        unsigned.signature = identity.sign_base64(&unsigned.signing_bytes());
+        let bundles = (0..count)
+            .map(|_| {
+                let id = self.take_id();
+                let secret = StoredKey::generate();
+                let public_key = PublicKey::from(&x25519_secret(&secret));
+                self.one_time_prekeys.insert(id, secret);
+                PrekeyBundle {
+                    one_time_prekey_id: Some(id),
+                    one_time_prekey: Some(encode_base64(public_key.as_bytes())),
+                    ..unsigned.clone()
+                }
+            })
+            .collect();
+        while self.one_time_prekeys.len() > MAX_ONE_TIME_PREKEYS {
+            self.one_time_prekeys.pop_first();
+        }
+        bundles
+    }
+
+    pub fn one_time_prekey_count(&self) -> usize {
+        self.one_time_prekeys.len()
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(serde_json::to_vec(self).expect("prekeys serialize"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PrekeySecrets, Error> {
+        serde_json::from_slice(bytes).map_err(|_| Error::Malformed("prekeys"))
+    }
+
+    fn take_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    fn signed_prekey(&self, id: u32) -> Option<&StoredKey> {
+        if id == self.signed_prekey_id {
+            return Some(&self.signed_prekey);
+        }
+        self.previous_signed_prekey
+            .as_ref()
+            .filter(|(previous, _)| *previous == id)
+            .map(|(_, secret)| secret)
+    }
+}
+
+/// Sent with the first messages of a session, until the recipient replies,
+/// so they can run their side of X3DH.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PrekeyHeader {
+    pub identity_key: String,
+    pub ephemeral_key: String,
+    pub signed_prekey_id: u32,
+    pub one_time_prekey_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RatchetHeader {
+    /// The sender's current ratchet key, X25519 Base64.
+    pub dh: String,
+    /// Messages sent in the sender's previous chain.
+    pub previous_count: u32,
+    pub number: u32,
+}
+
+/// One encrypted direct message, stored as JSON in
+/// `messages.encrypted_content`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RatchetMessage {
+    pub version: u32,
+    pub algorithm: String,
+    pub header: RatchetHeader,
+    #[serde(default)]
+    pub prekey: Option<PrekeyHeader>,
+    pub ciphertext: String,
+}
+
+impl RatchetMessage {
+    pub const VERSION: u32 = 1;
+}
+
+/// A key kept for a message that hasn't arrived yet.
+#[derive(Serialize, Deserialize)]
+struct SkippedKey {
+    dh: [u8; 32],
+    number: u32,
+    key: StoredKey,
+}
+
+/// One side of a conversation. Kept in the keystore between messages.
+#[derive(Serialize, Deserialize)]
+pub struct RatchetSession {
+    version: u32,
+    remote_identity_key: String,
+    /// Both identity keys, initiator first, bound into every ciphertext.
+    associated_data: Vec<u8>,
+    root_key: StoredKey,
+    dh_secret: StoredKey,
+    dh_remote: Option<[u8; 32]>,
+    send_chain: Option<StoredKey>,
+    recv_chain: Option<StoredKey>,
+    send_count: u32,
+    recv_count: u32,
+    previous_count: u32,
+    skipped: Vec<SkippedKey>,
+    pending_prekey: Option<PrekeyHeader>,
+    /// The initiator's ephemeral key, on the responding side, so repeats of
+    /// the same prekey message reuse this session.
+    remote_ephemeral_key: Option<String>,
+}
+
+impl RatchetSession {
+    pub const VERSION: u32 = 1;
+
+    /// Starts a session to the owner of `bundle`.
+    pub fn initiate(identity: &Identity, bundle: &PrekeyBundle) -> Result<RatchetSession, Error> {
+        bundle.verify()?;
+        let remote_identity = identity_public(&bundle.identity_key)?;
+        let signed_prekey = decode_key(&bundle.signed_prekey, "signed prekey")?;
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let identity_secret = identity_secret(identity);
+
+        let mut secrets = vec![
+            dh(&identity_secret, &signed_prekey)?,
+            dh(&ephemeral, &remote_identity)?,
+            dh(&ephemeral, &signed_prekey)?,
+        ];
+        if let Some(one_time) = &bundle.one_time_prekey {
+            secrets.push(dh(&ephemeral, &decode_key(one_time, "one-time prekey")?)?);
+        }
+        let shared = x3dh_secret(&secrets);
+
+        let ratchet = StoredKey::generate();
+        let (root_key, send_chain) =
+            kdf_root(&shared, &*dh(&x25519_secret(&ratchet), &signed_prekey)?);
+        Ok(RatchetSession {
+            version: RatchetSession::VERSION,
+            remote_identity_key: bundle.identity_key.clone(),
+            associated_data: associated_data(&identity.public_key(), &bundle.identity_key)?,
+            root_key: StoredKey::copy_of(&root_key),
+            dh_secret: ratchet,
+            dh_remote: Some(signed_prekey),
+            send_chain: Some(StoredKey::copy_of(&send_chain)),
+            recv_chain: None,
+            send_count: 0,
+            recv_count: 0,
+            previous_count: 0,
+            skipped: Vec::new(),
+            pending_prekey: Some(PrekeyHeader {
+                identity_key: identity.public_key(),
+                ephemeral_key: encode_base64(PublicKey::from(&ephemeral).as_bytes()),
+                signed_prekey_id: bundle.signed_prekey_id,
+                one_time_prekey_id: bundle.one_time_prekey_id,
+            }),
+            remote_ephemeral_key: None,
+        })
+    }
+
+    /// Accepts the first message of a session started against one of our
+    /// bundles. The one-time prekey it used is deleted from `prekeys`, so
+    /// the message can't be replayed into a second session.
+    pub fn respond(
+        identity: &Identity,
+        prekeys: &mut PrekeySecrets,
+        message: &RatchetMessage,
+    ) -> Result<(RatchetSession, Zeroizing<Vec<u8>>), Error> {
+        let prekey = message
+            .prekey
+            .as_ref()
+            .ok_or(Error::Malformed("prekey header"))?;
+        let remote_identity = identity_public(&prekey.identity_key)?;
+        let ephemeral = decode_key(&prekey.ephemeral_key, "ephemeral key")?;
+        let signed_prekey_key = prekeys
+            .signed_prekey(prekey.signed_prekey_id)
+            .ok_or(Error::NotRecipient)?;
+        let signed_prekey = x25519_secret(signed_prekey_key);
+
+        let mut secrets = vec![
+            dh(&signed_prekey, &remote_identity)?,
+            dh(&identity_secret(identity), &ephemeral)?,
+            dh(&signed_prekey, &ephemeral)?,
+        ];
+        if let Some(id) = prekey.one_time_prekey_id {
+            let one_time = prekeys
+                .one_time_prekeys
+                .get(&id)
+                .map(x25519_secret)
+                .ok_or(Error::NotRecipient)?;
+            secrets.push(dh(&one_time, &ephemeral)?);
+        }
+        let shared = x3dh_secret(&secrets);
+
+        let mut session = RatchetSession {
+            version: RatchetSession::VERSION,
+            remote_identity_key: prekey.identity_key.clone(),
+            associated_data: associated_data(&prekey.identity_key, &identity.public_key())?,
+            root_key: StoredKey::copy_of(&shared),
+            dh_secret: signed_prekey_key.duplicate(),
+            dh_remote: None,
+            send_chain: None,
+            recv_chain: None,
+            send_count: 0,
+            recv_count: 0,
+            previous_count: 0,
+            skipped: Vec::new(),
+            pending_prekey: None,
+            remote_ephemeral_key: Some(prekey.ephemeral_key.clone()),
+        };
+        let plaintext = session.decrypt(message)?;
+        if let Some(id) = prekey.one_time_prekey_id {
+            prekeys.one_time_prekeys.remove(&id);
+        }
+        Ok((session, plaintext))
+    }
+
+    pub fn remote_identity_key(&self) -> &str {
+        &self.remote_identity_key
+    }
+
+    /// Whether `message` is another copy of the prekey message that started
+    /// this session, rather than a new session from the same sender.
+    pub fn started_by(&self, message: &RatchetMessage) -> bool {
+        match &message.prekey {
+            Some(prekey) => self.remote_ephemeral_key.as_deref() == Some(&prekey.ephemeral_key),
+            None => true,
+        }
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<RatchetMessage, Error> {
+        let chain = self
+            .send_chain
+            .as_ref()
+            .ok_or(Error::Malformed("ratchet session"))?;
+        let (next, message_key) = kdf_chain(chain.expose());
+        let header = RatchetHeader {
+            dh: encode_base64(PublicKey::from(&x25519_secret(&self.dh_secret)).as_bytes()),
+            previous_count: self.previous_count,
+            number: self.send_count,
+        };
+        let ciphertext = seal(&message_key, &self.header_data(&header)?, plaintext);
+        self.send_chain = Some(StoredKey::copy_of(&next));
+        self.send_count += 1;
+        Ok(RatchetMessage {
+            version: RatchetMessage::VERSION,
+            algorithm: ALGORITHM.to_string(),
+            header,
+            prekey: self.pending_prekey.clone(),
+            ciphertext: encode_base64(&ciphertext),
+        })
+    }
+
+    /// Decrypts and advances the ratchet. On failure the session is left as
+    /// it was.
+    pub fn decrypt(&mut self, message: &RatchetMessage) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if message.version != RatchetMessage::VERSION || message.algorithm != ALGORITHM {
+            return Err(Error::Malformed("ratchet message version"));
+        }
+        let mut next = self.duplicate();
+        let plaintext = next.decrypt_in_place(message)?;
+        *self = next;
+        Ok(plaintext)
+    }
+
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(serde_json::to_vec(self).expect("ratchet session serializes"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<RatchetSession, Error> {
+        serde_json::from_slice(bytes).map_err(|_| Error::Malformed("ratchet session"))
+    }
+
+    fn decrypt_in_place(&mut self, message: &RatchetMessage) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let header = &message.header;
+        let remote = decode_key(&header.dh, "ratchet key")?;
+        let ciphertext = decode_base64(&message.ciphertext, "ciphertext")?;
+        let associated_data = self.header_data(header)?;
+
+        if let Some(index) = self
+            .skipped
+            .iter()
+            .position(|skipped| skipped.dh == remote && skipped.number == header.number)
+        {
+            let skipped = self.skipped.remove(index);
+            return open(skipped.key.expose(), &associated_data, &ciphertext);
+        }
+        if self.dh_remote != Some(remote) {
+            self.skip_to(header.previous_count)?;
+            self.step(&remote)?;
+        }
+        if header.number < self.recv_count {
+            // Already read, and its key is gone
+            return Err(Error::DecryptionFailed);
+        }
+        self.skip_to(header.number)?;
+        let chain = self.recv_chain.as_ref().ok_or(Error::DecryptionFailed)?;
+        let (next, message_key) = kdf_chain(chain.expose());
+        let plaintext = open(&message_key, &associated_data, &ciphertext)?;
+        self.recv_chain = Some(StoredKey::copy_of(&next));
+        self.recv_count += 1;
+        self.pending_prekey = None;
+        Ok(plaintext)
+    }
+
+    /// Keeps keys for messages in the current receiving chain up to `until`.
+    fn skip_to(&mut self, until: u32) -> Result<(), Error> {
+        let (Some(chain), Some(dh)) = (self.recv_chain.as_ref(), self.dh_remote) else {
+            return Ok(());
+        };
+        let mut chain = Zeroizing::new(*chain.expose());
+        if until.saturating_sub(self.recv_count) > MAX_SKIP {
+            return Err(Error::Malformed("message number"));
+        }
+        while self.recv_count < until {
+            let (next, key) = kdf_chain(&chain);
+            self.skipped.push(SkippedKey {
+                dh,
+                number: self.recv_count,
+                key: StoredKey::copy_of(&key),
+            });
+            *chain = *next;
+            self.recv_count += 1;
+        }
+        self.recv_chain = Some(StoredKey::copy_of(&chain));
+        let excess = self.skipped.len().saturating_sub(MAX_SKIP as usize);
+        self.skipped.drain(..excess);
+        Ok(())
+    }
+
+    /// A Diffie-Hellman ratchet step on a new key from the other side.
+    fn step(&mut self, remote: &[u8; 32]) -> Result<(), Error> {
+        self.previous_count = self.send_count;
+        self.send_count = 0;
+        self.recv_count = 0;
+        self.dh_remote = Some(*remote);
+
+        let current = x25519_secret(&self.dh_secret);
+        let (root_key, recv_chain) = kdf_root(self.root_key.expose(), &*dh(&current, remote)?);
+        let ratchet = StoredKey::generate();
+        let (root_key, send_chain) = kdf_root(&root_key, &*dh(&x25519_secret(&ratchet), remote)?);
+        self.root_key = StoredKey::copy_of(&root_key);
+        self.recv_chain = Some(StoredKey::copy_of(&recv_chain));
+        self.send_chain = Some(StoredKey::copy_of(&send_chain));
+        self.dh_secret = ratchet;
+        Ok(())
+    }
+
+    /// A copy to advance, so that a failed decryption leaves `self` as it
+    /// was.
+    fn duplicate(&self) -> RatchetSession {
+        RatchetSession {
+            version: self.version,
+            remote_identity_key: self.remote_identity_key.clone(),
+            associated_data: self.associated_data.clone(),
+            root_key: self.root_key.duplicate(),
+            dh_secret: self.dh_secret.duplicate(),
+            dh_remote: self.dh_remote,
+            send_chain: self.send_chain.as_ref().map(StoredKey::duplicate),
+            recv_chain: self.recv_chain.as_ref().map(StoredKey::duplicate),
+            send_count: self.send_count,
+            recv_count: self.recv_count,
+            previous_count: self.previous_count,
+            skipped: self
+                .skipped
+                .iter()
+                .map(|skipped| SkippedKey {
+                    dh: skipped.dh,
+                    number: skipped.number,
+                    key: skipped.key.duplicate(),
+                })
+                .collect(),
+            pending_prekey: self.pending_prekey.clone(),
+            remote_ephemeral_key: self.remote_ephemeral_key.clone(),
+        }
+    }
+
+    fn header_data(&self, header: &RatchetHeader) -> Result<Vec<u8>, Error> {
+        let mut data = self.associated_data.clone();
+        data.extend_from_slice(&decode_key(&header.dh, "ratchet key")?);
+        data.extend_from_slice(&header.previous_count.to_be_bytes());
+        data.extend_from_slice(&header.number.to_be_bytes());
+        Ok(data)
+    }
+}
+
+impl fmt::Debug for RatchetSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RatchetSession")
+            .field("remote_identity_key", &self.remote_identity_key)
+            .field("send_count", &self.send_count)
+            .field("recv_count", &self.recv_count)
+            .finish_non_exhaustive()
+    }
+}
+
+fn x25519_secret(key: &StoredKey) -> StaticSecret {
+    StaticSecret::from(*key.expose())
+}
+
+fn identity_secret(identity: &Identity) -> StaticSecret {
+    StaticSecret::from(identity.box_secret_key().to_bytes())
+}
+
+fn identity_public(public_key: &str) -> Result<[u8; 32], Error> {
+    Ok(*identity::box_public_key(public_key)?.as_bytes())
+}
+
+fn decode_key(value: &str, field: &'static str) -> Result<[u8; 32], Error> {
+    decode_base64(value, field)?
+        .try_into()
+        .map_err(|_| Error::Malformed(field))
+}
+
+fn associated_data(initiator: &str, responder: &str) -> Result<Vec<u8>, Error> {
+    let mut data = b"cipher-ratchet".to_vec();
+    data.extend_from_slice(identity::decode_public_key(initiator)?.as_bytes());
+    data.extend_from_slice(identity::decode_public_key(responder)?.as_bytes());
+    Ok(data)
+}
+
+/// Rejects low-order keys, whose output the other side doesn't influence.
+fn dh(secret: &StaticSecret, public: &[u8; 32]) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let shared = secret.diffie_hellman(&PublicKey::from(*public));
+    if !shared.was_contributory() {
+        return Err(Error::InvalidKey);
+    }
+    Ok(Zeroizing::new(shared.to_bytes()))
+}
+
+/// HKDF over 32 0xFF bytes and the DH outputs, as X3DH specifies for
+/// curve25519.
+fn x3dh_secret(secrets: &[Zeroizing<[u8; 32]>]) -> Zeroizing<[u8; 32]> {
+    let mut input = Zeroizing::new(vec![0xFF; 32]);
+    for secret in secrets {
+        input.extend_from_slice(secret.as_ref());
+    }
+    let mut out = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(&[0u8; 32]), &input)
+        .expand(X3DH_INFO, out.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    out
+}
+
+fn kdf_root(
+    root_key: &[u8; 32],
+    dh_output: &[u8; 32],
+) -> (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>) {
+    let mut okm = Zeroizing::new([0u8; 64]);
+    Hkdf::<Sha256>::new(Some(root_key), dh_output)
+        .expand(ROOT_INFO, okm.as_mut())
+        .expect("64 bytes is a valid HKDF-SHA256 length");
+    split(&okm)
+}
+
+/// The next chain key and this message's key.
+fn kdf_chain(chain_key: &[u8; 32]) -> (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>) {
+    let derive = |tag: u8| {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(chain_key).expect("HMAC takes any key size");
+        mac.update(&[tag]);
+        Zeroizing::new(<[u8; 32]>::from(mac.finalize().into_bytes()))
+    };
+    (derive(0x02), derive(0x01))
+}
+
+fn split(okm: &[u8; 64]) -> (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>) {
+    let mut first = Zeroizing::new([0u8; 32]);
+    let mut second = Zeroizing::new([0u8; 32]);
+    first.copy_from_slice(&okm[..32]);
+    second.copy_from_slice(&okm[32..]);
+    (first, second)
+}
+
+/// ChaCha20-Poly1305 under a key and nonce expanded from the message key.
+/// Each message key is used once, so the nonce never repeats under a key.
+fn message_cipher(message_key: &[u8; 32]) -> (ChaCha20Poly1305, Zeroizing<[u8; 44]>) {
+    let mut okm = Zeroizing::new([0u8; 44]);
+    Hkdf::<Sha256>::new(None, message_key)
+        .expand(MESSAGE_INFO, okm.as_mut())
+        .expect("44 bytes is a valid HKDF-SHA256 length");
+    let cipher = ChaCha20Poly1305::new(okm[..32].into());
+    (cipher, okm)
+}
+
+fn seal(message_key: &[u8; 32], associated_data: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let (cipher, okm) = message_cipher(message_key);
+    cipher
+        .encrypt(
+            Nonce::from_slice(&okm[32..]),
+            Payload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .expect("ChaCha20-Poly1305 encryption cannot fail")
+}
+
+fn open(
+    message_key: &[u8; 32],
+    associated_data: &[u8],
+    ciphertext: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let (cipher, okm) = message_cipher(message_key);
+    cipher
+        .decrypt(
+            Nonce::from_slice(&okm[32..]),
+            Payload {
+                msg: ciphertext,
+                aad: associated_data,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| Error::DecryptionFailed)
+}
@@ -0,0 +1,145 @@
+//! Recovery phrases. An identity created with one comes from a random master
+//! seed rather than the password, and the seed is shown to the user once as a
+//! 24-word BIP39 mnemonic. Keys are derived from it with HKDF-SHA256, so the
+//! phrase alone restores the account after a forgotten password.
+
+use std::fmt;
+
+use bip39::Mnemonic;
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, seq::index, RngCore};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use super::{secret::Secret, Error, Identity};
+
+pub const WORD_COUNT: usize = 24;
+
+/// How many words the backup check asks the user to re-enter.
+pub const CHALLENGE_WORDS: usize = 3;
+
+const HKDF_SALT: &[u8] = b"cipher-master-seed-v1";
+const SIGNING_INFO: &[u8] = b"signing";
+const ENCRYPTION_INFO: &[u8] = b"encryption";
+
+/// 256 bits of entropy, the same bytes the mnemonic encodes.
+pub struct MasterSeed {
+    entropy: Secret<[u8; 32]>,
+}
+
+impl MasterSeed {
+    pub fn generate() -> MasterSeed {
+        let mut entropy = Secret::zeroed();
+        OsRng.fill_bytes(entropy.expose_mut());
+        MasterSeed { entropy }
+    }
+
+    pub fn from_entropy(entropy: &[u8; 32]) -> MasterSeed {
+        MasterSeed {
+            entropy: Secret::copy_of(entropy),
+        }
+    }
+
+    /// Parses an English phrase. Case and extra whitespace are ignored;
+    /// unknown words, a bad checksum or a word count other than 24 fail.
+    pub fn from_phrase(phrase: &str) -> Result<MasterSeed, Error> {
+        let normalized = Zeroizing::new(
+            phrase
+                .split_whitespace()
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        let mnemonic = Mnemonic::parse_normalized(&normalized)
+            .map_err(|_| Error::Malformed("recovery phrase"))?;
+        if mnemonic.word_count() != WORD_COUNT {
+            return Err(Error::Malformed("recovery phrase"));
+        }
+        let (bytes, len) = mnemonic.to_entropy_array();
+        let bytes = Zeroizing::new(bytes);
+        let entropy: &[u8; 32] = bytes[..len]
+            .try_into()
+            .map_err(|_| Error::Malformed("recovery phrase"))?;
+        Ok(MasterSeed::from_entropy(entropy))
+    }
+
+    /// For the keystore only. Never hand it to the webview.
+    pub(crate) fn entropy(&self) -> &[u8; 32] {
+        self.entropy.expose()
+    }
+
+    pub fn phrase(&self) -> Zeroizing<String> {
+        let mnemonic = Mnemonic::from_entropy(self.entropy.expose())
+            .expect("32 bytes is a valid BIP39 entropy length");
+        Zeroizing::new(mnemonic.to_string())
+    }
+
+    /// The signing identity. Its Ed25519 key also yields the X25519 key that
+    /// post envelopes are sealed to.
+    pub fn identity(&self) -> Identity {
+        Identity::from_seed(&self.derive(SIGNING_INFO))
+    }
+
+    /// Symmetric key for data the account encrypts to itself.
+    pub fn encryption_key(&self) -> Zeroizing<[u8; 32]> {
+        self.derive(ENCRYPTION_INFO)
+    }
+
+    /// Whether each `(position, word)` answer matches the phrase. Positions
+    /// are zero-based; case and surrounding whitespace are ignored.
+    pub fn check_words(&self, answers: &[(usize, &str)]) -> bool {
+        let phrase = self.phrase();
+        let words: Vec<&str> = phrase.split(' ').collect();
+        !answers.is_empty()
+            && answers.iter().all(|(position, word)| {
+                words
+                    .get(*position)
+                    .is_some_and(|expected| expected.eq_ignore_ascii_case(word.trim()))
+            })
+    }
+
+    fn derive(&self, info: &[u8]) -> Zeroizing<[u8; 32]> {
+        let mut out = Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(Some(HKDF_SALT), self.entropy.expose())
+            .expand(info, out.as_mut())
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        out
+    }
+}
+
+impl fmt::Debug for MasterSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterSeed").finish_non_exhaustive()
+    }
+}
+
+/// A freshly generated seed waiting for the user to show they wrote the
+/// phrase down, by re-entering the words at a few random positions.
+#[derive(Debug)]
+pub struct PendingBackup {
+    pub seed: MasterSeed,
+    /// Zero-based, in order.
+    pub positions: Vec<usize>,
+}
+
+impl PendingBackup {
+    pub fn generate() -> PendingBackup {
+        let mut positions = index::sample(&mut OsRng, WORD_COUNT, CHALLENGE_WORDS).into_vec();
+        positions.sort_unstable();
+        PendingBackup {
+            seed: MasterSeed::generate(),
+            positions,
+        }
+    }
+
+    /// `words` are the user's answers for [`PendingBackup::positions`].
+    pub fn check(&self, words: &[String]) -> bool {
+        let answers: Vec<(usize, &str)> = self
+            .positions
+            .iter()
+            .copied()
+            .zip(words.iter().map(String::as_str))
+            .collect();
+        words.len() == self.positions.len() && self.seed.check_words(&answers)
+    }
+}
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{identity, Error, Identity};
+
+/// A statement that `new_public_key` replaces `old_public_key`, signed by
+/// both keys so friends can follow the change without trusting the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeyRotation {
+    pub version: u32,
+    pub old_public_key: String,
+    pub new_public_key: String,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub created_at: i64,
+    /// Short machine-readable cause, e.g. `kdf_upgrade`.
+    pub reason: String,
+    /// Old key's signature over [`KeyRotation::signing_bytes`].
+    pub signature: String,
+    /// New key's signature over the same bytes, proving possession.
+    pub new_key_signature: String,
+}
+
+impl KeyRotation {
+    pub const VERSION: u32 = 1;
+
+    pub fn sign(old: &Identity, new: &Identity, reason: &str, created_at: i64) -> KeyRotation {
+        let mut rotation = KeyRotation {
+            version: KeyRotation::VERSION,
+            old_public_key: old.public_key(),
+            new_public_key: new.public_key(),
+            created_at,
+            reason: reason.to_string(),
+            signature: String::new(),
+            new_key_signature: String::new(),
+        };
+        let bytes = rotation.signing_bytes();
+        rotation.signature = old.sign_base64(&bytes);
+        rotation.new_key_signature = new.sign_base64(&bytes);
+        rotation
+    }
+
+    /// Newline-separated fields under a domain tag, so a rotation signature
+    /// can never be replayed as a post or message signature.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-key-rotation\n{}\n{}\n{}\n{}\n{}",
+            self.version, self.old_public_key, self.new_public_key, self.created_at, self.reason
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != KeyRotation::VERSION || self.reason.contains('\n') {
+            return Err(Error::Malformed("key rotation"));
+        }
+        let bytes = self.signing_bytes();
+        identity::verify(&self.old_public_key, &bytes, &self.signature)?;
+        identity::verify(&self.new_public_key, &bytes, &self.new_key_signature)
+    }
+}
+
+/// Retires a key that may have been copied, such as one on a lost device,
+/// and hands the account to `new_public_key`. Signed by the revoked key
+/// (recovered from the phrase or friends' shares) and by the new one. Only
+/// the account's current key can be revoked: one that already rotated away
+/// keeps its rotation, see [`KeyHistory::build`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeyRevocation {
+    pub version: u32,
+    pub revoked_public_key: String,
+    pub new_public_key: String,
+    /// Unix seconds from which the revoked key is untrusted. Usually before
+    /// `created_at`, to cover the time since the key was lost; posts it
+    /// signed from then on are rejected. Never before the revoked key
+    /// became the account's.
+    #[ts(type = "number")]
+    pub revoked_at: i64,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub created_at: i64,
+    pub reason: String,
+    /// Revoked key's signature over [`KeyRevocation::signing_bytes`].
+    pub signature: String,
+    /// New key's signature over the same bytes.
+    pub new_key_signature: String,
+}
+
+impl KeyRevocation {
+    pub const VERSION: u32 = 1;
+
+    pub fn sign(
+        revoked: &Identity,
+        new: &Identity,
+        revoked_at: i64,
+        reason: &str,
+        created_at: i64,
+    ) -> KeyRevocation {
+        let mut revocation = KeyRevocation {
+            version: KeyRevocation::VERSION,
+            revoked_public_key: revoked.public_key(),
+            new_public_key: new.public_key(),
+            revoked_at,
+            created_at,
+            reason: reason.to_string(),
+            signature: String::new(),
+            new_key_signature: String::new(),
+        };
+        let bytes = revocation.signing_bytes();
+        revocation.signature = revoked.sign_base64(&bytes);
+        revocation.new_key_signature = new.sign_base64(&bytes);
+        revocation
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-key-revocation\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.revoked_public_key,
+            self.new_public_key,
+            self.revoked_at,
+            self.created_at,
+            self.reason
+        )
+        .into_bytes()
+    }
+
+    /// Checks the fields and both signatures. Whether `revoked_at` falls
+    /// after the revoked key became the account's needs its history; see
+    /// [`KeyHistory::build`].
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != KeyRevocation::VERSION
+            || self.reason.contains('\n')
+            || self.revoked_at > self.created_at
+        {
+            return Err(Error::Malformed("key revocation"));
+        }
+        let bytes = self.signing_bytes();
+        identity::verify(&self.revoked_public_key, &bytes, &self.signature)?;
+        identity::verify(&self.new_public_key, &bytes, &self.new_key_signature)
+    }
+}
+
+/// One link in an account's key chain, as stored in `key_events` and sent
+/// to peers over sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum KeyEvent {
+    Rotation(KeyRotation),
+    Revocation(KeyRevocation),
+}
+
+impl KeyEvent {
+    /// The serialized tag, stored in `key_events.event_type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyEvent::Rotation(_) => "rotation",
+            KeyEvent::Revocation(_) => "revocation",
+        }
+    }
+
+    /// The key the event moves away from.
+    pub fn old_public_key(&self) -> &str {
+        match self {
+            KeyEvent::Rotation(rotation) => &rotation.old_public_key,
+            KeyEvent::Revocation(revocation) => &revocation.revoked_public_key,
+        }
+    }
+
+    pub fn new_public_key(&self) -> &str {
+        match self {
+            KeyEvent::Rotation(rotation) => &rotation.new_public_key,
+            KeyEvent::Revocation(revocation) => &revocation.new_public_key,
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        match self {
+            KeyEvent::Rotation(rotation) => rotation.verify(),
+            KeyEvent::Revocation(revocation) => revocation.verify(),
+        }
+    }
+}
+
+/// When one key was the account's. Bounds are Unix seconds; `valid_until`
+/// is exclusive and `None` means still valid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeyPeriod {
+    pub public_key: String,
+    #[ts(type = "number | null")]
+    pub valid_from: Option<i64>,
+    #[ts(type = "number | null")]
+    pub valid_until: Option<i64>,
+}
+
+/// An account's keys in order, built by following its verified events from
+/// the first key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct KeyHistory {
+    pub periods: Vec<KeyPeriod>,
+}
+
+impl KeyHistory {
+    /// The first key of a chain: the one events leave but none arrive at.
+    /// `None` if there are no events.
+    pub fn root(events: &[KeyEvent]) -> Option<&str> {
+        events
+            .iter()
+            .map(KeyEvent::old_public_key)
+            .find(|key| !events.iter().any(|event| event.new_public_key() == *key))
+    }
+
+    /// Follows `events` from `root`. From each key the chain takes its one
+    /// rotation, or else its revocation's new key. A key with both, or with
+    /// revocations handing it to different keys, is a fork and fails:
+    /// whoever holds a retired key could otherwise backdate a revocation of
+    /// it past the rotation and take the account. So does a revocation from
+    /// before its key became the account's.
+    pub fn build(root: &str, events: &[KeyEvent]) -> Result<KeyHistory, Error> {
+        for event in events {
+            event.verify()?;
+        }
+        let mut periods: Vec<KeyPeriod> = Vec::new();
+        let mut current = root.to_string();
+        let mut valid_from = None;
+        loop {
+            let revocations: Vec<&KeyRevocation> = events
+                .iter()
+                .filter_map(|event| match event {
+                    KeyEvent::Revocation(revocation)
+                        if revocation.revoked_public_key == current =>
+                    {
+                        Some(revocation)
+                    }
+                    _ => None,
+                })
+                .collect();
+            let rotations: Vec<&KeyRotation> = events
+                .iter()
+                .filter_map(|event| match event {
+                    KeyEvent::Rotation(rotation)
+                        if rotation.old_public_key == current
+                            && valid_from.is_none_or(|from| rotation.created_at >= from) =>
+                    {
+                        Some(rotation)
+                    }
+                    _ => None,
+                })
+                .collect();
+            if revocations
+                .iter()
+                .any(|r| valid_from.is_some_and(|from| r.revoked_at < from))
+            {
+                return Err(Error::Malformed("key revocation predates its key"));
+            }
+
+            // (next key, this key's end, next key's start)
+            let next = match (rotations.as_slice(), revocations.as_slice()) {
+                ([rotation], []) => Some((
+                    &rotation.new_public_key,
+                    rotation.created_at,
+                    rotation.created_at,
+                )),
+                ([], [first, ..])
+                    if revocations
+                        .iter()
+                        .all(|r| r.new_public_key == first.new_public_key) =>
+                {
+                    // Repeats of one revocation: trust the key the least
+                    let revocation = revocations
+                        .iter()
+                        .min_by_key(|revocation| revocation.revoked_at)
+                        .expect("at least one revocation");
+                    Some((
+                        &revocation.new_public_key,
+                        revocation.revoked_at,
+                        revocation.created_at,
+                    ))
+                }
+                ([], []) => None,
+                _ => return Err(Error::Malformed("key chain fork")),
+            };
+            periods.push(KeyPeriod {
+                public_key: current.clone(),
+                valid_from,
+                valid_until: next.map(|(_, until, _)| until),
+            });
+            let Some((next_key, _, from)) = next else {
+                return Ok(KeyHistory { periods });
+            };
+            if periods.iter().any(|period| &period.public_key == next_key) {
+                return Err(Error::Malformed("key chain loop"));
+            }
+            current = next_key.clone();
+            valid_from = Some(from);
+        }
+    }
+
+    /// The key that was valid at `timestamp` (Unix seconds), if any. Gaps
+    /// left by a revocation have none.
+    pub fn key_at(&self, timestamp: i64) -> Option<&str> {
+        self.periods
+            .iter()
+            .find(|period| {
+                period.valid_from.is_none_or(|from| timestamp >= from)
+                    && period.valid_until.is_none_or(|until| timestamp < until)
+            })
+            .map(|period| period.public_key.as_str())
+    }
+
+    pub fn current(&self) -> &str {
+        &self
+            .periods
+            .last()
+            .expect("a history has at least its root")
+            .public_key
+    }
+
+    pub fn contains(&self, public_key: &str) -> bool {
+        self.periods
+            .iter()
+            .any(|period| period.public_key == public_key)
+    }
+}
@@ -0,0 +1,102 @@
+//! Safety numbers. Two friends compare one 60-digit number, or scan it as a
+//! QR code, to check that each holds the other's real public key and not one
+//! a relay swapped in. Each half is a fingerprint of one key; the halves are
+//! sorted so both sides see the same number.
+
+use std::fmt;
+
+use qrcode::{render::svg, QrCode};
+use sha2::{Digest, Sha512};
+
+use super::{identity, Error};
+
+pub const VERSION: u32 = 1;
+
+/// Hash iterations per fingerprint, as in Signal, to make a key whose
+/// fingerprint collides with a victim's expensive to search for.
+const ITERATIONS: usize = 5200;
+
+const QR_PREFIX: &str = "cipher-safety-number";
+
+/// Six groups of five digits for one key.
+pub fn fingerprint(public_key: &str) -> Result<String, Error> {
+    let key = identity::decode_public_key(public_key)?.to_bytes();
+    let mut hash = Sha512::new()
+        .chain_update(b"cipher-fingerprint")
+        .chain_update(VERSION.to_be_bytes())
+        .chain_update(key)
+        .finalize();
+    for _ in 0..ITERATIONS {
+        hash = Sha512::new()
+            .chain_update(hash)
+            .chain_update(key)
+            .finalize();
+    }
+    Ok(hash[..30]
+        .chunks(5)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+            format!("{:05}", value % 100_000)
+        })
+        .collect())
+}
+
+/// The number two keys share. Displays as twelve groups of five digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyNumber {
+    digits: String,
+}
+
+impl SafetyNumber {
+    pub fn new(local_key: &str, remote_key: &str) -> Result<SafetyNumber, Error> {
+        let mut halves = [fingerprint(local_key)?, fingerprint(remote_key)?];
+        halves.sort();
+        Ok(SafetyNumber {
+            digits: halves.concat(),
+        })
+    }
+
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    /// What the QR code encodes.
+    pub fn qr_payload(&self) -> String {
+        format!("{}:{}:{}", QR_PREFIX, VERSION, self.digits)
+    }
+
+    pub fn qr_svg(&self) -> String {
+        QrCode::new(self.qr_payload())
+            .expect("a safety number payload fits in a QR code")
+            .render::<svg::Color>()
+            .min_dimensions(240, 240)
+            .build()
+    }
+
+    /// Whether `scanned` is this number, either as a scanned QR payload or
+    /// typed digits. Spaces and dashes between digits are ignored.
+    pub fn matches(&self, scanned: &str) -> bool {
+        let scanned = scanned.trim();
+        let digits = match scanned.strip_prefix(QR_PREFIX) {
+            Some(rest) => match rest.strip_prefix(&format!(":{}:", VERSION)) {
+                Some(digits) => digits.to_string(),
+                None => return false,
+            },
+            None => scanned
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '-')
+                .collect(),
+        };
+        digits == self.digits
+    }
+}
+
+impl fmt::Display for SafetyNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups: Vec<&str> = (0..self.digits.len())
+            .step_by(5)
+            .map(|i| &self.digits[i..i + 5])
+            .collect();
+        f.write_str(&groups.join(" "))
+    }
+}
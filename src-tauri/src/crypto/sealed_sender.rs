@@ -0,0 +1,167 @@
+//! Sealed-sender sync envelopes. Everything a relay or hosting peer could
+//! learn from a sync message, namely who sent it, who it's for and what kind
+//! of message it is, goes inside a sealed box to the recipient's key. The
+//! sealed box uses a fresh ephemeral key, so the outside says nothing about
+//! the sender, and the contents are padded so the size only reveals a bucket.
+
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{decode_base64, encode_base64, identity, Error, Identity};
+
+/// Sizes the sealed ciphertext is padded up to. Anything larger is padded to
+/// a whole number of the last bucket.
+pub const BUCKETS: [usize; 6] = [1024, 4096, 16384, 65536, 262144, 1048576];
+
+/// Bytes a sealed box adds: the ephemeral public key and the Poly1305 tag.
+const SEAL_OVERHEAD: usize = 48;
+
+/// What travels between peers. Only the recipient can open it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SealedEnvelope {
+    pub version: u32,
+    /// Base64 sealed box, always one of the [`BUCKETS`] sizes (or a multiple
+    /// of the largest).
+    pub ciphertext: String,
+}
+
+/// The sync message inside an envelope, signed by its sender.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedContent {
+    pub version: u32,
+    pub sender_key: String,
+    /// Everyone the sender addressed. A recipient that isn't listed rejects
+    /// the message, so a recipient can't pass a signed message on to someone
+    /// else as if it had been sent to them.
+    pub recipient_keys: Vec<String>,
+    /// The outbound sync message type, e.g. `outbound_key_event`.
+    pub message_type: String,
+    /// Unix seconds.
+    pub timestamp: i64,
+    pub payload: String,
+    /// Sender's signature over [`SealedContent::signing_bytes`].
+    pub signature: String,
+}
+
+impl SealedEnvelope {
+    pub const VERSION: u32 = 1;
+
+    /// Signs the message as `sender` and seals it to `recipient_key`.
+    pub fn seal(
+        sender: &Identity,
+        recipient_key: &str,
+        message_type: &str,
+        payload: &str,
+        timestamp: i64,
+    ) -> Result<SealedEnvelope, Error> {
+        if message_type.contains('\n') {
+            return Err(Error::Malformed("message type"));
+        }
+        let mut content = SealedContent {
+            version: SealedContent::VERSION,
+            sender_key: sender.public_key(),
+            recipient_keys: vec![recipient_key.to_string()],
+            message_type: message_type.to_string(),
+            timestamp,
+            payload: payload.to_string(),
+            signature: String::new(),
+        };
+        content.signature = sender.sign_base64(&content.signing_bytes());
+        let plaintext =
+            serde_json::to_vec(&content).map_err(|_| Error::Malformed("sealed content"))?;
+        let sealed = identity::box_public_key(recipient_key)?
+            .seal(&mut OsRng, &pad(&plaintext))
+            .map_err(|_| Error::InvalidKey)?;
+        Ok(SealedEnvelope {
+            version: SealedEnvelope::VERSION,
+            ciphertext: encode_base64(&sealed),
+        })
+    }
+
+    /// Opens the envelope with `identity` and checks the sender's signature.
+    /// Fails with [`Error::NotRecipient`] if it wasn't addressed to
+    /// `identity`.
+    pub fn open(&self, identity: &Identity) -> Result<SealedContent, Error> {
+        if self.version != SealedEnvelope::VERSION {
+            return Err(Error::Malformed("sealed envelope version"));
+        }
+        let sealed = decode_base64(&self.ciphertext, "sealed envelope")?;
+        let padded = identity
+            .box_secret_key()
+            .unseal(&sealed)
+            .map_err(|_| Error::NotRecipient)?;
+        let content: SealedContent = serde_json::from_slice(unpad(&padded)?)
+            .map_err(|_| Error::Malformed("sealed content"))?;
+        content.verify()?;
+        if !content.recipient_keys.contains(&identity.public_key()) {
+            return Err(Error::NotRecipient);
+        }
+        Ok(content)
+    }
+
+    /// Length of the decoded ciphertext, which is what an observer sees.
+    pub fn sealed_len(&self) -> Result<usize, Error> {
+        Ok(decode_base64(&self.ciphertext, "sealed envelope")?.len())
+    }
+}
+
+impl SealedContent {
+    pub const VERSION: u32 = 1;
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "cipher-sealed-sender\n{}\n{}\n{}\n{}\n{}\n",
+            self.version,
+            self.sender_key,
+            self.recipient_keys.join(","),
+            self.message_type,
+            self.timestamp
+        )
+        .into_bytes();
+        bytes.extend_from_slice(self.payload.as_bytes());
+        bytes
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != SealedContent::VERSION
+            || self.message_type.contains('\n')
+            || self.recipient_keys.iter().any(|key| key.contains(','))
+        {
+            return Err(Error::Malformed("sealed content"));
+        }
+        identity::verify(&self.sender_key, &self.signing_bytes(), &self.signature)
+    }
+}
+
+/// The bucket a sealed message of `len` bytes is padded up to.
+pub fn bucket_for(len: usize) -> usize {
+    match BUCKETS.iter().find(|&&bucket| len <= bucket) {
+        Some(&bucket) => bucket,
+        None => {
+            let largest = BUCKETS[BUCKETS.len() - 1];
+            len.div_ceil(largest) * largest
+        }
+    }
+}
+
+/// Appends 0x80 and then zeros (ISO/IEC 7816-4) so the sealed box fills a
+/// bucket exactly.
+fn pad(plaintext: &[u8]) -> Vec<u8> {
+    let target = bucket_for(plaintext.len() + 1 + SEAL_OVERHEAD) - SEAL_OVERHEAD;
+    let mut padded = Vec::with_capacity(target);
+    padded.extend_from_slice(plaintext);
+    padded.push(0x80);
+    padded.resize(target, 0);
+    padded
+}
+
+fn unpad(padded: &[u8]) -> Result<&[u8], Error> {
+    let end = padded
+        .iter()
+        .rposition(|&b| b != 0)
+        .filter(|&i| padded[i] == 0x80)
+        .ok_or(Error::Malformed("padding"))?;
+    Ok(&padded[..end])
+}
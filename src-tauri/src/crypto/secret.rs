@@ -0,0 +1,237 @@
+//! The wrapper every long-lived secret in the crate is held in: identity
+//! seeds, recovery entropy, keystore keys and entries, ratchet, prekey and
+//! circle keys, and passwords on their way through a command. A [`Secret`] can't be printed or serialized,
+//! compares in constant time, is zeroized when dropped and is kept out of swap
+//! with `mlock` where the OS allows it.
+//!
+//! `Zeroizing` is still fine for a buffer that lives inside one function, such
+//! as a KDF output on its way into a `Secret`. Anything kept in a struct or
+//! passed between threads uses `Secret`.
+
+use std::fmt;
+
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+/// A value whose bytes can be locked in memory and compared.
+pub trait SecretMemory: Zeroize {
+    /// Every byte of the secret, wherever it is stored.
+    fn secret_bytes(&self) -> &[u8];
+}
+
+impl<const N: usize> SecretMemory for [u8; N] {
+    fn secret_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl SecretMemory for Vec<u8> {
+    fn secret_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl SecretMemory for String {
+    fn secret_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// A heap-allocated secret. Read it with [`Secret::expose`]; there is no
+/// `Deref`, so every use is visible at the call site.
+pub struct Secret<T: SecretMemory> {
+    value: Box<T>,
+    locked: bool,
+}
+
+impl<T: SecretMemory> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        let value = Box::new(value);
+        let locked = lock(value.secret_bytes());
+        Secret { value, locked }
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.value
+    }
+
+    /// Whether the OS agreed to keep the secret out of swap. Failing to lock
+    /// isn't an error; the secret is still zeroized.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<const N: usize> Secret<[u8; N]> {
+    /// All zeros, to be filled in place with [`Secret::expose_mut`].
+    pub fn zeroed() -> Secret<[u8; N]> {
+        Secret::new([0u8; N])
+    }
+
+    /// Copies `bytes` straight into locked memory, leaving no other copy.
+    pub fn copy_of(bytes: &[u8; N]) -> Secret<[u8; N]> {
+        let mut secret = Secret::zeroed();
+        secret.expose_mut().copy_from_slice(bytes);
+        secret
+    }
+
+    /// Fixed-size secrets can be written in place; their memory never moves.
+    pub fn expose_mut(&mut self) -> &mut [u8; N] {
+        &mut self.value
+    }
+}
+
+impl<T: SecretMemory + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Secret<T> {
+        Secret::new(T::clone(&self.value))
+    }
+}
+
+impl<T: SecretMemory> PartialEq for Secret<T> {
+    /// Constant time in the contents; only the lengths can leak.
+    fn eq(&self, other: &Secret<T>) -> bool {
+        self.value
+            .secret_bytes()
+            .ct_eq(other.value.secret_bytes())
+            .into()
+    }
+}
+
+impl<T: SecretMemory> Eq for Secret<T> {}
+
+impl<T: SecretMemory> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl<T: SecretMemory> Drop for Secret<T> {
+    fn drop(&mut self) {
+        let bytes = self.value.secret_bytes();
+        let (ptr, len) = (bytes.as_ptr(), bytes.len());
+        self.value.zeroize();
+        if self.locked {
+            unlock(ptr, len);
+        }
+    }
+}
+
+/// A 32-byte key in a [`Secret`] that serializes as its bytes. Only for
+/// state the keystore stores encrypted, such as ratchet sessions, prekeys and
+/// circle keys; a `Secret` itself never serializes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StoredKey(Secret<[u8; 32]>);
+
+impl StoredKey {
+    /// Random bytes, written straight into locked memory.
+    pub fn generate() -> StoredKey {
+        let mut key = Secret::zeroed();
+        OsRng.fill_bytes(key.expose_mut());
+        StoredKey(key)
+    }
+
+    pub fn copy_of(bytes: &[u8; 32]) -> StoredKey {
+        StoredKey(Secret::copy_of(bytes))
+    }
+
+    pub fn expose(&self) -> &[u8; 32] {
+        self.0.expose()
+    }
+
+    /// A second copy, for state that is advanced speculatively. Not `Clone`,
+    /// so every copy is visible at the call site.
+    pub fn duplicate(&self) -> StoredKey {
+        StoredKey::copy_of(self.expose())
+    }
+}
+
+impl Serialize for StoredKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.expose().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StoredKey, D::Error> {
+        let mut bytes = <[u8; 32]>::deserialize(deserializer)?;
+        let key = StoredKey::copy_of(&bytes);
+        bytes.zeroize();
+        Ok(key)
+    }
+}
+
+/// `mlock` and `munlock` work on whole pages and don't nest, so several
+/// secrets on one page would be unlocked together when the first is dropped.
+/// This counts the live secrets on each locked page and only unlocks a page
+/// when its last one goes.
+#[cfg(unix)]
+static LOCKED_PAGES: std::sync::Mutex<std::collections::BTreeMap<usize, usize>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+#[cfg(unix)]
+fn lock(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return false;
+    }
+    let mut pages = LOCKED_PAGES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // SAFETY: mlock only changes paging for memory we own; it reads nothing.
+    if unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } != 0 {
+        return false;
+    }
+    for page in pages_of(bytes.as_ptr(), bytes.len()) {
+        *pages.entry(page).or_insert(0) += 1;
+    }
+    true
+}
+
+#[cfg(unix)]
+fn unlock(ptr: *const u8, len: usize) {
+    let mut pages = LOCKED_PAGES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for page in pages_of(ptr, len) {
+        let Some(count) = pages.get_mut(&page) else {
+            continue;
+        };
+        *count -= 1;
+        if *count == 0 {
+            pages.remove(&page);
+            // SAFETY: the page was locked by `lock` and no live secret is on
+            // it any more.
+            unsafe {
+                libc::munlock(page as *const libc::c_void, page_size());
+            }
+        }
+    }
+}
+
+/// Start addresses of the pages `len` bytes from `ptr` touch.
+#[cfg(unix)]
+fn pages_of(ptr: *const u8, len: usize) -> impl Iterator<Item = usize> {
+    let size = page_size();
+    let first = ptr as usize / size * size;
+    let last = (ptr as usize + len - 1) / size * size;
+    (first..=last).step_by(size)
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    static SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    // SAFETY: sysconf has no preconditions.
+    *SIZE.get_or_init(|| match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    })
+}
+
+#[cfg(not(unix))]
+fn lock(_bytes: &[u8]) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn unlock(_ptr: *const u8, _len: usize) {}
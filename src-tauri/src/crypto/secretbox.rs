@@ -0,0 +1,81 @@
+//! RbNaCl's `SecretBox` (XSalsa20-Poly1305, libsodium's
+//! `crypto_secretbox_easy`) and `SimpleBox`, the same with a random nonce
+//! prepended. These are the formats the Rails models wrote before
+//! [`super::envelope`]: `Post#encrypt_content` uses a `SecretBox` and
+//! `Attachment#encrypt_data` a `SimpleBox`, so content they encrypted can be
+//! opened here. `test-vectors/secretbox.json` and `simplebox.json` pin both.
+
+use crypto_secretbox::{
+    aead::{Aead, KeyInit},
+    Nonce, XSalsa20Poly1305,
+};
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroizing;
+
+use super::{decode_base64, Error};
+
+pub const KEY_BYTES: usize = 32;
+pub const NONCE_BYTES: usize = 24;
+/// Poly1305 tag, which comes before the ciphertext.
+pub const TAG_BYTES: usize = 16;
+
+/// Encrypts `plaintext` as `RbNaCl::SecretBox#encrypt(nonce, plaintext)`
+/// does: the tag followed by the ciphertext.
+pub fn seal(key: &[u8], nonce: &[u8; NONCE_BYTES], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    cipher(key)?
+        .encrypt(Nonce::from_slice(nonce), plaintext)
+        .map_err(|_| Error::Malformed("plaintext"))
+}
+
+/// Decrypts the output of [`seal`] or `RbNaCl::SecretBox#encrypt`.
+pub fn open(
+    key: &[u8],
+    nonce: &[u8; NONCE_BYTES],
+    ciphertext: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let plaintext = cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::DecryptionFailed)?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Encrypts `plaintext` as `RbNaCl::SimpleBox#encrypt` does, under a fresh
+/// random nonce that is prepended to the [`seal`] output.
+pub fn simple_seal(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let sealed = seal(key, &nonce, plaintext)?;
+
+    let mut out = Vec::with_capacity(NONCE_BYTES + sealed.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Decrypts the output of [`simple_seal`] or `RbNaCl::SimpleBox#encrypt`.
+pub fn simple_open(key: &[u8], boxed: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    if boxed.len() < NONCE_BYTES + TAG_BYTES {
+        return Err(Error::Malformed("simple box"));
+    }
+    let (nonce, ciphertext) = boxed.split_at(NONCE_BYTES);
+    open(
+        key,
+        nonce.try_into().expect("split at nonce length"),
+        ciphertext,
+    )
+}
+
+/// Decodes Base64 as Ruby's `Base64.encode64` writes it, with a line break
+/// every 60 characters. `attachments.data_encrypted` and `dev_owner_key` are
+/// stored that way.
+pub fn decode_ruby_base64(value: &str, field: &'static str) -> Result<Vec<u8>, Error> {
+    let joined: String = value.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    decode_base64(&joined, field)
+}
+
+fn cipher(key: &[u8]) -> Result<XSalsa20Poly1305, Error> {
+    if key.len() != KEY_BYTES {
+        return Err(Error::InvalidKey);
+    }
+    Ok(XSalsa20Poly1305::new(key.into()))
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{identity, Error, Identity};
+
+const DOMAIN: &[u8] = b"cipher-signed-post\0";
+
+/// Everything a post signature covers. Replaces the Rails scheme of signing
+/// `content_encrypted` and checksums concatenated, which falls back to an
+/// unkeyed SHA-256 when no key is at hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SignedPost {
+    pub version: u32,
+    /// Base64 Ed25519 public key of the author.
+    pub author: String,
+    /// `posts.content_encrypted` exactly as stored, e.g. a post envelope.
+    pub content_encrypted: String,
+    /// Lowercase hex SHA-256 checksums of the attachments, sorted.
+    pub attachment_checksums: Vec<String>,
+    /// Unix seconds, UTC.
+    #[ts(type = "number")]
+    pub timestamp: i64,
+}
+
+impl SignedPost {
+    pub const VERSION: u32 = 1;
+
+    /// Checks and sorts the checksums so the same post always serializes
+    /// the same way.
+    pub fn new(
+        author: &str,
+        content_encrypted: &str,
+        attachment_checksums: impl IntoIterator<Item = String>,
+        timestamp: i64,
+    ) -> Result<SignedPost, Error> {
+        identity::decode_public_key(author)?;
+        let mut checksums: Vec<String> = attachment_checksums.into_iter().collect();
+        if !checksums.iter().all(|checksum| is_checksum(checksum)) {
+            return Err(Error::Malformed("attachment checksum"));
+        }
+        checksums.sort();
+        checksums.dedup();
+        Ok(SignedPost {
+            version: Self::VERSION,
+            author: author.to_string(),
+            content_encrypted: content_encrypted.to_string(),
+            attachment_checksums: checksums,
+            timestamp,
+        })
+    }
+
+    /// The bytes that are signed:
+    ///
+    /// ```text
+    /// "cipher-signed-post" 0x00
+    /// u32 version
+    /// field(author) field(content_encrypted)
+    /// u32 count, then field(checksum) for each
+    /// i64 timestamp
+    /// ```
+    ///
+    /// Integers are big-endian and `field` is a u32 length then the UTF-8
+    /// bytes, so no two posts share an encoding.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.content_encrypted.len());
+        out.extend_from_slice(DOMAIN);
+        out.extend_from_slice(&self.version.to_be_bytes());
+        field(&mut out, &self.author);
+        field(&mut out, &self.content_encrypted);
+        out.extend_from_slice(&(self.attachment_checksums.len() as u32).to_be_bytes());
+        for checksum in &self.attachment_checksums {
+            field(&mut out, checksum);
+        }
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out
+    }
+
+    /// Base64 signature by `identity`, which must be the author.
+    pub fn sign(&self, identity: &Identity) -> Result<String, Error> {
+        if identity.public_key() != self.author {
+            return Err(Error::InvalidKey);
+        }
+        Ok(identity.sign_base64(&self.canonical_bytes()))
+    }
+
+    pub fn verify(&self, signature: &str) -> Result<(), Error> {
+        if self.version != Self::VERSION {
+            return Err(Error::Malformed("signed post version"));
+        }
+        identity::verify(&self.author, &self.canonical_bytes(), signature)
+    }
+}
+
+pub(crate) fn field(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn is_checksum(value: &str) -> bool {
+    value.len() == 64
+        && value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Formats Unix seconds the way SQLite's `strftime('%Y-%m-%d %H:%M:%f')`
+/// writes `posts.timestamp`, e.g. `2025-01-02 03:04:05.000`.
+pub fn format_timestamp(unix: i64) -> String {
+    let days = unix.div_euclid(86_400);
+    let seconds = unix.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.000",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parses a UTC timestamp as stored by SQLite or Rails
+/// (`2025-01-02 03:04:05.123`, `2025-01-02T03:04:05Z`, ...) into Unix
+/// seconds. Fractions are dropped, as Ruby's `Time#to_i` does.
+pub fn parse_timestamp(value: &str) -> Result<i64, Error> {
+    let malformed = || Error::Malformed("timestamp");
+    let value = value.trim();
+    let value = value
+        .strip_suffix('Z')
+        .or_else(|| value.strip_suffix("+00:00"))
+        .or_else(|| value.strip_suffix(" UTC"))
+        .unwrap_or(value);
+    if value.len() < 19 || !value.is_char_boundary(19) {
+        return Err(malformed());
+    }
+    let (date_time, fraction) = value.split_at(19);
+    if !(fraction.is_empty()
+        || fraction.starts_with('.') && fraction[1..].bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(malformed());
+    }
+
+    let bytes = date_time.as_bytes();
+    let separators_ok = bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && matches!(bytes[10], b' ' | b'T')
+        && bytes[13] == b':'
+        && bytes[16] == b':';
+    if !separators_ok {
+        return Err(malformed());
+    }
+    let number = |range: std::ops::Range<usize>| -> Result<i64, Error> {
+        let digits = &date_time[range];
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(malformed());
+        }
+        digits.parse().map_err(|_| malformed())
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return Err(malformed());
+    }
+    if second > 60 {
+        return Err(malformed());
+    }
+    Ok(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+// Howard Hinnant's days <-> civil date algorithms, proleptic Gregorian.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
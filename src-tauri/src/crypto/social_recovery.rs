@@ -0,0 +1,301 @@
+//! Social recovery. The account's signing seed is split with Shamir's scheme
+//! over GF(256) into one share per trusted friend, and any `threshold` of
+//! them rebuild it. Each share is sealed to its holder's key and signed by
+//! the owner. To recover, a new device makes a one-off key and holders reseal
+//! their share to it, so no share is ever readable in transit.
+
+use std::collections::BTreeMap;
+
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use blahaj::{Share, Sharks};
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+use super::{decode_base64, encode_base64, identity, Error, Identity};
+
+pub const MIN_THRESHOLD: usize = 2;
+
+/// Shares are indexed by a nonzero byte.
+pub const MAX_SHARES: usize = 255;
+
+/// One friend's share of an account's seed, as delivered over sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ShareGrant {
+    pub version: u32,
+    /// Base64, 16 random bytes common to every grant from one split.
+    pub set_id: String,
+    pub owner_username: String,
+    pub owner_public_key: String,
+    pub holder_public_key: String,
+    pub threshold: u32,
+    pub total: u32,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub created_at: i64,
+    /// Base64 sealed box of the share, for the holder's key.
+    pub sealed_share: String,
+    /// Owner's signature over [`ShareGrant::signing_bytes`].
+    pub signature: String,
+}
+
+/// Asks holders to return their shares to a new device. Only the device
+/// that made it has the secret half of `request_public_key`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RecoveryRequest {
+    pub version: u32,
+    pub owner_username: String,
+    pub request_public_key: String,
+    /// Unix seconds.
+    #[ts(type = "number")]
+    pub created_at: i64,
+}
+
+/// A share resealed by its holder to a [`RecoveryRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReturnedShare {
+    pub grant: ShareGrant,
+    pub request_public_key: String,
+    /// Base64 sealed box of the share, for the request key.
+    pub sealed_share: String,
+    /// Holder's signature over [`ReturnedShare::signing_bytes`].
+    pub signature: String,
+}
+
+impl ShareGrant {
+    pub const VERSION: u32 = 1;
+
+    /// Splits `owner`'s seed into one grant per holder (Base64 Ed25519
+    /// public keys), any `threshold` of which recover it.
+    pub fn deal(
+        owner: &Identity,
+        owner_username: &str,
+        holders: &[String],
+        threshold: usize,
+        created_at: i64,
+    ) -> Result<Vec<ShareGrant>, Error> {
+        let owner_public_key = owner.public_key();
+        let mut distinct = holders.to_vec();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() != holders.len()
+            || holders.len() > MAX_SHARES
+            || holders.contains(&owner_public_key)
+            || !(MIN_THRESHOLD..=holders.len()).contains(&threshold)
+            || owner_username.contains('\n')
+        {
+            return Err(Error::Malformed("recovery share holders"));
+        }
+
+        let mut set_id = [0u8; 16];
+        OsRng.fill_bytes(&mut set_id);
+        let set_id = encode_base64(&set_id);
+        let seed = owner.seed();
+        let shares = Sharks(threshold as u8).dealer_rng(seed.expose(), &mut OsRng);
+
+        holders
+            .iter()
+            .zip(shares)
+            .map(|(holder, share)| {
+                let mut grant = ShareGrant {
+                    version: ShareGrant::VERSION,
+                    set_id: set_id.clone(),
+                    owner_username: owner_username.to_string(),
+                    owner_public_key: owner_public_key.clone(),
+                    holder_public_key: holder.clone(),
+                    threshold: threshold as u32,
+                    total: holders.len() as u32,
+                    created_at,
+                    sealed_share: seal(&Zeroizing::new(Vec::from(&share)), holder)?,
+                    signature: String::new(),
+                };
+                grant.signature = owner.sign_base64(&grant.signing_bytes());
+                Ok(grant)
+            })
+            .collect()
+    }
+
+    /// Newline-separated fields under a domain tag, like key rotations.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-recovery-share\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.set_id,
+            self.owner_username,
+            self.owner_public_key,
+            self.holder_public_key,
+            self.threshold,
+            self.total,
+            self.created_at,
+            self.sealed_share
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != ShareGrant::VERSION
+            || self.owner_username.contains('\n')
+            || !(MIN_THRESHOLD as u32..=self.total).contains(&self.threshold)
+            || self.total as usize > MAX_SHARES
+        {
+            return Err(Error::Malformed("recovery share"));
+        }
+        identity::verify(
+            &self.owner_public_key,
+            &self.signing_bytes(),
+            &self.signature,
+        )
+    }
+
+    /// Reseals the share held by `holder` to `request`. Only do this once
+    /// the owner has confirmed the request's [`RecoveryRequest::code`].
+    pub fn release(
+        &self,
+        holder: &Identity,
+        request: &RecoveryRequest,
+    ) -> Result<ReturnedShare, Error> {
+        self.verify()?;
+        request.verify()?;
+        if holder.public_key() != self.holder_public_key {
+            return Err(Error::NotRecipient);
+        }
+        if request.owner_username != self.owner_username {
+            return Err(Error::Malformed("recovery request"));
+        }
+        let share = open(&self.sealed_share, holder)?;
+        let mut returned = ReturnedShare {
+            grant: self.clone(),
+            request_public_key: request.request_public_key.clone(),
+            sealed_share: seal(&share, &request.request_public_key)?,
+            signature: String::new(),
+        };
+        returned.signature = holder.sign_base64(&returned.signing_bytes());
+        Ok(returned)
+    }
+}
+
+impl RecoveryRequest {
+    pub const VERSION: u32 = 1;
+
+    /// A request and the one-off identity its returned shares open with.
+    pub fn generate(owner_username: &str, created_at: i64) -> (RecoveryRequest, Identity) {
+        let mut seed = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(seed.as_mut());
+        let identity = Identity::from_seed(&seed);
+        let request = RecoveryRequest {
+            version: RecoveryRequest::VERSION,
+            owner_username: owner_username.to_string(),
+            request_public_key: identity.public_key(),
+            created_at,
+        };
+        (request, identity)
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.version != RecoveryRequest::VERSION || self.owner_username.contains('\n') {
+            return Err(Error::Malformed("recovery request"));
+        }
+        identity::box_public_key(&self.request_public_key).map(|_| ())
+    }
+
+    /// Ten digits from the request key. The owner reads it to each holder
+    /// over a channel they trust, so a stranger's request can't collect
+    /// shares by claiming the owner's username.
+    pub fn code(&self) -> String {
+        let digest = Sha256::digest(format!(
+            "cipher-recovery-request\n{}\n{}",
+            self.owner_username, self.request_public_key
+        ));
+        let number = u64::from_be_bytes(digest[..8].try_into().expect("8 bytes")) % 10_000_000_000;
+        format!("{:05}-{:05}", number / 100_000, number % 100_000)
+    }
+}
+
+impl ReturnedShare {
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        format!(
+            "cipher-returned-share\n{}\n{}\n{}\n{}",
+            self.grant.set_id,
+            self.grant.holder_public_key,
+            self.request_public_key,
+            self.sealed_share
+        )
+        .into_bytes()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        self.grant.verify()?;
+        identity::verify(
+            &self.grant.holder_public_key,
+            &self.signing_bytes(),
+            &self.signature,
+        )
+    }
+}
+
+/// Rebuilds the owner's identity from shares returned to `request`, opened
+/// with the identity [`RecoveryRequest::generate`] returned alongside it.
+/// Shares must come from one split; duplicates are ignored.
+pub fn recover(
+    request: &RecoveryRequest,
+    request_identity: &Identity,
+    returned: &[ReturnedShare],
+) -> Result<Identity, Error> {
+    let first = returned.first().ok_or(Error::NotEnoughShares)?;
+    let mut shares: BTreeMap<String, Share> = BTreeMap::new();
+    for share in returned {
+        share.verify()?;
+        let grant = &share.grant;
+        if share.request_public_key != request.request_public_key
+            || grant.owner_username != request.owner_username
+            || grant.set_id != first.grant.set_id
+            || grant.owner_public_key != first.grant.owner_public_key
+            || grant.threshold != first.grant.threshold
+        {
+            return Err(Error::Malformed("recovery share"));
+        }
+        let bytes = open(&share.sealed_share, request_identity)?;
+        let share_value =
+            Share::try_from(bytes.as_slice()).map_err(|_| Error::Malformed("recovery share"))?;
+        shares.insert(grant.holder_public_key.clone(), share_value);
+    }
+    if shares.len() < first.grant.threshold as usize {
+        return Err(Error::NotEnoughShares);
+    }
+
+    let seed = Zeroizing::new(
+        Sharks(first.grant.threshold as u8)
+            .recover(shares.values())
+            .map_err(|_| Error::NotEnoughShares)?,
+    );
+    let seed: &[u8; 32] = seed
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::Malformed("recovery share"))?;
+    let identity = Identity::from_seed(seed);
+    // Shamir shares carry no integrity of their own; the owner's key does
+    if identity.public_key() != first.grant.owner_public_key {
+        return Err(Error::DecryptionFailed);
+    }
+    Ok(identity)
+}
+
+fn seal(share: &[u8], public_key: &str) -> Result<String, Error> {
+    let sealed = identity::box_public_key(public_key)?
+        .seal(&mut OsRng, share)
+        .map_err(|_| Error::InvalidKey)?;
+    Ok(encode_base64(&sealed))
+}
+
+fn open(sealed: &str, identity: &Identity) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let sealed = decode_base64(sealed, "sealed share")?;
+    identity
+        .box_secret_key()
+        .unseal(&sealed)
+        .map(Zeroizing::new)
+        .map_err(|_| Error::DecryptionFailed)
+}
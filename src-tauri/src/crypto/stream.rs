@@ -0,0 +1,460 @@
+//! Chunked encryption for attachment blobs, after libsodium's secretstream
+//! and the STREAM construction: the plaintext is cut into fixed-size chunks,
+//! each sealed with XChaCha20-Poly1305 under a nonce made of a random prefix,
+//! the chunk index and a final-chunk flag. Reordered, dropped or truncated
+//! chunks fail authentication, and any chunk can be decrypted on its own, so
+//! media can seek without reading the whole file.
+//!
+//! Layout: a 32-byte header (`MAGIC`, version, chunk size, nonce prefix)
+//! followed by every chunk's ciphertext and 16-byte tag. Only the last chunk
+//! may be shorter than the chunk size, and an empty input is a single empty
+//! final chunk.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+use super::{
+    encode_hex,
+    envelope::{unwrap_key, wrap_key, WrappedKey},
+    Error, Identity,
+};
+
+pub const MAGIC: &[u8; 8] = b"CIPHBLOB";
+pub const VERSION: u8 = 1;
+pub const HEADER_BYTES: u64 = 32;
+pub const TAG_BYTES: u64 = 16;
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Directory under the app data dir holding encrypted attachment blobs.
+pub const BLOBS_DIR: &str = "blobs";
+
+const NONCE_PREFIX_BYTES: usize = 19;
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    chunk_size: u32,
+    nonce_prefix: [u8; NONCE_PREFIX_BYTES],
+}
+
+impl Header {
+    fn to_bytes(&self) -> [u8; HEADER_BYTES as usize] {
+        let mut bytes = [0u8; HEADER_BYTES as usize];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8] = VERSION;
+        bytes[9..13].copy_from_slice(&self.chunk_size.to_be_bytes());
+        bytes[13..].copy_from_slice(&self.nonce_prefix);
+        bytes
+    }
+
+    fn parse(bytes: &[u8; HEADER_BYTES as usize]) -> io::Result<Header> {
+        if &bytes[..8] != MAGIC || bytes[8] != VERSION {
+            return Err(invalid_data("not a version 1 attachment blob"));
+        }
+        let chunk_size = u32::from_be_bytes(bytes[9..13].try_into().unwrap());
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(invalid_data("invalid chunk size"));
+        }
+        Ok(Header {
+            chunk_size,
+            nonce_prefix: bytes[13..].try_into().unwrap(),
+        })
+    }
+
+    fn nonce(&self, index: u64, last: bool) -> io::Result<XNonce> {
+        let index = u32::try_from(index).map_err(|_| invalid_data("too many chunks"))?;
+        let mut nonce = [0u8; 24];
+        nonce[..NONCE_PREFIX_BYTES].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_BYTES..23].copy_from_slice(&index.to_be_bytes());
+        nonce[23] = last as u8;
+        Ok(XNonce::from(nonce))
+    }
+
+    fn sealed_chunk_size(&self) -> u64 {
+        u64::from(self.chunk_size) + TAG_BYTES
+    }
+}
+
+/// What [`encrypt_stream`] wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobSummary {
+    pub plaintext_len: u64,
+    pub blob_len: u64,
+    /// Hex SHA-256 of the blob as written, i.e. of the ciphertext.
+    pub sha256: String,
+}
+
+/// Encrypts everything read from `input` onto `output`, one chunk in memory
+/// at a time.
+pub fn encrypt_stream(
+    key: &[u8; 32],
+    chunk_size: u32,
+    mut input: impl Read,
+    output: impl Write,
+) -> io::Result<BlobSummary> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid chunk size",
+        ));
+    }
+    let mut header = Header {
+        chunk_size,
+        nonce_prefix: [0u8; NONCE_PREFIX_BYTES],
+    };
+    OsRng.fill_bytes(&mut header.nonce_prefix);
+    let header_bytes = header.to_bytes();
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let mut output = HashingWriter::new(output);
+    output.write_all(&header_bytes)?;
+
+    let chunk_size = chunk_size as usize;
+    let mut plaintext_len = 0u64;
+    let mut current = read_chunk(&mut input, chunk_size)?;
+    for index in 0u64.. {
+        // A full chunk may be the last one; only the next read can tell
+        let next = if current.len() == chunk_size {
+            read_chunk(&mut input, chunk_size)?
+        } else {
+            Zeroizing::new(Vec::new())
+        };
+        let last = next.is_empty();
+
+        let sealed = cipher
+            .encrypt(
+                &header.nonce(index, last)?,
+                Payload {
+                    msg: &current,
+                    aad: &header_bytes,
+                },
+            )
+            .map_err(|_| invalid_data("chunk encryption failed"))?;
+        output.write_all(&sealed)?;
+        plaintext_len += current.len() as u64;
+
+        if last {
+            break;
+        }
+        current = next;
+    }
+
+    output.flush()?;
+    Ok(BlobSummary {
+        plaintext_len,
+        blob_len: output.written,
+        sha256: encode_hex(&output.hasher.finalize()),
+    })
+}
+
+/// Reads up to `chunk_size` bytes, fewer only at end of input.
+fn read_chunk(input: &mut impl Read, chunk_size: usize) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut chunk = Zeroizing::new(vec![0u8; chunk_size]);
+    let mut filled = 0;
+    while filled < chunk_size {
+        match input.read(&mut chunk[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    chunk.truncate(filled);
+    Ok(chunk)
+}
+
+/// Decrypts a blob with random access. Implements `Read` and `Seek` over
+/// the plaintext, decrypting and caching one chunk at a time.
+pub struct BlobReader<R> {
+    inner: R,
+    cipher: XChaCha20Poly1305,
+    header: Header,
+    header_bytes: [u8; HEADER_BYTES as usize],
+    chunk_count: u64,
+    plaintext_len: u64,
+    position: u64,
+    cached: Option<(u64, Zeroizing<Vec<u8>>)>,
+}
+
+impl<R: Read + Seek> BlobReader<R> {
+    /// Reads the header and authenticates the final chunk, so a truncated
+    /// blob is rejected here rather than part way through playback.
+    pub fn open(mut inner: R, key: &[u8; 32]) -> io::Result<BlobReader<R>> {
+        let blob_len = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+        let mut header_bytes = [0u8; HEADER_BYTES as usize];
+        inner.read_exact(&mut header_bytes)?;
+        let header = Header::parse(&header_bytes)?;
+
+        let body_len = blob_len
+            .checked_sub(HEADER_BYTES)
+            .filter(|len| *len >= TAG_BYTES)
+            .ok_or_else(|| invalid_data("blob is truncated"))?;
+        let sealed_chunk = header.sealed_chunk_size();
+        let chunk_count = body_len.div_ceil(sealed_chunk);
+        let last_sealed = body_len - (chunk_count - 1) * sealed_chunk;
+        if last_sealed < TAG_BYTES {
+            return Err(invalid_data("blob is truncated"));
+        }
+
+        let mut reader = BlobReader {
+            inner,
+            cipher: XChaCha20Poly1305::new(key.into()),
+            header,
+            header_bytes,
+            chunk_count,
+            plaintext_len: body_len - chunk_count * TAG_BYTES,
+            position: 0,
+            cached: None,
+        };
+        reader.chunk(chunk_count - 1)?;
+        Ok(reader)
+    }
+
+    /// Plaintext length in bytes.
+    pub fn len(&self) -> u64 {
+        self.plaintext_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plaintext_len == 0
+    }
+
+    /// Reads the plaintext in `offset..offset + len`, clamped to the end.
+    pub fn read_range(&mut self, offset: u64, len: usize) -> io::Result<Zeroizing<Vec<u8>>> {
+        self.seek(SeekFrom::Start(offset))?;
+        let len = len.min(self.plaintext_len.saturating_sub(offset) as usize);
+        let mut out = Zeroizing::new(vec![0u8; len]);
+        self.read_exact(&mut out)?;
+        Ok(out)
+    }
+
+    fn chunk(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.cached.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let last = index + 1 == self.chunk_count;
+            let sealed_chunk = self.header.sealed_chunk_size();
+            let sealed_len = if last {
+                self.plaintext_len - index * u64::from(self.header.chunk_size) + TAG_BYTES
+            } else {
+                sealed_chunk
+            };
+            let mut sealed = vec![0u8; sealed_len as usize];
+            self.inner
+                .seek(SeekFrom::Start(HEADER_BYTES + index * sealed_chunk))?;
+            self.inner.read_exact(&mut sealed)?;
+            let plaintext = self
+                .cipher
+                .decrypt(
+                    &self.header.nonce(index, last)?,
+                    Payload {
+                        msg: &sealed,
+                        aad: &self.header_bytes,
+                    },
+                )
+                .map_err(|_| invalid_data("chunk failed authentication"))?;
+            self.cached = Some((index, Zeroizing::new(plaintext)));
+        }
+        Ok(self
+            .cached
+            .as_ref()
+            .map(|(_, chunk)| chunk.as_slice())
+            .unwrap())
+    }
+}
+
+impl<R: Read + Seek> Read for BlobReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.plaintext_len {
+            return Ok(0);
+        }
+        let chunk_size = u64::from(self.header.chunk_size);
+        let index = self.position / chunk_size;
+        let start = (self.position % chunk_size) as usize;
+        let chunk = self.chunk(index)?;
+        let count = buf.len().min(chunk.len() - start);
+        buf[..count].copy_from_slice(&chunk[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for BlobReader<R> {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let position = match from {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plaintext_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before start of blob")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Stored in `attachments.data_encrypted` in place of inline ciphertext:
+/// where the blob is and who can open it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BlobEnvelope {
+    pub version: u32,
+    /// Hex SHA-256 of the blob, which is also its file name in `blobs/`.
+    pub blob: String,
+    #[ts(type = "number")]
+    pub size: u64,
+    pub chunk_size: u32,
+    /// The blob key sealed to each recipient.
+    pub recipients: Vec<WrappedKey>,
+}
+
+impl BlobEnvelope {
+    pub const VERSION: u32 = 1;
+
+    /// Encrypts `source` into `blobs_dir` under a fresh key sealed to each of
+    /// `recipients`.
+    pub fn encrypt_file(
+        source: &Path,
+        blobs_dir: &Path,
+        recipients: &[String],
+    ) -> Result<BlobEnvelope, BlobError> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(key.as_mut());
+        let wrapped = wrap_key(&key, recipients)?;
+
+        fs::create_dir_all(blobs_dir)?;
+        let mut random = [0u8; 8];
+        OsRng.fill_bytes(&mut random);
+        let temp = blobs_dir.join(format!("{}.tmp", encode_hex(&random)));
+        let written = (|| {
+            let input = io::BufReader::new(fs::File::open(source)?);
+            let mut output = io::BufWriter::new(fs::File::create(&temp)?);
+            let summary = encrypt_stream(&key, DEFAULT_CHUNK_SIZE, input, &mut output)?;
+            output
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            Ok::<_, io::Error>(summary)
+        })();
+        let summary = match written {
+            Ok(summary) => summary,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e.into());
+            }
+        };
+        fs::rename(&temp, blobs_dir.join(&summary.sha256))?;
+
+        Ok(BlobEnvelope {
+            version: Self::VERSION,
+            blob: summary.sha256,
+            size: summary.plaintext_len,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            recipients: wrapped,
+        })
+    }
+
+    pub fn path(&self, blobs_dir: &Path) -> Result<PathBuf, Error> {
+        // The name comes from the database; keep it inside blobs_dir
+        if self.blob.len() != 64 || !self.blob.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::Malformed("blob name"));
+        }
+        Ok(blobs_dir.join(&self.blob))
+    }
+
+    /// Opens the blob for reading with `identity`'s copy of the key.
+    pub fn open(
+        &self,
+        blobs_dir: &Path,
+        identity: &Identity,
+    ) -> Result<BlobReader<fs::File>, BlobError> {
+        if self.version != Self::VERSION {
+            return Err(Error::Malformed("blob version").into());
+        }
+        let key = unwrap_key(&self.recipients, identity)?;
+        let file = fs::File::open(self.path(blobs_dir)?)?;
+        Ok(BlobReader::open(file, &key)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("blob envelope serializes to JSON")
+    }
+
+    pub fn from_json(data_encrypted: &str) -> Result<BlobEnvelope, Error> {
+        serde_json::from_str(data_encrypted).map_err(|_| Error::Malformed("blob envelope"))
+    }
+}
+
+#[derive(Debug)]
+pub enum BlobError {
+    Crypto(Error),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlobError::Crypto(e) => e.fmt(f),
+            BlobError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {}
+
+impl From<Error> for BlobError {
+    fn from(error: Error) -> BlobError {
+        BlobError::Crypto(error)
+    }
+}
+
+impl From<io::Error> for BlobError {
+    fn from(error: io::Error) -> BlobError {
+        BlobError::Io(error)
+    }
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> HashingWriter<W> {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.hasher.update(&buf[..count]);
+        self.written += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
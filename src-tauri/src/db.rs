@@ -0,0 +1,2806 @@
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::crypto::{
+    circle::CircleKey,
+    content_hash::PostContent,
+    device::{DeviceCapability, DeviceCertificate, DeviceEvent, DeviceRevocation},
+    encode_hex,
+    ratchet::PrekeyBundle,
+    rotation::{KeyEvent, KeyHistory},
+    sealed_sender::{SealedContent, SealedEnvelope},
+    secret::Secret,
+    signed_post::{self, SignedPost},
+    social_recovery::ShareGrant,
+};
+
+/// Tables shared with the Rails app. Column names, types and defaults follow
+/// `db/schema.rb` so a database file can be opened by either backend.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS "users" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "public_key" text,
+    "username" varchar,
+    "display_name" varchar,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    "email" varchar,
+    "email_verified_at" datetime(6),
+    "verification_code" varchar,
+    "verification_code_expires_at" datetime(6),
+    "content_size_limit" integer DEFAULT 10485760
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "index_users_on_email" ON "users" ("email");
+CREATE INDEX IF NOT EXISTS "index_users_on_verification_code" ON "users" ("verification_code");
+
+CREATE TABLE IF NOT EXISTS "posts" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "content_encrypted" text,
+    "signature" text,
+    "timestamp" datetime(6),
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    "original_user_id" integer,
+    "synced_from_user_id" integer,
+    "is_synced" boolean DEFAULT 0,
+    "synced_at" datetime(6),
+    "content_hash" varchar,
+    "encryption_key" text,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("original_user_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("synced_from_user_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_posts_on_content_hash" ON "posts" ("content_hash");
+CREATE INDEX IF NOT EXISTS "index_posts_on_is_synced" ON "posts" ("is_synced");
+CREATE INDEX IF NOT EXISTS "index_posts_on_original_user_id" ON "posts" ("original_user_id");
+CREATE INDEX IF NOT EXISTS "index_posts_on_synced_at" ON "posts" ("synced_at");
+CREATE INDEX IF NOT EXISTS "index_posts_on_synced_from_user_id" ON "posts" ("synced_from_user_id");
+CREATE INDEX IF NOT EXISTS "index_posts_on_user_id" ON "posts" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "attachments" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "post_id" integer NOT NULL,
+    "filename" varchar,
+    "content_type" varchar,
+    "file_size" integer,
+    "data_encrypted" text,
+    "checksum" varchar,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    "dev_owner_key" text,
+    FOREIGN KEY ("post_id") REFERENCES "posts" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_attachments_on_post_id" ON "attachments" ("post_id");
+
+CREATE TABLE IF NOT EXISTS "friendships" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "requester_id" integer NOT NULL,
+    "addressee_id" integer NOT NULL,
+    "status" varchar DEFAULT 'pending',
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("requester_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("addressee_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_friendships_on_addressee_id_and_requester_id" ON "friendships" ("addressee_id", "requester_id");
+CREATE INDEX IF NOT EXISTS "index_friendships_on_addressee_id" ON "friendships" ("addressee_id");
+CREATE UNIQUE INDEX IF NOT EXISTS "index_friendships_on_requester_id_and_addressee_id" ON "friendships" ("requester_id", "addressee_id");
+CREATE INDEX IF NOT EXISTS "index_friendships_on_requester_id" ON "friendships" ("requester_id");
+CREATE INDEX IF NOT EXISTS "index_friendships_on_status" ON "friendships" ("status");
+
+CREATE TABLE IF NOT EXISTS "messages" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "sender_id" integer NOT NULL,
+    "recipient_id" integer NOT NULL,
+    "content" text,
+    "encrypted_content" text,
+    "read_at" datetime(6),
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("sender_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("recipient_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_messages_on_created_at" ON "messages" ("created_at");
+CREATE INDEX IF NOT EXISTS "index_messages_on_recipient_id_and_read_at" ON "messages" ("recipient_id", "read_at");
+CREATE INDEX IF NOT EXISTS "index_messages_on_recipient_id" ON "messages" ("recipient_id");
+CREATE INDEX IF NOT EXISTS "index_messages_on_sender_id_and_recipient_id_and_created_at" ON "messages" ("sender_id", "recipient_id", "created_at");
+CREATE INDEX IF NOT EXISTS "index_messages_on_sender_id" ON "messages" ("sender_id");
+
+CREATE TABLE IF NOT EXISTS "peers" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "address" varchar,
+    "port" integer,
+    "last_seen" datetime(6),
+    "public_key" text,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_peers_on_user_id" ON "peers" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "sync_messages" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "peer_id" integer NOT NULL,
+    "payload" text,
+    "message_type" varchar,
+    "status" varchar,
+    "processed_count" integer,
+    "error_count" integer,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("peer_id") REFERENCES "peers" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_sync_messages_on_peer_id" ON "sync_messages" ("peer_id");
+CREATE INDEX IF NOT EXISTS "index_sync_messages_on_user_id" ON "sync_messages" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "key_events" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "event_type" varchar NOT NULL,
+    "payload" text NOT NULL,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_key_events_on_user_id" ON "key_events" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "key_verifications" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "friend_id" integer NOT NULL,
+    "public_key" text NOT NULL,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id"),
+    FOREIGN KEY ("friend_id") REFERENCES "users" ("id")
+);
+CREATE INDEX IF NOT EXISTS "index_key_verifications_on_friend_id" ON "key_verifications" ("friend_id");
+CREATE UNIQUE INDEX IF NOT EXISTS "index_key_verifications_on_user_id_and_friend_id" ON "key_verifications" ("user_id", "friend_id");
+CREATE INDEX IF NOT EXISTS "index_key_verifications_on_user_id" ON "key_verifications" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "circles" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "name" varchar NOT NULL,
+    "public_id" varchar NOT NULL,
+    "epoch" integer DEFAULT 1 NOT NULL,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id")
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "index_circles_on_public_id" ON "circles" ("public_id");
+CREATE INDEX IF NOT EXISTS "index_circles_on_user_id" ON "circles" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "circle_members" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "circle_id" integer NOT NULL,
+    "user_id" integer NOT NULL,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("circle_id") REFERENCES "circles" ("id"),
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id")
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "index_circle_members_on_circle_id_and_user_id" ON "circle_members" ("circle_id", "user_id");
+CREATE INDEX IF NOT EXISTS "index_circle_members_on_circle_id" ON "circle_members" ("circle_id");
+CREATE INDEX IF NOT EXISTS "index_circle_members_on_user_id" ON "circle_members" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "devices" (
+    "id" integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "user_id" integer NOT NULL,
+    "device_key" varchar NOT NULL,
+    "name" varchar NOT NULL,
+    "certificate" text NOT NULL,
+    "revocation" text,
+    "created_at" datetime(6) NOT NULL,
+    "updated_at" datetime(6) NOT NULL,
+    FOREIGN KEY ("user_id") REFERENCES "users" ("id")
+);
+CREATE UNIQUE INDEX IF NOT EXISTS "index_devices_on_device_key" ON "devices" ("device_key");
+CREATE INDEX IF NOT EXISTS "index_devices_on_user_id" ON "devices" ("user_id");
+"#;
+
+/// Same format ActiveRecord writes for `datetime(6)` columns (UTC).
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+/// Errors from the validated operations shared by the embedded server and the
+/// native command API.
+#[derive(Debug)]
+pub enum Error {
+    /// The input broke a model rule; the message mirrors the Rails validation.
+    Invalid(String),
+    NotFound(&'static str),
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(message) => f.write_str(message),
+            Error::NotFound(message) => f.write_str(message),
+            Error::Sqlite(e) => write!(f, "database error: {}", e),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Error {
+        match error {
+            rusqlite::Error::SqliteFailure(failure, message)
+                if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Error::Invalid(message.unwrap_or_else(|| failure.to_string()))
+            }
+            error => Error::Sqlite(error),
+        }
+    }
+}
+
+/// Returns `<app_data_dir>/storage/<platform>.sqlite3`, creating the storage
+/// directory if needed. Both backends use this location.
+pub fn database_path(app_data_dir: &Path, platform: &str) -> io::Result<PathBuf> {
+    let storage_dir = app_data_dir.join("storage");
+    if !storage_dir.exists() {
+        std::fs::create_dir_all(&storage_dir)?;
+        println!("Created storage directory at: {:?}", storage_dir);
+    }
+    Ok(storage_dir.join(format!("{}.sqlite3", platform)))
+}
+
+/// Environment variable the launcher hands Rails the SQLCipher key in, as 64
+/// hex digits.
+pub const DATABASE_KEY_ENV: &str = "CIPHER_DATABASE_KEY";
+
+/// How every unencrypted SQLite file starts. SQLCipher files start with a
+/// random salt instead.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// A database that may only open after launch, once the keystore unlocks the
+/// key to an encrypted file. The command API and the embedded server share it.
+pub type SharedDatabase = Arc<OnceLock<Database>>;
+
+/// `key` as SQLCipher's raw-key literal, `x'<hex>'`, so no KDF runs on it.
+fn raw_key_literal(key: &Secret<[u8; 32]>) -> Secret<String> {
+    Secret::new(format!("x'{}'", database_key_hex(key).expose()))
+}
+
+/// `key` as the value of [`DATABASE_KEY_ENV`].
+pub fn database_key_hex(key: &Secret<[u8; 32]>) -> Secret<String> {
+    Secret::new(encode_hex(key.expose()))
+}
+
+/// Whether SQLite was built as SQLCipher, i.e. with the `sqlcipher` feature.
+fn has_sqlcipher(conn: &Connection) -> rusqlite::Result<bool> {
+    Ok(conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get::<_, String>(0))
+        .optional()?
+        .is_some())
+}
+
+fn require_sqlcipher(conn: &Connection) -> Result<(), Error> {
+    if !has_sqlcipher(conn)? {
+        return Err(Error::Invalid(
+            "This build can't encrypt the database; rebuild with the sqlcipher feature".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `path` holds an unencrypted SQLite database. A missing or empty
+/// file is neither, and gets encrypted when it is created.
+pub fn is_plaintext_database(path: &Path) -> io::Result<bool> {
+    let mut header = [0u8; 16];
+    match fs::File::open(path) {
+        Ok(mut file) => match file.read_exact(&mut header) {
+            Ok(()) => Ok(&header == SQLITE_HEADER),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Rewrites the unencrypted database at `path` as SQLCipher under `key`. The
+/// copy is made beside it and renamed over it, so a failure leaves the
+/// original as it was.
+pub fn encrypt_database_file(path: &Path, key: &Secret<[u8; 32]>) -> Result<(), Error> {
+    let temp = path.with_extension("sqlite3.encrypting");
+    match fs::remove_file(&temp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(Error::Io(e)),
+        _ => {}
+    }
+    {
+        let conn = Connection::open(path)?;
+        require_sqlcipher(&conn)?;
+        // Rails writes through a WAL; fold it in before copying
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            params![temp.to_string_lossy(), raw_key_literal(key).expose()],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute_batch("DETACH DATABASE encrypted;")?;
+    }
+    fs::rename(&temp, path).map_err(Error::Io)?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.to_string_lossy(), suffix));
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> rusqlite::Result<Database> {
+        Database::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens, or creates, a SQLCipher database under `key`. Fails if this
+    /// build has no SQLCipher or `key` doesn't open the file.
+    pub fn open_encrypted(path: &Path, key: &Secret<[u8; 32]>) -> Result<Database, Error> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "key", raw_key_literal(key).expose())?;
+        require_sqlcipher(&conn)?;
+        if conn
+            .query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .is_err()
+        {
+            return Err(Error::Invalid(format!(
+                "The database key doesn't open {}",
+                path.display()
+            )));
+        }
+        Ok(Database::from_connection(conn)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Database> {
+        Database::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> rusqlite::Result<Database> {
+        // Rails may hold the same file open when switching backends.
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `f` in one transaction, committed if it returns `Ok` and rolled
+    /// back otherwise. The connection is shared, so anything written through
+    /// this `Database` meanwhile, from any thread, is part of it.
+    pub fn transaction<T, E: From<rusqlite::Error>>(
+        &self,
+        f: impl FnOnce(&Database) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.connection().execute_batch("BEGIN IMMEDIATE")?;
+        let result = f(self).and_then(|value| {
+            self.connection().execute_batch("COMMIT")?;
+            Ok(value)
+        });
+        if result.is_err() {
+            let _ = self.connection().execute_batch("ROLLBACK");
+        }
+        result
+    }
+
+    // Validated operations. These apply the same rules as the Rails models
+    // before writing, so every native entry point rejects the same input.
+
+    /// Creates a user, rejecting duplicate usernames and public keys.
+    pub fn register_user(&self, new_user: &NewUser) -> Result<User, Error> {
+        if new_user.username.trim().is_empty() || new_user.public_key.trim().is_empty() {
+            return Err(Error::Invalid(
+                "Username and public key are required".to_string(),
+            ));
+        }
+        if self.find_user_by_username(&new_user.username)?.is_some() {
+            return Err(Error::Invalid(
+                "Username is already taken. Please choose a different username.".to_string(),
+            ));
+        }
+        if self
+            .find_user_by_public_key(&new_user.public_key)?
+            .is_some()
+        {
+            return Err(Error::Invalid(
+                "Public key is already registered to another account. Please regenerate your keys."
+                    .to_string(),
+            ));
+        }
+        Ok(self.create_user(new_user)?)
+    }
+
+    /// Sends a friend request unless the pair already has a friendship in
+    /// either direction.
+    pub fn request_friendship(
+        &self,
+        requester_id: i64,
+        addressee_id: i64,
+    ) -> Result<Friendship, Error> {
+        if requester_id == addressee_id {
+            return Err(Error::Invalid(
+                "Cannot send a friend request to yourself".to_string(),
+            ));
+        }
+        if self.find_user(addressee_id)?.is_none() {
+            return Err(Error::NotFound("User not found"));
+        }
+        let duplicate = self
+            .friendships_for_user(requester_id)?
+            .iter()
+            .any(|friendship| {
+                friendship.requester_id == addressee_id || friendship.addressee_id == addressee_id
+            });
+        if duplicate {
+            return Err(Error::Invalid("Friendship already exists".to_string()));
+        }
+        Ok(self.send_friend_request(requester_id, addressee_id)?)
+    }
+
+    pub fn respond_to_friendship(&self, id: i64, status: &str) -> Result<Friendship, Error> {
+        if !matches!(status, "accepted" | "declined" | "blocked") {
+            return Err(Error::Invalid(
+                "status must be accepted, declined or blocked".to_string(),
+            ));
+        }
+        self.update_friendship_status(id, status)?
+            .ok_or(Error::NotFound("Friendship not found"))
+    }
+
+    pub fn send_message(&self, new_message: &NewMessage) -> Result<Message, Error> {
+        if new_message.sender_id == new_message.recipient_id {
+            return Err(Error::Invalid(
+                "Recipient can't be the same as sender".to_string(),
+            ));
+        }
+        if new_message.content.is_empty() || new_message.content.chars().count() > 2000 {
+            return Err(Error::Invalid(
+                "Content must be between 1 and 2000 characters".to_string(),
+            ));
+        }
+        Ok(self.create_message(new_message)?)
+    }
+
+    /// Stores a message whose text exists only in `encrypted_content`, such
+    /// as a [`crate::crypto::ratchet::RatchetMessage`].
+    pub fn send_encrypted_message(
+        &self,
+        sender_id: i64,
+        recipient_id: i64,
+        encrypted_content: &str,
+    ) -> Result<Message, Error> {
+        if sender_id == recipient_id {
+            return Err(Error::Invalid(
+                "Recipient can't be the same as sender".to_string(),
+            ));
+        }
+        Ok(self.create_message(&NewMessage {
+            sender_id,
+            recipient_id,
+            content: String::new(),
+            encrypted_content: Some(encrypted_content.to_string()),
+        })?)
+    }
+
+    pub fn record_peer(&self, new_peer: &NewPeer) -> Result<Peer, Error> {
+        if !(1..65536).contains(&new_peer.port) {
+            return Err(Error::Invalid(
+                "Port must be between 1 and 65535".to_string(),
+            ));
+        }
+        Ok(self.upsert_peer(new_peer)?)
+    }
+
+    /// Checks that `new_post` carries its author's signature over the
+    /// canonical [`SignedPost`] form and returns what was verified. The
+    /// author is `original_user_id` for synced posts and `user_id` otherwise,
+    /// and the key is whichever of theirs was valid at the post's timestamp.
+    /// The signer picks that timestamp, so a device key is only accepted while
+    /// the device is still unrevoked.
+    pub fn verify_new_post(&self, new_post: &NewPost) -> Result<SignedPost, Error> {
+        self.verify_signed_post(new_post, true)
+    }
+
+    fn verify_signed_post(
+        &self,
+        new_post: &NewPost,
+        unrevoked_devices_only: bool,
+    ) -> Result<SignedPost, Error> {
+        let author_id = new_post.original_user_id.unwrap_or(new_post.user_id);
+        let author = self
+            .find_user(author_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        let timestamp = new_post
+            .timestamp
+            .as_deref()
+            .ok_or_else(|| Error::Invalid("Timestamp can't be blank".to_string()))?;
+        let timestamp = signed_post::parse_timestamp(timestamp)
+            .map_err(|_| Error::Invalid("Timestamp is not a valid UTC time".to_string()))?;
+        let keys = self.signing_keys_at(author.id, timestamp, unrevoked_devices_only)?;
+        if keys.is_empty() {
+            return Err(Error::Invalid(
+                "Author had no valid key at the post's timestamp".to_string(),
+            ));
+        }
+        let candidates = keys
+            .iter()
+            .map(|public_key| {
+                SignedPost::new(
+                    public_key,
+                    new_post.content_encrypted.as_deref().unwrap_or_default(),
+                    new_post.attachment_checksums.clone().unwrap_or_default(),
+                    timestamp,
+                )
+                .map_err(|e| Error::Invalid(format!("Post can't be verified: {}", e)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let signature = new_post
+            .signature
+            .as_deref()
+            .ok_or_else(|| Error::Invalid("Post must be signed by its author".to_string()))?;
+        candidates
+            .into_iter()
+            .find(|signed| signed.verify(signature).is_ok())
+            .ok_or_else(|| {
+                Error::Invalid("Signature does not match the post or its author".to_string())
+            })
+    }
+
+    /// Keys that could sign a post for the user at `timestamp`: their
+    /// account key then, followed by their devices allowed to post that
+    /// were certified and not yet revoked. With `unrevoked_only`, devices
+    /// revoked since are left out too.
+    fn signing_keys_at(
+        &self,
+        user_id: i64,
+        timestamp: i64,
+        unrevoked_only: bool,
+    ) -> Result<Vec<String>, Error> {
+        let history = self.key_history(user_id)?;
+        let mut keys: Vec<String> = history
+            .key_at(timestamp)
+            .map(str::to_string)
+            .into_iter()
+            .collect();
+        keys.extend(
+            self.devices_for_user(user_id)?
+                .into_iter()
+                .filter(|device| {
+                    device.certificate.allows(DeviceCapability::Post)
+                        && device.is_valid_at(timestamp)
+                        && !(unrevoked_only && device.revocation.is_some())
+                        && history.key_at(device.certificate.created_at)
+                            == Some(device.certificate.root_key.as_str())
+                })
+                .map(|device| device.device_key),
+        );
+        Ok(keys)
+    }
+
+    /// Stores a post only if [`Database::verify_new_post`] accepts it, so
+    /// unsigned or forged content never reaches a feed. `content_hash` is
+    /// always the canonical [`PostContent`] hash of what was verified.
+    pub fn ingest_post(&self, new_post: &NewPost) -> Result<Post, Error> {
+        let signed = self.verify_new_post(new_post)?;
+        let content = PostContent {
+            content_encrypted: signed.content_encrypted,
+            timestamp: signed.timestamp,
+            signature: new_post.signature.clone().unwrap_or_default(),
+            attachment_checksums: signed.attachment_checksums,
+        };
+        Ok(self.create_post(&NewPost {
+            timestamp: Some(signed_post::format_timestamp(signed.timestamp)),
+            content_hash: Some(content.hash()),
+            ..new_post.clone()
+        })?)
+    }
+
+    /// Re-checks a stored post against its attachment rows, so an attachment
+    /// added or swapped after signing fails verification. Posts a device
+    /// signed before it was revoked still verify.
+    pub fn verify_post(&self, id: i64) -> Result<SignedPost, Error> {
+        let post = self
+            .find_post(id)?
+            .ok_or(Error::NotFound("Post not found"))?;
+        let checksums = self
+            .attachments_for_post(id)?
+            .into_iter()
+            .map(|attachment| attachment.checksum.unwrap_or_default())
+            .collect();
+        self.verify_signed_post(
+            &NewPost {
+                user_id: post.user_id,
+                content_encrypted: post.content_encrypted,
+                signature: post.signature,
+                timestamp: post.timestamp,
+                original_user_id: post.original_user_id,
+                attachment_checksums: Some(checksums),
+                ..NewPost::default()
+            },
+            false,
+        )
+    }
+
+    /// Records a sync message. An `inbound_sync` batch from a peer is applied
+    /// first: every post in it must verify against the peer's key, and any
+    /// that doesn't is dropped and counted in `error_count`. Recovery shares,
+    /// key and device events, prekey bundles and circle keys are checked by their own
+    /// `receive_*` operations. Sealed envelopes are held until the recipient
+    /// opens them; see [`Database::receive_unsealed`].
+    pub fn receive_sync(&self, new_sync_message: &NewSyncMessage) -> Result<SyncMessage, Error> {
+        match new_sync_message.message_type.as_str() {
+            "inbound_sync" => {}
+            INBOUND_RECOVERY_SHARE => return self.receive_recovery_share(new_sync_message),
+            INBOUND_KEY_EVENT => return self.receive_key_event(new_sync_message),
+            INBOUND_DEVICE_EVENT => return self.receive_device_event(new_sync_message),
+            INBOUND_PREKEY_BUNDLE => return self.receive_prekey_bundle(new_sync_message),
+            INBOUND_CIRCLE_KEY => return self.receive_circle_key(new_sync_message),
+            INBOUND_SEALED => return self.receive_sealed(new_sync_message),
+            _ => return Ok(self.create_sync_message(new_sync_message)?),
+        }
+        let (peer, author) = self.peer_author(new_sync_message.peer_id)?;
+        let batch: SyncBatch = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid sync payload: {}", e)))?;
+
+        let (mut processed, mut errors) = (0, 0);
+        for synced_post in &batch.messages {
+            match self.ingest_synced_post(new_sync_message.user_id, &author, synced_post) {
+                Ok(_) => processed += 1,
+                Err(e) => {
+                    println!("Rejected synced post from peer {}: {}", peer.id, e);
+                    errors += 1;
+                }
+            }
+        }
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "processed".to_string(),
+            processed_count: Some(processed),
+            error_count: Some(errors),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Keeps a friend's recovery share for them. The grant must be signed by
+    /// the peer, be sealed to the receiving user and come from an accepted
+    /// friend; it is stored with status `held` until they ask for it back.
+    pub fn receive_recovery_share(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> Result<SyncMessage, Error> {
+        let (_, owner) = self.peer_author(new_sync_message.peer_id)?;
+        let holder = self
+            .find_user(new_sync_message.user_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        let grant: ShareGrant = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid recovery share: {}", e)))?;
+        if grant.verify().is_err()
+            || owner.public_key.as_deref() != Some(grant.owner_public_key.as_str())
+        {
+            return Err(Error::Invalid(
+                "Recovery share is not signed by the peer".to_string(),
+            ));
+        }
+        if holder.public_key.as_deref() != Some(grant.holder_public_key.as_str()) {
+            return Err(Error::Invalid(
+                "Recovery share is for someone else".to_string(),
+            ));
+        }
+        if !self.are_friends(holder.id, owner.id)? {
+            return Err(Error::Invalid(
+                "Recovery shares are only accepted from friends".to_string(),
+            ));
+        }
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "held".to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Queues each grant as an outbound sync message to the owner's peer
+    /// with the holder's key. Nothing is queued unless every holder has one.
+    pub fn deliver_recovery_shares(
+        &self,
+        owner_id: i64,
+        grants: &[ShareGrant],
+    ) -> Result<Vec<SyncMessage>, Error> {
+        let peers = self.peers_for_user(owner_id)?;
+        let routes = grants
+            .iter()
+            .map(|grant| {
+                peers
+                    .iter()
+                    .find(|peer| peer.public_key.as_deref() == Some(&grant.holder_public_key))
+                    .map(|peer| (peer.id, grant))
+                    .ok_or_else(|| {
+                        Error::Invalid(format!(
+                            "No peer for {}; connect to them before sharing",
+                            grant.holder_public_key
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        routes
+            .into_iter()
+            .map(|(peer_id, grant)| {
+                Ok(self.create_sync_message(&NewSyncMessage {
+                    user_id: owner_id,
+                    peer_id,
+                    payload: serde_json::to_string(grant)
+                        .map_err(|e| Error::Invalid(e.to_string()))?,
+                    message_type: OUTBOUND_RECOVERY_SHARE.to_string(),
+                    status: "pending".to_string(),
+                    processed_count: None,
+                    error_count: None,
+                })?)
+            })
+            .collect()
+    }
+
+    /// Recovery shares `user_id` holds for friends, keyed by sync message id.
+    pub fn held_recovery_shares(&self, user_id: i64) -> Result<Vec<(i64, ShareGrant)>, Error> {
+        let held = {
+            let conn = self.connection();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages \
+                 WHERE user_id = ?1 AND message_type = ?2 AND status = 'held' ORDER BY created_at"
+            ))?;
+            let rows = stmt
+                .query_map(
+                    params![user_id, INBOUND_RECOVERY_SHARE],
+                    SyncMessage::from_row,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        held.into_iter()
+            .map(|sync_message| {
+                let grant = serde_json::from_str(sync_message.payload.as_deref().unwrap_or(""))
+                    .map_err(|e| Error::Invalid(format!("Invalid recovery share: {}", e)))?;
+                Ok((sync_message.id, grant))
+            })
+            .collect()
+    }
+
+    /// Applies a peer's rotation or revocation to the user behind the peer.
+    pub fn receive_key_event(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> Result<SyncMessage, Error> {
+        let (_, author) = self.peer_author(new_sync_message.peer_id)?;
+        let event: KeyEvent = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid key event: {}", e)))?;
+        let updated = self.record_key_event(author.id, &event)?;
+        let verified = self
+            .find_key_verification(new_sync_message.user_id, author.id)?
+            .is_some();
+        // Flag the message so the sync log shows it next to the warning
+        let status = if verified && updated.public_key != author.public_key {
+            "verified_key_changed"
+        } else {
+            "processed"
+        };
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: status.to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Verifies `event` against the user's key chain and stores it. The
+    /// event must start from one of the user's keys; if it changes the
+    /// current key, the user and any peers with the old key move to the new
+    /// one. Replaying a stored event is a no-op.
+    pub fn record_key_event(&self, user_id: i64, event: &KeyEvent) -> Result<User, Error> {
+        event
+            .verify()
+            .map_err(|e| Error::Invalid(format!("Key event can't be verified: {}", e)))?;
+        let user = self
+            .find_user(user_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        let mut events = self.key_events_for_user(user_id)?;
+        if events.contains(event) {
+            return Ok(user);
+        }
+        let history = self.key_history(user_id)?;
+        if !history.contains(event.old_public_key()) {
+            return Err(Error::Invalid(
+                "Key event doesn't start from one of this user's keys".to_string(),
+            ));
+        }
+        events.push(event.clone());
+        let root = history.periods[0].public_key.clone();
+        let updated = KeyHistory::build(&root, &events)
+            .map_err(|e| Error::Invalid(format!("Key event breaks the key chain: {}", e)))?;
+
+        let current = updated.current();
+        let changed = user.public_key.as_deref() != Some(current);
+        if changed && self.find_user_by_public_key(current)?.is_some() {
+            return Err(Error::Invalid(
+                "Public key is already registered to another account".to_string(),
+            ));
+        }
+        self.insert_key_event(user_id, event)?;
+        if !changed {
+            return Ok(user);
+        }
+        if let Some(old) = &user.public_key {
+            self.update_peer_public_keys(old, current)?;
+        }
+        let verifiers = self.key_verifiers_of(user_id)?;
+        if !verifiers.is_empty() {
+            println!(
+                "WARNING: the key of user {} changed after {} user(s) verified it; \
+                 their safety numbers no longer match",
+                user_id,
+                verifiers.len()
+            );
+        }
+        Ok(self.update_user_public_key(user_id, current)?)
+    }
+
+    /// The user's keys over time. A user with no key events has had one key
+    /// all along.
+    pub fn key_history(&self, user_id: i64) -> Result<KeyHistory, Error> {
+        let events = self.key_events_for_user(user_id)?;
+        let root = match KeyHistory::root(&events) {
+            Some(root) => root.to_string(),
+            None => self
+                .find_user(user_id)?
+                .ok_or(Error::NotFound("User not found"))?
+                .public_key
+                .ok_or_else(|| Error::Invalid("User has no public key".to_string()))?,
+        };
+        KeyHistory::build(&root, &events)
+            .map_err(|e| Error::Invalid(format!("Stored key chain is invalid: {}", e)))
+    }
+
+    /// Queues `event` to every peer of `user_id`, so friends' clients can
+    /// follow the change.
+    pub fn broadcast_key_event(
+        &self,
+        user_id: i64,
+        event: &KeyEvent,
+    ) -> Result<Vec<SyncMessage>, Error> {
+        let payload = serde_json::to_string(event).map_err(|e| Error::Invalid(e.to_string()))?;
+        self.peers_for_user(user_id)?
+            .into_iter()
+            .map(|peer| {
+                Ok(self.create_sync_message(&NewSyncMessage {
+                    user_id,
+                    peer_id: peer.id,
+                    payload: payload.clone(),
+                    message_type: OUTBOUND_KEY_EVENT.to_string(),
+                    status: "pending".to_string(),
+                    processed_count: None,
+                    error_count: None,
+                })?)
+            })
+            .collect()
+    }
+
+    /// Applies a peer's device certificate or revocation to the user behind
+    /// the peer.
+    pub fn receive_device_event(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> Result<SyncMessage, Error> {
+        let (_, author) = self.peer_author(new_sync_message.peer_id)?;
+        let event: DeviceEvent = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid device event: {}", e)))?;
+        self.record_device_event(author.id, &event)?;
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "processed".to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    pub fn record_device_event(&self, user_id: i64, event: &DeviceEvent) -> Result<Device, Error> {
+        match event {
+            DeviceEvent::Certificate(certificate) => {
+                self.record_device_certificate(user_id, certificate)
+            }
+            DeviceEvent::Revocation(revocation) => {
+                self.record_device_revocation(user_id, revocation)
+            }
+        }
+    }
+
+    /// Stores a certificate for one of the user's devices. It must be signed
+    /// by the key the user had when it was issued. Storing the same
+    /// certificate again is a no-op.
+    pub fn record_device_certificate(
+        &self,
+        user_id: i64,
+        certificate: &DeviceCertificate,
+    ) -> Result<Device, Error> {
+        certificate
+            .verify()
+            .map_err(|e| Error::Invalid(format!("Device certificate can't be verified: {}", e)))?;
+        let history = self.key_history(user_id)?;
+        if history.key_at(certificate.created_at) != Some(certificate.root_key.as_str()) {
+            return Err(Error::Invalid(
+                "Device certificate isn't signed by the user's key at the time".to_string(),
+            ));
+        }
+        match self.find_device_by_key(&certificate.device_key)? {
+            Some(device) if device.user_id == user_id && device.certificate == *certificate => {
+                return Ok(device)
+            }
+            Some(_) => {
+                return Err(Error::Invalid(
+                    "Device key is already registered".to_string(),
+                ))
+            }
+            None => {}
+        }
+        if self
+            .find_user_by_public_key(&certificate.device_key)?
+            .is_some()
+        {
+            return Err(Error::Invalid(
+                "Device key is already registered to an account".to_string(),
+            ));
+        }
+        Ok(self.insert_device(user_id, certificate)?)
+    }
+
+    /// Revokes one of the user's devices. The signer chooses the
+    /// revocation's dates, so it is authorised by its standing now: it must
+    /// be the user's current key, or one of their devices that may revoke and
+    /// isn't revoked itself. A device can't be revoked from before it was
+    /// certified, and keeps its earliest `revoked_at`.
+    pub fn record_device_revocation(
+        &self,
+        user_id: i64,
+        revocation: &DeviceRevocation,
+    ) -> Result<Device, Error> {
+        revocation
+            .verify()
+            .map_err(|e| Error::Invalid(format!("Device revocation can't be verified: {}", e)))?;
+        let device = self
+            .find_device_by_key(&revocation.device_key)?
+            .filter(|device| device.user_id == user_id)
+            .ok_or(Error::NotFound("Device not found"))?;
+        let history = self.key_history(user_id)?;
+        if !history.contains(&revocation.root_key) {
+            return Err(Error::Invalid(
+                "Device revocation is for another account".to_string(),
+            ));
+        }
+        if revocation.revoked_at < device.certificate.created_at {
+            return Err(Error::Invalid(
+                "Device revocation predates the device's certificate".to_string(),
+            ));
+        }
+        let by_root = history.current() == revocation.signer_key;
+        let by_device = || -> Result<bool, Error> {
+            Ok(self
+                .find_device_by_key(&revocation.signer_key)?
+                .is_some_and(|signer| {
+                    signer.user_id == user_id
+                        && signer.certificate.allows(DeviceCapability::Revoke)
+                        && signer.revocation.is_none()
+                        && history.key_at(signer.certificate.created_at)
+                            == Some(signer.certificate.root_key.as_str())
+                }))
+        };
+        if !by_root && !by_device()? {
+            return Err(Error::Invalid(
+                "Device revocation isn't signed by the account or one of its devices".to_string(),
+            ));
+        }
+        if device
+            .revoked_at()
+            .is_some_and(|revoked_at| revoked_at <= revocation.revoked_at)
+        {
+            return Ok(device);
+        }
+        self.update_device_revocation(device.id, revocation)?;
+        self.find_device(device.id)?
+            .ok_or(Error::NotFound("Device not found"))
+    }
+
+    /// Queues `event` to every peer of `user_id`, so friends accept posts
+    /// from a new device and stop accepting them from a revoked one.
+    pub fn broadcast_device_event(
+        &self,
+        user_id: i64,
+        event: &DeviceEvent,
+    ) -> Result<Vec<SyncMessage>, Error> {
+        let payload = serde_json::to_string(event).map_err(|e| Error::Invalid(e.to_string()))?;
+        self.peers_for_user(user_id)?
+            .into_iter()
+            .map(|peer| {
+                Ok(self.create_sync_message(&NewSyncMessage {
+                    user_id,
+                    peer_id: peer.id,
+                    payload: payload.clone(),
+                    message_type: OUTBOUND_DEVICE_EVENT.to_string(),
+                    status: "pending".to_string(),
+                    processed_count: None,
+                    error_count: None,
+                })?)
+            })
+            .collect()
+    }
+
+    /// Records that `user_id` checked `friend_id`'s key out of band.
+    /// `public_key` is the key the safety number was computed from; if the
+    /// friend's key changed meanwhile, nothing is recorded.
+    pub fn verify_friend_key(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+        public_key: &str,
+    ) -> Result<FriendVerification, Error> {
+        if !self.are_friends(user_id, friend_id)? {
+            return Err(Error::Invalid(
+                "Only accepted friends can be verified".to_string(),
+            ));
+        }
+        let friend = self
+            .find_user(friend_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        if friend.public_key.as_deref() != Some(public_key) {
+            return Err(Error::Invalid(
+                "Your friend's key changed while verifying. Compare the new safety number."
+                    .to_string(),
+            ));
+        }
+        self.upsert_key_verification(user_id, friend_id, public_key)?;
+        self.friend_verification(user_id, friend_id)
+    }
+
+    /// Whether `user_id` has verified `friend_id`'s current key.
+    pub fn friend_verification(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+    ) -> Result<FriendVerification, Error> {
+        let friend = self
+            .find_user(friend_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        let verification = self.find_key_verification(user_id, friend_id)?;
+        Ok(FriendVerification::new(friend, verification))
+    }
+
+    /// Friends `user_id` verified whose key has since changed.
+    pub fn key_change_warnings(&self, user_id: i64) -> Result<Vec<FriendVerification>, Error> {
+        let mut warnings = Vec::new();
+        for friend in self.friends_of(user_id)? {
+            let verification = self.find_key_verification(user_id, friend.id)?;
+            let verification = FriendVerification::new(friend, verification);
+            if verification.status == VerificationStatus::KeyChanged {
+                warnings.push(verification);
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Keeps a peer's prekey bundle for starting message sessions with them.
+    /// It must be signed by the key the peer carries.
+    pub fn receive_prekey_bundle(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> Result<SyncMessage, Error> {
+        let (peer, _) = self.peer_author(new_sync_message.peer_id)?;
+        let bundle: PrekeyBundle = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid prekey bundle: {}", e)))?;
+        if peer.public_key.as_deref() != Some(bundle.identity_key.as_str()) {
+            return Err(Error::Invalid(
+                "Prekey bundle is for a different key than the peer's".to_string(),
+            ));
+        }
+        bundle
+            .verify()
+            .map_err(|e| Error::Invalid(format!("Prekey bundle can't be verified: {}", e)))?;
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "held".to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Queues one bundle to each of `user_id`'s peers, so no two peers get
+    /// the same one-time prekey. Returns how many were sent.
+    pub fn deliver_prekey_bundles(
+        &self,
+        user_id: i64,
+        bundles: Vec<PrekeyBundle>,
+    ) -> Result<usize, Error> {
+        let peers = self.peers_for_user(user_id)?;
+        let mut sent = 0;
+        for (peer, bundle) in peers.iter().zip(bundles) {
+            self.create_sync_message(&NewSyncMessage {
+                user_id,
+                peer_id: peer.id,
+                payload: serde_json::to_string(&bundle)
+                    .map_err(|e| Error::Invalid(e.to_string()))?,
+                message_type: OUTBOUND_PREKEY_BUNDLE.to_string(),
+                status: "pending".to_string(),
+                processed_count: None,
+                error_count: None,
+            })?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// The newest unused bundle `user_id` holds for `public_key`, marked
+    /// used. Once they are all used, the newest one without its one-time
+    /// prekey, which X3DH still accepts.
+    pub fn take_prekey_bundle(
+        &self,
+        user_id: i64,
+        public_key: &str,
+    ) -> Result<Option<PrekeyBundle>, Error> {
+        let held = {
+            let conn = self.connection();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages \
+                 WHERE user_id = ?1 AND message_type = ?2 AND status IN ('held', 'used') \
+                 ORDER BY id DESC"
+            ))?;
+            let rows = stmt
+                .query_map(
+                    params![user_id, INBOUND_PREKEY_BUNDLE],
+                    SyncMessage::from_row,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        let bundles: Vec<(SyncMessage, PrekeyBundle)> = held
+            .into_iter()
+            .filter_map(|message| {
+                let bundle: PrekeyBundle =
+                    serde_json::from_str(message.payload.as_deref()?).ok()?;
+                (bundle.identity_key == public_key).then_some((message, bundle))
+            })
+            .collect();
+        if let Some((message, bundle)) = bundles
+            .iter()
+            .find(|(message, _)| message.status.as_deref() == Some("held"))
+        {
+            self.update_sync_message_status(message.id, "used")?;
+            return Ok(Some(bundle.clone()));
+        }
+        Ok(bundles
+            .first()
+            .map(|(_, bundle)| bundle.without_one_time_prekey()))
+    }
+
+    /// Creates a circle of the owner's friends and queues `keys`, the first
+    /// epoch's key sealed to each member.
+    pub fn create_circle(
+        &self,
+        new_circle: &NewCircle,
+        public_id: &str,
+        keys: &[CircleKey],
+    ) -> Result<Circle, Error> {
+        let name = new_circle.name.trim();
+        if name.is_empty() || name.chars().count() > 50 {
+            return Err(Error::Invalid(
+                "Name must be between 1 and 50 characters".to_string(),
+            ));
+        }
+        let mut member_ids = new_circle.member_ids.clone();
+        member_ids.sort_unstable();
+        member_ids.dedup();
+        for &member_id in &member_ids {
+            self.check_circle_candidate(new_circle.user_id, member_id)?;
+        }
+        let routes = self.circle_key_routes(new_circle.user_id, keys)?;
+        let circle = self.insert_circle(new_circle.user_id, name, public_id)?;
+        for member_id in member_ids {
+            self.insert_circle_member(circle.id, member_id)?;
+        }
+        self.queue_circle_keys(new_circle.user_id, routes)?;
+        Ok(circle)
+    }
+
+    /// Adds a friend at `epoch`, which must follow the circle's current one.
+    /// Only the new member needs a key; the others hash theirs forward.
+    pub fn add_circle_member(
+        &self,
+        circle_id: i64,
+        user_id: i64,
+        epoch: u32,
+        keys: &[CircleKey],
+    ) -> Result<Circle, Error> {
+        let circle = self.next_circle_epoch(circle_id, epoch)?;
+        self.check_circle_candidate(circle.user_id, user_id)?;
+        if self.is_circle_member(circle_id, user_id)? {
+            return Err(Error::Invalid(
+                "Already a member of this circle".to_string(),
+            ));
+        }
+        let routes = self.circle_key_routes(circle.user_id, keys)?;
+        self.insert_circle_member(circle_id, user_id)?;
+        self.update_circle_epoch(circle_id, epoch)?;
+        self.queue_circle_keys(circle.user_id, routes)?;
+        self.find_circle(circle_id)?
+            .ok_or(Error::NotFound("Circle not found"))
+    }
+
+    /// Removes a member at `epoch`, whose fresh key goes to everyone left.
+    pub fn remove_circle_member(
+        &self,
+        circle_id: i64,
+        user_id: i64,
+        epoch: u32,
+        keys: &[CircleKey],
+    ) -> Result<Circle, Error> {
+        let circle = self.next_circle_epoch(circle_id, epoch)?;
+        if !self.is_circle_member(circle_id, user_id)? {
+            return Err(Error::NotFound("Not a member of this circle"));
+        }
+        let routes = self.circle_key_routes(circle.user_id, keys)?;
+        self.delete_circle_member(circle_id, user_id)?;
+        self.update_circle_epoch(circle_id, epoch)?;
+        self.queue_circle_keys(circle.user_id, routes)?;
+        self.find_circle(circle_id)?
+            .ok_or(Error::NotFound("Circle not found"))
+    }
+
+    /// Keeps a circle key the peer sealed to the receiving user. It must be
+    /// signed by the peer, who must be an accepted friend; it is stored with
+    /// status `held` until a post from the circle is opened.
+    pub fn receive_circle_key(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> Result<SyncMessage, Error> {
+        let (_, owner) = self.peer_author(new_sync_message.peer_id)?;
+        let member = self
+            .find_user(new_sync_message.user_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        let key: CircleKey = serde_json::from_str(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid circle key: {}", e)))?;
+        if key.verify().is_err() || owner.public_key.as_deref() != Some(key.owner_key.as_str()) {
+            return Err(Error::Invalid(
+                "Circle key is not signed by the peer".to_string(),
+            ));
+        }
+        if member.public_key.as_deref() != Some(key.recipient_key()) {
+            return Err(Error::Invalid("Circle key is for someone else".to_string()));
+        }
+        if !self.are_friends(member.id, owner.id)? {
+            return Err(Error::Invalid(
+                "Circle keys are only accepted from friends".to_string(),
+            ));
+        }
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "held".to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Circle keys `user_id` holds for the circle `public_id` of the owner
+    /// with `owner_key`.
+    pub fn held_circle_keys(
+        &self,
+        user_id: i64,
+        owner_key: &str,
+        public_id: &str,
+    ) -> Result<Vec<CircleKey>, Error> {
+        let held = {
+            let conn = self.connection();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages \
+                 WHERE user_id = ?1 AND message_type = ?2 AND status = 'held' ORDER BY id"
+            ))?;
+            let rows = stmt
+                .query_map(params![user_id, INBOUND_CIRCLE_KEY], SyncMessage::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        Ok(held
+            .into_iter()
+            .filter_map(|message| serde_json::from_str(message.payload.as_deref()?).ok())
+            .filter(|key: &CircleKey| key.owner_key == owner_key && key.circle_id == public_id)
+            .collect())
+    }
+
+    fn check_circle_candidate(&self, owner_id: i64, user_id: i64) -> Result<(), Error> {
+        if !self.are_friends(owner_id, user_id)? {
+            return Err(Error::Invalid(
+                "Only accepted friends can be added to a circle".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn next_circle_epoch(&self, circle_id: i64, epoch: u32) -> Result<Circle, Error> {
+        let circle = self
+            .find_circle(circle_id)?
+            .ok_or(Error::NotFound("Circle not found"))?;
+        if epoch != circle.epoch + 1 {
+            return Err(Error::Invalid(
+                "Circle keys are out of date with the circle".to_string(),
+            ));
+        }
+        Ok(circle)
+    }
+
+    /// The owner's peer for each key's recipient. Fails before anything is
+    /// queued if a member has no peer.
+    fn circle_key_routes<'a>(
+        &self,
+        owner_id: i64,
+        keys: &'a [CircleKey],
+    ) -> Result<Vec<(i64, &'a CircleKey)>, Error> {
+        let peers = self.peers_for_user(owner_id)?;
+        keys.iter()
+            .map(|key| {
+                peers
+                    .iter()
+                    .find(|peer| peer.public_key.as_deref() == Some(key.recipient_key()))
+                    .map(|peer| (peer.id, key))
+                    .ok_or_else(|| {
+                        Error::Invalid(format!(
+                            "No peer for {}; connect to them before sharing",
+                            key.recipient_key()
+                        ))
+                    })
+            })
+            .collect()
+    }
+
+    fn queue_circle_keys(
+        &self,
+        owner_id: i64,
+        routes: Vec<(i64, &CircleKey)>,
+    ) -> Result<(), Error> {
+        for (peer_id, key) in routes {
+            self.create_sync_message(&NewSyncMessage {
+                user_id: owner_id,
+                peer_id,
+                payload: serde_json::to_string(key).map_err(|e| Error::Invalid(e.to_string()))?,
+                message_type: OUTBOUND_CIRCLE_KEY.to_string(),
+                status: "pending".to_string(),
+                processed_count: None,
+                error_count: None,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Holds a [`SealedEnvelope`] for the user until their device opens it.
+    /// Nothing about the sender or contents can be checked yet, and the peer
+    /// that delivered it needn't be the sender. The same envelope is only
+    /// accepted once.
+    pub fn receive_sealed(&self, new_sync_message: &NewSyncMessage) -> Result<SyncMessage, Error> {
+        serde_json::from_str::<SealedEnvelope>(&new_sync_message.payload)
+            .map_err(|e| Error::Invalid(format!("Invalid sealed envelope: {}", e)))?;
+        self.find_user(new_sync_message.user_id)?
+            .ok_or(Error::NotFound("User not found"))?;
+        if self.sync_message_exists(
+            new_sync_message.user_id,
+            INBOUND_SEALED,
+            &new_sync_message.payload,
+        )? {
+            return Err(Error::Invalid(
+                "Sealed envelope was already received".to_string(),
+            ));
+        }
+        Ok(self.create_sync_message(&NewSyncMessage {
+            status: "sealed".to_string(),
+            ..new_sync_message.clone()
+        })?)
+    }
+
+    /// Applies the contents of held sealed message `sealed_id`, opened by the
+    /// recipient. The sender must be one of the user's peers; the message
+    /// then goes through [`Database::receive_sync`] as its inbound type. The
+    /// sealed message is marked `opened`, or `rejected` if this fails.
+    pub fn receive_unsealed(
+        &self,
+        sealed_id: i64,
+        content: &SealedContent,
+    ) -> Result<SyncMessage, Error> {
+        let result = self.apply_unsealed(sealed_id, content);
+        let status = if result.is_ok() { "opened" } else { "rejected" };
+        self.update_sync_message_status(sealed_id, status)?;
+        result
+    }
+
+    fn apply_unsealed(
+        &self,
+        sealed_id: i64,
+        content: &SealedContent,
+    ) -> Result<SyncMessage, Error> {
+        let sealed = self
+            .find_sync_message(sealed_id)?
+            .filter(|message| message.status.as_deref() == Some("sealed"))
+            .ok_or(Error::NotFound("Sealed message not found"))?;
+        let message_type = content
+            .message_type
+            .strip_prefix("outbound_")
+            .filter(|kind| !kind.is_empty())
+            .map(|kind| format!("inbound_{}", kind))
+            .filter(|kind| kind != INBOUND_SEALED)
+            .ok_or_else(|| Error::Invalid("Sealed message has no inbound type".to_string()))?;
+        let peer = self
+            .peers_for_user(sealed.user_id)?
+            .into_iter()
+            .find(|peer| peer.public_key.as_deref() == Some(content.sender_key.as_str()))
+            .ok_or(Error::NotFound(
+                "No peer for the sender of a sealed message",
+            ))?;
+        self.receive_sync(&NewSyncMessage {
+            user_id: sealed.user_id,
+            peer_id: peer.id,
+            payload: content.payload.clone(),
+            message_type,
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+    }
+
+    /// The peer and the user whose public key it carries.
+    fn peer_author(&self, peer_id: i64) -> Result<(Peer, User), Error> {
+        let peer = self
+            .find_peer(peer_id)?
+            .ok_or(Error::NotFound("Peer not found"))?;
+        let author = match &peer.public_key {
+            Some(public_key) => self.find_user_by_public_key(public_key)?,
+            None => None,
+        }
+        .ok_or(Error::NotFound("No user for the peer's public key"))?;
+        Ok((peer, author))
+    }
+
+    fn ingest_synced_post(
+        &self,
+        user_id: i64,
+        author: &User,
+        synced_post: &SyncedPost,
+    ) -> Result<Post, Error> {
+        if let Some(signature) = &synced_post.signature {
+            if let Some(existing) = self.find_synced_post(user_id, author.id, signature)? {
+                return Ok(existing);
+            }
+        }
+        let post = self.ingest_post(&NewPost {
+            user_id,
+            content_encrypted: synced_post.content_encrypted.clone(),
+            signature: synced_post.signature.clone(),
+            timestamp: Some(signed_post::format_timestamp(synced_post.timestamp)),
+            is_synced: true,
+            original_user_id: Some(author.id),
+            synced_from_user_id: Some(author.id),
+            attachment_checksums: Some(
+                synced_post
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.checksum.clone())
+                    .collect(),
+            ),
+            ..NewPost::default()
+        })?;
+        for attachment in &synced_post.attachments {
+            self.create_attachment(&NewAttachment {
+                post_id: post.id,
+                ..attachment.clone()
+            })?;
+        }
+        Ok(post)
+    }
+
+    // Users
+
+    pub fn create_user(&self, new_user: &NewUser) -> rusqlite::Result<User> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO users (public_key, username, display_name, email, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, {NOW}, {NOW})"
+            ),
+            params![
+                new_user.public_key,
+                new_user.username,
+                new_user.display_name,
+                new_user.email
+            ],
+        )?;
+        find_user(&conn, conn.last_insert_rowid())
+    }
+
+    pub fn find_user(&self, id: i64) -> rusqlite::Result<Option<User>> {
+        find_user(&self.connection(), id).optional()
+    }
+
+    /// Points the account at a rotated key. Use
+    /// [`Database::record_key_event`], which checks the change first.
+    pub fn update_user_public_key(&self, id: i64, public_key: &str) -> rusqlite::Result<User> {
+        let conn = self.connection();
+        conn.execute(
+            &format!("UPDATE users SET public_key = ?1, updated_at = {NOW} WHERE id = ?2"),
+            params![public_key, id],
+        )?;
+        find_user(&conn, id)
+    }
+
+    pub fn find_user_by_public_key(&self, public_key: &str) -> rusqlite::Result<Option<User>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE public_key = ?1"),
+                [public_key],
+                User::from_row,
+            )
+            .optional()
+    }
+
+    pub fn find_user_by_username(&self, username: &str) -> rusqlite::Result<Option<User>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {USER_COLUMNS} FROM users WHERE username = ?1"),
+                [username],
+                User::from_row,
+            )
+            .optional()
+    }
+
+    pub fn list_users(&self) -> rusqlite::Result<Vec<User>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!("SELECT {USER_COLUMNS} FROM users ORDER BY id"))?;
+        let users = stmt.query_map([], User::from_row)?.collect();
+        users
+    }
+
+    // Posts
+
+    pub fn create_post(&self, new_post: &NewPost) -> rusqlite::Result<Post> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO posts (user_id, content_encrypted, signature, timestamp, content_hash, \
+                 encryption_key, is_synced, original_user_id, synced_from_user_id, synced_at, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, COALESCE(?4, {NOW}), ?5, ?6, ?7, ?8, ?9, CASE WHEN ?7 THEN {NOW} END, {NOW}, {NOW})"
+            ),
+            params![
+                new_post.user_id,
+                new_post.content_encrypted,
+                new_post.signature,
+                new_post.timestamp,
+                new_post.content_hash,
+                new_post.encryption_key,
+                new_post.is_synced,
+                new_post.original_user_id,
+                new_post.synced_from_user_id
+            ],
+        )?;
+        find_post(&conn, conn.last_insert_rowid())
+    }
+
+    pub fn find_post(&self, id: i64) -> rusqlite::Result<Option<Post>> {
+        find_post(&self.connection(), id).optional()
+    }
+
+    /// A copy of `original_user_id`'s post already synced to `user_id`.
+    pub fn find_synced_post(
+        &self,
+        user_id: i64,
+        original_user_id: i64,
+        signature: &str,
+    ) -> rusqlite::Result<Option<Post>> {
+        self.connection()
+            .query_row(
+                &format!(
+                    "SELECT {POST_COLUMNS} FROM posts WHERE user_id = ?1 AND original_user_id = ?2 \
+                     AND signature = ?3"
+                ),
+                params![user_id, original_user_id, signature],
+                Post::from_row,
+            )
+            .optional()
+    }
+
+    pub fn post_exists_with_hash(&self, content_hash: &str) -> rusqlite::Result<bool> {
+        self.connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM posts WHERE content_hash = ?1)",
+            [content_hash],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn post_exists_with_signature(
+        &self,
+        user_id: i64,
+        signature: &str,
+    ) -> rusqlite::Result<bool> {
+        self.connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM posts WHERE user_id = ?1 AND signature = ?2)",
+            params![user_id, signature],
+            |row| row.get(0),
+        )
+    }
+
+    /// Most recent posts by `user_id` and their accepted friends.
+    pub fn feed_for_user(&self, user_id: i64, limit: i64) -> rusqlite::Result<Vec<Post>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {POST_COLUMNS} FROM posts WHERE user_id = ?1 OR user_id IN ({FRIEND_IDS}) \
+             ORDER BY timestamp DESC LIMIT ?2"
+        ))?;
+        let posts = stmt
+            .query_map(params![user_id, limit], Post::from_row)?
+            .collect();
+        posts
+    }
+
+    pub fn posts_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Post>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {POST_COLUMNS} FROM posts WHERE user_id = ?1 ORDER BY timestamp DESC"
+        ))?;
+        let posts = stmt.query_map([user_id], Post::from_row)?.collect();
+        posts
+    }
+
+    // Attachments
+
+    pub fn create_attachment(
+        &self,
+        new_attachment: &NewAttachment,
+    ) -> rusqlite::Result<Attachment> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO attachments (post_id, filename, content_type, file_size, data_encrypted, \
+                 checksum, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, {NOW}, {NOW})"
+            ),
+            params![
+                new_attachment.post_id,
+                new_attachment.filename,
+                new_attachment.content_type,
+                new_attachment.file_size,
+                new_attachment.data_encrypted,
+                new_attachment.checksum
+            ],
+        )?;
+        conn.query_row(
+            &format!("SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            Attachment::from_row,
+        )
+    }
+
+    pub fn find_attachment(&self, id: i64) -> rusqlite::Result<Option<Attachment>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE id = ?1"),
+                [id],
+                Attachment::from_row,
+            )
+            .optional()
+    }
+
+    pub fn attachments_for_post(&self, post_id: i64) -> rusqlite::Result<Vec<Attachment>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE post_id = ?1 ORDER BY id"
+        ))?;
+        let attachments = stmt.query_map([post_id], Attachment::from_row)?.collect();
+        attachments
+    }
+
+    // Friendships
+
+    pub fn send_friend_request(
+        &self,
+        requester_id: i64,
+        addressee_id: i64,
+    ) -> rusqlite::Result<Friendship> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO friendships (requester_id, addressee_id, status, created_at, updated_at) \
+                 VALUES (?1, ?2, 'pending', {NOW}, {NOW})"
+            ),
+            params![requester_id, addressee_id],
+        )?;
+        find_friendship(&conn, conn.last_insert_rowid())
+    }
+
+    /// Sets a friendship's status (`accepted`, `declined` or `blocked`).
+    pub fn update_friendship_status(
+        &self,
+        id: i64,
+        status: &str,
+    ) -> rusqlite::Result<Option<Friendship>> {
+        let conn = self.connection();
+        conn.execute(
+            &format!("UPDATE friendships SET status = ?1, updated_at = {NOW} WHERE id = ?2"),
+            params![status, id],
+        )?;
+        find_friendship(&conn, id).optional()
+    }
+
+    /// Friendships where `user_id` is either side, newest first.
+    pub fn friendships_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Friendship>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FRIENDSHIP_COLUMNS} FROM friendships WHERE requester_id = ?1 OR addressee_id = ?1 \
+             ORDER BY created_at DESC"
+        ))?;
+        let friendships = stmt.query_map([user_id], Friendship::from_row)?.collect();
+        friendships
+    }
+
+    /// Users with an accepted friendship to `user_id`.
+    pub fn friends_of(&self, user_id: i64) -> rusqlite::Result<Vec<User>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {USER_COLUMNS} FROM users WHERE id IN ({FRIEND_IDS}) ORDER BY username"
+        ))?;
+        let users = stmt.query_map([user_id], User::from_row)?.collect();
+        users
+    }
+
+    pub fn are_friends(&self, user_id: i64, other_id: i64) -> rusqlite::Result<bool> {
+        self.connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM friendships WHERE status = 'accepted' AND \
+             ((requester_id = ?1 AND addressee_id = ?2) OR (requester_id = ?2 AND addressee_id = ?1)))",
+            params![user_id, other_id],
+            |row| row.get(0),
+        )
+    }
+
+    // Messages
+
+    pub fn create_message(&self, new_message: &NewMessage) -> rusqlite::Result<Message> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO messages (sender_id, recipient_id, content, encrypted_content, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, {NOW}, {NOW})"
+            ),
+            params![
+                new_message.sender_id,
+                new_message.recipient_id,
+                new_message.content,
+                new_message.encrypted_content
+            ],
+        )?;
+        conn.query_row(
+            &format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            Message::from_row,
+        )
+    }
+
+    /// Conversation between two users, oldest first.
+    pub fn messages_between(&self, user_id: i64, other_id: i64) -> rusqlite::Result<Vec<Message>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE \
+             (sender_id = ?1 AND recipient_id = ?2) OR (sender_id = ?2 AND recipient_id = ?1) \
+             ORDER BY created_at"
+        ))?;
+        let messages = stmt
+            .query_map(params![user_id, other_id], Message::from_row)?
+            .collect();
+        messages
+    }
+
+    /// Every message `user_id` sent or received, oldest first.
+    pub fn messages_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Message>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE sender_id = ?1 OR recipient_id = ?1 \
+             ORDER BY created_at"
+        ))?;
+        let messages = stmt.query_map([user_id], Message::from_row)?.collect();
+        messages
+    }
+
+    /// Stores a message restored from an account archive with its original
+    /// times. Returns `None` if the pair already has a message sent at
+    /// `created_at`.
+    pub fn import_message(
+        &self,
+        new_message: &NewMessage,
+        created_at: &str,
+        read_at: Option<&str>,
+    ) -> rusqlite::Result<Option<Message>> {
+        let conn = self.connection();
+        let inserted = conn.execute(
+            "INSERT INTO messages (sender_id, recipient_id, content, encrypted_content, read_at, \
+             created_at, updated_at) \
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?6 WHERE NOT EXISTS (SELECT 1 FROM messages \
+             WHERE sender_id = ?1 AND recipient_id = ?2 AND created_at = ?6)",
+            params![
+                new_message.sender_id,
+                new_message.recipient_id,
+                new_message.content,
+                new_message.encrypted_content,
+                read_at,
+                created_at
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+        conn.query_row(
+            &format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            Message::from_row,
+        )
+        .map(Some)
+    }
+
+    pub fn find_message(&self, id: i64) -> rusqlite::Result<Option<Message>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE id = ?1"),
+                [id],
+                Message::from_row,
+            )
+            .optional()
+    }
+
+    pub fn mark_message_read(&self, id: i64) -> rusqlite::Result<()> {
+        self.connection().execute(
+            &format!("UPDATE messages SET read_at = {NOW}, updated_at = {NOW} WHERE id = ?1 AND read_at IS NULL"),
+            [id],
+        )?;
+        Ok(())
+    }
+
+    // Peers
+
+    /// Records a peer address for `user_id`, refreshing `last_seen` if the
+    /// same address and port are already known.
+    pub fn upsert_peer(&self, new_peer: &NewPeer) -> rusqlite::Result<Peer> {
+        let conn = self.connection();
+        let updated = conn.execute(
+            &format!(
+                "UPDATE peers SET public_key = ?4, last_seen = {NOW}, updated_at = {NOW} \
+                 WHERE user_id = ?1 AND address = ?2 AND port = ?3"
+            ),
+            params![
+                new_peer.user_id,
+                new_peer.address,
+                new_peer.port,
+                new_peer.public_key
+            ],
+        )?;
+        if updated == 0 {
+            conn.execute(
+                &format!(
+                    "INSERT INTO peers (user_id, address, port, public_key, last_seen, created_at, updated_at) \
+                     VALUES (?1, ?2, ?3, ?4, {NOW}, {NOW}, {NOW})"
+                ),
+                params![new_peer.user_id, new_peer.address, new_peer.port, new_peer.public_key],
+            )?;
+        }
+        conn.query_row(
+            &format!("SELECT {PEER_COLUMNS} FROM peers WHERE user_id = ?1 AND address = ?2 AND port = ?3"),
+            params![new_peer.user_id, new_peer.address, new_peer.port],
+            Peer::from_row,
+        )
+    }
+
+    pub fn find_peer(&self, id: i64) -> rusqlite::Result<Option<Peer>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {PEER_COLUMNS} FROM peers WHERE id = ?1"),
+                [id],
+                Peer::from_row,
+            )
+            .optional()
+    }
+
+    pub fn peers_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Peer>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {PEER_COLUMNS} FROM peers WHERE user_id = ?1 ORDER BY last_seen DESC"
+        ))?;
+        let peers = stmt.query_map([user_id], Peer::from_row)?.collect();
+        peers
+    }
+
+    // Sync messages
+
+    pub fn create_sync_message(
+        &self,
+        new_sync_message: &NewSyncMessage,
+    ) -> rusqlite::Result<SyncMessage> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO sync_messages (user_id, peer_id, payload, message_type, status, \
+                 processed_count, error_count, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, {NOW}, {NOW})"
+            ),
+            params![
+                new_sync_message.user_id,
+                new_sync_message.peer_id,
+                new_sync_message.payload,
+                new_sync_message.message_type,
+                new_sync_message.status,
+                new_sync_message.processed_count,
+                new_sync_message.error_count
+            ],
+        )?;
+        conn.query_row(
+            &format!("SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            SyncMessage::from_row,
+        )
+    }
+
+    pub fn update_sync_message_status(&self, id: i64, status: &str) -> rusqlite::Result<()> {
+        self.connection().execute(
+            &format!("UPDATE sync_messages SET status = ?2, updated_at = {NOW} WHERE id = ?1"),
+            params![id, status],
+        )?;
+        Ok(())
+    }
+
+    pub fn find_sync_message(&self, id: i64) -> rusqlite::Result<Option<SyncMessage>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages WHERE id = ?1"),
+                [id],
+                SyncMessage::from_row,
+            )
+            .optional()
+    }
+
+    pub fn sync_message_exists(
+        &self,
+        user_id: i64,
+        message_type: &str,
+        payload: &str,
+    ) -> rusqlite::Result<bool> {
+        self.connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM sync_messages \
+             WHERE user_id = ?1 AND message_type = ?2 AND payload = ?3)",
+            params![user_id, message_type, payload],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn sync_messages_with_status(
+        &self,
+        user_id: i64,
+        status: &str,
+    ) -> rusqlite::Result<Vec<SyncMessage>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages WHERE user_id = ?1 AND status = ?2 \
+             ORDER BY id"
+        ))?;
+        let sync_messages = stmt
+            .query_map(params![user_id, status], SyncMessage::from_row)?
+            .collect();
+        sync_messages
+    }
+
+    pub fn pending_sync_messages(&self, user_id: i64) -> rusqlite::Result<Vec<SyncMessage>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {SYNC_MESSAGE_COLUMNS} FROM sync_messages WHERE user_id = ?1 AND status = 'pending' \
+             ORDER BY created_at"
+        ))?;
+        let sync_messages = stmt.query_map([user_id], SyncMessage::from_row)?.collect();
+        sync_messages
+    }
+
+    // Key events
+
+    pub fn insert_key_event(&self, user_id: i64, event: &KeyEvent) -> rusqlite::Result<()> {
+        let payload = serde_json::to_string(event).expect("key event serializes to JSON");
+        self.connection().execute(
+            &format!(
+                "INSERT INTO key_events (user_id, event_type, payload, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, {NOW}, {NOW})"
+            ),
+            params![user_id, event.name(), payload],
+        )?;
+        Ok(())
+    }
+
+    /// Stored events for the user, oldest first. Rows that don't parse are
+    /// skipped.
+    pub fn key_events_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<KeyEvent>> {
+        let conn = self.connection();
+        let mut stmt =
+            conn.prepare("SELECT payload FROM key_events WHERE user_id = ?1 ORDER BY id")?;
+        let payloads = stmt
+            .query_map([user_id], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(payloads
+            .iter()
+            .filter_map(|payload| serde_json::from_str(payload).ok())
+            .collect())
+    }
+
+    pub fn update_peer_public_keys(&self, old: &str, new: &str) -> rusqlite::Result<usize> {
+        self.connection().execute(
+            &format!("UPDATE peers SET public_key = ?2, updated_at = {NOW} WHERE public_key = ?1"),
+            params![old, new],
+        )
+    }
+
+    // Key verifications
+
+    pub fn upsert_key_verification(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+        public_key: &str,
+    ) -> rusqlite::Result<()> {
+        let conn = self.connection();
+        let updated = conn.execute(
+            &format!(
+                "UPDATE key_verifications SET public_key = ?3, updated_at = {NOW} \
+                 WHERE user_id = ?1 AND friend_id = ?2"
+            ),
+            params![user_id, friend_id, public_key],
+        )?;
+        if updated == 0 {
+            conn.execute(
+                &format!(
+                    "INSERT INTO key_verifications (user_id, friend_id, public_key, created_at, updated_at) \
+                     VALUES (?1, ?2, ?3, {NOW}, {NOW})"
+                ),
+                params![user_id, friend_id, public_key],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The key `user_id` verified for `friend_id` and when.
+    pub fn find_key_verification(
+        &self,
+        user_id: i64,
+        friend_id: i64,
+    ) -> rusqlite::Result<Option<(String, String)>> {
+        self.connection()
+            .query_row(
+                "SELECT public_key, updated_at FROM key_verifications \
+                 WHERE user_id = ?1 AND friend_id = ?2",
+                params![user_id, friend_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
+
+    /// Users who verified a key of `friend_id`.
+    pub fn key_verifiers_of(&self, friend_id: i64) -> rusqlite::Result<Vec<i64>> {
+        let conn = self.connection();
+        let mut stmt =
+            conn.prepare("SELECT user_id FROM key_verifications WHERE friend_id = ?1")?;
+        let ids = stmt.query_map([friend_id], |row| row.get(0))?.collect();
+        ids
+    }
+    // Circles
+
+    pub fn insert_circle(
+        &self,
+        user_id: i64,
+        name: &str,
+        public_id: &str,
+    ) -> rusqlite::Result<Circle> {
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO circles (user_id, name, public_id, epoch, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, 1, {NOW}, {NOW})"
+            ),
+            params![user_id, name, public_id],
+        )?;
+        conn.query_row(
+            &format!("SELECT {CIRCLE_COLUMNS} FROM circles WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            Circle::from_row,
+        )
+    }
+
+    pub fn find_circle(&self, id: i64) -> rusqlite::Result<Option<Circle>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {CIRCLE_COLUMNS} FROM circles WHERE id = ?1"),
+                [id],
+                Circle::from_row,
+            )
+            .optional()
+    }
+
+    pub fn find_circle_by_public_id(&self, public_id: &str) -> rusqlite::Result<Option<Circle>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {CIRCLE_COLUMNS} FROM circles WHERE public_id = ?1"),
+                [public_id],
+                Circle::from_row,
+            )
+            .optional()
+    }
+
+    pub fn circles_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Circle>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CIRCLE_COLUMNS} FROM circles WHERE user_id = ?1 ORDER BY name"
+        ))?;
+        let circles = stmt.query_map([user_id], Circle::from_row)?.collect();
+        circles
+    }
+
+    pub fn update_circle_epoch(&self, id: i64, epoch: u32) -> rusqlite::Result<()> {
+        self.connection().execute(
+            &format!("UPDATE circles SET epoch = ?2, updated_at = {NOW} WHERE id = ?1"),
+            params![id, epoch],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_circle_member(&self, circle_id: i64, user_id: i64) -> rusqlite::Result<()> {
+        self.connection().execute(
+            &format!(
+                "INSERT INTO circle_members (circle_id, user_id, created_at, updated_at) \
+                 VALUES (?1, ?2, {NOW}, {NOW})"
+            ),
+            params![circle_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_circle_member(&self, circle_id: i64, user_id: i64) -> rusqlite::Result<()> {
+        self.connection().execute(
+            "DELETE FROM circle_members WHERE circle_id = ?1 AND user_id = ?2",
+            params![circle_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn is_circle_member(&self, circle_id: i64, user_id: i64) -> rusqlite::Result<bool> {
+        self.connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM circle_members WHERE circle_id = ?1 AND user_id = ?2)",
+            params![circle_id, user_id],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn circle_members(&self, circle_id: i64) -> rusqlite::Result<Vec<User>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {USER_COLUMNS} FROM users WHERE id IN \
+             (SELECT user_id FROM circle_members WHERE circle_id = ?1) ORDER BY username"
+        ))?;
+        let members = stmt.query_map([circle_id], User::from_row)?.collect();
+        members
+    }
+
+    // Devices
+
+    pub fn insert_device(
+        &self,
+        user_id: i64,
+        certificate: &DeviceCertificate,
+    ) -> rusqlite::Result<Device> {
+        let json = serde_json::to_string(certificate).expect("certificate serializes to JSON");
+        let conn = self.connection();
+        conn.execute(
+            &format!(
+                "INSERT INTO devices (user_id, device_key, name, certificate, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, {NOW}, {NOW})"
+            ),
+            params![user_id, certificate.device_key, certificate.device_name, json],
+        )?;
+        conn.query_row(
+            &format!("SELECT {DEVICE_COLUMNS} FROM devices WHERE id = ?1"),
+            [conn.last_insert_rowid()],
+            Device::from_row,
+        )
+    }
+
+    pub fn find_device(&self, id: i64) -> rusqlite::Result<Option<Device>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {DEVICE_COLUMNS} FROM devices WHERE id = ?1"),
+                [id],
+                Device::from_row,
+            )
+            .optional()
+    }
+
+    pub fn find_device_by_key(&self, device_key: &str) -> rusqlite::Result<Option<Device>> {
+        self.connection()
+            .query_row(
+                &format!("SELECT {DEVICE_COLUMNS} FROM devices WHERE device_key = ?1"),
+                [device_key],
+                Device::from_row,
+            )
+            .optional()
+    }
+
+    pub fn devices_for_user(&self, user_id: i64) -> rusqlite::Result<Vec<Device>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {DEVICE_COLUMNS} FROM devices WHERE user_id = ?1 ORDER BY id"
+        ))?;
+        let devices = stmt.query_map([user_id], Device::from_row)?.collect();
+        devices
+    }
+
+    pub fn update_device_revocation(
+        &self,
+        id: i64,
+        revocation: &DeviceRevocation,
+    ) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(revocation).expect("revocation serializes to JSON");
+        self.connection().execute(
+            &format!("UPDATE devices SET revocation = ?2, updated_at = {NOW} WHERE id = ?1"),
+            params![id, json],
+        )?;
+        Ok(())
+    }
+}
+
+const FRIEND_IDS: &str =
+    "SELECT CASE WHEN requester_id = ?1 THEN addressee_id ELSE requester_id END \
+     FROM friendships WHERE status = 'accepted' AND (requester_id = ?1 OR addressee_id = ?1)";
+
+fn find_user(conn: &Connection, id: i64) -> rusqlite::Result<User> {
+    conn.query_row(
+        &format!("SELECT {USER_COLUMNS} FROM users WHERE id = ?1"),
+        [id],
+        User::from_row,
+    )
+}
+
+fn find_post(conn: &Connection, id: i64) -> rusqlite::Result<Post> {
+    conn.query_row(
+        &format!("SELECT {POST_COLUMNS} FROM posts WHERE id = ?1"),
+        [id],
+        Post::from_row,
+    )
+}
+
+fn find_friendship(conn: &Connection, id: i64) -> rusqlite::Result<Friendship> {
+    conn.query_row(
+        &format!("SELECT {FRIENDSHIP_COLUMNS} FROM friendships WHERE id = ?1"),
+        [id],
+        Friendship::from_row,
+    )
+}
+
+const USER_COLUMNS: &str = "id, public_key, username, display_name, email, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct User {
+    #[ts(type = "number")]
+    pub id: i64,
+    pub public_key: Option<String>,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl User {
+    fn from_row(row: &Row) -> rusqlite::Result<User> {
+        Ok(User {
+            id: row.get(0)?,
+            public_key: row.get(1)?,
+            username: row.get(2)?,
+            display_name: row.get(3)?,
+            email: row.get(4)?,
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewUser {
+    pub public_key: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+}
+
+fn json_column<T: DeserializeOwned>(index: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+const DEVICE_COLUMNS: &str =
+    "id, user_id, device_key, name, certificate, revocation, created_at, updated_at";
+
+/// One of a user's devices, with the certificate that vouches for its key.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Device {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub device_key: String,
+    pub name: String,
+    pub certificate: DeviceCertificate,
+    pub revocation: Option<DeviceRevocation>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Device {
+    fn from_row(row: &Row) -> rusqlite::Result<Device> {
+        let revocation: Option<String> = row.get(5)?;
+        Ok(Device {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            device_key: row.get(2)?,
+            name: row.get(3)?,
+            certificate: json_column(4, &row.get::<_, String>(4)?)?,
+            revocation: revocation.map(|text| json_column(5, &text)).transpose()?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+
+    pub fn revoked_at(&self) -> Option<i64> {
+        self.revocation
+            .as_ref()
+            .map(|revocation| revocation.revoked_at)
+    }
+
+    /// Whether the device's key could sign at `timestamp` (Unix seconds).
+    pub fn is_valid_at(&self, timestamp: i64) -> bool {
+        self.certificate.created_at <= timestamp
+            && self
+                .revoked_at()
+                .is_none_or(|revoked_at| timestamp < revoked_at)
+    }
+}
+
+const POST_COLUMNS: &str =
+    "id, user_id, content_encrypted, signature, timestamp, content_hash, encryption_key, \
+     is_synced, original_user_id, synced_from_user_id, synced_at, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Post {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub content_encrypted: Option<String>,
+    pub signature: Option<String>,
+    pub timestamp: Option<String>,
+    pub content_hash: Option<String>,
+    pub encryption_key: Option<String>,
+    pub is_synced: bool,
+    #[ts(type = "number | null")]
+    pub original_user_id: Option<i64>,
+    #[ts(type = "number | null")]
+    pub synced_from_user_id: Option<i64>,
+    pub synced_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Post {
+    fn from_row(row: &Row) -> rusqlite::Result<Post> {
+        Ok(Post {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            content_encrypted: row.get(2)?,
+            signature: row.get(3)?,
+            timestamp: row.get(4)?,
+            content_hash: row.get(5)?,
+            encryption_key: row.get(6)?,
+            is_synced: row.get::<_, Option<bool>>(7)?.unwrap_or(false),
+            original_user_id: row.get(8)?,
+            synced_from_user_id: row.get(9)?,
+            synced_at: row.get(10)?,
+            created_at: row.get(11)?,
+            updated_at: row.get(12)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewPost {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub content_encrypted: Option<String>,
+    pub signature: Option<String>,
+    /// Defaults to the current time when omitted.
+    #[serde(default)]
+    #[ts(optional)]
+    pub timestamp: Option<String>,
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub encryption_key: Option<String>,
+    #[serde(default)]
+    pub is_synced: bool,
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub original_user_id: Option<i64>,
+    #[serde(default)]
+    #[ts(optional, type = "number")]
+    pub synced_from_user_id: Option<i64>,
+    /// Checksums of the attachments the signature covers. Not stored; the
+    /// attachment rows carry them.
+    #[serde(default)]
+    #[ts(optional)]
+    pub attachment_checksums: Option<Vec<String>>,
+}
+
+const ATTACHMENT_COLUMNS: &str =
+    "id, post_id, filename, content_type, file_size, data_encrypted, checksum, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Attachment {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub post_id: i64,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    #[ts(type = "number | null")]
+    pub file_size: Option<i64>,
+    pub data_encrypted: Option<String>,
+    pub checksum: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Attachment {
+    fn from_row(row: &Row) -> rusqlite::Result<Attachment> {
+        Ok(Attachment {
+            id: row.get(0)?,
+            post_id: row.get(1)?,
+            filename: row.get(2)?,
+            content_type: row.get(3)?,
+            file_size: row.get(4)?,
+            data_encrypted: row.get(5)?,
+            checksum: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewAttachment {
+    /// Taken from the route, or the post being synced, when omitted.
+    #[serde(default)]
+    #[ts(type = "number")]
+    pub post_id: i64,
+    pub filename: String,
+    pub content_type: String,
+    #[ts(type = "number")]
+    pub file_size: i64,
+    pub data_encrypted: String,
+    pub checksum: String,
+}
+
+const FRIENDSHIP_COLUMNS: &str = "id, requester_id, addressee_id, status, created_at, updated_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum VerificationStatus {
+    Unverified,
+    Verified,
+    /// Verified once, but the friend's key has changed since. Show this
+    /// loudly: it is what a substituted key looks like.
+    KeyChanged,
+}
+
+/// Where one user stands on a friend's key.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FriendVerification {
+    #[ts(type = "number")]
+    pub friend_id: i64,
+    pub status: VerificationStatus,
+    pub public_key: Option<String>,
+    /// The key that was verified, if it differs from `public_key`.
+    pub verified_key: Option<String>,
+    pub verified_at: Option<String>,
+}
+
+impl FriendVerification {
+    fn new(friend: User, verification: Option<(String, String)>) -> FriendVerification {
+        let (status, verified_key, verified_at) = match verification {
+            Some((key, at)) if friend.public_key.as_deref() == Some(key.as_str()) => {
+                (VerificationStatus::Verified, None, Some(at))
+            }
+            Some((key, at)) => (VerificationStatus::KeyChanged, Some(key), Some(at)),
+            None => (VerificationStatus::Unverified, None, None),
+        };
+        FriendVerification {
+            friend_id: friend.id,
+            status,
+            public_key: friend.public_key,
+            verified_key,
+            verified_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Friendship {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub requester_id: i64,
+    #[ts(type = "number")]
+    pub addressee_id: i64,
+    pub status: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Friendship {
+    fn from_row(row: &Row) -> rusqlite::Result<Friendship> {
+        Ok(Friendship {
+            id: row.get(0)?,
+            requester_id: row.get(1)?,
+            addressee_id: row.get(2)?,
+            status: row
+                .get::<_, Option<String>>(3)?
+                .unwrap_or_else(|| "pending".to_string()),
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+        })
+    }
+}
+
+const CIRCLE_COLUMNS: &str = "id, user_id, name, public_id, epoch, created_at, updated_at";
+
+/// A group of the owner's friends that posts can be encrypted to once.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Circle {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub name: String,
+    /// Names the circle in keys and envelopes.
+    pub public_id: String,
+    /// Bumped on every membership change.
+    pub epoch: u32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Circle {
+    fn from_row(row: &Row) -> rusqlite::Result<Circle> {
+        Ok(Circle {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            name: row.get(2)?,
+            public_id: row.get(3)?,
+            epoch: row.get(4)?,
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewCircle {
+    #[ts(type = "number")]
+    pub user_id: i64,
+    pub name: String,
+    #[ts(type = "number[]")]
+    pub member_ids: Vec<i64>,
+}
+
+const MESSAGE_COLUMNS: &str =
+    "id, sender_id, recipient_id, content, encrypted_content, read_at, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Message {
+    #[ts(type = "number")]
+    pub id: i64,
+    #[ts(type = "number")]
+    pub sender_id: i64,
+    #[ts(type = "number")]
+    pub recipient_id: i64,
+    pub content: Option<String>,
+    pub encrypted_content: Option<String>,
+    pub read_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Message {
+    fn from_row(row: &Row) -> rusqlite::Result<Message> {
+        Ok(Message {
+            id: row.get(0)?,
+            sender_id: row.get(1)?,
+            recipient_id: row.get(2)?,
+            content: row.get(3)?,
+            encrypted_content: row.get(4)?,
+            read_at: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewMessage {
+    #[ts(type = "number")]
+    pub sender_id: i64,
+    #[ts(type = "number")]
+    pub recipient_id: i64,
+    pub content: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub encrypted_content: Option<String>,
+}
+
+const PEER_COLUMNS: &str =
+    "id, user_id, address, port, public_key, last_seen, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peer {
+    pub id: i64,
+    pub user_id: i64,
+    pub address: Option<String>,
+    pub port: Option<i64>,
+    pub public_key: Option<String>,
+    pub last_seen: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Peer {
+    fn from_row(row: &Row) -> rusqlite::Result<Peer> {
+        Ok(Peer {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            address: row.get(2)?,
+            port: row.get(3)?,
+            public_key: row.get(4)?,
+            last_seen: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPeer {
+    pub user_id: i64,
+    pub address: String,
+    pub port: i64,
+    pub public_key: String,
+}
+
+const SYNC_MESSAGE_COLUMNS: &str =
+    "id, user_id, peer_id, payload, message_type, status, processed_count, \
+     error_count, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncMessage {
+    pub id: i64,
+    pub user_id: i64,
+    pub peer_id: i64,
+    pub payload: Option<String>,
+    pub message_type: Option<String>,
+    pub status: Option<String>,
+    pub processed_count: Option<i64>,
+    pub error_count: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl SyncMessage {
+    fn from_row(row: &Row) -> rusqlite::Result<SyncMessage> {
+        Ok(SyncMessage {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            peer_id: row.get(2)?,
+            payload: row.get(3)?,
+            message_type: row.get(4)?,
+            status: row.get(5)?,
+            processed_count: row.get(6)?,
+            error_count: row.get(7)?,
+            created_at: row.get(8)?,
+            updated_at: row.get(9)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewSyncMessage {
+    pub user_id: i64,
+    pub peer_id: i64,
+    pub payload: String,
+    pub message_type: String,
+    pub status: String,
+    #[serde(default)]
+    pub processed_count: Option<i64>,
+    #[serde(default)]
+    pub error_count: Option<i64>,
+}
+
+/// Sync message types carrying a [`ShareGrant`] as the payload.
+pub const OUTBOUND_RECOVERY_SHARE: &str = "outbound_recovery_share";
+pub const INBOUND_RECOVERY_SHARE: &str = "inbound_recovery_share";
+
+/// Sync message types carrying a [`KeyEvent`] as the payload.
+pub const OUTBOUND_KEY_EVENT: &str = "outbound_key_event";
+pub const INBOUND_KEY_EVENT: &str = "inbound_key_event";
+
+/// Sync message types carrying a [`DeviceEvent`] as the payload.
+pub const OUTBOUND_DEVICE_EVENT: &str = "outbound_device_event";
+pub const INBOUND_DEVICE_EVENT: &str = "inbound_device_event";
+
+/// Sync message types carrying a [`PrekeyBundle`] as the payload.
+pub const OUTBOUND_PREKEY_BUNDLE: &str = "outbound_prekey_bundle";
+pub const INBOUND_PREKEY_BUNDLE: &str = "inbound_prekey_bundle";
+
+/// Sync message types carrying a [`CircleKey`] as the payload.
+pub const OUTBOUND_CIRCLE_KEY: &str = "outbound_circle_key";
+pub const INBOUND_CIRCLE_KEY: &str = "inbound_circle_key";
+
+/// Sync message type carrying a [`SealedEnvelope`], held with status
+/// `sealed` until opened.
+pub const INBOUND_SEALED: &str = "inbound_sealed";
+
+/// Payload of an `inbound_sync` message, as `MessageSyncService` builds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncBatch {
+    #[serde(default)]
+    pub messages: Vec<SyncedPost>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedPost {
+    #[serde(default)]
+    pub content_encrypted: Option<String>,
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Unix seconds.
+    pub timestamp: i64,
+    #[serde(default)]
+    pub attachments: Vec<NewAttachment>,
+}
@@ -0,0 +1,42 @@
+//! C entry points into the shared library, for the Rails app to load with
+//! Fiddle (see `app/services/content_hash.rb`). Strings cross as
+//! NUL-terminated UTF-8; results are allocated here and must be passed back
+//! to [`cipher_free_string`].
+
+use std::ffi::{c_char, CStr, CString};
+
+use crate::crypto::content_hash::PostContent;
+
+/// Hashes a post given as JSON [`PostContent`]. Returns the lowercase hex
+/// hash, or null if the input isn't valid.
+///
+/// # Safety
+///
+/// `json` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cipher_content_hash(json: *const c_char) -> *mut c_char {
+    if json.is_null() {
+        return std::ptr::null_mut();
+    }
+    let content = CStr::from_ptr(json)
+        .to_str()
+        .ok()
+        .and_then(|json| serde_json::from_str::<PostContent>(json).ok());
+    match content.and_then(|content| CString::new(content.hash()).ok()) {
+        Some(hash) => hash.into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+///
+/// `value` must be null or a pointer this library returned that hasn't
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cipher_free_string(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
@@ -1,3 +1,12 @@
+pub mod api;
+pub mod backend;
+pub mod commands;
+pub mod crypto;
+pub mod db;
+pub mod ffi;
+pub mod server;
+pub mod settings;
+
 #[cfg(mobile)]
 mod mobile;
 #[cfg(mobile)]
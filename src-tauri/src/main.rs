@@ -1,6 +1,16 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::{Path, PathBuf};
+
+use app::{
+    api::{self, ApiState},
+    backend::{self, BackendMode, BackendStatus, EncryptedLaunch},
+    commands,
+    db::{database_path, Database, SharedDatabase},
+    server,
+    settings::Settings,
+};
 use tauri::Manager;
 
 #[cfg(not(mobile))]
@@ -8,12 +18,6 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init::<tauri::Wry>())
         .setup(|app| {
-            // Start bundled Rails server for packaged builds only
-            let resource_dir = app
-                .path()
-                .resource_dir()
-                .expect("failed to resolve resource directory");
-
             let platform = if cfg!(target_os = "android") {
                 "android"
             } else if cfg!(target_os = "ios") {
@@ -22,248 +26,60 @@ fn main() {
                 "desktop"
             };
 
-            let mut candidate_roots: Vec<std::path::PathBuf> = vec![resource_dir.clone()];
-            candidate_roots.push(resource_dir.join("_up_"));
-            if let Ok(current_exe) = std::env::current_exe() {
-                if let Some(exe_dir) = current_exe.parent() {
-                    candidate_roots.push(exe_dir.join("../.."));
-                }
+            // Get app data directory for user-writable storage
+            let app_data_dir = app
+                .path()
+                .app_data_dir()
+                .expect("failed to resolve app data directory");
+
+            let settings = Settings::load(&app_data_dir);
+            let backend = BackendMode::resolve(
+                std::env::args().skip(1),
+                std::env::var("CIPHER_BACKEND").ok().as_deref(),
+                &settings,
+            );
+            println!("{} backend mode: {}", platform, backend);
+
+            // Rails, when it is the backend, comes from the bundled resources
+            let rails_root = if backend == BackendMode::Rails {
+                find_rails_root(app, platform)
+            } else {
+                None
+            };
+
+            if settings.encrypt_database {
+                return start_encrypted(app, backend, platform, &app_data_dir, &settings, rails_root);
             }
-            candidate_roots.push(std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".."));
 
-            let rails_root = candidate_roots
-                .into_iter()
-                .find(|root| root.join("bin/rails").exists());
+            // The native command API reads the same database file in either mode
+            let (db, db_path) = open_database(&app_data_dir, platform).unzip();
+            app.manage(ApiState::new(db));
+            api::start_auto_lock(app.handle().clone(), settings.auto_lock());
+
+            if backend == BackendMode::Embedded {
+                let shared_db = app.state::<ApiState>().shared_db();
+                let database_path = start_embedded_backend(shared_db, db_path, platform);
+                app.manage(BackendStatus {
+                    mode: backend,
+                    port: server::DEFAULT_PORT,
+                    database_path,
+                });
+                return Ok(());
+            }
 
-            println!(
-                "{} app resource directory: {:?}; rails root: {:?}",
-                platform, resource_dir, rails_root
-            );
+            app.manage(BackendStatus {
+                mode: backend,
+                port: server::DEFAULT_PORT,
+                database_path: None,
+            });
 
             if let Some(root) = rails_root {
-                // Get app data directory for user-writable storage
-                let app_data_dir = app
-                    .path()
-                    .app_data_dir()
-                    .expect("failed to resolve app data directory");
-
                 // Start Rails server in bundled directory (localhost-only for security)
                 std::thread::spawn(move || {
-                    // First, ensure app data and storage directories exist
-                    let storage_dir = app_data_dir.join("storage");
-                    if !storage_dir.exists() {
-                        if let Err(e) = std::fs::create_dir_all(&storage_dir) {
-                            println!("Failed to create storage directory: {}", e);
-                        } else {
-                            println!("Created storage directory at: {:?}", storage_dir);
-                        }
-                    }
-
                     // Set database path to app data directory
-                    let db_path = storage_dir.join(format!("{}.sqlite3", platform));
-                    std::env::set_var("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()));
-                    println!("Database path set to: {:?}", db_path);
-
-                    // Initialize the database with explicit steps
-                    println!("Initializing database for {} environment...", platform);
-
-                    // Step 1: Create database
-                    let db_create_command = if cfg!(target_os = "windows") {
-                        std::process::Command::new("cmd")
-                            .args(&[
-                                "/C",
-                                "ruby",
-                                "bin/rails",
-                                "db:create",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    } else {
-                        std::process::Command::new("ruby")
-                            .args(&[
-                                "bin/rails",
-                                "db:create",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    };
-
-                    match db_create_command {
-                        Ok(output) => {
-                            println!("Database create result: {}", output.status);
-                            if !output.stdout.is_empty() {
-                                println!("Create stdout: {}", String::from_utf8_lossy(&output.stdout));
-                            }
-                            if !output.stderr.is_empty() {
-                                println!("Create stderr: {}", String::from_utf8_lossy(&output.stderr));
-                            }
-                        }
-                        Err(e) => {
-                            println!("Failed to run db:create for {}: {}", platform, e);
-                        }
-                    }
-
-                    // Step 2: Load schema
-                    let db_schema_command = if cfg!(target_os = "windows") {
-                        std::process::Command::new("cmd")
-                            .args(&[
-                                "/C",
-                                "ruby",
-                                "bin/rails",
-                                "db:schema:load",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    } else {
-                        std::process::Command::new("ruby")
-                            .args(&[
-                                "bin/rails",
-                                "db:schema:load",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    };
-
-                    match db_schema_command {
-                        Ok(output) => {
-                            if output.status.success() {
-                                println!("Database schema loaded successfully for {}", platform);
-                            } else {
-                                println!("Schema load failed for {}: {}", platform, String::from_utf8_lossy(&output.stderr));
-                                // Fallback to migrations if schema load fails
-                                println!("Attempting fallback to db:migrate...");
-                                let db_migrate_command = if cfg!(target_os = "windows") {
-                                    std::process::Command::new("cmd")
-                                        .args(&[
-                                            "/C",
-                                            "ruby",
-                                            "bin/rails",
-                                            "db:migrate",
-                                        ])
-                                        .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                                        .current_dir(&root)
-                                        .output()
-                                } else {
-                                    std::process::Command::new("ruby")
-                                        .args(&[
-                                            "bin/rails",
-                                            "db:migrate",
-                                        ])
-                                        .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                                        .current_dir(&root)
-                                        .output()
-                                };
-
-                                match db_migrate_command {
-                                    Ok(migrate_output) => {
-                                        if migrate_output.status.success() {
-                                            println!("Database migrations completed successfully for {}", platform);
-                                        } else {
-                                            println!("Migration failed for {}: {}", platform, String::from_utf8_lossy(&migrate_output.stderr));
-                                        }
-                                    }
-                                    Err(e) => {
-                                        println!("Failed to run db:migrate for {}: {}", platform, e);
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("Failed to run db:schema:load for {}: {}", platform, e);
-                        }
-                    }
-
-                    // Verify database was properly initialized by checking for users table
-                    println!("Verifying database initialization for {}...", platform);
-                    let verify_command = if cfg!(target_os = "windows") {
-                        std::process::Command::new("cmd")
-                            .args(&[
-                                "/C",
-                                "ruby",
-                                "-e",
-                                "require_relative 'config/environment'; puts User.table_exists? ? 'Database verified' : 'Database missing tables'",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    } else {
-                        std::process::Command::new("ruby")
-                            .args(&[
-                                "-e",
-                                "require_relative 'config/environment'; puts User.table_exists? ? 'Database verified' : 'Database missing tables'",
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .output()
-                    };
-
-                    match verify_command {
-                        Ok(verify_output) => {
-                            let output_str = String::from_utf8_lossy(&verify_output.stdout);
-                            println!("Database verification result: {}", output_str.trim());
-                        }
-                        Err(e) => {
-                            println!("Failed to verify database for {}: {}", platform, e);
-                        }
-                    }
-
-                    // Now start the Rails server
-                    let rails_command = if cfg!(target_os = "windows") {
-                        std::process::Command::new("cmd")
-                            .args(&[
-                                "/C",
-                                "ruby",
-                                "bin/rails",
-                                "server",
-                                "-p",
-                                "3000",
-                                "-b",
-                                "127.0.0.1",
-                                "-e",
-                                platform,
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .spawn()
-                    } else {
-                        std::process::Command::new("ruby")
-                            .args(&[
-                                "bin/rails",
-                                "server",
-                                "-p",
-                                "3000",
-                                "-b",
-                                "127.0.0.1",
-                                "-e",
-                                platform,
-                            ])
-                            .env("RAILS_ENV", platform)
-                            .env("DATABASE_URL", format!("sqlite3://{}", db_path.to_string_lossy()))
-                            .current_dir(&root)
-                            .spawn()
-                    };
-
-                    match rails_command {
-                        Ok(child) => {
-                            println!("Rails server started successfully for {}", platform);
-                            let _ = child.wait_with_output();
-                        }
-                        Err(e) => {
-                            println!("Failed to start Rails server for {}: {}", platform, e);
-                        }
+                    match database_path(&app_data_dir, platform) {
+                        Ok(db_path) => backend::launch_rails(&root, platform, &db_path, None),
+                        Err(e) => println!("Failed to create storage directory: {}", e),
                     }
                 });
 
@@ -278,7 +94,122 @@ fn main() {
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![])
+        .invoke_handler(commands::handler())
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Looks for the Rails app among the bundled resources, then beside the
+/// executable and the source tree.
+#[cfg(not(mobile))]
+fn find_rails_root(app: &tauri::App, platform: &str) -> Option<PathBuf> {
+    let resource_dir = app
+        .path()
+        .resource_dir()
+        .expect("failed to resolve resource directory");
+
+    let mut candidate_roots: Vec<std::path::PathBuf> = vec![resource_dir.clone()];
+    candidate_roots.push(resource_dir.join("_up_"));
+    if let Ok(current_exe) = std::env::current_exe() {
+        if let Some(exe_dir) = current_exe.parent() {
+            candidate_roots.push(exe_dir.join("../.."));
+        }
+    }
+    candidate_roots.push(std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".."));
+
+    let rails_root = candidate_roots
+        .into_iter()
+        .find(|root| root.join("bin/rails").exists());
+
+    println!(
+        "{} app resource directory: {:?}; rails root: {:?}",
+        platform, resource_dir, rails_root
+    );
+    rails_root
+}
+
+/// Starts without a database when it is encrypted at rest. The embedded
+/// server serves the pages so the keystore can be unlocked; unlocking opens
+/// the database and, in Rails mode, hands the port to Rails with the key.
+#[cfg(not(mobile))]
+fn start_encrypted(
+    app: &mut tauri::App,
+    backend: BackendMode,
+    platform: &str,
+    app_data_dir: &Path,
+    settings: &Settings,
+    rails_root: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = database_path(app_data_dir, platform)?;
+    println!("Encrypted database at {:?} opens when the keystore is unlocked", db_path);
+
+    app.manage(ApiState::new(None));
+    api::start_auto_lock(app.handle().clone(), settings.auto_lock());
+
+    let shared_db = app.state::<ApiState>().shared_db();
+    let server = match server::start_local_embedded_server(server::DEFAULT_PORT, shared_db) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            println!("Failed to start embedded server for {}: {}", platform, e);
+            None
+        }
+    };
+    if backend == BackendMode::Rails && rails_root.is_none() {
+        println!(
+            "Skipping bundled Rails launch for {} (bin/rails not found in bundled resources)",
+            platform
+        );
+    }
+    app.manage(EncryptedLaunch {
+        mode: backend,
+        platform: platform.to_string(),
+        database_path: db_path.clone(),
+        rails_root,
+        server,
+    });
+    app.manage(BackendStatus {
+        mode: backend,
+        port: server::DEFAULT_PORT,
+        database_path: Some(db_path),
+    });
+    Ok(())
+}
+
+/// Opens `storage/<platform>.sqlite3` in the app data directory, the same
+/// file Rails is pointed at through `DATABASE_URL`.
+#[cfg(not(mobile))]
+fn open_database(app_data_dir: &Path, platform: &str) -> Option<(Database, PathBuf)> {
+    let opened = database_path(app_data_dir, platform)
+        .map_err(|e| e.to_string())
+        .and_then(|db_path| {
+            println!("Database path set to: {:?}", db_path);
+            Database::open(&db_path)
+                .map(|db| (db, db_path))
+                .map_err(|e| e.to_string())
+        });
+
+    match opened {
+        Ok(opened) => Some(opened),
+        Err(e) => {
+            println!("Failed to open database for {}: {}", platform, e);
+            None
+        }
+    }
+}
+
+/// Serves the app from the Rust embedded server instead of spawning Rails, so
+/// the desktop build runs without a Ruby install. Returns the database path if
+/// the embedded API is available.
+#[cfg(not(mobile))]
+fn start_embedded_backend(
+    db: SharedDatabase,
+    db_path: Option<PathBuf>,
+    platform: &str,
+) -> Option<PathBuf> {
+    match server::start_local_embedded_server(server::DEFAULT_PORT, db) {
+        Ok(_) => println!("Embedded server started successfully for {}", platform),
+        Err(e) => println!("Failed to start embedded server for {}: {}", platform, e),
+    }
+
+    db_path
+}
@@ -0,0 +1,225 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use subtle::ConstantTimeEq;
+
+use super::{api_token, Request, Response, API_TOKEN_HEADER};
+use crate::db::{
+    Database, Error, NewAttachment, NewMessage, NewPeer, NewPost, NewSyncMessage, NewUser,
+};
+
+/// JSON routes served from SQLite when running without Rails. Paths follow
+/// `config/routes.rb` where an equivalent Rails endpoint exists.
+pub(super) fn route(request: &Request, db: &Database) -> Response {
+    let segments: Vec<&str> = request
+        .path
+        .trim_start_matches("/api/v1/")
+        .trim_end_matches('/')
+        .split('/')
+        .collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["users"]) => ok(db.list_users()),
+        ("POST", ["users"]) => with_body(request, |new_user: NewUser| {
+            created(db.register_user(&new_user))
+        }),
+        ("POST", ["users", "by_public_key"]) => by_public_key(request, db),
+        ("GET", ["users", id]) => with_id(id, |id| found(db.find_user(id), "User not found")),
+        ("GET", ["users", id, "friends"]) => with_id(id, |id| ok(db.friends_of(id))),
+        ("GET", ["users", id, "posts"]) => with_id(id, |id| ok(db.posts_for_user(id))),
+
+        ("GET", ["posts"]) => match param_id(request, "user_id") {
+            Some(user_id) => ok(db.feed_for_user(user_id, 100)),
+            None => bad_request("user_id required"),
+        },
+        ("POST", ["posts"]) => with_body(request, |new_post: NewPost| {
+            created(db.ingest_post(&new_post))
+        }),
+        ("GET", ["posts", id]) => with_id(id, |id| show_post(db, id)),
+        ("POST", ["posts", id, "attachments"]) => with_id(id, |post_id| {
+            with_body(request, |mut new_attachment: NewAttachment| {
+                new_attachment.post_id = post_id;
+                created(db.create_attachment(&new_attachment).map_err(Error::from))
+            })
+        }),
+        ("GET", ["content", hash, "exists"]) => match db.post_exists_with_hash(hash) {
+            Ok(exists) => Response::json(200, &json!({ "exists": exists })),
+            Err(e) => internal_error(e),
+        },
+
+        ("GET", ["friends"]) => match param_id(request, "user_id") {
+            Some(user_id) => ok(db.friendships_for_user(user_id)),
+            None => bad_request("user_id required"),
+        },
+        ("POST", ["friends", "send_request"]) => send_friend_request(request, db),
+        ("POST", ["friends", "respond_to_request"]) => respond_to_friend_request(request, db),
+
+        ("GET", ["messages"]) => match (param_id(request, "user_id"), param_id(request, "with")) {
+            (Some(user_id), Some(other_id)) => ok(db.messages_between(user_id, other_id)),
+            _ => bad_request("user_id and with required"),
+        },
+        ("POST", ["messages"]) => with_body(request, |new_message: NewMessage| {
+            created(db.send_message(&new_message))
+        }),
+
+        ("GET", ["peers"]) => match param_id(request, "user_id") {
+            Some(user_id) => ok(db.peers_for_user(user_id)),
+            None => bad_request("user_id required"),
+        },
+        ("POST", ["peers"]) => with_body(request, |new_peer: NewPeer| {
+            created(db.record_peer(&new_peer))
+        }),
+
+        ("POST", ["sync"]) => with_body(request, |new_sync_message: NewSyncMessage| {
+            created(db.receive_sync(&new_sync_message))
+        }),
+
+        _ => Response::json(404, &json!({ "error": "Not found" })),
+    }
+}
+
+/// Refuses requests a page on another site could have made: from a foreign
+/// `Origin`, without the launch's [`api_token`], or with a body that isn't
+/// declared as JSON.
+pub(super) fn authorize(request: &Request) -> Result<(), Response> {
+    if !request.is_same_origin() {
+        return Err(forbidden("Cross-origin requests are not allowed"));
+    }
+    let token = request.header(API_TOKEN_HEADER).unwrap_or_default();
+    if !bool::from(token.as_bytes().ct_eq(api_token().as_bytes())) {
+        return Err(forbidden("Missing or invalid API token"));
+    }
+    if request.method != "GET" && !request.is_json() {
+        return Err(Response::json(
+            415,
+            &json!({ "error": "Content-Type must be application/json" }),
+        ));
+    }
+    Ok(())
+}
+
+fn by_public_key(request: &Request, db: &Database) -> Response {
+    let public_key = request
+        .json()
+        .and_then(|body| body["public_key"].as_str().map(str::to_string))
+        .or_else(|| request.query_param("public_key"));
+
+    match public_key {
+        Some(public_key) if !public_key.is_empty() => {
+            found(db.find_user_by_public_key(&public_key), "User not found")
+        }
+        _ => bad_request("public_key required"),
+    }
+}
+
+fn show_post(db: &Database, id: i64) -> Response {
+    let post = match db.find_post(id) {
+        Ok(Some(post)) => post,
+        Ok(None) => return not_found("Post not found"),
+        Err(e) => return internal_error(e),
+    };
+    match db.attachments_for_post(id) {
+        Ok(attachments) => {
+            Response::json(200, &json!({ "post": post, "attachments": attachments }))
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+fn send_friend_request(request: &Request, db: &Database) -> Response {
+    let body = request.json().unwrap_or_default();
+    let (Some(requester_id), Some(addressee_id)) =
+        (body["requester_id"].as_i64(), body["addressee_id"].as_i64())
+    else {
+        return bad_request("requester_id and addressee_id required");
+    };
+    created(db.request_friendship(requester_id, addressee_id))
+}
+
+fn respond_to_friend_request(request: &Request, db: &Database) -> Response {
+    let body = request.json().unwrap_or_default();
+    let (Some(friendship_id), Some(status)) =
+        (body["friendship_id"].as_i64(), body["status"].as_str())
+    else {
+        return bad_request("friendship_id and status required");
+    };
+    match db.respond_to_friendship(friendship_id, status) {
+        Ok(friendship) => Response::json(200, &json!(friendship)),
+        Err(e) => error_response(e),
+    }
+}
+
+fn param_id(request: &Request, name: &str) -> Option<i64> {
+    request
+        .query_param(name)
+        .and_then(|value| value.parse().ok())
+}
+
+fn with_id(id: &str, handler: impl FnOnce(i64) -> Response) -> Response {
+    match id.parse() {
+        Ok(id) => handler(id),
+        Err(_) => not_found("Not found"),
+    }
+}
+
+fn with_body<T: DeserializeOwned>(
+    request: &Request,
+    handler: impl FnOnce(T) -> Response,
+) -> Response {
+    match serde_json::from_slice(&request.body) {
+        Ok(body) => handler(body),
+        Err(e) => bad_request(&format!("Invalid request body: {}", e)),
+    }
+}
+
+fn ok<T: Serialize>(result: rusqlite::Result<T>) -> Response {
+    match result {
+        Ok(value) => Response::json(200, &json!(value)),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn created<T: Serialize>(result: Result<T, Error>) -> Response {
+    match result {
+        Ok(value) => Response::json(201, &json!(value)),
+        Err(e) => error_response(e),
+    }
+}
+
+fn found<T: Serialize>(result: rusqlite::Result<Option<T>>, missing: &str) -> Response {
+    match result {
+        Ok(Some(value)) => Response::json(200, &json!(value)),
+        Ok(None) => not_found(missing),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn error_response(error: Error) -> Response {
+    match error {
+        Error::Invalid(message) => unprocessable(&message),
+        Error::NotFound(message) => not_found(message),
+        Error::Sqlite(e) => internal_error(e),
+        Error::Io(e) => internal_error(e),
+    }
+}
+
+fn bad_request(error: &str) -> Response {
+    Response::json(400, &json!({ "error": error }))
+}
+
+fn forbidden(error: &str) -> Response {
+    Response::json(403, &json!({ "error": error }))
+}
+
+fn not_found(error: &str) -> Response {
+    Response::json(404, &json!({ "error": error }))
+}
+
+fn unprocessable(error: &str) -> Response {
+    Response::json(422, &json!({ "error": error }))
+}
+
+fn internal_error(error: impl std::fmt::Display) -> Response {
+    println!("Embedded API database error: {}", error);
+    Response::json(500, &json!({ "error": error.to_string() }))
+}
@@ -0,0 +1,175 @@
+use std::io::{self, Write};
+
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+
+use super::Response;
+
+/// Bodies smaller than this are sent as-is; the gzip framing would eat most
+/// of the saving.
+pub const MIN_COMPRESS_BYTES: usize = 1024;
+
+/// Bodies larger than this are compressed straight onto the socket with
+/// chunked transfer encoding instead of being buffered first.
+pub const STREAM_THRESHOLD: usize = 64 * 1024;
+
+const STREAM_SLICE_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// HTTP `deflate`, i.e. zlib-wrapped DEFLATE (RFC 1950).
+    Deflate,
+}
+
+impl Encoding {
+    /// Picks an encoding from an `Accept-Encoding` header, honouring q-values
+    /// and preferring gzip when both are equally acceptable.
+    pub fn negotiate(accept_encoding: Option<&str>) -> Option<Encoding> {
+        let mut gzip = None;
+        let mut deflate = None;
+        let mut wildcard = None;
+
+        for entry in accept_encoding?.split(',') {
+            let mut parts = entry.split(';');
+            let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match coding.as_str() {
+                "gzip" | "x-gzip" => gzip = Some(quality),
+                "deflate" => deflate = Some(quality),
+                "*" => wildcard = Some(quality),
+                _ => {}
+            }
+        }
+
+        let gzip = gzip.or(wildcard).unwrap_or(0.0);
+        let deflate = deflate.or(wildcard).unwrap_or(0.0);
+        if gzip > 0.0 && gzip >= deflate {
+            Some(Encoding::Gzip)
+        } else if deflate > 0.0 {
+            Some(Encoding::Deflate)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encoder<W: Write>(&self, out: W) -> Encoder<W> {
+        match self {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(out, Compression::default())),
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(out, Compression::default())),
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Deflate(ZlibEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Deflate(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Deflate(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Text-like content that compresses well. Images and other binary assets
+/// are already compressed.
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/javascript" | "image/svg+xml"
+        )
+}
+
+/// Writes `response` to `out`, compressed if the client accepts it and the
+/// body is text above [`MIN_COMPRESS_BYTES`].
+pub fn write_response(
+    out: &mut impl Write,
+    response: &Response,
+    accept_encoding: Option<&str>,
+) -> io::Result<()> {
+    let encoding = Encoding::negotiate(accept_encoding).filter(|_| {
+        response.body.len() >= MIN_COMPRESS_BYTES && is_compressible(response.content_type)
+    });
+    let Some(encoding) = encoding else {
+        return out.write_all(&response.to_bytes());
+    };
+
+    let encoding_headers = format!(
+        "Content-Encoding: {}\r\nVary: Accept-Encoding\r\n",
+        encoding.as_str()
+    );
+
+    if response.body.len() <= STREAM_THRESHOLD {
+        let mut encoder = encoding.encoder(Vec::new());
+        encoder.write_all(&response.body)?;
+        let compressed = encoder.finish()?;
+        let headers = format!(
+            "{}Content-Length: {}\r\n",
+            encoding_headers,
+            compressed.len()
+        );
+        out.write_all(response.head(&headers).as_bytes())?;
+        return out.write_all(&compressed);
+    }
+
+    let headers = format!("{}Transfer-Encoding: chunked\r\n", encoding_headers);
+    out.write_all(response.head(&headers).as_bytes())?;
+    let mut encoder = encoding.encoder(Chunked(&mut *out));
+    for slice in response.body.chunks(STREAM_SLICE_BYTES) {
+        encoder.write_all(slice)?;
+    }
+    encoder.finish()?;
+    out.write_all(b"0\r\n\r\n")
+}
+
+/// Frames everything written to it as HTTP/1.1 chunks.
+struct Chunked<W: Write>(W);
+
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            write!(self.0, "{:x}\r\n", buf.len())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
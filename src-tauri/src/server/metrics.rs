@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{Request, Response};
+
+/// Path of the Prometheus endpoint. Only served by debug builds.
+pub const METRICS_PATH: &str = "/__metrics";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const SIZE_BUCKETS: &[f64] = &[
+    256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0,
+];
+
+/// Request counters and histograms for the embedded server, kept in memory
+/// for the life of the process.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: BTreeMap<(String, String, u16), u64>,
+    latency: BTreeMap<String, Histogram>,
+    size: BTreeMap<String, Histogram>,
+}
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, route: &str) {
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                name, route, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+            name, route, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{route=\"{}\"}} {}", name, route, self.sum);
+        let _ = writeln!(out, "{}_count{{route=\"{}\"}} {}", name, route, self.count);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn record(&self, method: &str, path: &str, status: u16, bytes: usize, latency: Duration) {
+        let route = route_label(path);
+        let method = if method.bytes().all(|b| b.is_ascii_uppercase()) {
+            method
+        } else {
+            "OTHER"
+        };
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        *inner
+            .requests
+            .entry((method.to_string(), route.clone(), status))
+            .or_default() += 1;
+        inner
+            .latency
+            .entry(route.clone())
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(latency.as_secs_f64());
+        inner
+            .size
+            .entry(route)
+            .or_insert_with(|| Histogram::new(SIZE_BUCKETS))
+            .observe(bytes as f64);
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP cipher_http_requests_total Requests handled by the embedded server.\n",
+        );
+        out.push_str("# TYPE cipher_http_requests_total counter\n");
+        for ((method, route, status), count) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "cipher_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method, route, status, count
+            );
+        }
+
+        out.push_str(
+            "# HELP cipher_http_request_duration_seconds Time spent routing and rendering a request.\n",
+        );
+        out.push_str("# TYPE cipher_http_request_duration_seconds histogram\n");
+        for (route, histogram) in &inner.latency {
+            histogram.render(&mut out, "cipher_http_request_duration_seconds", route);
+        }
+
+        out.push_str("# HELP cipher_http_response_size_bytes Size of response bodies.\n");
+        out.push_str("# TYPE cipher_http_response_size_bytes histogram\n");
+        for (route, histogram) in &inner.size {
+            histogram.render(&mut out, "cipher_http_response_size_bytes", route);
+        }
+
+        out
+    }
+
+    pub fn response(&self) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: self.render().into_bytes(),
+        }
+    }
+}
+
+/// Access-log middleware: runs `handler`, prints one line per request and
+/// records it in `metrics`.
+pub fn observe(
+    metrics: &Metrics,
+    request: &Request,
+    handler: impl FnOnce(&Request) -> Response,
+) -> Response {
+    let started = Instant::now();
+    let response = handler(request);
+    let latency = started.elapsed();
+
+    println!(
+        "{} {} {} {}B {:.1}ms",
+        request.method,
+        request.path,
+        response.status,
+        response.body.len(),
+        latency.as_secs_f64() * 1000.0
+    );
+    metrics.record(
+        &request.method,
+        &request.path,
+        response.status,
+        response.body.len(),
+        latency,
+    );
+    response
+}
+
+/// Collapses a request path to its route so ids and hashes don't each get
+/// their own time series, e.g. `/api/v1/users/42/posts` becomes
+/// `/api/v1/users/:id/posts`.
+pub fn route_label(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("/api/v1/") {
+        let mut label = String::from("/api/v1");
+        let mut previous = "";
+        for segment in rest.trim_end_matches('/').split('/') {
+            label.push('/');
+            if previous == "content" {
+                label.push_str(":hash");
+            } else if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+                label.push_str(":id");
+            } else if segment
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            {
+                label.push_str(segment);
+            } else {
+                // Keeps quotes and other junk out of the label value
+                label.push_str(":param");
+            }
+            previous = segment;
+        }
+        return label;
+    }
+    if path.starts_with("/assets/") {
+        return "/assets/*".to_string();
+    }
+    match path {
+        "/"
+        | "/up"
+        | "/users/new"
+        | "/users/sign_up"
+        | "/users/sign_in"
+        | "/users/local_hosting"
+        | "/users/host_dashboard"
+        | METRICS_PATH => path.to_string(),
+        _ => "other".to_string(),
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    io::{self, prelude::*},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::db::{Database, SharedDatabase};
+
+mod api;
+pub mod compression;
+pub mod metrics;
+mod pages;
+
+pub use metrics::{Metrics, METRICS_PATH};
+
+/// Port shared by the Rails server and the embedded server, so the webview URL
+/// in `tauri.conf.json` works for either backend.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Upper bound on request bodies accepted by the embedded server.
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Header that `/api/v1` requests carry [`api_token`] in.
+pub const API_TOKEN_HEADER: &str = "x-cipher-token";
+
+/// Origins of the Tauri webview itself, besides pages served from here.
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+/// A random token made once per launch. The app's pages carry it in a
+/// `cipher-api-token` meta tag and send it back in [`API_TOKEN_HEADER`]; a
+/// page on another site can't read it, and can't send the header without a
+/// CORS preflight this server never approves.
+pub fn api_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        URL_SAFE_NO_PAD.encode(bytes)
+    })
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads a single HTTP/1.1 request (headers plus `Content-Length` body).
+    pub fn read_from(stream: &mut impl Read) -> io::Result<Request> {
+        let mut raw = Vec::new();
+        let mut buffer = [0; 4096];
+
+        let header_end = loop {
+            let size = stream.read(&mut buffer)?;
+            if size == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed",
+                ));
+            }
+            raw.extend_from_slice(&buffer[..size]);
+            if let Some(pos) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos;
+            }
+            if raw.len() > 64 * 1024 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "headers too large",
+                ));
+            }
+        };
+
+        let mut request = Request::parse_head(&String::from_utf8_lossy(&raw[..header_end]))?;
+        let content_length = request
+            .header("content-length")
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(0);
+        if content_length > MAX_BODY_BYTES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "body too large"));
+        }
+
+        let mut body = raw[header_end + 4..].to_vec();
+        while body.len() < content_length {
+            let size = stream.read(&mut buffer)?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&buffer[..size]);
+        }
+        body.truncate(content_length);
+        request.body = body;
+
+        Ok(request)
+    }
+
+    fn parse_head(head: &str) -> io::Result<Request> {
+        let mut lines = head.lines();
+        let request_line = lines.next().unwrap_or("");
+        let parts: Vec<&str> = request_line.split_whitespace().collect();
+        if parts.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Malformed request line",
+            ));
+        }
+
+        let (path, query) = match parts[1].split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (parts[1].to_string(), String::new()),
+        };
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        Ok(Request {
+            method: parts[0].to_string(),
+            path,
+            query,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    /// Looks up a header by its lowercase name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| percent_decode(key) == name)
+            .map(|(_, value)| percent_decode(value))
+    }
+
+    pub fn json(&self) -> Option<serde_json::Value> {
+        serde_json::from_slice(&self.body).ok()
+    }
+
+    /// Whether the body is declared as JSON. Browsers only send that type
+    /// cross-site after a preflight.
+    pub fn is_json(&self) -> bool {
+        self.header("content-type").is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case("application/json")
+        })
+    }
+
+    /// Whether `Host` names the loopback interface. A page that rebinds its
+    /// own domain to 127.0.0.1 still sends that domain.
+    pub fn is_local_host(&self) -> bool {
+        self.header("host").is_some_and(is_loopback_authority)
+    }
+
+    /// Whether the request comes from the app's own pages. Requests without
+    /// an `Origin` are not from a cross-site page and pass.
+    pub fn is_same_origin(&self) -> bool {
+        match self.header("origin") {
+            None => true,
+            Some(origin) => {
+                APP_ORIGINS.contains(&origin)
+                    || origin
+                        .strip_prefix("http://")
+                        .is_some_and(is_loopback_authority)
+            }
+        }
+    }
+}
+
+/// `127.0.0.1`, `localhost` or `[::1]`, with or without a port.
+fn is_loopback_authority(authority: &str) -> bool {
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    matches!(host, "127.0.0.1" | "localhost" | "[::1]")
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn html(body: &str) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/html",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn text(status: u16, body: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn json(status: u16, value: &serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self
+            .head(&format!("Content-Length: {}\r\n", self.body.len()))
+            .into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Status line and headers; `headers` is inserted as-is and must end in
+    /// `\r\n` if not empty.
+    fn head(&self, headers: &str) -> String {
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n{}Connection: close\r\n\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            headers
+        )
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Entity",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A running embedded server.
+pub struct ServerHandle {
+    port: u16,
+    stopped: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ServerHandle {
+    /// Stops accepting connections and frees the port, so Rails can take it
+    /// over. Requests already being handled finish.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        let thread = self
+            .thread
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Starts the embedded HTTP server on `127.0.0.1:<port>`.
+///
+/// Until the database is set only the static Cipher pages are served; then
+/// the `/api/v1` routes read and write the same SQLite schema as the Rails
+/// app.
+pub fn start_local_embedded_server(port: u16, db: SharedDatabase) -> io::Result<ServerHandle> {
+    println!("Starting local embedded server on localhost:{}", port);
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Successfully bound to 127.0.0.1:{}", port);
+
+    let metrics = Arc::new(Metrics::new());
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stopping = Arc::clone(&stopped);
+    let thread = std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stopping.load(Ordering::SeqCst) {
+                println!("Stopped local embedded server on localhost:{}", port);
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let db = Arc::clone(&db);
+                    let metrics = Arc::clone(&metrics);
+                    std::thread::spawn(move || handle_connection(stream, db.get(), &metrics));
+                }
+                Err(e) => {
+                    println!("Failed to accept local connection: {}", e);
+                }
+            }
+        }
+    });
+
+    // Give server time to start
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    Ok(ServerHandle {
+        port,
+        stopped,
+        thread: Mutex::new(Some(thread)),
+    })
+}
+
+fn handle_connection(mut stream: TcpStream, db: Option<&Database>, metrics: &Metrics) {
+    let (response, accept_encoding) = match Request::read_from(&mut stream) {
+        Ok(request) => (
+            handle(&request, db, metrics),
+            request.header("accept-encoding").map(str::to_string),
+        ),
+        Err(e) => {
+            println!("Failed to read local request: {}", e);
+            (pages::error_response(&e.to_string()), None)
+        }
+    };
+
+    if let Err(e) = compression::write_response(&mut stream, &response, accept_encoding.as_deref())
+    {
+        println!("Failed to write response: {}", e);
+    }
+    let _ = stream.flush();
+}
+
+/// Routes a request through the access log and metrics. Requests for another
+/// host are refused before anything else, `/__metrics` included. `/__metrics`
+/// is only served by debug builds; release builds answer it with a 404.
+pub fn handle(request: &Request, db: Option<&Database>, metrics: &Metrics) -> Response {
+    metrics::observe(metrics, request, |request| {
+        if !request.is_local_host() {
+            return Response::text(403, "Forbidden");
+        }
+        if request.path == METRICS_PATH {
+            return if cfg!(debug_assertions) {
+                metrics.response()
+            } else {
+                Response::text(404, "Not Found")
+            };
+        }
+        route(request, db)
+    })
+}
+
+/// Dispatches a request to the page or API handler for its path. `/api/v1`
+/// requests must pass [`api::authorize`]; [`handle`] has already refused
+/// requests for another host.
+pub fn route(request: &Request, db: Option<&Database>) -> Response {
+    let path = request.path.as_str();
+
+    if path.starts_with("/api/v1/") {
+        if let Err(response) = api::authorize(request) {
+            return response;
+        }
+        return match db {
+            Some(db) => api::route(request, db),
+            None => Response::json(
+                503,
+                &serde_json::json!({ "error": "Embedded database is not available" }),
+            ),
+        };
+    }
+
+    // Serve different responses based on path
+    let response = match path {
+        "/" => pages::cipher_home_response(),
+        "/up" => Response::text(200, "OK"),
+        "/users/new" => pages::signup_form_response(),
+        "/users/sign_up" => pages::signup_form_response(),
+        "/users/sign_in" => pages::signin_form_response(),
+        "/users/local_hosting" => pages::local_hosting_response(),
+        "/users/host_dashboard" => pages::host_dashboard_response(),
+        "/users/recovery" => pages::recovery_response(),
+        _ if path.starts_with("/assets/") => pages::serve_asset_response(path),
+        _ => pages::cipher_home_response(), // Default to home for now
+    };
+    pages::with_api_token(response)
+}
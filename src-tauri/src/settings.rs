@@ -0,0 +1,72 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::backend::BackendMode;
+use crate::crypto::kdf::Argon2Params;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Idle time before the keystore locks itself when the setting is unset.
+pub const DEFAULT_AUTO_LOCK_SECONDS: u32 = 5 * 60;
+
+/// User-editable launcher settings stored as `settings.json` in the app data
+/// directory. Unknown or missing fields fall back to their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct Settings {
+    /// Backend to start when neither `--backend` nor `CIPHER_BACKEND` is given.
+    #[ts(optional = nullable)]
+    pub backend: Option<BackendMode>,
+    /// Argon2id cost for new and migrated accounts; defaults when unset.
+    #[ts(optional = nullable)]
+    pub kdf: Option<Argon2Params>,
+    /// Give PBKDF2 accounts a new key on migration instead of keeping the
+    /// old one under Argon2id.
+    pub rotate_legacy_keys: bool,
+    /// Seconds without key use before the keystore locks; `0` disables
+    /// auto-lock. Defaults to [`DEFAULT_AUTO_LOCK_SECONDS`] when unset.
+    #[ts(optional = nullable)]
+    pub auto_lock_seconds: Option<u32>,
+    /// Keep the database encrypted at rest with SQLCipher, under a key kept
+    /// in the keystore. The database opens when the keystore is unlocked.
+    /// Needs a build with the `sqlcipher` feature; applies from next launch.
+    pub encrypt_database: bool,
+}
+
+impl Settings {
+    pub fn path(app_data_dir: &Path) -> PathBuf {
+        app_data_dir.join(SETTINGS_FILE)
+    }
+
+    pub fn auto_lock(&self) -> Option<Duration> {
+        match self.auto_lock_seconds.unwrap_or(DEFAULT_AUTO_LOCK_SECONDS) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds.into())),
+        }
+    }
+
+    /// Loads settings, returning defaults if the file is missing or unreadable.
+    pub fn load(app_data_dir: &Path) -> Settings {
+        let path = Settings::path(app_data_dir);
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                println!("Ignoring invalid settings file {:?}: {}", path, e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self, app_data_dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(app_data_dir)?;
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(Settings::path(app_data_dir), contents)
+    }
+}
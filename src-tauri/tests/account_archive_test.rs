@@ -0,0 +1,405 @@
+mod common;
+
+use std::path::Path;
+
+use app::api::v1::archive::{self, ArchivedPeer, ConflictKind, ConflictPolicy};
+use app::api::ApiError;
+use app::crypto::{
+    account::{AccountRecord, ACCOUNTS_DIR},
+    archive::SealedArchive,
+    keystore::Keystore,
+    secret::Secret,
+    signed_post::{format_timestamp, SignedPost},
+    Identity,
+};
+use app::db::{Database, NewMessage, NewPost};
+use app::settings::Settings;
+
+use common::{open_device, register, temp_dir, PARAMS};
+
+struct Exported {
+    alice: Identity,
+    bob: Identity,
+    sealed: SealedArchive,
+}
+
+/// Alice with a friend, a signed post, a forged post and a forward-secret
+/// message, exported under "archive passphrase".
+fn export_alice(dir: &Path) -> Exported {
+    let (db, mut keystore) = open_device(dir);
+    let (record, alice) = AccountRecord::create("alice", "alice password", PARAMS).unwrap();
+    record.save(&dir.join(ACCOUNTS_DIR)).unwrap();
+    keystore.set_identity("alice", &alice);
+    let bob = Identity::from_seed(&[2; 32]);
+    let alice_id = register(&db, "alice", &alice);
+    let bob_id = register(&db, "bob", &bob);
+
+    let friendship = db.send_friend_request(alice_id, bob_id).unwrap();
+    db.update_friendship_status(friendship.id, "accepted")
+        .unwrap();
+    db.upsert_key_verification(alice_id, bob_id, &bob.public_key())
+        .unwrap();
+
+    let signed = SignedPost::new(&alice.public_key(), "hello", vec![], 1_700_000_000).unwrap();
+    db.ingest_post(&NewPost {
+        user_id: alice_id,
+        content_encrypted: Some("hello".to_string()),
+        signature: Some(signed.sign(&alice).unwrap()),
+        timestamp: Some(format_timestamp(1_700_000_000)),
+        ..NewPost::default()
+    })
+    .unwrap();
+    db.create_post(&NewPost {
+        user_id: alice_id,
+        content_encrypted: Some("forged".to_string()),
+        signature: Some(bob.sign_base64(&signed.canonical_bytes())),
+        timestamp: Some(format_timestamp(1_700_000_000)),
+        ..NewPost::default()
+    })
+    .unwrap();
+
+    let message = db
+        .create_message(&NewMessage {
+            sender_id: alice_id,
+            recipient_id: bob_id,
+            content: String::new(),
+            encrypted_content: Some("ratchet ciphertext".to_string()),
+        })
+        .unwrap();
+    keystore.set_message_text("alice", message.id, b"see you soon");
+
+    let settings = Settings {
+        rotate_legacy_keys: true,
+        ..Settings::default()
+    };
+    settings.save(dir).unwrap();
+
+    let (manifest, secrets) = archive::export(&db, dir, &alice, &keystore, 1_700_000_100).unwrap();
+    let sealed = archive::seal(&alice, "archive passphrase", PARAMS, &manifest, secrets).unwrap();
+    Exported { alice, bob, sealed }
+}
+
+fn import(
+    db: &Database,
+    dir: &Path,
+    keystore: &mut Keystore,
+    sealed: &SealedArchive,
+    policy: ConflictPolicy,
+) -> archive::ImportReport {
+    let (manifest, secrets) = archive::open(sealed, "archive passphrase").unwrap();
+    archive::import(db, dir, keystore, &manifest, &secrets, policy, true).unwrap()
+}
+
+#[test]
+fn test_archive_moves_an_account_to_a_new_device() {
+    let source = temp_dir("archive-source");
+    let exported = export_alice(&source);
+    let path = source.join("alice.cipherarchive");
+    exported.sealed.save(&path).unwrap();
+    let sealed = SealedArchive::load(&path).unwrap();
+    assert_eq!(sealed, exported.sealed);
+
+    // Nothing readable without the passphrase
+    let file = std::fs::read_to_string(&path).unwrap();
+    assert!(!file.contains("see you soon") && !file.contains("hello"));
+
+    let target = temp_dir("archive-target");
+    let (db, mut keystore) = open_device(&target);
+    let report = import(&db, &target, &mut keystore, &sealed, ConflictPolicy::Abort);
+    assert!(report.imported, "{:?}", report.conflicts);
+    assert_eq!(report.archive.username, "alice");
+    assert_eq!(
+        (
+            report.added_contacts,
+            report.added_posts,
+            report.added_messages
+        ),
+        (1, 1, 1)
+    );
+    assert_eq!(report.skipped_posts, 1, "the forged post stays behind");
+
+    let alice = db
+        .find_user_by_public_key(&exported.alice.public_key())
+        .unwrap()
+        .unwrap();
+    let bob = db
+        .find_user_by_public_key(&exported.bob.public_key())
+        .unwrap()
+        .unwrap();
+    let friendships = db.friendships_for_user(alice.id).unwrap();
+    assert_eq!(friendships.len(), 1);
+    assert_eq!(friendships[0].status, "accepted");
+    assert!(db
+        .find_key_verification(alice.id, bob.id)
+        .unwrap()
+        .is_some());
+    let posts = db.posts_for_user(alice.id).unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].content_encrypted.as_deref(), Some("hello"));
+
+    let messages = db.messages_for_user(alice.id).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        keystore.message_text("alice", messages[0].id),
+        Some(&b"see you soon"[..])
+    );
+    let identity = keystore.identity("alice").unwrap().unwrap();
+    assert_eq!(identity.public_key(), exported.alice.public_key());
+    assert!(AccountRecord::load(&target.join(ACCOUNTS_DIR), "alice")
+        .unwrap()
+        .is_some());
+    assert!(Settings::load(&target).rotate_legacy_keys);
+
+    // The keystore was saved with the account's secrets in it
+    let reopened = Keystore::unlock(&Keystore::path(&target), "device password").unwrap();
+    assert!(reopened.identity("alice").is_some());
+
+    // Importing again adds nothing
+    let again = import(&db, &target, &mut keystore, &sealed, ConflictPolicy::Abort);
+    assert!(again.imported && again.conflicts.is_empty());
+    assert_eq!(
+        (
+            again.added_contacts,
+            again.added_posts,
+            again.added_messages
+        ),
+        (0, 0, 0)
+    );
+    assert_eq!(db.posts_for_user(alice.id).unwrap().len(), 1);
+}
+
+#[test]
+fn test_archive_needs_its_passphrase_and_signature() {
+    let exported = export_alice(&temp_dir("archive-tamper"));
+
+    let wrong = archive::open(&exported.sealed, "not the passphrase").err();
+    assert!(
+        matches!(&wrong, Some(ApiError::Invalid(message)) if message.contains("passphrase")),
+        "{:?}",
+        wrong
+    );
+
+    let mut tampered = exported.sealed.clone();
+    tampered.created_at += 1;
+    assert!(tampered.verify().is_err());
+    assert!(archive::open(&tampered, "archive passphrase").is_err());
+
+    // Re-signing by another key doesn't pass for the account in the archive
+    let mut resigned = exported.sealed.clone();
+    resigned.public_key = exported.bob.public_key();
+    resigned.signature = exported.bob.sign_base64(&resigned.signing_bytes());
+    assert!(resigned.verify().is_ok());
+    assert!(archive::open(&resigned, "archive passphrase").is_err());
+}
+
+#[test]
+fn test_conflicts_follow_the_chosen_policy() {
+    let exported = export_alice(&temp_dir("archive-conflicts"));
+
+    // Settings differ: resolvable
+    let target = temp_dir("archive-conflicts-settings");
+    let (db, mut keystore) = open_device(&target);
+    Settings::default().save(&target).unwrap();
+    let report = import(
+        &db,
+        &target,
+        &mut keystore,
+        &exported.sealed,
+        ConflictPolicy::Abort,
+    );
+    assert!(!report.imported);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].kind, ConflictKind::Settings);
+    assert!(db.find_user_by_username("alice").unwrap().is_none());
+
+    let report = import(
+        &db,
+        &target,
+        &mut keystore,
+        &exported.sealed,
+        ConflictPolicy::KeepLocal,
+    );
+    assert!(report.imported);
+    assert!(!Settings::load(&target).rotate_legacy_keys);
+    let report = import(
+        &db,
+        &target,
+        &mut keystore,
+        &exported.sealed,
+        ConflictPolicy::UseArchive,
+    );
+    assert!(report.imported);
+    assert!(Settings::load(&target).rotate_legacy_keys);
+
+    // Someone else is "bob" here: only keeping local data gets past it
+    let target = temp_dir("archive-conflicts-contact");
+    let (db, mut keystore) = open_device(&target);
+    register(&db, "bob", &Identity::from_seed(&[9; 32]));
+    let report = import(
+        &db,
+        &target,
+        &mut keystore,
+        &exported.sealed,
+        ConflictPolicy::UseArchive,
+    );
+    assert!(!report.imported);
+    assert_eq!(report.conflicts[0].kind, ConflictKind::Contact);
+    assert!(!report.conflicts[0].resolvable);
+
+    let report = import(
+        &db,
+        &target,
+        &mut keystore,
+        &exported.sealed,
+        ConflictPolicy::KeepLocal,
+    );
+    assert!(report.imported);
+    assert_eq!((report.added_contacts, report.added_messages), (0, 0));
+    assert!(db
+        .find_user_by_public_key(&exported.bob.public_key())
+        .unwrap()
+        .is_none());
+    let alice = db.find_user_by_username("alice").unwrap().unwrap();
+    assert!(db.friendships_for_user(alice.id).unwrap().is_empty());
+    assert_eq!(db.posts_for_user(alice.id).unwrap().len(), 1);
+
+    // A different "alice" here can't take this archive at all
+    let target = temp_dir("archive-conflicts-account");
+    let (db, mut keystore) = open_device(&target);
+    register(&db, "alice", &Identity::from_seed(&[7; 32]));
+    let (manifest, secrets) = archive::open(&exported.sealed, "archive passphrase").unwrap();
+    let inspect = archive::import(
+        &db,
+        &target,
+        &mut keystore,
+        &manifest,
+        &secrets,
+        ConflictPolicy::UseArchive,
+        false,
+    );
+    assert!(matches!(inspect, Err(ApiError::Invalid(_))));
+
+    // A keystore entry that differs is reported, and kept unless asked
+    let target = temp_dir("archive-conflicts-keystore");
+    let (db, mut keystore) = open_device(&target);
+    let name = Keystore::identity_entry("alice");
+    keystore.insert(&name, b"something else");
+    let report = archive::import(
+        &db,
+        &target,
+        &mut keystore,
+        &manifest,
+        &secrets,
+        ConflictPolicy::Abort,
+        true,
+    )
+    .unwrap();
+    assert!(!report.imported);
+    assert!(report
+        .conflicts
+        .iter()
+        .any(|conflict| conflict.kind == ConflictKind::Keystore && conflict.subject == name));
+    archive::import(
+        &db,
+        &target,
+        &mut keystore,
+        &manifest,
+        &secrets,
+        ConflictPolicy::UseArchive,
+        true,
+    )
+    .unwrap();
+    assert_eq!(
+        keystore.get(&name),
+        Some(secrets[&name].expose().as_slice())
+    );
+}
+
+#[test]
+fn test_archive_entries_for_other_accounts_are_refused() {
+    let exported = export_alice(&temp_dir("archive-foreign"));
+    let target = temp_dir("archive-foreign-target");
+    let (db, mut keystore) = open_device(&target);
+    let carol = Identity::from_seed(&[3; 32]);
+    keystore.set_identity("carol", &carol);
+    keystore.set_database_key("desktop", &Secret::copy_of(&[5; 32]));
+    let (manifest, secrets) = archive::open(&exported.sealed, "archive passphrase").unwrap();
+
+    // Another account's seed, or this device's database key, would be
+    // overwritten under UseArchive
+    for name in [
+        Keystore::identity_entry("carol"),
+        "database/desktop".to_string(),
+    ] {
+        let mut crafted = secrets.clone();
+        crafted.insert(name.clone(), Secret::new(vec![6; 32]));
+        let result = archive::import(
+            &db,
+            &target,
+            &mut keystore,
+            &manifest,
+            &crafted,
+            ConflictPolicy::UseArchive,
+            true,
+        );
+        assert!(
+            matches!(&result, Err(ApiError::Invalid(message)) if message.contains(&name)),
+            "{:?}",
+            result.map(|report| report.imported)
+        );
+    }
+    assert!(db.find_user_by_username("alice").unwrap().is_none());
+    assert_eq!(
+        keystore.identity("carol").unwrap().unwrap().public_key(),
+        carol.public_key()
+    );
+    assert_eq!(
+        keystore.database_key("desktop").unwrap().unwrap(),
+        Secret::copy_of(&[5; 32])
+    );
+}
+
+#[test]
+fn test_failed_import_leaves_nothing_behind() {
+    let exported = export_alice(&temp_dir("archive-partial"));
+    let target = temp_dir("archive-partial-target");
+    let (db, mut keystore) = open_device(&target);
+    let (manifest, secrets) = archive::open(&exported.sealed, "archive passphrase").unwrap();
+
+    // Rejected only after the users and friendship are written
+    let mut broken = manifest.clone();
+    broken.peers.push(ArchivedPeer {
+        address: "10.0.0.2".to_string(),
+        port: 0,
+        public_key: exported.bob.public_key(),
+    });
+    let result = archive::import(
+        &db,
+        &target,
+        &mut keystore,
+        &broken,
+        &secrets,
+        ConflictPolicy::Abort,
+        true,
+    );
+    assert!(matches!(result, Err(ApiError::Invalid(_))));
+    assert!(db.list_users().unwrap().is_empty());
+    assert!(keystore.identity("alice").is_none());
+    assert!(AccountRecord::load(&target.join(ACCOUNTS_DIR), "alice")
+        .unwrap()
+        .is_none());
+    assert!(!Settings::path(&target).exists());
+
+    let report = archive::import(
+        &db,
+        &target,
+        &mut keystore,
+        &manifest,
+        &secrets,
+        ConflictPolicy::Abort,
+        true,
+    )
+    .unwrap();
+    assert!(report.imported);
+    assert_eq!(report.added_contacts, 1);
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use std::path::Path;
+
+use app::api::v1::circles::{self, CircleMemberRequest, EncryptCircleRequest};
+use app::crypto::{
+    circle::{CircleEnvelope, CircleKey, CircleKeys, EpochChange},
+    keystore::Keystore,
+    Error, Identity,
+};
+use app::db::{
+    Database, NewCircle, NewPeer, NewSyncMessage, NewUser, INBOUND_CIRCLE_KEY, OUTBOUND_CIRCLE_KEY,
+};
+
+use common::{key, temp_dir, text, PARAMS};
+
+#[test]
+fn test_membership_changes_move_the_circle_to_new_keys() {
+    let (alice, bob, carol, dave) = (key(1), key(2), key(3), key(4));
+    let mut keys = CircleKeys::generate();
+    let mut held: Vec<CircleKey> = keys
+        .distribute(&alice, &[bob.public_key(), carol.public_key()])
+        .unwrap();
+    assert_eq!(held.len(), 2);
+    assert!(held
+        .iter()
+        .all(|key| key.verify().is_ok() && key.epoch == 1));
+    let first = keys.seal(&alice, b"first").unwrap();
+    assert_eq!(text(first.open_with(&held, &bob).unwrap()), "first");
+    assert_eq!(text(first.open_with(&held, &carol).unwrap()), "first");
+    assert_eq!(first.open_with(&held, &dave), Err(Error::NotRecipient));
+
+    // Adding dave hashes the key forward; only he gets a new key
+    assert_eq!(keys.rotate(EpochChange::Advanced), 2);
+    held.extend(keys.distribute(&alice, &[dave.public_key()]).unwrap());
+    let second = keys.seal(&alice, b"second").unwrap();
+    for member in [&bob, &carol, &dave] {
+        assert_eq!(text(second.open_with(&held, member).unwrap()), "second");
+    }
+    assert_eq!(
+        first.open_with(&held, &dave),
+        Err(Error::NotRecipient),
+        "new members can't read older posts"
+    );
+
+    // Removing carol starts a fresh key for the rest
+    assert_eq!(keys.rotate(EpochChange::Reset), 3);
+    held.extend(
+        keys.distribute(&alice, &[bob.public_key(), dave.public_key()])
+            .unwrap(),
+    );
+    let third = keys.seal(&alice, b"third").unwrap();
+    assert_eq!(text(third.open_with(&held, &bob).unwrap()), "third");
+    assert_eq!(text(third.open_with(&held, &dave).unwrap()), "third");
+    assert_eq!(third.open_with(&held, &carol), Err(Error::NotRecipient));
+    assert_eq!(text(second.open_with(&held, &carol).unwrap()), "second");
+
+    // The owner reads every epoch, including after a round trip
+    let keys = CircleKeys::from_bytes(&keys.to_bytes()).unwrap();
+    for (envelope, expected) in [(&first, "first"), (&second, "second"), (&third, "third")] {
+        assert_eq!(text(keys.open(envelope).unwrap()), expected);
+    }
+    assert_eq!(CircleEnvelope::from_json(&third.to_json()).unwrap(), third);
+
+    // Relabelled envelopes and forged keys don't open
+    let mut relabelled = second.clone();
+    relabelled.epoch = 3;
+    assert!(relabelled.open_with(&held, &bob).is_err());
+    assert!(keys.open(&relabelled).is_err());
+    let mut forged = held.last().unwrap().clone();
+    forged.epoch = 4;
+    assert_eq!(forged.verify(), Err(Error::BadSignature));
+    let mut future = third.clone();
+    future.epoch = 4;
+    assert!(future.open_with(&[forged], &dave).is_err());
+}
+
+struct Account {
+    identity: Identity,
+    keystore: Keystore,
+    user_id: i64,
+}
+
+fn account(db: &Database, dir: &Path, username: &str, seed: u8) -> Account {
+    let identity = key(seed);
+    let user_id = db
+        .register_user(&NewUser {
+            public_key: identity.public_key(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    Account {
+        identity,
+        keystore: Keystore::create(&dir.join(username), "pw", PARAMS).unwrap(),
+        user_id,
+    }
+}
+
+#[test]
+fn test_circle_keys_travel_over_sync_and_follow_membership() {
+    let db = Database::open_in_memory().unwrap();
+    let dir = temp_dir("circles");
+    let mut alice = account(&db, &dir, "alice", 1);
+    let mut bob = account(&db, &dir, "bob", 2);
+    let mut carol = account(&db, &dir, "carol", 3);
+    let stranger = account(&db, &dir, "stranger", 4);
+    for friend in [&bob, &carol] {
+        let friendship = db
+            .request_friendship(alice.user_id, friend.user_id)
+            .unwrap();
+        db.respond_to_friendship(friendship.id, "accepted").unwrap();
+    }
+    let peer = |user: &Account, other: &Account| {
+        db.record_peer(&NewPeer {
+            user_id: user.user_id,
+            address: format!("10.0.0.{}", other.user_id),
+            port: 4000,
+            public_key: other.identity.public_key(),
+        })
+        .unwrap()
+        .id
+    };
+    peer(&alice, &bob);
+    peer(&alice, &carol);
+    let bob_sees_alice = peer(&bob, &alice);
+    let carol_sees_alice = peer(&carol, &alice);
+
+    let (alice_id, bob_id, carol_id) = (alice.user_id, bob.user_id, carol.user_id);
+    let bob_key = bob.identity.public_key();
+    let new_circle = |member_ids: Vec<i64>| NewCircle {
+        user_id: alice_id,
+        name: "Close friends".to_string(),
+        member_ids,
+    };
+    let create = |alice: &mut Account, member_ids| {
+        circles::create(
+            &db,
+            &alice.identity,
+            &mut alice.keystore,
+            &new_circle(member_ids),
+        )
+    };
+    assert!(create(&mut alice, vec![stranger.user_id]).is_err());
+    let circle = create(&mut alice, vec![bob.user_id, carol.user_id]).unwrap();
+    assert_eq!(circle.epoch, 1);
+    assert_eq!(db.circle_members(circle.id).unwrap().len(), 2);
+
+    // Each member's key goes to their own peer, and is only held by them
+    let deliver_keys = |circle_keys: &mut Vec<i64>| {
+        for message in db.pending_sync_messages(alice_id).unwrap() {
+            if message.message_type.as_deref() != Some(OUTBOUND_CIRCLE_KEY)
+                || circle_keys.contains(&message.id)
+            {
+                continue;
+            }
+            circle_keys.push(message.id);
+            let payload = message.payload.unwrap();
+            let key: CircleKey = serde_json::from_str(&payload).unwrap();
+            let (member, peer_id, other) = if key.recipient_key() == bob_key {
+                (bob_id, bob_sees_alice, carol_id)
+            } else {
+                (carol_id, carol_sees_alice, bob_id)
+            };
+            let receive = |user_id| {
+                db.receive_sync(&NewSyncMessage {
+                    user_id,
+                    peer_id,
+                    payload: payload.clone(),
+                    message_type: INBOUND_CIRCLE_KEY.to_string(),
+                    status: "pending".to_string(),
+                    processed_count: None,
+                    error_count: None,
+                })
+            };
+            assert!(receive(other).is_err());
+            assert_eq!(receive(member).unwrap().status.as_deref(), Some("held"));
+        }
+    };
+    let mut delivered = Vec::new();
+    deliver_keys(&mut delivered);
+    assert_eq!(delivered.len(), 2);
+
+    let post = |alice: &mut Account, content: &str| {
+        let request = EncryptCircleRequest {
+            circle_id: circle.id,
+            content: content.to_string(),
+        };
+        circles::encrypt(&db, &alice.identity, &mut alice.keystore, &request)
+            .unwrap()
+            .content_encrypted
+    };
+    let read = |reader: &mut Account, content_encrypted: &str| {
+        circles::decrypt(
+            &db,
+            &reader.identity,
+            &mut reader.keystore,
+            content_encrypted,
+        )
+    };
+    let before = post(&mut alice, "before");
+    assert!(!before.contains("before"));
+    assert_eq!(read(&mut bob, &before).unwrap(), "before");
+    assert_eq!(read(&mut carol, &before).unwrap(), "before");
+    assert_eq!(read(&mut alice, &before).unwrap(), "before");
+
+    let member = |user_id| CircleMemberRequest {
+        circle_id: circle.id,
+        user_id,
+    };
+    let removed = circles::remove_member(
+        &db,
+        &alice.identity,
+        &mut alice.keystore,
+        &member(carol.user_id),
+    )
+    .unwrap();
+    assert_eq!(removed.epoch, 2);
+    deliver_keys(&mut delivered);
+    assert_eq!(delivered.len(), 3, "only bob gets the new key");
+
+    let after = post(&mut alice, "after");
+    assert_eq!(read(&mut bob, &after).unwrap(), "after");
+    assert!(read(&mut carol, &after).is_err());
+    assert_eq!(read(&mut carol, &before).unwrap(), "before");
+
+    // Carol comes back at a hashed-forward epoch without needing bob to hear
+    let added = circles::add_member(
+        &db,
+        &alice.identity,
+        &mut alice.keystore,
+        &member(carol.user_id),
+    )
+    .unwrap();
+    assert_eq!(added.epoch, 3);
+    assert!(circles::add_member(
+        &db,
+        &alice.identity,
+        &mut alice.keystore,
+        &member(carol.user_id)
+    )
+    .is_err());
+    deliver_keys(&mut delivered);
+    assert_eq!(delivered.len(), 4);
+    let again = post(&mut alice, "again");
+    assert_eq!(read(&mut bob, &again).unwrap(), "again");
+    assert_eq!(read(&mut carol, &again).unwrap(), "again");
+    assert!(read(&mut carol, &after).is_err());
+
+    // The owner's keys survive the keystore being locked
+    alice.keystore = Keystore::unlock(&dir.join("alice"), "pw").unwrap();
+    assert_eq!(read(&mut alice, &after).unwrap(), "after");
+}
@@ -0,0 +1,81 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use app::api::{self, ApiError};
+use app::db::{self, Database, NewMessage, NewUser};
+
+#[test]
+fn test_generate_typescript_bindings() {
+    let commands = api::commands();
+    let mut names = HashSet::new();
+    for command in &commands {
+        assert!(
+            names.insert(command.name),
+            "duplicate command {}",
+            command.name
+        );
+        assert!(
+            command.name.starts_with("v1_") || command.name.starts_with("get_"),
+            "{} is not versioned",
+            command.name
+        );
+    }
+
+    let bindings = api::typescript_bindings();
+    assert!(bindings.contains("export const API_VERSION = \"v1\";"));
+    assert!(bindings.contains("import type { NewPost } from \"./NewPost\";"));
+    assert!(bindings.contains(
+        "export function v1PostsCreate(request: NewPost): Promise<Post> {\n  return invoke(\"v1_posts_create\", { request });\n}"
+    ));
+    assert!(bindings.contains("export function getDeviceInfo(): Promise<DeviceInfo> {"));
+    assert!(bindings.contains("Promise<User | null>"));
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("bindings");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("commands.ts"), bindings).unwrap();
+}
+
+#[test]
+fn test_api_errors_serialize_for_invoke() {
+    let db = Database::open_in_memory().unwrap();
+    let alice = NewUser {
+        public_key: "alice-key".to_string(),
+        username: "alice".to_string(),
+        display_name: None,
+        email: None,
+    };
+    let alice_id = db.register_user(&alice).unwrap().id;
+
+    let duplicate = ApiError::from(db.register_user(&alice).unwrap_err());
+    assert!(matches!(duplicate, ApiError::Invalid(_)));
+
+    let to_self = db
+        .send_message(&NewMessage {
+            sender_id: alice_id,
+            recipient_id: alice_id,
+            content: "hi".to_string(),
+            encrypted_content: None,
+        })
+        .unwrap_err();
+    assert_eq!(
+        serde_json::to_value(ApiError::from(to_self)).unwrap(),
+        serde_json::json!({
+            "kind": "invalid",
+            "message": "Recipient can't be the same as sender"
+        })
+    );
+
+    let missing = ApiError::from(db.respond_to_friendship(42, "accepted").unwrap_err());
+    assert_eq!(
+        missing,
+        ApiError::NotFound("Friendship not found".to_string())
+    );
+
+    let unavailable = api::ApiState::default().db().err().expect("no database");
+    assert_eq!(
+        serde_json::to_value(unavailable).unwrap()["kind"],
+        "unavailable"
+    );
+
+    let invalid_status = db.respond_to_friendship(1, "maybe").unwrap_err();
+    assert!(matches!(invalid_status, db::Error::Invalid(_)));
+}
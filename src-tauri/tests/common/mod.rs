@@ -0,0 +1,79 @@
+//! Helpers shared by the integration tests. Each test binary compiles its
+//! own copy and uses only some of them.
+#![allow(dead_code)]
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use app::crypto::{kdf::Argon2Params, keystore::Keystore, Identity};
+use app::db::{Database, NewUser};
+
+/// Cheap enough for debug-build tests; production uses the defaults.
+pub const PARAMS: Argon2Params = Argon2Params {
+    memory_kib: Argon2Params::MIN_MEMORY_KIB,
+    iterations: 1,
+    parallelism: 1,
+};
+
+/// An empty directory under the system temp dir, deleted when dropped.
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A fresh [`TempDir`] named for the test, so parallel tests don't share one.
+pub fn temp_dir(name: &str) -> TempDir {
+    let dir = std::env::temp_dir().join(format!("cipher-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn unhex(value: &str) -> Vec<u8> {
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// The identity for a one-byte seed, so tests can name keys 1, 2, 3.
+pub fn key(seed: u8) -> Identity {
+    Identity::from_seed(&[seed; 32])
+}
+
+pub fn text(plaintext: impl AsRef<[u8]>) -> String {
+    String::from_utf8(plaintext.as_ref().to_vec()).unwrap()
+}
+
+pub fn register(db: &Database, username: &str, identity: &Identity) -> i64 {
+    db.register_user(&NewUser {
+        public_key: identity.public_key(),
+        username: username.to_string(),
+        display_name: None,
+        email: None,
+    })
+    .unwrap()
+    .id
+}
+
+/// A device's database and keystore in `dir`, unlocked with "device password".
+pub fn open_device(dir: &Path) -> (Database, Keystore) {
+    let db = Database::open(&dir.join("desktop.sqlite3")).unwrap();
+    let keystore = Keystore::create(&Keystore::path(dir), "device password", PARAMS).unwrap();
+    (db, keystore)
+}
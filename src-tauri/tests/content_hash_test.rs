@@ -0,0 +1,133 @@
+mod common;
+
+use std::ffi::{CStr, CString};
+
+use app::crypto::{
+    content_hash::PostContent,
+    signed_post::{format_timestamp, SignedPost},
+    Identity,
+};
+use app::db::{Database, NewPost, NewUser};
+use app::ffi::{cipher_content_hash, cipher_free_string};
+use serde::Deserialize;
+
+use common::hex;
+
+#[derive(Deserialize)]
+struct Vectors {
+    version: u32,
+    vectors: Vec<Vector>,
+}
+
+#[derive(Deserialize)]
+struct Vector {
+    name: String,
+    input: PostContent,
+    canonical_hex: String,
+    hash: String,
+    legacy_hash: String,
+}
+
+fn vectors() -> Vectors {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../test-vectors/content_hash.json"
+    );
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn native_hash(json: &str) -> Option<String> {
+    let json = CString::new(json).unwrap();
+    unsafe {
+        let hash = cipher_content_hash(json.as_ptr());
+        if hash.is_null() {
+            return None;
+        }
+        let value = CStr::from_ptr(hash).to_str().unwrap().to_string();
+        cipher_free_string(hash);
+        Some(value)
+    }
+}
+
+#[test]
+fn test_content_hash_matches_published_vectors() {
+    let vectors = vectors();
+    assert_eq!(vectors.version, PostContent::VERSION);
+    for vector in &vectors.vectors {
+        let content = &vector.input;
+        assert_eq!(
+            hex(&content.canonical_bytes()),
+            vector.canonical_hex,
+            "{}",
+            vector.name
+        );
+        assert_eq!(content.hash(), vector.hash, "{}", vector.name);
+        assert_eq!(content.legacy_hash(), vector.legacy_hash, "{}", vector.name);
+        assert!(content.matches(&vector.hash) && content.matches(&vector.legacy_hash));
+
+        let json = serde_json::to_string(content).unwrap();
+        assert_eq!(native_hash(&json).as_deref(), Some(vector.hash.as_str()));
+    }
+
+    // Posts the old concatenation couldn't tell apart hash differently
+    let [.., first, second] = vectors.vectors.as_slice() else {
+        panic!("missing collision vectors");
+    };
+    assert_eq!(first.legacy_hash, second.legacy_hash);
+    assert_ne!(first.hash, second.hash);
+    assert!(!first.input.matches(&second.hash));
+}
+
+#[test]
+fn test_native_entry_point_rejects_bad_input() {
+    assert_eq!(native_hash("not json"), None);
+    assert_eq!(native_hash(r#"{"content_encrypted":"x"}"#), None);
+    assert!(native_hash(r#"{"timestamp":0}"#).is_some());
+    assert!(unsafe { cipher_content_hash(std::ptr::null()) }.is_null());
+    unsafe { cipher_free_string(std::ptr::null_mut()) };
+}
+
+#[test]
+fn test_ingested_posts_get_the_canonical_hash() {
+    let db = Database::open_in_memory().unwrap();
+    let author = Identity::from_seed(&[1; 32]);
+    let user_id = db
+        .register_user(&NewUser {
+            public_key: author.public_key(),
+            username: "alice".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    let checksum = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    let signed = SignedPost::new(
+        &author.public_key(),
+        "ciphertext",
+        vec![checksum.to_string()],
+        1_700_000_000,
+    )
+    .unwrap();
+    let signature = signed.sign(&author).unwrap();
+    let post = db
+        .ingest_post(&NewPost {
+            user_id,
+            content_encrypted: Some("ciphertext".to_string()),
+            signature: Some(signature.clone()),
+            timestamp: Some(format_timestamp(1_700_000_000)),
+            content_hash: Some("whatever the client sent".to_string()),
+            attachment_checksums: Some(vec![checksum.to_string()]),
+            ..NewPost::default()
+        })
+        .unwrap();
+
+    let expected = PostContent {
+        content_encrypted: "ciphertext".to_string(),
+        timestamp: 1_700_000_000,
+        signature,
+        attachment_checksums: vec![checksum.to_string()],
+    }
+    .hash();
+    assert_eq!(post.content_hash.as_deref(), Some(expected.as_str()));
+    assert!(db.post_exists_with_hash(&expected).unwrap());
+}
@@ -0,0 +1,192 @@
+//! The fixtures in `test-vectors/` were produced with libsodium, which RbNaCl
+//! wraps, and `test/services/crypto_vectors_test.rb` checks the Rails models
+//! against the same files. A change here that breaks them no longer
+//! interoperates with the Rails backend.
+
+mod common;
+
+use app::crypto::{
+    identity::{self, LEGACY_PBKDF2_ITERATIONS},
+    kdf::{pbkdf2_seed, KdfDescriptor},
+    secretbox, Error, Identity,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use common::{hex, unhex};
+
+#[derive(Deserialize)]
+struct Vectors<T> {
+    vectors: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct KdfVector {
+    name: String,
+    username: String,
+    password: String,
+    iterations: u32,
+    seed_hex: String,
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct SignatureVector {
+    name: String,
+    seed_hex: String,
+    public_key: String,
+    message_hex: String,
+    signature: String,
+    valid: bool,
+}
+
+#[derive(Deserialize)]
+struct SecretBoxVector {
+    name: String,
+    key_hex: String,
+    nonce_hex: String,
+    plaintext_hex: String,
+    ciphertext_hex: String,
+}
+
+#[derive(Deserialize)]
+struct SimpleBoxVector {
+    name: String,
+    key_hex: String,
+    plaintext_hex: String,
+    boxed_hex: String,
+    dev_owner_key: String,
+    data_encrypted: String,
+}
+
+fn vectors<T: DeserializeOwned>(file: &str) -> Vec<T> {
+    let path = format!("{}/../test-vectors/{}", env!("CARGO_MANIFEST_DIR"), file);
+    let vectors: Vectors<T> =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert!(!vectors.vectors.is_empty(), "{} has no vectors", file);
+    vectors.vectors
+}
+
+#[test]
+fn test_kdf_matches_rails_key_derivation() {
+    let vectors: Vec<KdfVector> = vectors("kdf.json");
+    for vector in &vectors {
+        let seed = pbkdf2_seed(&vector.username, &vector.password, vector.iterations);
+        assert_eq!(hex(seed.as_ref()), vector.seed_hex, "{}", vector.name);
+        assert_eq!(
+            Identity::from_seed(&seed).public_key(),
+            vector.public_key,
+            "{}",
+            vector.name
+        );
+
+        if vector.iterations == LEGACY_PBKDF2_ITERATIONS {
+            let identity = Identity::from_legacy_credentials(&vector.username, &vector.password);
+            assert_eq!(identity.public_key(), vector.public_key, "{}", vector.name);
+            let derived = KdfDescriptor::legacy()
+                .derive(&vector.username, &vector.password)
+                .unwrap();
+            assert_eq!(hex(derived.as_ref()), vector.seed_hex, "{}", vector.name);
+        }
+    }
+    assert!(vectors
+        .iter()
+        .any(|vector| vector.iterations == LEGACY_PBKDF2_ITERATIONS));
+}
+
+#[test]
+fn test_signatures_interoperate_with_rbnacl() {
+    let vectors: Vec<SignatureVector> = vectors("signatures.json");
+    for vector in &vectors {
+        let message = unhex(&vector.message_hex);
+        let verified = identity::verify(&vector.public_key, &message, &vector.signature);
+        assert_eq!(verified.is_ok(), vector.valid, "{}", vector.name);
+        if !vector.valid {
+            assert_eq!(verified, Err(Error::BadSignature), "{}", vector.name);
+            continue;
+        }
+
+        // Ed25519 is deterministic, so signing here reproduces RbNaCl's bytes
+        let seed: [u8; 32] = unhex(&vector.seed_hex).try_into().unwrap();
+        let identity = Identity::from_seed(&seed);
+        assert_eq!(identity.public_key(), vector.public_key, "{}", vector.name);
+        assert_eq!(
+            identity.sign_base64(&message),
+            vector.signature,
+            "{}",
+            vector.name
+        );
+    }
+    assert!(vectors.iter().any(|vector| !vector.valid));
+}
+
+#[test]
+fn test_secretbox_matches_rbnacl() {
+    for vector in vectors::<SecretBoxVector>("secretbox.json") {
+        let key = unhex(&vector.key_hex);
+        let nonce: [u8; 24] = unhex(&vector.nonce_hex).try_into().unwrap();
+        let plaintext = unhex(&vector.plaintext_hex);
+        let ciphertext = unhex(&vector.ciphertext_hex);
+
+        assert_eq!(
+            secretbox::seal(&key, &nonce, &plaintext).unwrap(),
+            ciphertext,
+            "{}",
+            vector.name
+        );
+        assert_eq!(
+            *secretbox::open(&key, &nonce, &ciphertext).unwrap(),
+            plaintext,
+            "{}",
+            vector.name
+        );
+
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            secretbox::open(&key, &nonce, &tampered).unwrap_err(),
+            Error::DecryptionFailed
+        );
+        assert_eq!(
+            secretbox::open(&key[1..], &nonce, &ciphertext).unwrap_err(),
+            Error::InvalidKey
+        );
+    }
+}
+
+#[test]
+fn test_simplebox_opens_rails_attachments() {
+    for vector in vectors::<SimpleBoxVector>("simplebox.json") {
+        let key = unhex(&vector.key_hex);
+        let plaintext = unhex(&vector.plaintext_hex);
+        let boxed = unhex(&vector.boxed_hex);
+
+        // Stored the way Attachment#encrypt_data leaves them
+        let stored_key = secretbox::decode_ruby_base64(&vector.dev_owner_key, "key").unwrap();
+        let stored_data =
+            secretbox::decode_ruby_base64(&vector.data_encrypted, "data_encrypted").unwrap();
+        assert_eq!(stored_key, key, "{}", vector.name);
+        assert_eq!(stored_data, boxed, "{}", vector.name);
+
+        assert_eq!(
+            *secretbox::simple_open(&key, &boxed).unwrap(),
+            plaintext,
+            "{}",
+            vector.name
+        );
+
+        let sealed = secretbox::simple_seal(&key, &plaintext).unwrap();
+        assert_eq!(sealed.len(), boxed.len());
+        assert_ne!(sealed, boxed);
+        assert_eq!(*secretbox::simple_open(&key, &sealed).unwrap(), plaintext);
+
+        assert_eq!(
+            secretbox::simple_open(&key, &boxed[..boxed.len() - 1]).unwrap_err(),
+            if plaintext.is_empty() {
+                Error::Malformed("simple box")
+            } else {
+                Error::DecryptionFailed
+            }
+        );
+    }
+}
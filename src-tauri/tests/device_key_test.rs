@@ -0,0 +1,259 @@
+mod common;
+
+use app::api::v1::devices;
+use app::crypto::{
+    device::{DeviceCapability, DeviceCertificate, DeviceEvent, DeviceRevocation},
+    signed_post::{format_timestamp, SignedPost},
+    Error, Identity,
+};
+use app::db::{
+    Database, NewPeer, NewPost, NewSyncMessage, INBOUND_DEVICE_EVENT, OUTBOUND_DEVICE_EVENT,
+};
+
+use common::{key, register};
+
+fn certificate(
+    root: &Identity,
+    device: &Identity,
+    capabilities: &[DeviceCapability],
+    at: i64,
+) -> DeviceEvent {
+    DeviceEvent::Certificate(DeviceCertificate::sign(
+        root,
+        &device.public_key(),
+        "Laptop",
+        capabilities,
+        at,
+    ))
+}
+
+fn revocation(
+    signer: &Identity,
+    root: &Identity,
+    device: &Identity,
+    revoked_at: i64,
+    at: i64,
+) -> DeviceEvent {
+    DeviceEvent::Revocation(DeviceRevocation::sign(
+        signer,
+        &root.public_key(),
+        &device.public_key(),
+        revoked_at,
+        at,
+    ))
+}
+
+fn post(user_id: i64, author: &Identity, timestamp: i64) -> NewPost {
+    let signed = SignedPost::new(&author.public_key(), "hello", vec![], timestamp).unwrap();
+    NewPost {
+        user_id,
+        content_encrypted: Some("hello".to_string()),
+        signature: Some(signed.sign(author).unwrap()),
+        timestamp: Some(format_timestamp(timestamp)),
+        ..NewPost::default()
+    }
+}
+
+#[test]
+fn test_device_certificates_and_revocations_are_signed() {
+    let (root, device, other) = (key(1), key(2), key(3));
+    let certificate = DeviceCertificate::sign(
+        &root,
+        &device.public_key(),
+        "Phone",
+        &[
+            DeviceCapability::Revoke,
+            DeviceCapability::Post,
+            DeviceCapability::Revoke,
+        ],
+        100,
+    );
+    assert!(certificate.verify().is_ok());
+    assert_eq!(certificate.capabilities, DeviceCapability::ALL.to_vec());
+    assert!(certificate.allows(DeviceCapability::Revoke));
+
+    let json = serde_json::to_value(DeviceEvent::Certificate(certificate.clone())).unwrap();
+    assert_eq!(json["type"], "certificate");
+    assert_eq!(json["capabilities"], serde_json::json!(["post", "revoke"]));
+
+    let mut narrowed = certificate.clone();
+    narrowed.capabilities = vec![DeviceCapability::Post];
+    assert_eq!(narrowed.verify(), Err(Error::BadSignature));
+
+    let revocation =
+        DeviceRevocation::sign(&other, &root.public_key(), &device.public_key(), 150, 200);
+    assert!(revocation.verify().is_ok());
+    assert_eq!(revocation.signer_key, other.public_key());
+    let mut backdated = revocation.clone();
+    backdated.revoked_at = 0;
+    assert_eq!(backdated.verify(), Err(Error::BadSignature));
+}
+
+#[test]
+fn test_peers_accept_posts_from_certified_devices() {
+    let db = Database::open_in_memory().unwrap();
+    let (root, laptop, phone, stranger) = (key(1), key(2), key(3), key(4));
+    let alice = register(&db, "alice", &root);
+    let bob = register(&db, "bob", &stranger);
+    let peer = db
+        .record_peer(&NewPeer {
+            user_id: bob,
+            address: "10.0.0.2".to_string(),
+            port: 4000,
+            public_key: root.public_key(),
+        })
+        .unwrap();
+    let receive = |event: &DeviceEvent| {
+        db.receive_sync(&NewSyncMessage {
+            user_id: bob,
+            peer_id: peer.id,
+            payload: serde_json::to_string(event).unwrap(),
+            message_type: INBOUND_DEVICE_EVENT.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+    };
+
+    assert!(db.ingest_post(&post(alice, &laptop, 150)).is_err());
+    let received = receive(&certificate(&root, &laptop, &DeviceCapability::ALL, 100)).unwrap();
+    assert_eq!(received.status.as_deref(), Some("processed"));
+    receive(&certificate(&root, &phone, &[DeviceCapability::Post], 100)).unwrap();
+    // Replays are harmless
+    receive(&certificate(&root, &laptop, &DeviceCapability::ALL, 100)).unwrap();
+    assert_eq!(db.devices_for_user(alice).unwrap().len(), 2);
+
+    let signed = db.ingest_post(&post(alice, &laptop, 150)).unwrap();
+    assert!(db.verify_post(signed.id).is_ok());
+    assert!(db.ingest_post(&post(alice, &root, 150)).is_ok());
+    let phone_post = db.ingest_post(&post(alice, &phone, 150)).unwrap();
+    // Not before the device was certified
+    assert!(db.ingest_post(&post(alice, &laptop, 50)).is_err());
+
+    // Only alice's key can certify her devices, and a key belongs to one
+    // account
+    assert!(receive(&certificate(
+        &stranger,
+        &key(5),
+        &DeviceCapability::ALL,
+        100
+    ))
+    .is_err());
+    assert!(db
+        .record_device_event(
+            bob,
+            &certificate(&stranger, &laptop, &DeviceCapability::ALL, 100)
+        )
+        .is_err());
+    assert!(db
+        .record_device_event(
+            alice,
+            &certificate(&root, &stranger, &DeviceCapability::ALL, 100)
+        )
+        .is_err());
+
+    // The phone may not revoke, and outsiders can't either
+    assert!(receive(&revocation(&phone, &root, &laptop, 200, 300)).is_err());
+    assert!(receive(&revocation(&stranger, &root, &laptop, 200, 300)).is_err());
+
+    // The laptop can: the phone's posts from 200 on are rejected, and its
+    // revocation is kept over a later one
+    receive(&revocation(&laptop, &root, &phone, 200, 300)).unwrap();
+    let revoked = receive(&revocation(&root, &root, &phone, 250, 300));
+    assert!(revoked.is_ok());
+    let phone_device = db
+        .devices_for_user(alice)
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_key == phone.public_key())
+        .unwrap();
+    assert_eq!(phone_device.revoked_at(), Some(200));
+    assert!(db.ingest_post(&post(alice, &phone, 210)).is_err());
+    // Nor can it backdate new posts, though what it signed before still
+    // verifies
+    assert!(db.ingest_post(&post(alice, &phone, 190)).is_err());
+    assert!(db.verify_post(phone_post.id).is_ok());
+    assert!(db.ingest_post(&post(alice, &laptop, 210)).is_ok());
+
+    // A revoked device can't revoke others
+    db.record_device_event(alice, &revocation(&root, &root, &laptop, 400, 400))
+        .unwrap();
+    let other = key(6);
+    receive(&certificate(&root, &other, &DeviceCapability::ALL, 100)).unwrap();
+    assert!(db
+        .record_device_event(alice, &revocation(&laptop, &root, &other, 500, 500))
+        .is_err());
+}
+
+#[test]
+fn test_revocations_cannot_be_backdated() {
+    let db = Database::open_in_memory().unwrap();
+    let (root, laptop, phone) = (key(1), key(2), key(3));
+    let alice = register(&db, "alice", &root);
+    for device in [&laptop, &phone] {
+        db.record_device_event(
+            alice,
+            &certificate(&root, device, &DeviceCapability::ALL, 100),
+        )
+        .unwrap();
+    }
+    db.record_device_event(alice, &revocation(&root, &root, &laptop, 300, 300))
+        .unwrap();
+
+    // Dated back to when the laptop was still valid, its revocation of the
+    // phone is refused all the same
+    assert!(db
+        .record_device_event(alice, &revocation(&laptop, &root, &phone, 150, 200))
+        .is_err());
+    // Nobody can revoke a device from before it was certified
+    assert!(db
+        .record_device_event(alice, &revocation(&root, &root, &phone, 0, 400))
+        .is_err());
+
+    let phone_device = db.find_device_by_key(&phone.public_key()).unwrap().unwrap();
+    assert_eq!(phone_device.revoked_at(), None);
+    assert!(db.ingest_post(&post(alice, &phone, 500)).is_ok());
+}
+
+#[test]
+fn test_revoke_from_a_linked_device_broadcasts() {
+    let db = Database::open_in_memory().unwrap();
+    let (root, laptop, phone) = (key(1), key(2), key(3));
+    let alice = register(&db, "alice", &root);
+    db.record_peer(&NewPeer {
+        user_id: alice,
+        address: "10.0.0.3".to_string(),
+        port: 4000,
+        public_key: key(5).public_key(),
+    })
+    .unwrap();
+    for device in [&laptop, &phone] {
+        db.record_device_event(
+            alice,
+            &certificate(&root, device, &DeviceCapability::ALL, 100),
+        )
+        .unwrap();
+    }
+    let user = db.find_user(alice).unwrap().unwrap();
+
+    let device = devices::revoke(&db, &user, &laptop, &phone.public_key(), 150, 200).unwrap();
+    assert_eq!(device.device_key, phone.public_key());
+    let revocation = device.revocation.clone().unwrap();
+    assert_eq!(revocation.signer_key, laptop.public_key());
+    assert_eq!(revocation.root_key, root.public_key());
+    assert!(device.is_valid_at(140));
+    assert!(!device.is_valid_at(160));
+
+    let outbound: Vec<_> = db
+        .pending_sync_messages(alice)
+        .unwrap()
+        .into_iter()
+        .filter(|message| message.message_type.as_deref() == Some(OUTBOUND_DEVICE_EVENT))
+        .collect();
+    assert_eq!(outbound.len(), 1);
+    let event: DeviceEvent = serde_json::from_str(outbound[0].payload.as_deref().unwrap()).unwrap();
+    assert_eq!(event, DeviceEvent::Revocation(revocation));
+
+    assert!(devices::revoke(&db, &user, &key(9), &laptop.public_key(), 150, 200).is_err());
+    assert!(devices::revoke(&db, &user, &root, &key(9).public_key(), 150, 200).is_err());
+}
@@ -0,0 +1,319 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use app::api::v1::archive::ConflictPolicy;
+use app::api::v1::devices;
+use app::api::ApiError;
+use app::crypto::{
+    account::{AccountRecord, ACCOUNTS_DIR},
+    archive::ArchiveContents,
+    device::{DeviceCapability, DeviceCertificate},
+    device_link::{respond, Joiner, PairingOffer},
+    keystore::Keystore,
+    Error, Identity,
+};
+use app::db::NewMessage;
+
+use common::{open_device, register, temp_dir, PARAMS};
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+fn contents() -> ArchiveContents {
+    ArchiveContents {
+        manifest: b"{}".to_vec().into(),
+        secrets: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn test_pairing_code_round_trips() {
+    let root = Identity::from_seed(&[1; 32]);
+    let offer = PairingOffer::new(&root.public_key(), "192.168.1.20", 40123, 1_700_000_300);
+    let payload = offer.qr_payload();
+    assert!(payload.starts_with("cipher-device-link:1:"));
+    assert_eq!(PairingOffer::parse(&payload).unwrap(), offer);
+    assert!(offer.qr_svg().starts_with("<?xml"));
+
+    let v6 = PairingOffer::new(&root.public_key(), "fe80::1", 40123, 1_700_000_300);
+    assert!(v6.qr_payload().ends_with("@[fe80::1]:40123"));
+    assert_eq!(
+        PairingOffer::parse(&v6.qr_payload()).unwrap().address,
+        "fe80::1"
+    );
+
+    // Each offer has its own secret, which Debug leaves out
+    let other = PairingOffer::new(&root.public_key(), "192.168.1.20", 40123, 1_700_000_300);
+    assert_ne!(other, offer);
+    let secret = payload.split(':').nth(3).unwrap();
+    assert!(!format!("{:?}", offer).contains(secret));
+
+    for bad in [
+        "cipher-safety-number:1:123",
+        &payload.replace("@", "#"),
+        &payload.replace(":40123", ":port"),
+        &payload.replacen(":1:", ":2:", 1),
+    ] {
+        assert!(PairingOffer::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_exchange_needs_the_pairing_secret() {
+    let root = Identity::from_seed(&[1; 32]);
+    let offer = PairingOffer::new(&root.public_key(), "127.0.0.1", 1, 1_700_000_300);
+    let scanned = PairingOffer::parse(&offer.qr_payload()).unwrap();
+
+    let joiner = Joiner::new(scanned.clone(), "Laptop").unwrap();
+    let (response, certificate) = respond(
+        &offer,
+        &root,
+        joiner.request(),
+        &DeviceCapability::ALL,
+        &contents(),
+        1_700_000_000,
+    )
+    .unwrap();
+    assert!(certificate.verify().is_ok());
+    assert_eq!(certificate.device_name, "Laptop");
+    assert_eq!(certificate.root_key, root.public_key());
+    let (device, bundle) = joiner.finish(&response).unwrap();
+    assert_eq!(bundle.certificate, certificate);
+    assert_eq!(certificate.device_key, device.public_key());
+    assert_eq!(&bundle.contents.manifest[..], b"{}");
+
+    // A request made without the secret is refused
+    let guess = PairingOffer::new(&root.public_key(), "127.0.0.1", 1, 1_700_000_300);
+    let joiner = Joiner::new(guess, "Laptop").unwrap();
+    assert_eq!(
+        respond(
+            &offer,
+            &root,
+            joiner.request(),
+            &DeviceCapability::ALL,
+            &contents(),
+            1_700_000_000
+        )
+        .err(),
+        Some(Error::BadSignature)
+    );
+
+    // So is a request whose device key was swapped after signing
+    let joiner = Joiner::new(scanned.clone(), "Laptop").unwrap();
+    let mut swapped = joiner.request().clone();
+    swapped.device_key = Identity::from_seed(&[9; 32]).public_key();
+    assert!(respond(
+        &offer,
+        &root,
+        &swapped,
+        &DeviceCapability::ALL,
+        &contents(),
+        1_700_000_000
+    )
+    .is_err());
+
+    // Or one that arrives after the code expired
+    assert!(respond(
+        &offer,
+        &root,
+        joiner.request(),
+        &DeviceCapability::ALL,
+        &contents(),
+        1_700_000_300
+    )
+    .is_err());
+
+    // The new device rejects an answer from anyone without the secret,
+    // or one that was tampered with
+    let impostor = PairingOffer::new(&root.public_key(), "127.0.0.1", 1, 1_700_000_300);
+    let joiner = Joiner::new(scanned.clone(), "Laptop").unwrap();
+    let other = Joiner::new(impostor.clone(), "Laptop").unwrap();
+    let (forged, _) = respond(
+        &impostor,
+        &root,
+        other.request(),
+        &DeviceCapability::ALL,
+        &contents(),
+        1_700_000_000,
+    )
+    .unwrap();
+    assert!(joiner.finish(&forged).is_err());
+
+    let joiner = Joiner::new(scanned, "Laptop").unwrap();
+    let (mut response, _) = respond(
+        &offer,
+        &root,
+        joiner.request(),
+        &DeviceCapability::ALL,
+        &contents(),
+        1_700_000_000,
+    )
+    .unwrap();
+    response.ciphertext = response.ciphertext.replacen('A', "B", 1);
+    assert!(joiner.finish(&response).is_err());
+
+    let mut forged = DeviceCertificate::sign(
+        &root,
+        &device.public_key(),
+        "Laptop",
+        &[DeviceCapability::Post],
+        1,
+    );
+    forged.device_name = "Someone else".to_string();
+    assert_eq!(forged.verify(), Err(Error::BadSignature));
+    let mut widened = DeviceCertificate::sign(
+        &root,
+        &device.public_key(),
+        "Laptop",
+        &[DeviceCapability::Post],
+        1,
+    );
+    widened.capabilities.push(DeviceCapability::Revoke);
+    assert_eq!(widened.verify(), Err(Error::BadSignature));
+}
+
+#[test]
+fn test_new_device_links_over_the_network() {
+    let source = temp_dir("link-source");
+    let (db, mut keystore) = open_device(&source);
+    let (record, alice) = AccountRecord::create("alice", "alice password", PARAMS).unwrap();
+    record.save(&source.join(ACCOUNTS_DIR)).unwrap();
+    keystore.set_identity("alice", &alice);
+    let bob = Identity::from_seed(&[2; 32]);
+    let alice_id = register(&db, "alice", &alice);
+    let bob_id = register(&db, "bob", &bob);
+    let friendship = db.send_friend_request(alice_id, bob_id).unwrap();
+    db.update_friendship_status(friendship.id, "accepted")
+        .unwrap();
+    let message = db
+        .create_message(&NewMessage {
+            sender_id: alice_id,
+            recipient_id: bob_id,
+            content: String::new(),
+            encrypted_content: Some("ratchet ciphertext".to_string()),
+        })
+        .unwrap();
+    keystore.set_message_text("alice", message.id, b"see you soon");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (offer, _pending, host) = devices::offer(
+        &db,
+        &source,
+        &alice,
+        &keystore,
+        listener,
+        "127.0.0.1",
+        &[DeviceCapability::Post],
+        now(),
+    )
+    .unwrap();
+    assert_eq!(offer.address, "127.0.0.1");
+
+    // Someone on the network without the code gets nowhere
+    let guess = PairingOffer::new(
+        &alice.public_key(),
+        "127.0.0.1",
+        offer.port,
+        offer.expires_at,
+    );
+    assert!(matches!(
+        devices::exchange(guess, "Intruder"),
+        Err(ApiError::Unavailable(_))
+    ));
+
+    // Nor does one that connects and stays silent hold up the real device
+    let silent = TcpStream::connect(("127.0.0.1", offer.port)).unwrap();
+    let started = Instant::now();
+    let scanned = PairingOffer::parse(&offer.payload).unwrap();
+    let (device, bundle) = devices::exchange(scanned.clone(), "Laptop").unwrap();
+    assert!(started.elapsed() < Duration::from_secs(30));
+    drop(silent);
+    let issued = host.join().unwrap().expect("the host issued a certificate");
+    assert_eq!(issued, bundle.certificate);
+    assert_eq!(issued.capabilities, vec![DeviceCapability::Post]);
+    // The host records the new device
+    let recorded = db.devices_for_user(alice_id).unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].certificate, issued);
+
+    let target = temp_dir("link-target");
+    let (db, mut keystore) = open_device(&target);
+    let linked = devices::join(
+        &db,
+        &target,
+        &mut keystore,
+        &device,
+        &bundle,
+        ConflictPolicy::Abort,
+    )
+    .unwrap();
+    assert!(linked.report.imported, "{:?}", linked.report.conflicts);
+    assert_eq!(linked.certificate.device_name, "Laptop");
+    assert_eq!(
+        (linked.report.added_contacts, linked.report.added_messages),
+        (1, 1)
+    );
+
+    let alice_here = db.find_user_by_username("alice").unwrap().unwrap();
+    assert_eq!(alice_here.public_key, Some(alice.public_key()));
+    let messages = db.messages_for_user(alice_here.id).unwrap();
+    assert_eq!(
+        keystore.message_text("alice", messages[0].id),
+        Some(&b"see you soon"[..])
+    );
+
+    // The new device has its own certified key, not the account's
+    assert!(keystore.identity("alice").is_none());
+    let kept = keystore.device_identity("alice").unwrap().unwrap();
+    assert_eq!(kept.public_key(), device.public_key());
+    assert_ne!(kept.public_key(), alice.public_key());
+    let certificate = keystore.device_certificate("alice").unwrap().unwrap();
+    assert_eq!(certificate.device_key, kept.public_key());
+    assert!(certificate.verify().is_ok());
+    assert_eq!(
+        db.devices_for_user(alice_here.id).unwrap()[0].device_key,
+        kept.public_key()
+    );
+    let reopened = Keystore::unlock(&Keystore::path(&target), "device password").unwrap();
+    assert!(reopened.device_identity("alice").is_some());
+
+    // The code only works once
+    assert!(devices::exchange(scanned, "Tablet").is_err());
+}
+
+#[test]
+fn test_locking_stops_offering_the_pairing_code() {
+    let source = temp_dir("link-cancelled");
+    let (db, mut keystore) = open_device(&source);
+    let alice = Identity::from_seed(&[1; 32]);
+    keystore.set_identity("alice", &alice);
+    register(&db, "alice", &alice);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (offer, pending, host) = devices::offer(
+        &db,
+        &source,
+        &alice,
+        &keystore,
+        listener,
+        "127.0.0.1",
+        &DeviceCapability::ALL,
+        now(),
+    )
+    .unwrap();
+
+    // As Session::lock does; the listener stops and drops its key copies
+    drop(pending);
+    let started = Instant::now();
+    assert_eq!(host.join().unwrap(), None);
+    assert!(started.elapsed() < Duration::from_secs(30));
+    let scanned = PairingOffer::parse(&offer.payload).unwrap();
+    assert!(devices::exchange(scanned, "Laptop").is_err());
+}
@@ -0,0 +1,380 @@
+use app::backend::BackendMode;
+use app::db::{Database, NewMessage, NewPost, NewUser};
+use app::server::{api_token, handle, metrics::route_label, route, Metrics, Request};
+use app::settings::Settings;
+
+/// A request as the app's own pages make it.
+fn request(method: &str, target: &str, body: &str) -> Request {
+    let headers = format!(
+        "Host: 127.0.0.1:3000\r\nContent-Type: application/json\r\nX-Cipher-Token: {}\r\n",
+        api_token()
+    );
+    raw_request(method, target, &headers, body)
+}
+
+fn raw_request(method: &str, target: &str, headers: &str, body: &str) -> Request {
+    let raw = format!(
+        "{} {} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+        method,
+        target,
+        headers,
+        body.len(),
+        body
+    );
+    Request::read_from(&mut raw.as_bytes()).expect("valid request")
+}
+
+fn new_user(db: &Database, username: &str) -> i64 {
+    db.create_user(&NewUser {
+        public_key: format!("{}-public-key", username),
+        username: username.to_string(),
+        display_name: Some(username.to_uppercase()),
+        email: None,
+    })
+    .expect("user created")
+    .id
+}
+
+#[test]
+fn test_backend_mode_resolution_order() {
+    let saved = Settings {
+        backend: Some(BackendMode::Embedded),
+        ..Settings::default()
+    };
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        BackendMode::resolve(args(&["--backend=rails"]), Some("embedded"), &saved),
+        BackendMode::Rails
+    );
+    assert_eq!(
+        BackendMode::resolve(args(&["--backend", "embedded"]), None, &Settings::default()),
+        BackendMode::Embedded
+    );
+    assert_eq!(
+        BackendMode::resolve(args(&["--embedded"]), Some("rails"), &Settings::default()),
+        BackendMode::Embedded
+    );
+    assert_eq!(
+        BackendMode::resolve(args(&[]), Some("rails"), &saved),
+        BackendMode::Rails
+    );
+    assert_eq!(
+        BackendMode::resolve(args(&[]), None, &saved),
+        BackendMode::Embedded
+    );
+    assert_eq!(
+        BackendMode::resolve(args(&[]), None, &Settings::default()),
+        BackendMode::platform_default()
+    );
+}
+
+#[test]
+fn test_database_shares_rails_schema() {
+    let db = Database::open_in_memory().unwrap();
+    let alice = new_user(&db, "alice");
+    let bob = new_user(&db, "bob");
+
+    let friendship = db.send_friend_request(alice, bob).unwrap();
+    assert_eq!(friendship.status, "pending");
+    assert!(!db.are_friends(alice, bob).unwrap());
+
+    db.update_friendship_status(friendship.id, "accepted")
+        .unwrap();
+    assert!(db.are_friends(bob, alice).unwrap());
+    assert_eq!(db.friends_of(alice).unwrap()[0].id, bob);
+
+    let post = db
+        .create_post(&NewPost {
+            user_id: bob,
+            content_encrypted: Some("ciphertext".to_string()),
+            signature: Some("signature".to_string()),
+            content_hash: Some("abc123".to_string()),
+            ..NewPost::default()
+        })
+        .unwrap();
+    assert!(!post.is_synced);
+    assert!(post.timestamp.is_some());
+    assert!(db.post_exists_with_hash("abc123").unwrap());
+    assert_eq!(db.feed_for_user(alice, 10).unwrap().len(), 1);
+
+    db.create_message(&NewMessage {
+        sender_id: alice,
+        recipient_id: bob,
+        content: "hi".to_string(),
+        encrypted_content: None,
+    })
+    .unwrap();
+    assert_eq!(db.messages_between(bob, alice).unwrap().len(), 1);
+}
+
+#[test]
+fn test_embedded_api_routes() {
+    let db = Database::open_in_memory().unwrap();
+
+    let created = route(
+        &request(
+            "POST",
+            "/api/v1/users",
+            r#"{"public_key":"pk+1=","username":"carol"}"#,
+        ),
+        Some(&db),
+    );
+    assert_eq!(created.status, 201);
+
+    let duplicate = route(
+        &request(
+            "POST",
+            "/api/v1/users",
+            r#"{"public_key":"pk+2=","username":"carol"}"#,
+        ),
+        Some(&db),
+    );
+    assert_eq!(duplicate.status, 422);
+
+    let lookup = route(
+        &request(
+            "GET",
+            "/api/v1/users/by_public_key?public_key=pk%2B1%3D",
+            "",
+        ),
+        Some(&db),
+    );
+    assert_eq!(lookup.status, 404, "by_public_key is POST only, like Rails");
+
+    let lookup = route(
+        &request(
+            "POST",
+            "/api/v1/users/by_public_key",
+            r#"{"public_key":"pk+1="}"#,
+        ),
+        Some(&db),
+    );
+    assert_eq!(lookup.status, 200);
+    let user: serde_json::Value = serde_json::from_slice(&lookup.body).unwrap();
+    assert_eq!(user["username"], "carol");
+
+    let missing_param = route(&request("GET", "/api/v1/posts", ""), Some(&db));
+    assert_eq!(missing_param.status, 400);
+
+    let no_db = route(&request("GET", "/api/v1/users", ""), None);
+    assert_eq!(no_db.status, 503);
+
+    let home = route(&request("GET", "/", ""), None);
+    assert_eq!(home.status, 200);
+    assert!(String::from_utf8_lossy(&home.to_bytes()).starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_embedded_api_refuses_cross_site_requests() {
+    let db = Database::open_in_memory().unwrap();
+    let token = format!("X-Cipher-Token: {}\r\n", api_token());
+    let body = r#"{"public_key":"pk","username":"mallory"}"#;
+    let post = |headers: &str| {
+        route(
+            &raw_request("POST", "/api/v1/users", headers, body),
+            Some(&db),
+        )
+    };
+
+    // A simple cross-site form or fetch: no token, text/plain body
+    let forged = post(
+        "Host: 127.0.0.1:3000\r\nOrigin: https://evil.example\r\nContent-Type: text/plain\r\n",
+    );
+    assert_eq!(forged.status, 403);
+    let foreign_origin = post(&format!(
+        "Host: 127.0.0.1:3000\r\nOrigin: https://evil.example\r\nContent-Type: application/json\r\n{}",
+        token
+    ));
+    assert_eq!(foreign_origin.status, 403);
+    let no_token = post("Host: 127.0.0.1:3000\r\nContent-Type: application/json\r\n");
+    assert_eq!(no_token.status, 403);
+    let wrong_token =
+        post("Host: 127.0.0.1:3000\r\nContent-Type: application/json\r\nX-Cipher-Token: guess\r\n");
+    assert_eq!(wrong_token.status, 403);
+    let not_json = post(&format!(
+        "Host: 127.0.0.1:3000\r\nContent-Type: text/plain\r\n{}",
+        token
+    ));
+    assert_eq!(not_json.status, 415);
+    assert!(db.list_users().unwrap().is_empty());
+
+    // DNS rebinding keeps the attacker's host name
+    let metrics = Metrics::new();
+    let rebound = handle(
+        &raw_request(
+            "GET",
+            "/api/v1/users",
+            &format!("Host: evil.example:3000\r\n{}", token),
+            "",
+        ),
+        Some(&db),
+        &metrics,
+    );
+    assert_eq!(rebound.status, 403);
+    let page = handle(
+        &raw_request("GET", "/", "Host: evil.example:3000\r\n", ""),
+        None,
+        &metrics,
+    );
+    assert_eq!(page.status, 403);
+
+    for headers in [
+        format!("Host: localhost:3000\r\nOrigin: http://localhost:3000\r\nContent-Type: application/json; charset=utf-8\r\n{}", token),
+        format!("Host: [::1]:3000\r\nOrigin: tauri://localhost\r\nContent-Type: application/json\r\n{}", token),
+    ] {
+        let response = route(
+            &raw_request("POST", "/api/v1/users/by_public_key", &headers, r#"{"public_key":"pk"}"#),
+            Some(&db),
+        );
+        assert_eq!(response.status, 404, "{}", headers);
+    }
+
+    // The app's pages hand the token to their scripts
+    let home = route(
+        &raw_request("GET", "/", "Host: 127.0.0.1:3000\r\n", ""),
+        None,
+    );
+    let html = String::from_utf8(home.body).unwrap();
+    assert!(html.contains(&format!(
+        "<meta name=\"cipher-api-token\" content=\"{}\">",
+        api_token()
+    )));
+}
+
+#[test]
+fn test_access_log_metrics() {
+    let db = Database::open_in_memory().unwrap();
+    let metrics = Metrics::new();
+    let alice = new_user(&db, "alice");
+
+    assert_eq!(
+        route_label("/api/v1/users/42/posts"),
+        "/api/v1/users/:id/posts"
+    );
+    assert_eq!(
+        route_label("/api/v1/content/deadbeef/exists"),
+        "/api/v1/content/:hash/exists"
+    );
+    assert_eq!(route_label("/assets/app.css"), "/assets/*");
+    assert_eq!(route_label("/wp-login.php"), "other");
+
+    handle(&request("GET", "/", ""), Some(&db), &metrics);
+    handle(&request("GET", "/", ""), Some(&db), &metrics);
+    handle(
+        &request("GET", &format!("/api/v1/users/{}", alice), ""),
+        Some(&db),
+        &metrics,
+    );
+    handle(
+        &request("GET", "/api/v1/users/999", ""),
+        Some(&db),
+        &metrics,
+    );
+
+    // A rebound page can't read the counters either
+    let foreign = handle(
+        &raw_request("GET", "/__metrics", "Host: evil.example:3000\r\n", ""),
+        Some(&db),
+        &metrics,
+    );
+    assert_eq!(foreign.status, 403);
+    assert!(!String::from_utf8(foreign.body)
+        .unwrap()
+        .contains("cipher_http_requests_total"));
+
+    let response = handle(&request("GET", "/__metrics", ""), Some(&db), &metrics);
+    assert_eq!(response.status, 200);
+    assert!(response.content_type.starts_with("text/plain"));
+    let body = String::from_utf8(response.body).unwrap();
+    assert!(body.contains("# TYPE cipher_http_requests_total counter"));
+    assert!(
+        body.contains("cipher_http_requests_total{method=\"GET\",route=\"/\",status=\"200\"} 2")
+    );
+    assert!(body.contains(
+        "cipher_http_requests_total{method=\"GET\",route=\"/api/v1/users/:id\",status=\"200\"} 1"
+    ));
+    assert!(body.contains(
+        "cipher_http_requests_total{method=\"GET\",route=\"/api/v1/users/:id\",status=\"404\"} 1"
+    ));
+    assert!(
+        body.contains("cipher_http_request_duration_seconds_count{route=\"/api/v1/users/:id\"} 2")
+    );
+    assert!(body.contains("cipher_http_response_size_bytes_bucket{route=\"/\",le=\"+Inf\"} 2"));
+}
+
+fn split_response(raw: &[u8]) -> (String, Vec<u8>) {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    (
+        String::from_utf8_lossy(&raw[..end]).into_owned(),
+        raw[end + 4..].to_vec(),
+    )
+}
+
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size =
+            usize::from_str_radix(std::str::from_utf8(&body[..line_end]).unwrap(), 16).unwrap();
+        if size == 0 {
+            return data;
+        }
+        data.extend_from_slice(&body[line_end + 2..line_end + 2 + size]);
+        body = &body[line_end + 2 + size + 2..];
+    }
+}
+
+#[test]
+fn test_response_compression() {
+    use app::server::compression::{write_response, Encoding};
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    assert_eq!(Encoding::negotiate(None), None);
+    assert_eq!(
+        Encoding::negotiate(Some("gzip, deflate, br")),
+        Some(Encoding::Gzip)
+    );
+    assert_eq!(
+        Encoding::negotiate(Some("gzip;q=0.5, deflate")),
+        Some(Encoding::Deflate)
+    );
+    assert_eq!(
+        Encoding::negotiate(Some("gzip;q=0, *")),
+        Some(Encoding::Deflate)
+    );
+    assert_eq!(Encoding::negotiate(Some("identity")), None);
+
+    let small = route(&request("GET", "/up", ""), None);
+    let mut raw = Vec::new();
+    write_response(&mut raw, &small, Some("gzip")).unwrap();
+    assert_eq!(raw, small.to_bytes());
+
+    let home = route(&request("GET", "/", ""), None);
+    let mut raw = Vec::new();
+    write_response(&mut raw, &home, Some("gzip, deflate")).unwrap();
+    let (head, body) = split_response(&raw);
+    assert!(head.contains("Content-Encoding: gzip"));
+    assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    assert!(body.len() < home.body.len());
+    let mut decoded = Vec::new();
+    GzDecoder::new(&body[..]).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, home.body);
+
+    let large = app::server::Response::json(
+        200,
+        &serde_json::json!({ "data": "cipher ".repeat(20_000) }),
+    );
+    let mut raw = Vec::new();
+    write_response(&mut raw, &large, Some("deflate")).unwrap();
+    let (head, body) = split_response(&raw);
+    assert!(head.contains("Content-Encoding: deflate"));
+    assert!(head.contains("Transfer-Encoding: chunked"));
+    assert!(!head.contains("Content-Length"));
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(&dechunk(&body)[..])
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, large.body);
+}
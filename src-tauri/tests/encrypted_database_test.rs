@@ -0,0 +1,88 @@
+mod common;
+
+use app::backend::unlock_database;
+use app::crypto::keystore::Keystore;
+use app::db::{is_plaintext_database, Database, NewUser};
+
+use common::{temp_dir, PARAMS};
+
+fn register(db: &Database, username: &str) {
+    db.register_user(&NewUser {
+        public_key: format!("{}-key", username),
+        username: username.to_string(),
+        display_name: None,
+        email: None,
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "sqlcipher")]
+#[test]
+fn test_plaintext_database_is_encrypted_under_a_keystore_key() {
+    let dir = temp_dir("encrypted-database");
+    let path = dir.join("desktop.sqlite3");
+    register(&Database::open(&path).unwrap(), "alice");
+    assert!(is_plaintext_database(&path).unwrap());
+
+    let keystore_path = Keystore::path(&dir);
+    let mut keystore = Keystore::create(&keystore_path, "open sesame", PARAMS).unwrap();
+    let (db, key) = unlock_database(&path, "desktop", &mut keystore).unwrap();
+    assert!(db.find_user_by_username("alice").unwrap().is_some());
+    register(&db, "bob");
+    drop(db);
+
+    // Nothing readable on disk, and no way in without the key
+    let bytes = std::fs::read(&path).unwrap();
+    assert!(!is_plaintext_database(&path).unwrap());
+    assert!(!bytes.windows(5).any(|window| window == b"alice"));
+    assert!(Database::open(&path).is_err());
+    let mut wrong = key.clone();
+    wrong.expose_mut()[0] ^= 1;
+    assert!(Database::open_encrypted(&path, &wrong).is_err());
+
+    // The key was saved, so the keystore opens the database next launch too
+    let mut keystore = Keystore::unlock(&keystore_path, "open sesame").unwrap();
+    assert!(keystore.database_key("desktop").unwrap().unwrap() == key);
+    let (db, again) = unlock_database(&path, "desktop", &mut keystore).unwrap();
+    assert!(again == key);
+    assert!(db.find_user_by_username("bob").unwrap().is_some());
+
+    // What Rails gets in CIPHER_DATABASE_KEY
+    let hex = app::db::database_key_hex(&key);
+    assert_eq!(hex.expose().len(), 64);
+    assert!(hex.expose().chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[cfg(feature = "sqlcipher")]
+#[test]
+fn test_new_database_is_created_encrypted() {
+    let dir = temp_dir("new-encrypted-database");
+    let path = dir.join("android.sqlite3");
+    let mut keystore = Keystore::create(&Keystore::path(&dir), "open sesame", PARAMS).unwrap();
+
+    let (db, _) = unlock_database(&path, "android", &mut keystore).unwrap();
+    register(&db, "carol");
+    drop(db);
+    assert!(path.exists());
+    assert!(!is_plaintext_database(&path).unwrap());
+    assert!(keystore.database_key("desktop").is_none());
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+#[test]
+fn test_encryption_needs_sqlcipher() {
+    let dir = temp_dir("unencrypted-build");
+    let path = dir.join("desktop.sqlite3");
+    register(&Database::open(&path).unwrap(), "alice");
+    let mut keystore = Keystore::create(&Keystore::path(&dir), "open sesame", PARAMS).unwrap();
+
+    let error = match unlock_database(&path, "desktop", &mut keystore) {
+        Ok(_) => panic!("opened an encrypted database without SQLCipher"),
+        Err(error) => error,
+    };
+    assert!(error.to_string().contains("sqlcipher"), "{}", error);
+    // The plaintext file is left as it was
+    assert!(is_plaintext_database(&path).unwrap());
+    let db = Database::open(&path).unwrap();
+    assert!(db.find_user_by_username("alice").unwrap().is_some());
+}
@@ -0,0 +1,79 @@
+use app::crypto::{envelope::PostEnvelope, Error, Identity};
+
+const ALICE_SEED: &str = "ef3347a758b1383e16f0b57c8ef65a2f2a31c4f1632de40db44dc8331ae4487d";
+const BOB_SEED: &str = "296dbdbeef123b9a7cb1580aef857e5709a8657c782f27146d75f0966c837f1f";
+
+// Sealed to alice's key with libsodium (crypto_sign_ed25519_pk_to_curve25519,
+// crypto_box_seal and crypto_aead_xchacha20poly1305_ietf_encrypt), the same
+// calls RbNaCl makes.
+const LIBSODIUM_ENVELOPE: &str = r#"{"version": 1, "cipher": "xchacha20poly1305", "nonce": "ZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7", "ciphertext": "FBaV3JAO7/DoXb1NxAHcqjctLpF8MYgRLDSUW7k84ZhT", "recipients": [{"public_key": "KVTET9dZSzXW/5uxda06OHoGvBqwMluAQibNDcJ/Rk8=", "wrapped_key": "DI3X9PeQpOyidcRaRWusOIDURZM21zGzgmemqgyto3kZ+ulxd0YqWGKbJydxyxWS69G2KA1g6D8Lx7OiBlIDvYt3BRcPryOkpokhKcqeMkM="}]}"#;
+
+fn identity(seed_hex: &str) -> Identity {
+    let seed: Vec<u8> = (0..seed_hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&seed_hex[i..i + 2], 16).unwrap())
+        .collect();
+    Identity::from_seed(&seed.try_into().unwrap())
+}
+
+#[test]
+fn test_open_libsodium_envelope() {
+    let envelope = PostEnvelope::from_json(LIBSODIUM_ENVELOPE).unwrap();
+    let plaintext = envelope.open(&identity(ALICE_SEED)).unwrap();
+    assert_eq!(plaintext.as_slice(), b"hello from rbnacl");
+
+    assert_eq!(envelope.open(&identity(BOB_SEED)), Err(Error::NotRecipient));
+}
+
+#[test]
+fn test_seal_for_each_recipient() {
+    let alice = identity(ALICE_SEED);
+    let bob = identity(BOB_SEED);
+    let mallory = Identity::from_seed(&[7; 32]);
+    let recipients = vec![alice.public_key(), bob.public_key(), alice.public_key()];
+
+    let envelope = PostEnvelope::seal(b"for friends only", &recipients).unwrap();
+    assert_eq!(
+        envelope.recipient_keys().collect::<Vec<_>>(),
+        vec![alice.public_key(), bob.public_key()]
+    );
+
+    // Stored form round-trips and holds no plaintext
+    let stored = envelope.to_json();
+    assert!(!stored.contains("for friends only"));
+    let envelope = PostEnvelope::from_json(&stored).unwrap();
+
+    assert_eq!(
+        envelope.open(&alice).unwrap().as_slice(),
+        b"for friends only"
+    );
+    assert_eq!(envelope.open(&bob).unwrap().as_slice(), b"for friends only");
+    assert_eq!(envelope.open(&mallory), Err(Error::NotRecipient));
+
+    // Listing yourself as a recipient doesn't give you the key
+    let mut forged = envelope.clone();
+    forged.recipients[1].public_key = mallory.public_key();
+    assert_eq!(forged.open(&mallory), Err(Error::DecryptionFailed));
+
+    let mut tampered = envelope.clone();
+    tampered.version = 2;
+    assert!(tampered.open(&alice).is_err());
+}
+
+#[test]
+fn test_seal_rejects_bad_recipients() {
+    assert_eq!(
+        PostEnvelope::seal(b"nobody", &[]),
+        Err(Error::Malformed("recipients"))
+    );
+    assert!(PostEnvelope::seal(b"x", &["not base64!".to_string()]).is_err());
+    // The identity point is a small-order key with no usable X25519 form
+    let mut identity_point = [0u8; 32];
+    identity_point[0] = 1;
+    let small_order = app::crypto::encode_base64(&identity_point);
+    assert_eq!(
+        PostEnvelope::seal(b"x", &[small_order]),
+        Err(Error::InvalidKey)
+    );
+    assert!(PostEnvelope::from_json("c2VjcmV0Ym94IG91dHB1dA==").is_err());
+}
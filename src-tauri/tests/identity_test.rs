@@ -0,0 +1,522 @@
+mod common;
+
+use app::crypto::{
+    identity::{self, legacy_seed},
+    Error, Identity,
+};
+
+use common::hex;
+
+// Produced with libsodium (which RbNaCl wraps) from the Ruby derivation:
+// PBKDF2-HMAC-SHA256("<username>:<password>", "cipher_salt_<username>", 100000, 32).
+struct Vector {
+    username: &'static str,
+    password: &'static str,
+    seed: &'static str,
+    public_key: &'static str,
+    message: &'static [u8],
+    signature: &'static str,
+}
+
+const VECTORS: &[Vector] = &[
+    Vector {
+        username: "alice",
+        password: "correct horse battery staple",
+        seed: "ef3347a758b1383e16f0b57c8ef65a2f2a31c4f1632de40db44dc8331ae4487d",
+        public_key: "KVTET9dZSzXW/5uxda06OHoGvBqwMluAQibNDcJ/Rk8=",
+        message: b"hello cipher",
+        signature: "xNypXxQoGT1NSkrmVpY6J3hyHBz4FCPpckL3niSpCFefc4V9fo7UYm1nqdr2CpRmOmlgo8bMHEmi3akrski/BA==",
+    },
+    Vector {
+        username: "bob",
+        password: "hunter2",
+        seed: "296dbdbeef123b9a7cb1580aef857e5709a8657c782f27146d75f0966c837f1f",
+        public_key: "hmDcPPhm5blAOBBCJgSZhvxdmzPQhmM/kkyi03fBMUM=",
+        message: b"",
+        signature: "nPrM+g3QjGE0V3QUlBXNe2X4NnnbCraVS3b8xuqUTeVMhyrKgBWm4aY23IdjYEKfKi6kSYTIyqFsi00heYMrCg==",
+    },
+];
+
+#[test]
+fn test_legacy_derivation_matches_rails() {
+    for vector in VECTORS {
+        let seed = legacy_seed(vector.username, vector.password);
+        assert_eq!(hex(seed.as_ref()), vector.seed, "{}", vector.username);
+
+        let identity = Identity::from_legacy_credentials(vector.username, vector.password);
+        assert_eq!(identity.public_key(), vector.public_key);
+        assert_eq!(identity.sign_base64(vector.message), vector.signature);
+    }
+}
+
+#[test]
+fn test_verify_rbnacl_signatures() {
+    for vector in VECTORS {
+        assert_eq!(
+            identity::verify(vector.public_key, vector.message, vector.signature),
+            Ok(())
+        );
+        assert_eq!(
+            identity::verify(vector.public_key, b"tampered", vector.signature),
+            Err(Error::BadSignature)
+        );
+    }
+
+    let alice = &VECTORS[0];
+    let bob = &VECTORS[1];
+    assert_eq!(
+        identity::verify(bob.public_key, alice.message, alice.signature),
+        Err(Error::BadSignature)
+    );
+    assert_eq!(
+        identity::verify("not base64!", alice.message, alice.signature),
+        Err(Error::Malformed("public key"))
+    );
+    assert_eq!(
+        identity::verify(alice.public_key, alice.message, "c2hvcnQ="),
+        Err(Error::Malformed("signature"))
+    );
+
+    let identity = Identity::from_seed(&[7; 32]);
+    let debug = format!("{:?}", identity);
+    assert!(debug.contains(&identity.public_key()));
+    assert!(!debug.contains("signing_key"));
+}
+
+mod accounts {
+    use app::api::{v1::identity::sign_in, ApiError};
+    use app::crypto::{account::AccountRecord, kdf::KdfDescriptor};
+    use app::db::{Database, NewUser};
+    use app::settings::Settings;
+
+    use super::common::{temp_dir, PARAMS};
+    use super::VECTORS;
+
+    fn settings(rotate_legacy_keys: bool) -> Settings {
+        Settings {
+            kdf: Some(PARAMS),
+            rotate_legacy_keys,
+            ..Settings::default()
+        }
+    }
+
+    fn register(db: &Database, username: &str, public_key: &str) -> i64 {
+        db.register_user(&NewUser {
+            public_key: public_key.to_string(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id
+    }
+
+    #[test]
+    fn test_new_accounts_use_argon2id() {
+        let db = Database::open_in_memory().unwrap();
+        let dir = temp_dir("new-accounts");
+
+        let (identity, created) = sign_in(&db, &dir, "carol", "pw", &settings(false), 0).unwrap();
+        assert_eq!(created.kdf, "argon2id_v1");
+        assert!(!created.migrated);
+        register(&db, "carol", &identity.public_key());
+
+        let (_, again) = sign_in(&db, &dir, "carol", "pw", &settings(false), 0).unwrap();
+        assert_eq!(again.public_key, created.public_key);
+        assert!(matches!(
+            sign_in(&db, &dir, "carol", "wrong", &settings(false), 0),
+            Err(ApiError::Invalid(_))
+        ));
+
+        let (_, dave) = sign_in(&db, &dir, "dave", "pw", &settings(false), 0).unwrap();
+        let salt = |username| match AccountRecord::load(&dir, username).unwrap().unwrap().kdf {
+            KdfDescriptor::Argon2idV1 { salt, .. } => salt,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_ne!(salt("carol"), salt("dave"), "salts are per account");
+        assert_ne!(dave.public_key, created.public_key);
+    }
+
+    #[test]
+    fn test_legacy_migration_keeps_public_key() {
+        let db = Database::open_in_memory().unwrap();
+        let dir = temp_dir("legacy-keep");
+        let alice = &VECTORS[0];
+        register(&db, alice.username, alice.public_key);
+
+        assert!(sign_in(&db, &dir, alice.username, "wrong", &settings(false), 0).is_err());
+        assert!(AccountRecord::load(&dir, alice.username).unwrap().is_none());
+
+        let (identity, migrated) = sign_in(
+            &db,
+            &dir,
+            alice.username,
+            alice.password,
+            &settings(false),
+            0,
+        )
+        .unwrap();
+        assert!(migrated.migrated);
+        assert!(migrated.rotation.is_none());
+        assert_eq!(migrated.public_key, alice.public_key);
+        assert_eq!(identity.sign_base64(alice.message), alice.signature);
+
+        let record = AccountRecord::load(&dir, alice.username).unwrap().unwrap();
+        assert!(record.wrapped_seed.is_some());
+        let json = std::fs::read_to_string(AccountRecord::path(&dir, alice.username)).unwrap();
+        assert!(json.contains("\"kdf\": \"argon2id_v1\""));
+        assert!(!json.contains(alice.seed));
+
+        let (_, again) = sign_in(
+            &db,
+            &dir,
+            alice.username,
+            alice.password,
+            &settings(false),
+            0,
+        )
+        .unwrap();
+        assert!(!again.migrated);
+        assert!(sign_in(&db, &dir, alice.username, "wrong", &settings(false), 0).is_err());
+    }
+
+    #[test]
+    fn test_legacy_migration_with_rotation() {
+        let db = Database::open_in_memory().unwrap();
+        let dir = temp_dir("legacy-rotate");
+        let bob = &VECTORS[1];
+        let bob_id = register(&db, bob.username, bob.public_key);
+
+        let (identity, migrated) = sign_in(
+            &db,
+            &dir,
+            bob.username,
+            bob.password,
+            &settings(true),
+            1_700_000_000,
+        )
+        .unwrap();
+        let rotation = migrated.rotation.expect("rotation record");
+        assert_ne!(migrated.public_key, bob.public_key);
+        assert_eq!(rotation.old_public_key, bob.public_key);
+        assert_eq!(rotation.new_public_key, identity.public_key());
+        assert_eq!(rotation.created_at, 1_700_000_000);
+        rotation.verify().unwrap();
+
+        let mut forged = rotation.clone();
+        forged.new_public_key = VECTORS[0].public_key.to_string();
+        assert!(forged.verify().is_err());
+
+        assert_eq!(
+            db.find_user(bob_id).unwrap().unwrap().public_key.as_deref(),
+            Some(migrated.public_key.as_str())
+        );
+        let (_, again) =
+            sign_in(&db, &dir, bob.username, bob.password, &settings(true), 0).unwrap();
+        assert_eq!(again.public_key, migrated.public_key);
+    }
+}
+
+mod keystore {
+    use std::time::{Duration, Instant};
+
+    use app::api::ApiState;
+    use app::crypto::{
+        encode_base64,
+        keystore::{Keystore, KeystoreError},
+        Error, Identity,
+    };
+
+    use super::common::{temp_dir, unhex, PARAMS};
+    use super::VECTORS;
+
+    fn seed(vector: &super::Vector) -> [u8; 32] {
+        unhex(vector.seed).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let dir = temp_dir("keystore-round-trip");
+        let path = Keystore::path(&dir);
+        let alice = &VECTORS[0];
+        let seed = seed(alice);
+
+        let mut keystore = Keystore::create(&path, "open sesame", PARAMS).unwrap();
+        keystore.set_identity(alice.username, &Identity::from_seed(&seed));
+        keystore.save().unwrap();
+
+        // Neither the seed nor the entry names are stored in the clear
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&encode_base64(&seed)));
+        assert!(!contents.contains(alice.seed));
+        assert!(!contents.contains(alice.username));
+
+        let unlocked = Keystore::unlock(&path, "open sesame").unwrap();
+        assert_eq!(unlocked.usernames(), vec![alice.username.to_string()]);
+        let identity = unlocked.identity(alice.username).unwrap().unwrap();
+        assert_eq!(identity.public_key(), alice.public_key);
+        assert!(unlocked.identity("bob").is_none());
+        assert!(!format!("{:?}", unlocked).contains(alice.seed));
+
+        assert!(matches!(
+            Keystore::unlock(&path, "open sesame!"),
+            Err(KeystoreError::Crypto(Error::WrongPassphrase))
+        ));
+        assert!(matches!(
+            Keystore::create(&path, "open sesame", PARAMS),
+            Err(KeystoreError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_change_passphrase() {
+        let dir = temp_dir("keystore-passphrase");
+        let path = Keystore::path(&dir);
+        let mut keystore = Keystore::create(&path, "first", PARAMS).unwrap();
+        keystore.insert("database", b"sqlcipher key");
+        keystore.change_passphrase("second", PARAMS).unwrap();
+
+        assert!(Keystore::unlock(&path, "first").is_err());
+        let unlocked = Keystore::unlock(&path, "second").unwrap();
+        assert_eq!(unlocked.get("database"), Some(&b"sqlcipher key"[..]));
+    }
+
+    #[test]
+    fn test_long_entry_names_are_refused() {
+        let dir = temp_dir("keystore-long-name");
+        let path = Keystore::path(&dir);
+        let mut keystore = Keystore::create(&path, "sesame", PARAMS).unwrap();
+        // Entry names must fit their u16 length
+        keystore.insert(&"x".repeat(70_000), b"secret");
+        assert!(matches!(
+            keystore.save(),
+            Err(KeystoreError::Crypto(Error::Malformed(_)))
+        ));
+        keystore.remove(&"x".repeat(70_000));
+        keystore.insert(&"x".repeat(65_535), b"secret");
+        keystore.save().unwrap();
+    }
+
+    #[test]
+    fn test_entries_are_scoped_to_one_username() {
+        let dir = temp_dir("keystore-scoped");
+        let path = Keystore::path(&dir);
+        let mut keystore = Keystore::create(&path, "sesame", PARAMS).unwrap();
+        keystore.set_identity("alice", &Identity::from_seed(&[1; 32]));
+        keystore.set_identity("alice/bob", &Identity::from_seed(&[2; 32]));
+        keystore.set_identity("50%", &Identity::from_seed(&[3; 32]));
+        keystore.set_message_text("alice", 1, b"to alice");
+        keystore.set_message_text("alice/bob", 1, b"to alice/bob");
+        keystore.save().unwrap();
+
+        // Plain usernames keep the names they always had
+        assert!(keystore.get("message/alice/1").is_some());
+        for entries in [
+            keystore.account_entries("alice"),
+            keystore.linked_device_entries("alice"),
+        ] {
+            assert!(entries.contains_key("message/alice/1"));
+            assert!(
+                entries.keys().all(|name| !name.contains("bob")),
+                "{:?}",
+                entries.keys()
+            );
+        }
+        assert_eq!(keystore.account_entries("alice/bob").len(), 2);
+
+        let unlocked = Keystore::unlock(&path, "sesame").unwrap();
+        let mut usernames = unlocked.usernames();
+        usernames.sort();
+        assert_eq!(usernames, ["50%", "alice", "alice/bob"]);
+        assert_eq!(
+            unlocked.message_text("alice/bob", 1),
+            Some(&b"to alice/bob"[..])
+        );
+    }
+
+    #[test]
+    fn test_session_auto_locks_when_idle() {
+        let dir = temp_dir("keystore-auto-lock");
+        let path = Keystore::path(&dir);
+        let state = ApiState::new(None);
+        {
+            let mut session = state.session();
+            session.set_keystore(Keystore::create(&path, "pass", PARAMS).unwrap());
+            session.set_identity(Some(Identity::from_seed(&seed(&VECTORS[1]))));
+            session.auto_lock = Some(Duration::from_secs(60));
+        }
+        let start = Instant::now();
+        state.session().touch(start);
+
+        assert!(!state.lock_if_idle(start + Duration::from_secs(30)));
+        assert!(state
+            .with_identity(|identity| identity.public_key())
+            .is_ok());
+
+        // Any use of the keys resets the idle clock
+        state.session().touch(start + Duration::from_secs(45));
+        assert!(!state.lock_if_idle(start + Duration::from_secs(90)));
+        assert!(state.lock_if_idle(start + Duration::from_secs(105)));
+        assert!(state.session().is_locked());
+        assert!(state.session().keystore().is_none());
+        assert!(state.with_identity(|_| ()).is_err());
+
+        state.session().auto_lock = None;
+        assert!(!state.lock_if_idle(Instant::now() + Duration::from_secs(86_400)));
+    }
+}
+
+mod recovery {
+    use app::api::{
+        v1::{identity::sign_in, recovery},
+        ApiError,
+    };
+    use app::crypto::{
+        recovery::{MasterSeed, PendingBackup, WORD_COUNT},
+        Error,
+    };
+    use app::db::{Database, NewUser};
+    use app::settings::Settings;
+
+    use super::common::{hex, temp_dir, PARAMS};
+
+    // Trezor's BIP39 vectors for 32 bytes of entropy; the derived keys were
+    // computed with Python's hmac (HKDF-SHA256) and libsodium.
+    const ZERO_PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+        abandon abandon abandon abandon art";
+    const ZERO_PUBLIC_KEY: &str = "LVCXnd7E5+DbDKbHFsiz2UeXHl+6B6Wat6K29t9I5bM=";
+    const ZERO_ENCRYPTION_KEY: &str =
+        "4f935fc0c19c014c2b50e8a4c110eef0ab00ffb64c1362970c9c8446d68c29d5";
+    const LEGAL_PHRASE: &str = "legal winner thank year wave sausage worth useful legal winner \
+        thank year wave sausage worth useful legal winner thank year wave sausage worth title";
+    const LEGAL_PUBLIC_KEY: &str = "pnUA5JLoifkoUlEQD1IVlO/JHaXUULjJixc8SUo6OsI=";
+
+    fn settings() -> Settings {
+        Settings {
+            kdf: Some(PARAMS),
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn test_phrase_vectors() {
+        let zero = MasterSeed::from_entropy(&[0; 32]);
+        assert_eq!(zero.phrase().as_str(), ZERO_PHRASE);
+        assert_eq!(zero.identity().public_key(), ZERO_PUBLIC_KEY);
+        assert_eq!(hex(zero.encryption_key().as_ref()), ZERO_ENCRYPTION_KEY);
+
+        let legal = MasterSeed::from_entropy(&[0x7f; 32]);
+        assert_eq!(legal.phrase().as_str(), LEGAL_PHRASE);
+        assert_eq!(legal.identity().public_key(), LEGAL_PUBLIC_KEY);
+
+        let typed = format!("  {}\n", LEGAL_PHRASE.to_uppercase());
+        assert_eq!(
+            MasterSeed::from_phrase(&typed)
+                .unwrap()
+                .identity()
+                .public_key(),
+            LEGAL_PUBLIC_KEY
+        );
+
+        let bad_checksum = LEGAL_PHRASE.replace("title", "legal");
+        let twelve_words = "abandon abandon abandon abandon abandon abandon abandon abandon \
+            abandon abandon abandon about";
+        let unknown_word = ZERO_PHRASE.replace("art", "cipher");
+        for phrase in [bad_checksum.as_str(), twelve_words, unknown_word.as_str()] {
+            assert_eq!(
+                MasterSeed::from_phrase(phrase).err(),
+                Some(Error::Malformed("recovery phrase"))
+            );
+        }
+        assert!(!format!("{:?}", zero).contains("abandon"));
+    }
+
+    #[test]
+    fn test_backup_check() {
+        let pending = PendingBackup::generate();
+        assert_eq!(pending.positions.len(), 3);
+        assert!(pending.positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(pending.positions.iter().all(|&p| p < WORD_COUNT));
+
+        let phrase = pending.seed.phrase();
+        let words: Vec<&str> = phrase.split(' ').collect();
+        let answers: Vec<String> = pending
+            .positions
+            .iter()
+            .map(|&p| format!(" {} ", words[p].to_uppercase()))
+            .collect();
+        assert!(pending.check(&answers));
+        assert!(!pending.check(&answers[..2]));
+        let mut wrong = answers.clone();
+        wrong[2] = if words[pending.positions[2]] == "zoo" {
+            "abandon"
+        } else {
+            "zoo"
+        }
+        .to_string();
+        assert!(!pending.check(&wrong));
+    }
+
+    #[test]
+    fn test_confirm_and_restore() {
+        let db = Database::open_in_memory().unwrap();
+        let dir = temp_dir("recovery");
+        let (identity, _) = sign_in(&db, &dir, "erin", "pw", &settings(), 0).unwrap();
+        let old_key = identity.public_key();
+        db.register_user(&NewUser {
+            public_key: old_key.clone(),
+            username: "erin".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap();
+
+        let seed = || MasterSeed::from_entropy(&[0x7f; 32]);
+        assert!(matches!(
+            recovery::confirm(&db, &dir, "erin", "wrong", seed(), &settings(), 10),
+            Err(ApiError::Invalid(_))
+        ));
+
+        let (identity, _, confirmed) =
+            recovery::confirm(&db, &dir, "erin", "pw", seed(), &settings(), 10).unwrap();
+        assert_eq!(identity.public_key(), LEGAL_PUBLIC_KEY);
+        let rotation = confirmed.rotation.unwrap();
+        assert_eq!(rotation.old_public_key, old_key);
+        assert_eq!(rotation.new_public_key, LEGAL_PUBLIC_KEY);
+        rotation.verify().unwrap();
+        let user = db.find_user_by_username("erin").unwrap().unwrap();
+        assert_eq!(user.public_key.as_deref(), Some(LEGAL_PUBLIC_KEY));
+
+        // The password still signs in, now to the phrase's key
+        let (_, signed_in) = sign_in(&db, &dir, "erin", "pw", &settings(), 20).unwrap();
+        assert_eq!(signed_in.public_key, LEGAL_PUBLIC_KEY);
+
+        // Forgotten password: the phrase sets a new one
+        let (_, _, restored) = recovery::restore(
+            &db,
+            &dir,
+            "erin",
+            "new pw",
+            MasterSeed::from_phrase(LEGAL_PHRASE).unwrap(),
+            &settings(),
+        )
+        .unwrap();
+        assert_eq!(restored.public_key, LEGAL_PUBLIC_KEY);
+        assert!(sign_in(&db, &dir, "erin", "pw", &settings(), 30).is_err());
+        let (_, signed_in) = sign_in(&db, &dir, "erin", "new pw", &settings(), 30).unwrap();
+        assert_eq!(signed_in.public_key, LEGAL_PUBLIC_KEY);
+
+        assert!(matches!(
+            recovery::restore(
+                &db,
+                &dir,
+                "erin",
+                "new pw",
+                MasterSeed::from_entropy(&[0; 32]),
+                &settings()
+            ),
+            Err(ApiError::Invalid(_))
+        ));
+    }
+}
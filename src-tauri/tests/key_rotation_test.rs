@@ -0,0 +1,298 @@
+mod common;
+
+use app::api::v1::{
+    identity::sign_in,
+    keys::{self, KeyChange},
+};
+use app::crypto::{
+    rotation::{KeyEvent, KeyHistory, KeyRevocation, KeyRotation},
+    signed_post::{format_timestamp, SignedPost},
+    Error, Identity,
+};
+use app::db::{
+    Database, NewPeer, NewPost, NewSyncMessage, NewUser, INBOUND_KEY_EVENT, OUTBOUND_KEY_EVENT,
+};
+use app::settings::Settings;
+
+use common::{key, temp_dir, PARAMS};
+
+fn rotation(old: &Identity, new: &Identity, at: i64) -> KeyEvent {
+    KeyEvent::Rotation(KeyRotation::sign(old, new, "test", at))
+}
+
+fn revocation(revoked: &Identity, new: &Identity, revoked_at: i64, at: i64) -> KeyEvent {
+    KeyEvent::Revocation(KeyRevocation::sign(revoked, new, revoked_at, "lost", at))
+}
+
+fn post(user_id: i64, author: &Identity, timestamp: i64) -> NewPost {
+    let signed = SignedPost::new(&author.public_key(), "hello", vec![], timestamp).unwrap();
+    NewPost {
+        user_id,
+        content_encrypted: Some("hello".to_string()),
+        signature: Some(signed.sign(author).unwrap()),
+        timestamp: Some(format_timestamp(timestamp)),
+        ..NewPost::default()
+    }
+}
+
+#[test]
+fn test_history_follows_rotations_and_revocations() {
+    let (a, b, c, thief) = (key(1), key(2), key(3), key(4));
+
+    let history = KeyHistory::build(&a.public_key(), &[rotation(&a, &b, 100)]).unwrap();
+    assert_eq!(history.key_at(50), Some(a.public_key().as_str()));
+    assert_eq!(history.key_at(100), Some(b.public_key().as_str()));
+    assert_eq!(history.current(), b.public_key());
+
+    // B leaks; the owner revokes it from 250
+    let events = [rotation(&a, &b, 100), revocation(&b, &c, 250, 400)];
+    assert_eq!(KeyHistory::root(&events), Some(a.public_key().as_str()));
+    let history = KeyHistory::build(&a.public_key(), &events).unwrap();
+    assert_eq!(history.current(), c.public_key());
+    assert!(!history.contains(&thief.public_key()));
+    assert_eq!(history.key_at(200), Some(b.public_key().as_str()));
+    assert_eq!(
+        history.key_at(260),
+        None,
+        "revoked before the new key existed"
+    );
+    assert_eq!(history.key_at(400), Some(c.public_key().as_str()));
+
+    // A rotation by the thief as well is a fork, whichever is dated first
+    let stolen = [
+        rotation(&a, &b, 100),
+        rotation(&b, &thief, 300),
+        revocation(&b, &c, 250, 400),
+    ];
+    assert_eq!(
+        KeyHistory::build(&a.public_key(), &stolen).err(),
+        Some(Error::Malformed("key chain fork"))
+    );
+    // As are two revocations handing B to different keys
+    let contested = [
+        rotation(&a, &b, 100),
+        revocation(&b, &c, 250, 400),
+        revocation(&b, &thief, 200, 400),
+    ];
+    assert_eq!(
+        KeyHistory::build(&a.public_key(), &contested).err(),
+        Some(Error::Malformed("key chain fork"))
+    );
+
+    // Two rotations from B are a fork too
+    let fork = [
+        rotation(&a, &b, 100),
+        rotation(&b, &thief, 300),
+        rotation(&b, &c, 310),
+    ];
+    assert_eq!(
+        KeyHistory::build(&a.public_key(), &fork).err(),
+        Some(Error::Malformed("key chain fork"))
+    );
+
+    let loop_back = [rotation(&a, &b, 100), rotation(&b, &a, 200)];
+    assert!(KeyHistory::build(&a.public_key(), &loop_back).is_err());
+
+    // Tampered or backwards events fail
+    let KeyEvent::Revocation(mut forged) = revocation(&b, &c, 250, 400) else {
+        unreachable!()
+    };
+    forged.revoked_at = 350;
+    assert_eq!(forged.verify(), Err(Error::BadSignature));
+    let KeyEvent::Revocation(future) = revocation(&b, &c, 500, 400) else {
+        unreachable!()
+    };
+    assert_eq!(future.verify(), Err(Error::Malformed("key revocation")));
+
+    // B only became the account's at 100, so it can't be revoked from 50
+    let backdated = [rotation(&a, &b, 100), revocation(&b, &c, 50, 400)];
+    assert_eq!(
+        KeyHistory::build(&a.public_key(), &backdated).err(),
+        Some(Error::Malformed("key revocation predates its key"))
+    );
+    let from_the_start = [rotation(&a, &b, 100), revocation(&b, &c, 100, 400)];
+    let history = KeyHistory::build(&a.public_key(), &from_the_start).unwrap();
+    assert_eq!(history.key_at(99), Some(a.public_key().as_str()));
+    assert_eq!(history.key_at(100), None);
+}
+
+#[test]
+fn test_posts_verify_against_the_key_valid_at_their_timestamp() {
+    let db = Database::open_in_memory().unwrap();
+    let (a, b, c, stranger) = (key(1), key(2), key(3), key(9));
+    let alice = db
+        .register_user(&NewUser {
+            public_key: a.public_key(),
+            username: "alice".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    let bob = db
+        .register_user(&NewUser {
+            public_key: stranger.public_key(),
+            username: "bob".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    let old_post = db.ingest_post(&post(alice, &a, 50)).unwrap();
+
+    // Bob's device knows alice by her old key
+    let peer = db
+        .record_peer(&NewPeer {
+            user_id: bob,
+            address: "10.0.0.2".to_string(),
+            port: 4000,
+            public_key: a.public_key(),
+        })
+        .unwrap();
+    let receive = |event: &KeyEvent| {
+        db.receive_sync(&NewSyncMessage {
+            user_id: bob,
+            peer_id: peer.id,
+            payload: serde_json::to_string(event).unwrap(),
+            message_type: INBOUND_KEY_EVENT.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+    };
+    let updated = receive(&rotation(&a, &b, 100)).unwrap();
+    assert_eq!(updated.status.as_deref(), Some("processed"));
+    let user = db.find_user(alice).unwrap().unwrap();
+    assert_eq!(user.public_key, Some(b.public_key()));
+    assert_eq!(
+        db.find_peer(peer.id).unwrap().unwrap().public_key,
+        Some(b.public_key())
+    );
+    // Replays are harmless
+    receive(&rotation(&a, &b, 100)).unwrap();
+    assert_eq!(db.key_events_for_user(alice).unwrap().len(), 1);
+
+    assert!(db.verify_post(old_post.id).is_ok());
+    assert!(db.ingest_post(&post(alice, &a, 150)).is_err());
+    assert!(db.ingest_post(&post(alice, &b, 150)).is_ok());
+    assert!(db.ingest_post(&post(alice, &b, 50)).is_err());
+
+    // Events must start from one of alice's keys
+    assert!(db
+        .record_key_event(alice, &rotation(&stranger, &c, 200))
+        .is_err());
+    // And can't move her onto someone else's key
+    assert!(db
+        .record_key_event(alice, &rotation(&b, &stranger, 200))
+        .is_err());
+
+    db.record_key_event(alice, &revocation(&b, &c, 120, 200))
+        .unwrap();
+    assert!(db.verify_post(old_post.id).is_ok());
+    assert!(db.ingest_post(&post(alice, &b, 130)).is_err());
+    assert!(db.ingest_post(&post(alice, &c, 200)).is_ok());
+    let history = db.key_history(alice).unwrap();
+    assert_eq!(history.periods.len(), 3);
+    assert_eq!(history.periods[1].valid_until, Some(120));
+}
+
+#[test]
+fn test_change_key_records_and_broadcasts() {
+    let db = Database::open_in_memory().unwrap();
+    let dir = temp_dir("keys");
+    let settings = Settings {
+        kdf: Some(PARAMS),
+        ..Settings::default()
+    };
+    let (identity, _) = sign_in(&db, &dir, "erin", "pw", &settings, 0).unwrap();
+    let first_key = identity.public_key();
+    let erin = db
+        .register_user(&NewUser {
+            public_key: first_key.clone(),
+            username: "erin".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    db.record_peer(&NewPeer {
+        user_id: erin,
+        address: "10.0.0.3".to_string(),
+        port: 4000,
+        public_key: key(5).public_key(),
+    })
+    .unwrap();
+
+    assert!(
+        keys::change_key(&db, &dir, "erin", "wrong", KeyChange::Rotate, &settings, 10).is_err()
+    );
+    assert_eq!(db.key_events_for_user(erin).unwrap().len(), 0);
+
+    let (rotated, response) =
+        keys::change_key(&db, &dir, "erin", "pw", KeyChange::Rotate, &settings, 10).unwrap();
+    assert_ne!(rotated.public_key(), first_key);
+    assert_eq!(response.public_key, rotated.public_key());
+    assert!(matches!(response.event, KeyEvent::Rotation(_)));
+
+    let revoke = KeyChange::Revoke {
+        revoked_at: 15,
+        reason: "lost_device".to_string(),
+    };
+    let (revoked, response) =
+        keys::change_key(&db, &dir, "erin", "pw", revoke, &settings, 20).unwrap();
+    assert!(matches!(response.event, KeyEvent::Revocation(_)));
+
+    let user = db.find_user(erin).unwrap().unwrap();
+    assert_eq!(user.public_key, Some(revoked.public_key()));
+    let history = db.key_history(erin).unwrap();
+    assert_eq!(history.key_at(5), Some(first_key.as_str()));
+    assert_eq!(history.key_at(12), Some(rotated.public_key().as_str()));
+    assert_eq!(history.key_at(17), None);
+    assert_eq!(history.current(), revoked.public_key());
+
+    let outbound = db
+        .pending_sync_messages(erin)
+        .unwrap()
+        .into_iter()
+        .filter(|message| message.message_type.as_deref() == Some(OUTBOUND_KEY_EVENT))
+        .count();
+    assert_eq!(outbound, 2);
+
+    // The password now signs in to the newest key
+    let (_, signed_in) = sign_in(&db, &dir, "erin", "pw", &settings, 30).unwrap();
+    assert_eq!(signed_in.public_key, revoked.public_key());
+}
+
+#[test]
+fn test_retired_keys_cannot_take_the_account_back() {
+    let (a, b, thief) = (key(1), key(2), key(4));
+
+    // A was rotated away from, then leaked; a revocation dated before the
+    // rotation mustn't hand the account to whoever found it
+    let takeover = [rotation(&a, &b, 100), revocation(&a, &thief, 50, 200)];
+    assert_eq!(
+        KeyHistory::build(&a.public_key(), &takeover).err(),
+        Some(Error::Malformed("key chain fork"))
+    );
+
+    let db = Database::open_in_memory().unwrap();
+    let alice = db
+        .register_user(&NewUser {
+            public_key: a.public_key(),
+            username: "alice".to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    db.record_key_event(alice, &rotation(&a, &b, 100)).unwrap();
+    assert!(db
+        .record_key_event(alice, &revocation(&a, &thief, 50, 200))
+        .is_err());
+    assert!(db
+        .record_key_event(alice, &revocation(&a, &thief, 150, 200))
+        .is_err());
+    let user = db.find_user(alice).unwrap().unwrap();
+    assert_eq!(user.public_key, Some(b.public_key()));
+    assert_eq!(db.key_events_for_user(alice).unwrap().len(), 1);
+}
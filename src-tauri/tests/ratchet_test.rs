@@ -0,0 +1,217 @@
+mod common;
+
+use std::path::Path;
+
+use app::api::v1::messages::{self, SecureMessageRequest};
+use app::crypto::{
+    keystore::Keystore,
+    ratchet::{PrekeySecrets, RatchetSession},
+    Error, Identity,
+};
+use app::db::{
+    Database, NewPeer, NewSyncMessage, NewUser, INBOUND_PREKEY_BUNDLE, OUTBOUND_PREKEY_BUNDLE,
+};
+
+use common::{key, temp_dir, text, PARAMS};
+
+#[test]
+fn test_sessions_survive_reordering_and_reject_replays() {
+    let (alice_key, bob_key) = (key(1), key(2));
+    let mut bob_prekeys = PrekeySecrets::generate();
+    let bundles = bob_prekeys.issue(&bob_key, 2, 100);
+    assert_eq!(bundles.len(), 2);
+    assert_eq!(bob_prekeys.one_time_prekey_count(), 2);
+    assert!(bundles.iter().all(|bundle| bundle.verify().is_ok()));
+
+    let mut forged = bundles[0].clone();
+    forged.signed_prekey = bundles[1].one_time_prekey.clone().unwrap();
+    assert_eq!(forged.verify(), Err(Error::BadSignature));
+    assert!(RatchetSession::initiate(&alice_key, &forged).is_err());
+
+    let mut alice = RatchetSession::initiate(&alice_key, &bundles[0]).unwrap();
+    let first = alice.encrypt(b"one").unwrap();
+    let second = alice.encrypt(b"two").unwrap();
+    assert!(first.prekey.is_some() && second.prekey.is_some());
+
+    // The second prekey message arrives first and still starts the session
+    let (mut bob, plaintext) =
+        RatchetSession::respond(&bob_key, &mut bob_prekeys, &second).unwrap();
+    assert_eq!(text(plaintext), "two");
+    assert_eq!(bob.remote_identity_key(), alice_key.public_key());
+    assert_eq!(bob_prekeys.one_time_prekey_count(), 1);
+    assert!(bob.started_by(&first));
+    assert_eq!(text(bob.decrypt(&first).unwrap()), "one");
+    // The one-time prekey is gone, so neither message starts a second session
+    assert!(RatchetSession::respond(&bob_key, &mut bob_prekeys, &first).is_err());
+    assert!(bob.decrypt(&first).is_err(), "replayed");
+
+    // Replies ratchet forward; skipped keys are kept for late messages
+    let reply = bob.encrypt(b"three").unwrap();
+    assert!(reply.prekey.is_none());
+    assert_eq!(text(alice.decrypt(&reply).unwrap()), "three");
+    let late = alice.encrypt(b"four").unwrap();
+    let next = alice.encrypt(b"five").unwrap();
+    assert!(next.prekey.is_none());
+    assert_eq!(text(bob.decrypt(&next).unwrap()), "five");
+
+    // Tampering fails and leaves the session as it was
+    let mut tampered = late.clone();
+    tampered.header.number += 7;
+    let before = bob.to_bytes();
+    assert!(bob.decrypt(&tampered).is_err());
+    assert_eq!(bob.to_bytes(), before);
+    assert_eq!(text(bob.decrypt(&late).unwrap()), "four");
+    assert!(bob.decrypt(&late).is_err(), "replayed");
+
+    // Sessions round-trip through their stored form
+    let mut restored = RatchetSession::from_bytes(&alice.to_bytes()).unwrap();
+    let after = bob.encrypt(b"six").unwrap();
+    assert_eq!(text(restored.decrypt(&after).unwrap()), "six");
+    let restored_prekeys = PrekeySecrets::from_bytes(&bob_prekeys.to_bytes()).unwrap();
+    assert_eq!(restored_prekeys.one_time_prekey_count(), 1);
+
+    // A bundle without its one-time prekey still starts a session
+    let mut late_prekeys = PrekeySecrets::generate();
+    let bundle = late_prekeys.issue(&bob_key, 1, 200).remove(0);
+    let mut carol = RatchetSession::initiate(&key(3), &bundle.without_one_time_prekey()).unwrap();
+    let hello = carol.encrypt(b"hi").unwrap();
+    let (_, plaintext) = RatchetSession::respond(&bob_key, &mut late_prekeys, &hello).unwrap();
+    assert_eq!(text(plaintext), "hi");
+    assert_eq!(late_prekeys.one_time_prekey_count(), 1);
+}
+
+struct Device {
+    identity: Identity,
+    keystore: Keystore,
+    user_id: i64,
+}
+
+fn device(db: &Database, dir: &Path, username: &str, seed: u8) -> Device {
+    let identity = key(seed);
+    let keystore = Keystore::create(&dir.join(username), "pw", PARAMS).unwrap();
+    let user_id = db
+        .register_user(&NewUser {
+            public_key: identity.public_key(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id;
+    Device {
+        identity,
+        keystore,
+        user_id,
+    }
+}
+
+#[test]
+fn test_secure_messages_use_prekeys_from_sync_and_keep_state_in_the_keystore() {
+    let db = Database::open_in_memory().unwrap();
+    let dir = temp_dir("ratchet");
+    let mut alice = device(&db, &dir, "alice", 1);
+    let mut bob = device(&db, &dir, "bob", 2);
+    let peer = |user: &Device, other: &Device| {
+        db.record_peer(&NewPeer {
+            user_id: user.user_id,
+            address: "10.0.0.2".to_string(),
+            port: 4000,
+            public_key: other.identity.public_key(),
+        })
+        .unwrap()
+        .id
+    };
+    // Alice's device delivers to bob's, and bob's knows her key
+    peer(&alice, &bob);
+    let bob_sees_alice = peer(&bob, &alice);
+
+    let send = |from: &mut Device, to: &Device, content: &str| {
+        messages::send_secure(
+            &db,
+            &from.identity,
+            &mut from.keystore,
+            &SecureMessageRequest {
+                sender_id: from.user_id,
+                recipient_id: to.user_id,
+                content: content.to_string(),
+            },
+        )
+    };
+    assert!(send(&mut bob, &alice, "hi").is_err(), "no prekeys yet");
+
+    // Alice publishes; her bundle reaches bob over sync
+    let queued =
+        messages::publish_prekeys(&db, &alice.identity, &mut alice.keystore, alice.user_id, 10)
+            .unwrap();
+    assert_eq!(queued, 1);
+    let outbound = db
+        .pending_sync_messages(alice.user_id)
+        .unwrap()
+        .into_iter()
+        .find(|message| message.message_type.as_deref() == Some(OUTBOUND_PREKEY_BUNDLE))
+        .unwrap();
+    let deliver = |peer_id: i64, payload: String| {
+        db.receive_sync(&NewSyncMessage {
+            user_id: bob.user_id,
+            peer_id,
+            payload,
+            message_type: INBOUND_PREKEY_BUNDLE.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+    };
+    let payload = outbound.payload.unwrap();
+    // A bundle can only come from the peer whose key signed it
+    let mallory = db
+        .record_peer(&NewPeer {
+            user_id: bob.user_id,
+            address: "10.0.0.9".to_string(),
+            port: 4000,
+            public_key: key(9).public_key(),
+        })
+        .unwrap();
+    assert!(deliver(mallory.id, payload.clone()).is_err());
+    let held = deliver(bob_sees_alice, payload).unwrap();
+    assert_eq!(held.status.as_deref(), Some("held"));
+
+    let first = send(&mut bob, &alice, "hello alice").unwrap();
+    assert!(!first
+        .encrypted_content
+        .as_deref()
+        .unwrap()
+        .contains("hello"));
+    assert_eq!(first.content.as_deref(), Some(""));
+    let second = send(&mut bob, &alice, "are you there?").unwrap();
+
+    let open = |device: &mut Device, id: i64| {
+        messages::open_secure(&db, &device.identity, &mut device.keystore, id)
+            .map(|opened| opened.content)
+    };
+    assert_eq!(open(&mut alice, second.id).unwrap(), "are you there?");
+    assert_eq!(open(&mut alice, first.id).unwrap(), "hello alice");
+    // Opened text is kept, since the message keys are gone
+    assert_eq!(open(&mut alice, first.id).unwrap(), "hello alice");
+    assert_eq!(open(&mut bob, first.id).unwrap(), "hello alice");
+
+    let reply = send(&mut alice, &bob, "here").unwrap();
+    assert_eq!(open(&mut bob, reply.id).unwrap(), "here");
+
+    // Everything survives locking and unlocking both keystores
+    let reopen = |device: &mut Device, username: &str| {
+        device.keystore = Keystore::unlock(&dir.join(username), "pw").unwrap();
+    };
+    reopen(&mut alice, "alice");
+    reopen(&mut bob, "bob");
+    assert!(alice
+        .keystore
+        .ratchet_session("alice", &bob.identity.public_key())
+        .is_some());
+    let later = send(&mut bob, &alice, "still here").unwrap();
+    assert_eq!(open(&mut alice, later.id).unwrap(), "still here");
+    assert_eq!(open(&mut alice, first.id).unwrap(), "hello alice");
+
+    // Someone else's messages stay closed
+    let mut carol = device(&db, &dir, "carol", 3);
+    assert!(open(&mut carol, later.id).is_err());
+}
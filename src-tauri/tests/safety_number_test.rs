@@ -0,0 +1,142 @@
+mod common;
+
+use app::api::{
+    v1::friends::{self, VerifyFriendRequest},
+    ApiError,
+};
+use app::crypto::{
+    rotation::{KeyEvent, KeyRotation},
+    safety_number::{fingerprint, SafetyNumber},
+    Identity,
+};
+use app::db::{Database, NewPeer, NewSyncMessage, NewUser, VerificationStatus, INBOUND_KEY_EVENT};
+
+use common::key;
+
+#[test]
+fn test_safety_number_is_shared_by_both_sides() {
+    let (alice, bob, mallory) = (key(1), key(2), key(3));
+    let number = SafetyNumber::new(&alice.public_key(), &bob.public_key()).unwrap();
+    assert_eq!(
+        number,
+        SafetyNumber::new(&bob.public_key(), &alice.public_key()).unwrap()
+    );
+    assert_eq!(number.digits().len(), 60);
+    assert!(number.digits().chars().all(|c| c.is_ascii_digit()));
+    let fingerprints = [
+        fingerprint(&alice.public_key()).unwrap(),
+        fingerprint(&bob.public_key()).unwrap(),
+    ];
+    assert!(fingerprints
+        .iter()
+        .all(|half| number.digits().contains(half.as_str())));
+
+    let display = number.to_string();
+    assert_eq!(display.split(' ').count(), 12);
+    assert!(number.matches(&display));
+    assert!(number.matches(&number.qr_payload()));
+    assert!(number.matches(&format!("  {}\n", number.digits())));
+
+    // A substituted key changes the number
+    let substituted = SafetyNumber::new(&alice.public_key(), &mallory.public_key()).unwrap();
+    assert_ne!(number, substituted);
+    assert!(!number.matches(&substituted.qr_payload()));
+    assert!(!number.matches(&number.digits()[..55]));
+    assert!(!number.matches(&number.qr_payload().replace(":1:", ":2:")));
+
+    let svg = number.qr_svg();
+    assert!(svg.contains("<svg"));
+    assert!(fingerprint("not a key").is_err());
+}
+
+#[test]
+fn test_verified_friends_warn_when_their_key_changes() {
+    let db = Database::open_in_memory().unwrap();
+    let (alice_key, bob_key, carol_key) = (key(1), key(2), key(3));
+    let register = |username: &str, identity: &Identity| {
+        db.register_user(&NewUser {
+            public_key: identity.public_key(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id
+    };
+    let alice = register("alice", &alice_key);
+    let bob = register("bob", &bob_key);
+    let carol = register("carol", &carol_key);
+    let friendship = db.request_friendship(alice, bob).unwrap();
+    db.respond_to_friendship(friendship.id, "accepted").unwrap();
+
+    let response = friends::safety_number(&db, alice, bob).unwrap();
+    assert_eq!(response.verification.status, VerificationStatus::Unverified);
+    assert_eq!(
+        response.number,
+        friends::safety_number(&db, bob, alice).unwrap().number
+    );
+
+    let verify = |user_id: i64, friend_id: i64, scanned: String| {
+        friends::verify(
+            &db,
+            &VerifyFriendRequest {
+                user_id,
+                friend_id,
+                scanned,
+            },
+        )
+    };
+    let wrong = SafetyNumber::new(&alice_key.public_key(), &carol_key.public_key()).unwrap();
+    assert!(matches!(
+        verify(alice, bob, wrong.qr_payload()),
+        Err(ApiError::Invalid(_))
+    ));
+    // Only friends can be verified
+    let carol_number = friends::safety_number(&db, alice, carol).unwrap();
+    assert!(verify(alice, carol, carol_number.qr_payload).is_err());
+
+    let verified = verify(alice, bob, response.qr_payload.clone()).unwrap();
+    assert_eq!(verified.status, VerificationStatus::Verified);
+    assert!(verified.verified_at.is_some());
+    assert!(db.key_change_warnings(alice).unwrap().is_empty());
+
+    // Bob's key changes; alice's device hears about it over sync
+    let peer = db
+        .record_peer(&NewPeer {
+            user_id: alice,
+            address: "10.0.0.2".to_string(),
+            port: 4000,
+            public_key: bob_key.public_key(),
+        })
+        .unwrap();
+    let new_bob = key(4);
+    let event = KeyEvent::Rotation(KeyRotation::sign(&bob_key, &new_bob, "manual", 100));
+    let message = db
+        .receive_sync(&NewSyncMessage {
+            user_id: alice,
+            peer_id: peer.id,
+            payload: serde_json::to_string(&event).unwrap(),
+            message_type: INBOUND_KEY_EVENT.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+        .unwrap();
+    assert_eq!(message.status.as_deref(), Some("verified_key_changed"));
+
+    let warnings = db.key_change_warnings(alice).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].friend_id, bob);
+    assert_eq!(warnings[0].status, VerificationStatus::KeyChanged);
+    assert_eq!(warnings[0].verified_key, Some(bob_key.public_key()));
+    assert_eq!(warnings[0].public_key, Some(new_bob.public_key()));
+
+    // The old number no longer verifies; the new one does
+    let changed = friends::safety_number(&db, alice, bob).unwrap();
+    assert_ne!(changed.number, response.number);
+    assert_eq!(changed.verification.status, VerificationStatus::KeyChanged);
+    assert!(verify(alice, bob, response.qr_payload).is_err());
+    let reverified = verify(alice, bob, changed.number).unwrap();
+    assert_eq!(reverified.status, VerificationStatus::Verified);
+    assert!(db.key_change_warnings(alice).unwrap().is_empty());
+}
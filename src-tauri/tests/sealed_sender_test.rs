@@ -0,0 +1,158 @@
+mod common;
+
+use app::api::v1::sync;
+use app::crypto::{
+    ratchet::PrekeySecrets,
+    sealed_sender::{bucket_for, SealedEnvelope, BUCKETS},
+    Error, Identity,
+};
+use app::db::{
+    Database, NewPeer, NewSyncMessage, NewUser, INBOUND_PREKEY_BUNDLE, INBOUND_SEALED,
+    OUTBOUND_PREKEY_BUNDLE,
+};
+
+use common::key;
+
+#[test]
+fn test_envelopes_hide_sender_and_type_and_pad_to_buckets() {
+    let (alice, bob, carol) = (key(1), key(2), key(3));
+    let envelope =
+        SealedEnvelope::seal(&alice, &bob.public_key(), "outbound_sync", "{}", 100).unwrap();
+    let json = serde_json::to_string(&envelope).unwrap();
+    assert!(!json.contains(&alice.public_key()));
+    assert!(!json.contains(&bob.public_key()));
+    assert!(!json.contains("outbound_sync"));
+
+    let content = envelope.open(&bob).unwrap();
+    assert_eq!(content.sender_key, alice.public_key());
+    assert_eq!(content.recipient_keys, vec![bob.public_key()]);
+    assert_eq!(content.message_type, "outbound_sync");
+    assert_eq!(content.timestamp, 100);
+    assert_eq!(content.payload, "{}");
+    assert_eq!(envelope.open(&carol), Err(Error::NotRecipient));
+
+    // Sizes only reveal the bucket
+    let sizes: Vec<usize> = ["", "short", &"x".repeat(500)]
+        .iter()
+        .map(|payload| {
+            SealedEnvelope::seal(&alice, &bob.public_key(), "outbound_sync", payload, 100)
+                .unwrap()
+                .sealed_len()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(sizes, vec![BUCKETS[0]; 3]);
+    let large = SealedEnvelope::seal(
+        &alice,
+        &bob.public_key(),
+        "outbound_sync",
+        &"x".repeat(BUCKETS[5] + 10),
+        100,
+    )
+    .unwrap();
+    assert_eq!(large.sealed_len().unwrap(), 2 * BUCKETS[5]);
+    assert_eq!(large.open(&bob).unwrap().payload.len(), BUCKETS[5] + 10);
+    assert_eq!(bucket_for(BUCKETS[0] + 1), BUCKETS[1]);
+
+    // Tampering fails to open
+    let mut tampered = envelope.clone();
+    tampered.ciphertext = tampered.ciphertext.replacen('A', "B", 1);
+    assert!(tampered.open(&bob).is_err());
+}
+
+#[test]
+fn test_sealed_sync_is_held_by_relays_and_applied_once_opened() {
+    let db = Database::open_in_memory().unwrap();
+    let (alice_key, bob_key, relay_key, stranger_key) = (key(1), key(2), key(3), key(4));
+    let register = |username: &str, identity: &Identity| {
+        db.register_user(&NewUser {
+            public_key: identity.public_key(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id
+    };
+    let alice = register("alice", &alice_key);
+    let bob = register("bob", &bob_key);
+    register("relay", &relay_key);
+    let peer = |user_id: i64, address: &str, identity: &Identity| {
+        db.record_peer(&NewPeer {
+            user_id,
+            address: address.to_string(),
+            port: 4000,
+            public_key: identity.public_key(),
+        })
+        .unwrap()
+        .id
+    };
+    peer(alice, "10.0.0.2", &bob_key);
+    peer(bob, "10.0.0.1", &alice_key);
+    // Bob's envelopes arrive through a relay, not from alice directly
+    let relay = peer(bob, "10.0.0.3", &relay_key);
+
+    let bundles = PrekeySecrets::generate().issue(&alice_key, 1, 10);
+    db.deliver_prekey_bundles(alice, bundles).unwrap();
+    assert!(
+        sync::outbox(&db, &bob_key, alice, 20).is_err(),
+        "wrong identity"
+    );
+    let outbox = sync::outbox(&db, &alice_key, alice, 20).unwrap();
+    assert_eq!(outbox.len(), 1);
+    let payload = serde_json::to_string(&outbox[0].envelope).unwrap();
+    assert!(!payload.contains(OUTBOUND_PREKEY_BUNDLE));
+
+    let deliver = |payload: String| {
+        db.receive_sync(&NewSyncMessage {
+            user_id: bob,
+            peer_id: relay,
+            payload,
+            message_type: INBOUND_SEALED.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+    };
+    let held = deliver(payload.clone()).unwrap();
+    assert_eq!(held.status.as_deref(), Some("sealed"));
+    assert!(deliver(payload).is_err(), "replayed");
+    assert!(deliver("{}".to_string()).is_err());
+
+    // A stranger's envelope is opened but has no peer to apply it to
+    let unknown = SealedEnvelope::seal(
+        &stranger_key,
+        &bob_key.public_key(),
+        OUTBOUND_PREKEY_BUNDLE,
+        "{}",
+        20,
+    )
+    .unwrap();
+    deliver(serde_json::to_string(&unknown).unwrap()).unwrap();
+
+    let opened = sync::open_sealed(&db, &bob_key, bob).unwrap();
+    assert_eq!((opened.opened, opened.rejected), (1, 1));
+    let applied = db
+        .sync_messages_with_status(bob, "held")
+        .unwrap()
+        .into_iter()
+        .filter(|message| message.message_type.as_deref() == Some(INBOUND_PREKEY_BUNDLE))
+        .count();
+    assert_eq!(applied, 1);
+    assert_eq!(
+        db.find_sync_message(held.id)
+            .unwrap()
+            .unwrap()
+            .status
+            .as_deref(),
+        Some("opened")
+    );
+    assert!(db
+        .take_prekey_bundle(bob, &alice_key.public_key())
+        .unwrap()
+        .is_some());
+
+    // Nothing is left to open
+    let again = sync::open_sealed(&db, &bob_key, bob).unwrap();
+    assert_eq!((again.opened, again.rejected), (0, 0));
+}
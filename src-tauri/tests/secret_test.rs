@@ -0,0 +1,184 @@
+mod common;
+
+use app::api::{
+    v1::{identity::sign_in, keystore::KeystoreStatus},
+    ApiError, ApiState,
+};
+use app::crypto::{
+    encode_base64,
+    keystore::Keystore,
+    recovery::{MasterSeed, PendingBackup},
+    secret::{Secret, StoredKey},
+    Identity,
+};
+use app::db::Database;
+use app::settings::Settings;
+
+use common::{hex, temp_dir, PARAMS};
+
+const PASSWORD: &str = "correct horse battery staple";
+const PASSPHRASE: &str = "open sesame";
+
+/// Fails if `text` holds `secret` in any of the forms it could be printed in.
+fn assert_hidden(text: &str, secret: &[u8], what: &str) {
+    let debug = format!("{:?}", secret);
+    for form in [
+        hex(secret),
+        encode_base64(secret),
+        debug[1..debug.len() - 1].to_string(),
+    ] {
+        assert!(!text.contains(&form), "{} leaked into {}", what, text);
+    }
+}
+
+#[test]
+fn test_secrets_are_redacted_and_compared_by_value() {
+    let key = Secret::copy_of(&[7u8; 32]);
+    assert_eq!(format!("{:?}", key), "Secret([redacted])");
+    assert_eq!(key.expose(), &[7u8; 32]);
+    assert_eq!(key, key.clone());
+    assert_ne!(key, Secret::copy_of(&[8u8; 32]));
+
+    let password = Secret::new(PASSWORD.to_string());
+    assert_eq!(format!("{:?}", password), "Secret([redacted])");
+    assert_eq!(password, Secret::new(PASSWORD.to_string()));
+    assert_ne!(password, Secret::new("correct horse".to_string()));
+    assert_ne!(Secret::new(vec![1, 2]), Secret::new(vec![1, 2, 3]));
+
+    let mut filled = Secret::<[u8; 4]>::zeroed();
+    filled.expose_mut().copy_from_slice(b"abcd");
+    assert_eq!(filled, Secret::copy_of(b"abcd"));
+
+    // Well inside the default RLIMIT_MEMLOCK
+    if cfg!(target_os = "linux") {
+        assert!(key.is_locked());
+        assert!(password.is_locked());
+    }
+
+    // Stored keys serialize as plain byte arrays, so keystores written
+    // before they were wrapped still load
+    let stored = StoredKey::copy_of(&[7u8; 32]);
+    assert_eq!(format!("{:?}", stored), "StoredKey(Secret([redacted]))");
+    let json = serde_json::to_string(&stored).unwrap();
+    assert_eq!(json, serde_json::to_string(&[7u8; 32]).unwrap());
+    let loaded: StoredKey = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, stored.duplicate());
+    assert_ne!(StoredKey::generate(), StoredKey::generate());
+}
+
+/// Whether the page holding `address` is mlocked, from the VMA flags in
+/// `/proc/self/smaps`.
+#[cfg(target_os = "linux")]
+fn is_page_locked(address: usize) -> bool {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let mut inside = false;
+    for line in smaps.lines() {
+        if let Some((start, end)) = line
+            .split_whitespace()
+            .next()
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, end)| {
+                Some((
+                    usize::from_str_radix(start, 16).ok()?,
+                    usize::from_str_radix(end, 16).ok()?,
+                ))
+            })
+        {
+            inside = (start..end).contains(&address);
+        } else if inside && line.starts_with("VmFlags:") {
+            return line.split_whitespace().any(|flag| flag == "lo");
+        }
+    }
+    false
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_dropping_a_secret_keeps_its_neighbours_locked() {
+    let address = |secret: &Secret<[u8; 32]>| secret.expose().as_ptr() as usize;
+    let mut secrets: Vec<_> = (0..64).map(|i| Secret::copy_of(&[i; 32])).collect();
+    let kept = (1..secrets.len())
+        .find(|&i| address(&secrets[i]) / 4096 == address(&secrets[0]) / 4096)
+        .map(|i| secrets.swap_remove(i))
+        .expect("two small secrets share a page");
+    assert!(kept.is_locked());
+    assert!(is_page_locked(address(&kept)));
+
+    drop(secrets);
+    assert!(is_page_locked(address(&kept)));
+}
+
+#[test]
+fn test_logged_state_never_shows_secrets() {
+    let seed: [u8; 32] = std::array::from_fn(|i| 200 - i as u8);
+    let identity = Identity::from_seed(&seed);
+    let public_key = identity.public_key();
+    let entropy = [0x42u8; 32];
+    let master_seed = MasterSeed::from_entropy(&entropy);
+    let phrase = master_seed.phrase();
+
+    let dir = temp_dir("secret-logs");
+    let path = Keystore::path(&dir);
+    let mut keystore = Keystore::create(&path, PASSPHRASE, PARAMS).unwrap();
+    keystore.set_identity("alice", &identity);
+    keystore.set_master_seed("alice", &master_seed);
+
+    let state = ApiState::new(None);
+    let mut session = state.session();
+    session.set_identity(Some(identity));
+    session.set_keystore(keystore);
+    session.pending_backup = Some(PendingBackup {
+        seed: master_seed,
+        positions: vec![0, 1, 2],
+    });
+
+    let logged = format!("{:?}", *session);
+    assert!(logged.contains(&public_key), "{}", logged);
+    assert!(logged.contains("identity/alice"), "{}", logged);
+    assert_hidden(&logged, &seed, "identity seed");
+    assert_hidden(&logged, &entropy, "recovery entropy");
+    assert!(!logged.contains(phrase.as_str()));
+    assert!(!logged.contains(PASSPHRASE));
+
+    let error = Keystore::unlock(&path, "not it").unwrap_err().to_string();
+    assert!(!error.contains("not it") && !error.contains(PASSPHRASE));
+}
+
+#[test]
+fn test_command_responses_never_include_secrets() {
+    let db = Database::open_in_memory().unwrap();
+    let dir = temp_dir("secret-responses");
+    let settings = Settings {
+        kdf: Some(PARAMS),
+        ..Settings::default()
+    };
+
+    let (identity, signed_in) = sign_in(&db, &dir, "carol", PASSWORD, &settings, 0).unwrap();
+    let path = Keystore::path(&dir);
+    let mut keystore = Keystore::create(&path, PASSPHRASE, PARAMS).unwrap();
+    keystore.set_identity("carol", &identity);
+    let seed = keystore.get("identity/carol").unwrap().to_vec();
+
+    let state = ApiState::new(None);
+    let mut session = state.session();
+    session.set_identity(Some(identity));
+    session.set_keystore(keystore);
+
+    let wrong = sign_in(&db, &dir, "carol", "hunter2", &settings, 0).unwrap_err();
+    let responses = [
+        serde_json::to_string(&signed_in).unwrap(),
+        serde_json::to_string(&KeystoreStatus::new(&path, &session)).unwrap(),
+        serde_json::to_string(&wrong).unwrap(),
+        serde_json::to_string(&ApiError::from(
+            Keystore::unlock(&path, "hunter2").unwrap_err(),
+        ))
+        .unwrap(),
+    ];
+    for response in &responses {
+        assert_hidden(response, &seed, "identity seed");
+        for password in [PASSWORD, PASSPHRASE, "hunter2"] {
+            assert!(!response.contains(password), "{}", response);
+        }
+    }
+    assert!(responses[0].contains(&signed_in.public_key));
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use app::crypto::{
+    signed_post::{format_timestamp, parse_timestamp, SignedPost},
+    Error, Identity,
+};
+use app::db::{Database, NewAttachment, NewPeer, NewPost, NewSyncMessage, NewUser};
+use sha2::{Digest, Sha256};
+
+use common::{hex, unhex};
+
+const ALICE_SEED: &str = "ef3347a758b1383e16f0b57c8ef65a2f2a31c4f1632de40db44dc8331ae4487d";
+const ALICE_PUBLIC_KEY: &str = "KVTET9dZSzXW/5uxda06OHoGvBqwMluAQibNDcJ/Rk8=";
+const CONTENT: &str = r#"{"version":1}"#;
+const TIMESTAMP: i64 = 1_735_787_045;
+
+// crypto_sign_detached over the canonical bytes, computed with libsodium.
+const CANONICAL_SHA256: &str = "8f9765ef3229ade4bab2ccc5555236c86a04e9cf8de4c6067ab8e871e3ddded6";
+const LIBSODIUM_SIGNATURE: &str =
+    "tJGGaE9U9aOycR4XYgKwX4PjotcWDHrvBhR+ZHT+Kih5c6osMZRoEHcfjcKakuk5grqZrOlRaFwOrw5WTMrnDQ==";
+
+fn identity(seed_hex: &str) -> Identity {
+    Identity::from_seed(&unhex(seed_hex).try_into().unwrap())
+}
+
+fn checksum(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn new_user(db: &Database, username: &str, identity: &Identity) -> i64 {
+    db.create_user(&NewUser {
+        public_key: identity.public_key(),
+        username: username.to_string(),
+        display_name: None,
+        email: None,
+    })
+    .unwrap()
+    .id
+}
+
+fn signed(identity: &Identity, new_post: NewPost) -> NewPost {
+    let post = SignedPost::new(
+        &identity.public_key(),
+        new_post.content_encrypted.as_deref().unwrap_or_default(),
+        new_post.attachment_checksums.clone().unwrap_or_default(),
+        parse_timestamp(new_post.timestamp.as_deref().unwrap()).unwrap(),
+    )
+    .unwrap();
+    NewPost {
+        signature: Some(post.sign(identity).unwrap()),
+        ..new_post
+    }
+}
+
+#[test]
+fn test_canonical_form_matches_libsodium_vector() {
+    let alice = identity(ALICE_SEED);
+    assert_eq!(alice.public_key(), ALICE_PUBLIC_KEY);
+
+    // Checksums are sorted, so input order doesn't change the signature
+    let post = SignedPost::new(
+        ALICE_PUBLIC_KEY,
+        CONTENT,
+        vec![checksum(b"a"), checksum(b"b"), checksum(b"a")],
+        TIMESTAMP,
+    )
+    .unwrap();
+    // sha256("b") = 3e23..., sha256("a") = ca97...
+    assert_eq!(
+        post.attachment_checksums,
+        vec![checksum(b"b"), checksum(b"a")]
+    );
+    assert_eq!(checksum(&post.canonical_bytes()), CANONICAL_SHA256);
+    assert_eq!(post.sign(&alice).unwrap(), LIBSODIUM_SIGNATURE);
+    assert_eq!(post.verify(LIBSODIUM_SIGNATURE), Ok(()));
+
+    let edits = [
+        SignedPost {
+            content_encrypted: format!("{} ", CONTENT),
+            ..post.clone()
+        },
+        SignedPost {
+            attachment_checksums: vec![checksum(b"a")],
+            ..post.clone()
+        },
+        SignedPost {
+            timestamp: TIMESTAMP + 1,
+            ..post.clone()
+        },
+    ];
+    for edited in edits {
+        assert_eq!(edited.verify(LIBSODIUM_SIGNATURE), Err(Error::BadSignature));
+    }
+
+    assert!(SignedPost::new(ALICE_PUBLIC_KEY, CONTENT, vec!["ABC".to_string()], 0).is_err());
+    assert_eq!(
+        post.sign(&Identity::from_seed(&[7; 32])),
+        Err(Error::InvalidKey)
+    );
+}
+
+#[test]
+fn test_timestamps() {
+    assert_eq!(format_timestamp(TIMESTAMP), "2025-01-02 03:04:05.000");
+    assert_eq!(format_timestamp(-86_401), "1969-12-30 23:59:59.000");
+    for value in [
+        "2025-01-02 03:04:05.000",
+        "2025-01-02 03:04:05.999999",
+        "2025-01-02T03:04:05Z",
+        "2025-01-02 03:04:05 UTC",
+        "2025-01-02T03:04:05+00:00",
+    ] {
+        assert_eq!(parse_timestamp(value), Ok(TIMESTAMP), "{}", value);
+    }
+    assert_eq!(parse_timestamp("2000-02-29 23:59:59"), Ok(951_868_799));
+    for value in [
+        "",
+        "2025-01-02",
+        "2025-13-02 03:04:05",
+        "2025-01-02 03:04:05+02:00",
+    ] {
+        assert!(parse_timestamp(value).is_err(), "{}", value);
+    }
+}
+
+#[test]
+fn test_ingest_rejects_unsigned_and_forged_posts() {
+    let db = Database::open_in_memory().unwrap();
+    let alice = identity(ALICE_SEED);
+    let bob = Identity::from_seed(&[7; 32]);
+    let alice_id = new_user(&db, "alice", &alice);
+    let bob_id = new_user(&db, "bob", &bob);
+
+    let unsigned = NewPost {
+        user_id: alice_id,
+        content_encrypted: Some(CONTENT.to_string()),
+        timestamp: Some("2025-01-02T03:04:05Z".to_string()),
+        ..NewPost::default()
+    };
+    assert!(db.ingest_post(&unsigned).is_err());
+
+    // What Post#sign_content used to fall back to
+    let fallback = NewPost {
+        signature: Some(checksum(b"1-content-1735787045")),
+        ..unsigned.clone()
+    };
+    assert!(db.ingest_post(&fallback).is_err());
+
+    let signed_by_bob = NewPost {
+        user_id: bob_id,
+        ..signed(&bob, unsigned.clone())
+    };
+    assert!(db
+        .ingest_post(&NewPost {
+            user_id: alice_id,
+            ..signed_by_bob.clone()
+        })
+        .is_err());
+
+    let post = db.ingest_post(&signed(&alice, unsigned)).unwrap();
+    assert_eq!(post.timestamp.as_deref(), Some("2025-01-02 03:04:05.000"));
+    assert_eq!(db.verify_post(post.id).unwrap().author, ALICE_PUBLIC_KEY);
+    assert!(db.ingest_post(&signed_by_bob).is_ok());
+}
+
+#[test]
+fn test_stored_post_covers_attachments() {
+    let db = Database::open_in_memory().unwrap();
+    let alice = identity(ALICE_SEED);
+    let alice_id = new_user(&db, "alice", &alice);
+    let attachment = |post_id: i64, data: &[u8]| NewAttachment {
+        post_id,
+        filename: "photo.jpg".to_string(),
+        content_type: "image/jpeg".to_string(),
+        file_size: data.len() as i64,
+        data_encrypted: "blob".to_string(),
+        checksum: checksum(data),
+    };
+
+    let post = db
+        .ingest_post(&signed(
+            &alice,
+            NewPost {
+                user_id: alice_id,
+                content_encrypted: Some(CONTENT.to_string()),
+                timestamp: Some(format_timestamp(TIMESTAMP)),
+                attachment_checksums: Some(vec![checksum(b"photo")]),
+                ..NewPost::default()
+            },
+        ))
+        .unwrap();
+    assert!(
+        db.verify_post(post.id).is_err(),
+        "signed attachment missing"
+    );
+
+    db.create_attachment(&attachment(post.id, b"photo"))
+        .unwrap();
+    assert!(db.verify_post(post.id).is_ok());
+
+    db.create_attachment(&attachment(post.id, b"extra"))
+        .unwrap();
+    assert!(db.verify_post(post.id).is_err());
+}
+
+#[test]
+fn test_inbound_sync_verifies_every_post() {
+    let db = Database::open_in_memory().unwrap();
+    let alice = identity(ALICE_SEED);
+    let bob = Identity::from_seed(&[7; 32]);
+    let alice_id = new_user(&db, "alice", &alice);
+    new_user(&db, "bob", &bob);
+    let peer = db
+        .record_peer(&NewPeer {
+            user_id: alice_id,
+            address: "10.0.0.2".to_string(),
+            port: 4000,
+            public_key: bob.public_key(),
+        })
+        .unwrap();
+
+    let good = SignedPost::new(&bob.public_key(), "from bob", vec![], TIMESTAMP).unwrap();
+    let forged = SignedPost::new(&bob.public_key(), "not from bob", vec![], TIMESTAMP).unwrap();
+    let payload = serde_json::json!({
+        "type": "message_sync",
+        "timestamp": TIMESTAMP,
+        "messages": [
+            { "id": 1, "content_encrypted": good.content_encrypted, "signature": good.sign(&bob).unwrap(), "timestamp": TIMESTAMP, "attachments": [] },
+            { "id": 2, "content_encrypted": forged.content_encrypted, "signature": alice.sign_base64(&forged.canonical_bytes()), "timestamp": TIMESTAMP },
+            { "id": 3, "content_encrypted": "unsigned", "timestamp": TIMESTAMP },
+        ]
+    });
+    let sync_message = NewSyncMessage {
+        user_id: alice_id,
+        peer_id: peer.id,
+        payload: payload.to_string(),
+        message_type: "inbound_sync".to_string(),
+        status: "pending".to_string(),
+        processed_count: None,
+        error_count: None,
+    };
+
+    let received = db.receive_sync(&sync_message).unwrap();
+    assert_eq!(received.status.as_deref(), Some("processed"));
+    assert_eq!(received.processed_count, Some(1));
+    assert_eq!(received.error_count, Some(2));
+
+    let feed = db.posts_for_user(alice_id).unwrap();
+    assert_eq!(feed.len(), 1);
+    assert!(feed[0].is_synced);
+    assert!(db.verify_post(feed[0].id).is_ok());
+
+    // Replaying the batch doesn't duplicate posts
+    db.receive_sync(&sync_message).unwrap();
+    assert_eq!(db.posts_for_user(alice_id).unwrap().len(), 1);
+}
@@ -0,0 +1,289 @@
+mod common;
+
+use app::api::{
+    v1::{
+        recovery,
+        social_recovery::{self, ReleaseShareRequest, SplitSeedRequest},
+    },
+    ApiError,
+};
+use app::crypto::{
+    social_recovery::{recover, RecoveryRequest, ShareGrant},
+    Error, Identity,
+};
+use app::db::{
+    Database, NewPeer, NewSyncMessage, NewUser, INBOUND_RECOVERY_SHARE, OUTBOUND_RECOVERY_SHARE,
+};
+use app::settings::Settings;
+
+use common::{temp_dir, PARAMS};
+
+fn holders(count: u8) -> Vec<Identity> {
+    (1..=count).map(|i| Identity::from_seed(&[i; 32])).collect()
+}
+
+fn public_keys(identities: &[Identity]) -> Vec<String> {
+    identities.iter().map(Identity::public_key).collect()
+}
+
+#[test]
+fn test_any_threshold_of_shares_recovers_the_seed() {
+    let owner = Identity::from_seed(&[42; 32]);
+    let friends = holders(5);
+    let grants = ShareGrant::deal(&owner, "alice", &public_keys(&friends), 3, 100).unwrap();
+    assert_eq!(grants.len(), 5);
+    assert!(grants.iter().all(|grant| grant.verify().is_ok()));
+    assert!(grants
+        .windows(2)
+        .all(|pair| pair[0].set_id == pair[1].set_id));
+
+    let (request, request_identity) = RecoveryRequest::generate("alice", 200);
+    let returned: Vec<_> = grants
+        .iter()
+        .zip(&friends)
+        .map(|(grant, friend)| grant.release(friend, &request).unwrap())
+        .collect();
+
+    for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let shares: Vec<_> = picked.iter().map(|&i| returned[i].clone()).collect();
+        let recovered = recover(&request, &request_identity, &shares).unwrap();
+        assert_eq!(recovered.public_key(), owner.public_key());
+    }
+
+    // Two shares, or the same share twice, aren't enough
+    let duplicated = [
+        returned[0].clone(),
+        returned[1].clone(),
+        returned[1].clone(),
+    ];
+    for shares in [&returned[..2], &duplicated[..]] {
+        assert_eq!(
+            recover(&request, &request_identity, shares).err(),
+            Some(Error::NotEnoughShares)
+        );
+    }
+
+    // Shares returned to one request can't be opened by another
+    let (other, other_identity) = RecoveryRequest::generate("alice", 200);
+    assert!(recover(&other, &other_identity, &returned[..3]).is_err());
+
+    // A holder can only release their own share
+    assert_eq!(
+        grants[0].release(&friends[1], &request).err(),
+        Some(Error::NotRecipient)
+    );
+    let (mallory, _) = RecoveryRequest::generate("mallory", 200);
+    assert!(grants[0].release(&friends[0], &mallory).is_err());
+}
+
+#[test]
+fn test_tampered_shares_are_rejected() {
+    let owner = Identity::from_seed(&[42; 32]);
+    let friends = holders(3);
+    let grants = ShareGrant::deal(&owner, "alice", &public_keys(&friends), 2, 100).unwrap();
+    let (request, request_identity) = RecoveryRequest::generate("alice", 200);
+
+    let lowered = ShareGrant {
+        threshold: 1,
+        ..grants[0].clone()
+    };
+    assert_eq!(lowered.verify(), Err(Error::Malformed("recovery share")));
+    let relabelled = ShareGrant {
+        owner_username: "bob".to_string(),
+        ..grants[0].clone()
+    };
+    assert_eq!(relabelled.verify(), Err(Error::BadSignature));
+
+    let mut returned = grants[0].release(&friends[0], &request).unwrap();
+    returned.sealed_share = grants[1]
+        .release(&friends[1], &request)
+        .unwrap()
+        .sealed_share;
+    let second = grants[2].release(&friends[2], &request).unwrap();
+    assert_eq!(
+        recover(&request, &request_identity, &[returned, second]).err(),
+        Some(Error::BadSignature)
+    );
+
+    // Bad splits
+    let keys = public_keys(&friends);
+    for (holders, threshold) in [
+        (keys.clone(), 1),
+        (keys.clone(), 4),
+        (vec![keys[0].clone(), keys[0].clone()], 2),
+        (vec![keys[0].clone(), owner.public_key()], 2),
+    ] {
+        assert!(ShareGrant::deal(&owner, "alice", &holders, threshold, 0).is_err());
+    }
+}
+
+#[test]
+fn test_request_code() {
+    let (request, _) = RecoveryRequest::generate("alice", 0);
+    let code = request.code();
+    assert_eq!(code.len(), 11);
+    assert!(code.chars().enumerate().all(|(i, c)| if i == 5 {
+        c == '-'
+    } else {
+        c.is_ascii_digit()
+    }));
+    assert_ne!(RecoveryRequest::generate("alice", 0).0.code(), code);
+}
+
+#[test]
+fn test_shares_travel_over_sync_and_restore_the_account() {
+    let db = Database::open_in_memory().unwrap();
+    let owner = Identity::from_seed(&[42; 32]);
+    let friends = holders(3);
+    let stranger = Identity::from_seed(&[9; 32]);
+    let register = |username: &str, identity: &Identity| {
+        db.register_user(&NewUser {
+            public_key: identity.public_key(),
+            username: username.to_string(),
+            display_name: None,
+            email: None,
+        })
+        .unwrap()
+        .id
+    };
+    let alice = register("alice", &owner);
+    let friend_ids: Vec<i64> = ["bob", "carol", "dave"]
+        .iter()
+        .zip(&friends)
+        .map(|(name, identity)| register(name, identity))
+        .collect();
+    let stranger_id = register("mallory", &stranger);
+    for &friend_id in &friend_ids {
+        let friendship = db.request_friendship(alice, friend_id).unwrap();
+        db.respond_to_friendship(friendship.id, "accepted").unwrap();
+    }
+    let peer = |user_id: i64, identity: &Identity| {
+        db.record_peer(&NewPeer {
+            user_id,
+            address: identity.public_key(),
+            port: 4000,
+            public_key: identity.public_key(),
+        })
+        .unwrap()
+        .id
+    };
+
+    let split = |friend_ids: Vec<i64>| SplitSeedRequest {
+        user_id: alice,
+        friend_ids,
+        threshold: 2,
+    };
+    // Every holder needs a peer, and must be a friend
+    assert!(social_recovery::split(&db, &owner, &split(friend_ids.clone()), 100).is_err());
+    for (friend_id, identity) in friend_ids.iter().zip(&friends) {
+        peer(alice, identity);
+        peer(*friend_id, &owner);
+    }
+    assert!(matches!(
+        social_recovery::split(&db, &owner, &split(vec![friend_ids[0], stranger_id]), 100),
+        Err(ApiError::Invalid(_))
+    ));
+    assert!(social_recovery::split(&db, &friends[0], &split(friend_ids.clone()), 100).is_err());
+    let grants = social_recovery::split(&db, &owner, &split(friend_ids.clone()), 100).unwrap();
+
+    let outbound: Vec<_> = db
+        .pending_sync_messages(alice)
+        .unwrap()
+        .into_iter()
+        .filter(|message| message.message_type.as_deref() == Some(OUTBOUND_RECOVERY_SHARE))
+        .collect();
+    assert_eq!(outbound.len(), grants.len());
+    assert!(outbound.iter().all(|message| !message
+        .payload
+        .as_deref()
+        .unwrap()
+        .contains(&owner_seed())));
+
+    // Each friend's device receives its grant from their peer for alice
+    for (message, &friend_id) in outbound.iter().zip(&friend_ids) {
+        let peer_id = db
+            .peers_for_user(friend_id)
+            .unwrap()
+            .into_iter()
+            .find(|peer| peer.public_key == Some(owner.public_key()))
+            .unwrap()
+            .id;
+        let inbound = NewSyncMessage {
+            user_id: friend_id,
+            peer_id,
+            payload: message.payload.clone().unwrap(),
+            message_type: INBOUND_RECOVERY_SHARE.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        };
+        if friend_id != friend_ids[0] {
+            // Not addressed to bob
+            assert!(db
+                .receive_sync(&NewSyncMessage {
+                    user_id: friend_ids[0],
+                    ..inbound.clone()
+                })
+                .is_err());
+        }
+        assert_eq!(
+            db.receive_sync(&inbound).unwrap().status.as_deref(),
+            Some("held")
+        );
+    }
+    // Only friends' shares are kept
+    let stranger_grant = ShareGrant::deal(
+        &owner,
+        "alice",
+        &[stranger.public_key(), friends[0].public_key()],
+        2,
+        100,
+    )
+    .unwrap()
+    .remove(0);
+    let stranger_peer = peer(stranger_id, &owner);
+    assert!(db
+        .receive_sync(&NewSyncMessage {
+            user_id: stranger_id,
+            peer_id: stranger_peer,
+            payload: serde_json::to_string(&stranger_grant).unwrap(),
+            message_type: INBOUND_RECOVERY_SHARE.to_string(),
+            status: "pending".to_string(),
+            processed_count: None,
+            error_count: None,
+        })
+        .is_err());
+
+    // On a new device alice asks bob and dave for their shares
+    let (request, request_identity) = RecoveryRequest::generate("alice", 200);
+    let mut returned = Vec::new();
+    for index in [0, 2] {
+        let held = db.held_recovery_shares(friend_ids[index]).unwrap();
+        assert_eq!(held.len(), 1);
+        let release = |code: String| ReleaseShareRequest {
+            id: held[0].0,
+            request: request.clone(),
+            code,
+        };
+        assert!(social_recovery::release(&db, &friends[index], &release("0".repeat(10))).is_err());
+        returned.push(
+            social_recovery::release(&db, &friends[index], &release(request.code())).unwrap(),
+        );
+    }
+
+    let identity = recover(&request, &request_identity, &returned).unwrap();
+    let dir = temp_dir("social");
+    let settings = Settings {
+        kdf: Some(PARAMS),
+        ..Settings::default()
+    };
+    let restored =
+        recovery::restore_identity(&db, &dir, "alice", "new pw", &identity, &settings).unwrap();
+    assert_eq!(restored.public_key, owner.public_key());
+    assert!(recovery::restore_identity(&db, &dir, "bob", "pw", &identity, &settings).is_err());
+}
+
+/// The Base64 seed of `Identity::from_seed(&[42; 32])`.
+fn owner_seed() -> String {
+    app::crypto::encode_base64(&[42; 32])
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use app::crypto::{
+    stream::{encrypt_stream, BlobEnvelope, BlobError, BlobReader, HEADER_BYTES, TAG_BYTES},
+    Error, Identity,
+};
+use sha2::{Digest, Sha256};
+
+use common::{hex, temp_dir};
+
+const KEY: [u8; 32] = [42; 32];
+const CHUNK: u32 = 64;
+
+fn plaintext(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn encrypt(data: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    let summary = encrypt_stream(&KEY, CHUNK, data, &mut blob).unwrap();
+    assert_eq!(summary.plaintext_len, data.len() as u64);
+    assert_eq!(summary.blob_len, blob.len() as u64);
+    blob
+}
+
+fn open(blob: Vec<u8>) -> std::io::Result<BlobReader<Cursor<Vec<u8>>>> {
+    BlobReader::open(Cursor::new(blob), &KEY)
+}
+
+#[test]
+fn test_round_trip_at_chunk_boundaries() {
+    for len in [0, 1, 63, 64, 65, 128, 224] {
+        let data = plaintext(len);
+        let blob = encrypt(&data);
+        // Empty input is one empty chunk; exact multiples get no trailing one
+        let chunks = len.div_ceil(CHUNK as usize).max(1);
+        assert_eq!(
+            blob.len() as u64,
+            HEADER_BYTES + len as u64 + chunks as u64 * TAG_BYTES,
+            "len {}",
+            len
+        );
+
+        let mut reader = open(blob).unwrap();
+        assert_eq!(reader.len(), len as u64);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data, "len {}", len);
+    }
+}
+
+#[test]
+fn test_random_access() {
+    let data = plaintext(1000);
+    let mut reader = open(encrypt(&data)).unwrap();
+
+    for (offset, len) in [(0, 10), (60, 10), (500, 200), (990, 50), (1000, 5)] {
+        let range = reader.read_range(offset, len).unwrap();
+        let end = (offset as usize + len).min(data.len());
+        assert_eq!(range.as_slice(), &data[offset as usize..end]);
+    }
+
+    reader.seek(SeekFrom::End(-3)).unwrap();
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, &data[997..]);
+    assert!(reader.seek(SeekFrom::Current(-2000)).is_err());
+}
+
+#[test]
+fn test_tampering_is_detected() {
+    let data = plaintext(300);
+    let blob = encrypt(&data);
+    let sealed_chunk = (CHUNK as u64 + TAG_BYTES) as usize;
+    let header = HEADER_BYTES as usize;
+
+    // A flipped bit fails only the chunk it is in
+    let mut flipped = blob.clone();
+    flipped[header + sealed_chunk + 5] ^= 1;
+    let mut reader = open(flipped).unwrap();
+    assert_eq!(reader.read_range(0, 64).unwrap().as_slice(), &data[..64]);
+    assert!(reader.read_range(64, 64).is_err());
+
+    // Cut at a chunk boundary: the new last chunk isn't marked final
+    let truncated = blob[..header + 2 * sealed_chunk].to_vec();
+    assert!(open(truncated).is_err());
+
+    let mut swapped = blob.clone();
+    let (first, second) = swapped[header..header + 2 * sealed_chunk].split_at_mut(sealed_chunk);
+    first.swap_with_slice(second);
+    assert!(open(swapped).unwrap().read_range(0, 10).is_err());
+
+    let mut extended = blob.clone();
+    extended.extend_from_slice(&blob[header..header + sealed_chunk]);
+    assert!(open(extended).is_err());
+
+    let wrong_key = BlobReader::open(Cursor::new(blob.clone()), &[0; 32]);
+    assert!(wrong_key.is_err());
+    assert!(open(blob[..10].to_vec()).is_err());
+}
+
+#[test]
+fn test_blob_envelope_files() {
+    let dir = temp_dir("blobs");
+    let source = dir.join("video.bin");
+    let data = plaintext(200_000);
+    std::fs::write(&source, &data).unwrap();
+
+    let alice = Identity::from_seed(&[1; 32]);
+    let bob = Identity::from_seed(&[2; 32]);
+    let blobs = dir.join("blobs");
+    let envelope = BlobEnvelope::encrypt_file(&source, &blobs, &[alice.public_key()]).unwrap();
+    assert_eq!(envelope.size, data.len() as u64);
+
+    let stored = std::fs::read(blobs.join(&envelope.blob)).unwrap();
+    assert_eq!(envelope.blob, hex(&Sha256::digest(&stored)));
+
+    let envelope = BlobEnvelope::from_json(&envelope.to_json()).unwrap();
+    let mut reader = envelope.open(&blobs, &alice).unwrap();
+    assert_eq!(
+        reader.read_range(150_000, 100).unwrap().as_slice(),
+        &data[150_000..150_100]
+    );
+    assert!(matches!(
+        envelope.open(&blobs, &bob),
+        Err(BlobError::Crypto(Error::NotRecipient))
+    ));
+
+    let mut escaped = envelope.clone();
+    escaped.blob = "../video.bin".to_string();
+    assert!(matches!(
+        escaped.open(&blobs, &alice),
+        Err(BlobError::Crypto(Error::Malformed(_)))
+    ));
+}
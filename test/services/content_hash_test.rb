@@ -0,0 +1,29 @@
+require "test_helper"
+require "json"
+
+class ContentHashTest < ActiveSupport::TestCase
+  VECTORS = JSON.parse(File.read(Rails.root.join("test-vectors/content_hash.json")))
+
+  test "Ruby port matches the published vectors" do
+    assert_equal ContentHash::VERSION, VECTORS["version"]
+
+    VECTORS["vectors"].each do |vector|
+      fields = vector["input"].symbolize_keys
+
+      assert_equal vector["canonical_hex"], ContentHash.canonical_bytes(**fields).unpack1("H*"), vector["name"]
+      assert_equal vector["hash"], ContentHash.ruby_digest(fields), vector["name"]
+      assert_equal vector["hash"], ContentHash.digest(**fields), vector["name"]
+      assert_equal vector["legacy_hash"], ContentHash.legacy_digest(**fields), vector["name"]
+      assert ContentHash.matches?(vector["legacy_hash"], **fields)
+    end
+  end
+
+  test "timestamps may be times or strings" do
+    fields = VECTORS["vectors"].find { |vector| vector["name"] == "signed post" }
+    input = fields["input"].symbolize_keys
+
+    assert_equal fields["hash"], ContentHash.digest(**input.merge(timestamp: Time.at(input[:timestamp]).utc))
+    assert_equal fields["hash"], ContentHash.digest(**input.merge(timestamp: "2023-11-14T22:13:20Z"))
+    assert_equal fields["hash"], ContentHash.digest(**input.merge(timestamp: input[:timestamp].to_s))
+  end
+end
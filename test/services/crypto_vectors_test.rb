@@ -0,0 +1,58 @@
+require "test_helper"
+require "json"
+
+# The Rails half of test-vectors/; src-tauri/tests/crypto_vectors_test.rs
+# checks the Rust backend against the same files.
+class CryptoVectorsTest < ActiveSupport::TestCase
+  def self.vectors(name)
+    JSON.parse(File.read(Rails.root.join("test-vectors", name)))["vectors"]
+  end
+
+  def bytes(hex)
+    [ hex ].pack("H*")
+  end
+
+  test "user keys derive as the vectors say" do
+    self.class.vectors("kdf.json").each do |vector|
+      seed = bytes(vector["seed_hex"])
+
+      if vector["iterations"] == 100_000
+        assert_equal seed, User.derive_private_key_from_credentials(vector["username"], vector["password"]), vector["name"]
+      end
+      assert_equal vector["public_key"], Base64.strict_encode64(User.public_key_from_private_key(seed)), vector["name"]
+    end
+  end
+
+  test "signatures verify and reproduce" do
+    self.class.vectors("signatures.json").each do |vector|
+      message = bytes(vector["message_hex"])
+
+      assert_equal vector["valid"], User.new.verify_signature(message, vector["signature"], vector["public_key"]), vector["name"]
+      next unless vector["valid"]
+
+      signature = RbNaCl::SigningKey.new(bytes(vector["seed_hex"])).sign(message)
+      assert_equal vector["signature"], Base64.strict_encode64(signature), vector["name"]
+    end
+  end
+
+  test "secret boxes match" do
+    self.class.vectors("secretbox.json").each do |vector|
+      box = RbNaCl::SecretBox.new(bytes(vector["key_hex"]))
+      nonce = bytes(vector["nonce_hex"])
+
+      assert_equal bytes(vector["ciphertext_hex"]), box.encrypt(nonce, bytes(vector["plaintext_hex"])), vector["name"]
+      assert_equal bytes(vector["plaintext_hex"]), box.decrypt(nonce, bytes(vector["ciphertext_hex"])), vector["name"]
+    end
+  end
+
+  test "attachment simple boxes open and store as the vectors say" do
+    self.class.vectors("simplebox.json").each do |vector|
+      key = bytes(vector["key_hex"])
+      boxed = bytes(vector["boxed_hex"])
+
+      assert_equal bytes(vector["plaintext_hex"]), RbNaCl::SimpleBox.from_secret_key(key).decrypt(boxed), vector["name"]
+      assert_equal vector["dev_owner_key"], Base64.encode64(key), vector["name"]
+      assert_equal vector["data_encrypted"], Base64.encode64(boxed), vector["name"]
+    end
+  end
+end